@@ -135,7 +135,7 @@ def get_current_test():
 # Assertions
 #
 
-__all__ += ["assert_equal", "assert_lines_match"]
+__all__ += ["assert_equal", "assert_lines_match", "parse_ktest", "assert_ktest_pass"]
 
 def assert_equal(got, expect, msg=""):
     if got == expect:
@@ -196,6 +196,37 @@ def assert_lines_match(text, *regexps, **kw):
         msg.append(color("red", "MISSING") + " '%s'" % r)
     raise AssertionError("\n".join(msg))
 
+def parse_ktest(text):
+    """Parse the output of the kernel unit test runner (built with the
+    unit_test feature).  Returns (results, summary) where results is a list
+    of (status, name, hart) tuples and summary is a dict of the counters in
+    the final 'ktest: summary' line, or None if the runner did not finish."""
+
+    results = []
+    summary = None
+    for line in text.splitlines():
+        m = re.match(r"^ktest: (PASS|FAIL|SKIP) (\S+) hart=(\d+)", line)
+        if m:
+            results.append((m.group(1), m.group(2), int(m.group(3))))
+            continue
+        m = re.match(r"^ktest: summary (.*)$", line)
+        if m:
+            summary = dict((k, int(v)) for k, v in
+                           re.findall(r"(\w+)=(\d+)", m.group(1)))
+    return results, summary
+
+def assert_ktest_pass(text):
+    """Assert that the kernel unit test runner finished without failures."""
+
+    results, summary = parse_ktest(text)
+    if summary is None:
+        raise AssertionError("kernel unit tests did not finish")
+    failed = ["%s (hart %d)" % (name, hart)
+              for status, name, hart in results if status == "FAIL"]
+    if failed or summary.get("failed", 0):
+        raise AssertionError("failed kernel unit tests:\n  " +
+                             "\n  ".join(failed))
+
 ##################################################################
 # Utilities
 #
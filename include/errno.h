@@ -0,0 +1,5 @@
+// error numbers reported through geterrno() after a system call
+// returns -1; 0 means the kernel gave no reason.
+
+#define E2BIG   7  // argument list too long
+#define ENOMEM 12  // out of memory
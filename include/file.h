@@ -38,3 +38,11 @@ struct devsw {
 extern struct devsw devsw[];
 
 #define CONSOLE 1
+#define RANDOM  2
+#define MEM     3
+#define TTYS    4  // minor is the serial port
+
+// minor numbers under MEM
+#define MEM_NULL 0
+#define MEM_ZERO 1
+#define MEM_FULL 2
@@ -69,4 +69,5 @@
 #define USYSCALL (TRAPFRAME - PGSIZE)
 struct usyscall {
   int pid;  // Process ID
+  int errno;  // why the last failed system call failed, see errno.h
 };
\ No newline at end of file
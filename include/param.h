@@ -6,8 +6,10 @@
 #define NDEV         10  // maximum major device number
 #define ROOTDEV       1  // device number of file system root disk
 #define MAXARG       64  // max exec arguments
+#define ARG_MAX    8192  // max bytes of exec arguments and environment, with pointers
 #define MAXOPBLOCKS  10  // max # of blocks any FS op writes
 #define LOGSIZE      (MAXOPBLOCKS*3)  // max data blocks in on-disk log
 #define NBUF         (MAXOPBLOCKS*3)  // size of disk block cache
 #define FSSIZE       2048  // size of file system in blocks
+#define SWAPSIZE    32768  // size of swap area in blocks, after the file system
 #define MAXPATH      128   // maximum file path name
@@ -0,0 +1,9 @@
+// per-hart scheduler statistics, see schedstat()
+
+struct schedstat {
+  uint64 switches;    // context switches into a process
+  uint64 idle_ns;     // time spent in wfi
+  uint64 steals;      // processes taken from other harts' run queues
+  uint64 migrations;  // processes that last ran on another hart
+  uint64 queued;      // processes currently waiting in the run queue
+};
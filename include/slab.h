@@ -0,0 +1,13 @@
+// per-size-class statistics of the kernel slab allocator, see slabstat()
+
+struct slabstat {
+  uint64 size;      // object size in bytes
+  uint64 slabs;     // slabs held by this size class
+  uint64 objects;   // objects the slabs can hold
+  uint64 free;      // free objects in the slabs
+  uint64 cached;    // free objects cached in per-hart magazines
+  uint64 allocs;    // allocations served
+  uint64 frees;     // frees served
+  uint64 refills;   // magazine refills from the slabs
+  uint64 flushes;   // magazine flushes back to the slabs
+};
@@ -8,4 +8,7 @@ struct stat {
   short type;  // Type of file
   short nlink; // Number of links to file
   uint64 size; // Size of file in bytes
+  uint64 atime; // Last access, seconds since the epoch
+  uint64 mtime; // Last modification
+  uint64 ctime; // Last status change
 };
@@ -20,3 +20,17 @@
 #define SYS_link   19
 #define SYS_mkdir  20
 #define SYS_close  21
+#define SYS_ioctl  24
+#define SYS_clock_gettime 25
+#define SYS_nanosleep 26
+#define SYS_setitimer 27
+#define SYS_timerfd_create  28
+#define SYS_timerfd_settime 29
+#define SYS_timerfd_gettime 30
+#define SYS_sched_setaffinity 31
+#define SYS_sched_getaffinity 32
+#define SYS_schedstat 33
+#define SYS_slabstat 34
+#define SYS_heapdump 35
+#define SYS_setaslr 36
+#define SYS_execve 37
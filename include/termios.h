@@ -0,0 +1,64 @@
+// terminal settings, same layout as the kernel's Termios
+
+#define NCCS 8
+
+// indices into c_cc
+#define VINTR  0
+#define VQUIT  1
+#define VERASE 2
+#define VKILL  3
+#define VEOF   4
+#define VTIME  5
+#define VMIN   6
+
+// c_iflag
+#define INLCR  0000100
+#define IGNCR  0000200
+#define ICRNL  0000400
+
+// c_oflag
+#define OPOST  0000001
+#define ONLCR  0000004
+
+// c_cflag
+#define CBAUD   0010017
+#define B1200   0000011
+#define B2400   0000013
+#define B4800   0000014
+#define B9600   0000015
+#define B19200  0000016
+#define B38400  0000017
+#define B57600  0010001
+#define B115200 0010002
+#define CSIZE   0000060
+#define CS5     0000000
+#define CS6     0000020
+#define CS7     0000040
+#define CS8     0000060
+#define CSTOPB  0000100
+#define CREAD   0000200
+#define PARENB  0000400
+#define PARODD  0001000
+
+// c_lflag
+#define ISIG   0000001
+#define ICANON 0000002
+#define ECHO   0000010
+#define ECHOE  0000020
+#define ECHOK  0000040
+
+// ioctl commands on terminals
+#define TCGETS    0x5401
+#define TCSETS    0x5402
+#define TCSETSW   0x5403
+#define TCSETSF   0x5404
+#define TIOCGPGRP 0x540F
+#define TIOCSPGRP 0x5410
+
+struct termios {
+  uint c_iflag;
+  uint c_oflag;
+  uint c_cflag;
+  uint c_lflag;
+  uchar c_cc[NCCS];
+};
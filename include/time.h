@@ -0,0 +1,30 @@
+// clocks, same numbering as the kernel's time module
+
+#define CLOCK_REALTIME  0   // wall clock, from the goldfish rtc
+#define CLOCK_MONOTONIC 1   // time since boot
+
+struct timespec {
+  long sec;
+  long nsec;
+};
+
+struct timeval {
+  long sec;
+  long usec;
+};
+
+// setitimer
+#define ITIMER_REAL 0       // counts real time; the process is killed on expiry
+
+struct itimerval {
+  struct timeval interval;  // period after the first expiry, 0 for one-shot
+  struct timeval value;     // time until the next expiry, 0 disarms
+};
+
+// timerfd_settime flags
+#define TFD_TIMER_ABSTIME 1 // value is an absolute time on the timerfd's clock
+
+struct itimerspec {
+  struct timespec interval;
+  struct timespec value;
+};
@@ -10,6 +10,83 @@ pub const UART_BUF: usize = 32;
 /// constant device index of console
 pub const DEV_CONSOLE: usize = 1;
 
+/// constant device index of /dev/random
+pub const DEV_RANDOM: usize = 2;
+
+/// entropy bytes requested from virtio-rng at a time
+pub const ENTROPY_BUF: usize = 64;
+
+/// constant device index of /dev/null, /dev/zero and /dev/full
+pub const DEV_MEM: usize = 3;
+
+/// minor numbers under DEV_MEM
+pub const MEM_NULL: u16 = 0;
+pub const MEM_ZERO: u16 = 1;
+pub const MEM_FULL: u16 = 2;
+
+/// constant device index of /dev/ttyS*, the minor number is the serial port
+pub const DEV_TTYS: usize = 4;
+
+/// poll events reported by character devices
+pub const POLLIN: u32 = 0x1;
+pub const POLLOUT: u32 = 0x4;
+
+/// number of control characters in termios
+pub const NCCS: usize = 8;
+
+/// indices into termios control characters
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+
+/// termios input flags
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+
+/// termios output flags
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+
+/// termios control flags
+pub const CBAUD: u32 = 0o10017;
+pub const B1200: u32 = 0o11;
+pub const B2400: u32 = 0o13;
+pub const B4800: u32 = 0o14;
+pub const B9600: u32 = 0o15;
+pub const B19200: u32 = 0o16;
+pub const B38400: u32 = 0o17;
+pub const B57600: u32 = 0o10001;
+pub const B115200: u32 = 0o10002;
+pub const CSIZE: u32 = 0o60;
+pub const CS5: u32 = 0o0;
+pub const CS6: u32 = 0o20;
+pub const CS7: u32 = 0o40;
+pub const CS8: u32 = 0o60;
+pub const CSTOPB: u32 = 0o100;
+pub const CREAD: u32 = 0o200;
+pub const PARENB: u32 = 0o400;
+pub const PARODD: u32 = 0o1000;
+
+/// termios local flags
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+
+/// tty ioctl commands, same numbers as linux
+pub const TCGETS: u32 = 0x5401;
+pub const TCSETS: u32 = 0x5402;
+pub const TCSETSW: u32 = 0x5403;
+pub const TCSETSF: u32 = 0x5404;
+pub const TIOCGPGRP: u32 = 0x540F;
+pub const TIOCSPGRP: u32 = 0x5410;
+
 ////////////////////////////////////////////////
 ///////////    Control Characters   ////////////
 ////////////////////////////////////////////////
@@ -31,6 +108,12 @@ pub const CTRL_CR: u8 = 0x0D;
 /// DEL
 pub const CTRL_DEL: u8 = 0x7f;
 
+/// ETX, ctrl-c
+pub const CTRL_ETX: u8 = 0x03;
+
+/// FS, ctrl-\\
+pub const CTRL_FS: u8 = 0x1c;
+
 /////////////////////////////////////////////////////////////
 ///////////    Self-defined Control Characters   ////////////
 /////////////////////////////////////////////////////////////
@@ -35,6 +35,8 @@ pub const LOGSIZE: usize = MAXOPBLOCKS * 3;
 
 /// maxinum number of file opened by a process
 pub const NFILE: usize = 16;
+/// maxinum number of timerfds open in the system
+pub const NTIMERFD: usize = 16;
 
 /////////////////////////////////////////////////
 ///////////    File Creation Flags   ////////////
@@ -4,10 +4,11 @@
 //! based on qemu's hw/riscv/virt.c:
 //!
 //! 00001000 -- boot ROM, provided by qemu
+//! 00101000 -- goldfish rtc
 //! 02000000 -- CLINT
 //! 0C000000 -- PLIC
 //! 10000000 -- uart0
-//! 10001000 -- virtio disk
+//! 10001000 -- virtio mmio slots 0..8 (disk, rng, ...)
 //! 80000000 -- boot ROM jumps here in machine mode
 //!             -kernel loads the kernel here
 //! unused RAM after 80000000.
@@ -22,29 +23,46 @@ use super::*;
 /// local interrupt controller, which contains the timer.
 pub const CLINT: ConstAddr = ConstAddr(0x2000000);
 pub const CLINT_MAP_SIZE: usize = 0x10000;
+/// one 32-bit machine software interrupt pending register per hart, used for interprocessor interrupts.
+pub const CLINT_MSIP: ConstAddr = CLINT.const_add(0);
 pub const CLINT_MTIMECMP: ConstAddr = CLINT.const_add(0x4000);
 pub const CLINT_MTIME: ConstAddr = CLINT.const_add(0xbff8);
+/// frequency of mtime, the timebase-frequency of qemu virt's cpus
+pub const TIMEBASE_FREQ: u64 = 10_000_000;
+
+/// goldfish real time clock, used when the device tree has no google,goldfish-rtc node.
+pub const RTC0: ConstAddr = ConstAddr(0x101000);
 
 /// qemu puts UART registers here in physical memory.
+/// more 16550 ports are discovered from the device tree, UART0 is used when there is none.
 pub const UART0: ConstAddr = ConstAddr(0x10000000);
 pub const UART0_MAP_SIZE: usize = PAGE_SIZE;
 pub const UART0_IRQ: usize = 10;
+pub const NUART: usize = 4;
+/// input clock of a 16550 whose device tree node has no clock-frequency
+pub const UART_CLOCK: usize = 1843200;
 
 /// virtio mmio interface
+/// qemu virt provides NVIRTIO slots, one page apart, slot i uses irq VIRTIO0_IRQ + i.
 pub const VIRTIO0: ConstAddr = ConstAddr(0x10001000);
-pub const VIRTIO0_MAP_SIZE: usize = PAGE_SIZE;
+pub const NVIRTIO: usize = 8;
+pub const VIRTIO_MMIO_STRIDE: usize = 0x1000;
+pub const VIRTIO0_MAP_SIZE: usize = NVIRTIO * VIRTIO_MMIO_STRIDE;
 pub const VIRTIO0_IRQ: usize = 1;
 
 /// qemu puts programmable interrupt controller here.
 pub const PLIC: ConstAddr = ConstAddr(0x0c000000);
 pub const PLIC_MAP_SIZE: usize = 0x400000;
+/// interrupt sources handled by the kernel, irq 0 means no interrupt
+pub const NIRQ: usize = 64;
+/// PLIC priorities are 1 (lowest) to PLIC_MAX_PRIORITY, 0 disables a source
+pub const PLIC_MAX_PRIORITY: u32 = 7;
 
 /// the kernel expects there to be RAM
 /// for use by the kernel and user pages
 /// from physical address 0x80000000 to PHYSTOP.
 pub const KERNBASE: ConstAddr = ConstAddr(0x80000000);
 pub const PHYSTOP: ConstAddr = KERNBASE.const_add((128 + 64) * 1024 * 1024);
-pub const KERNEL_HEAP_END: ConstAddr = KERNBASE.const_add(128 * 1024 * 1024);
 /// map the trampoline page to the highest address,
 /// in both user and kernel space.
 /// 0x3FFFFFF000
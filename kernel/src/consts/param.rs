@@ -13,9 +13,15 @@ pub const MAX_TASKS_PER_PROC: usize = 64;
 /// Same value is passed to qemu with -smp option
 pub const NSMP: usize = 3;
 
+/// Timer interrupts per second on a hart that is running a process.
+/// sleep() and uptime() count in these ticks; an idle hart takes no ticks.
+pub const HZ: u64 = 10;
+
 /// memory design
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PGSHIFT: usize = 12;
+/// `PAGE_SIZE` 的 xv6 写法
+pub const PGSIZE: usize = PAGE_SIZE;
 pub const PGMASK: usize = 0x1FF;
 pub const PGMASKLEN: usize = 9;
 pub const PAGE_SIZE_BITS: usize = 0xc;
@@ -26,10 +32,8 @@ pub const USER_STACK_SIZE: usize = PAGE_SIZE * 4;
 /// for syscall
 /// maximum length of a file system path
 pub const MAXPATH: usize = 128;
-/// maximum number of command line arguments
-pub const MAXARG: usize = 16;
-/// maximum length of a single command line argument
-pub const MAXARGLEN: usize = 64;
+/// maximum total size of exec arguments and environment, including the pointer arrays
+pub const ARG_MAX: usize = USER_STACK_SIZE / 2;
 
 /// The smallest block size of the buddy system
 pub const LEAF_SIZE: usize = 16;
\ No newline at end of file
@@ -0,0 +1,91 @@
+//! 字符设备注册表
+//!
+//! 每个字符设备驱动提供一个 [`Device`]，在初始化时通过 [`register_chrdev`] 登记到某个主设备号下。
+//! `mknod` 创建的设备节点在打开时按主设备号查找驱动，之后的每次操作都把次设备号交给驱动，
+//! 一个驱动可以借此提供多个设备，例如 `/dev/null`、`/dev/zero`、`/dev/full` 共用一个主设备号。
+
+use crate::consts::driver::{NDEV, POLLIN, POLLOUT};
+use crate::mm::Address;
+use crate::spinlock::SpinLock;
+
+/// 字符设备驱动的操作集合，所有操作的第一个参数都是次设备号
+#[derive(Debug)]
+pub struct Device {
+    /// 驱动名称，用于调试输出
+    pub name: &'static str,
+    /// 功能：打开设备，参数为打开标志。返回错误时打开失败。
+    pub open: fn(u16, i32) -> Result<(), ()>,
+    /// 功能：最后一个引用该设备的文件被关闭。
+    pub close: fn(u16),
+    /// 功能：从 [Address] 读取 count 个字节。
+    pub read: fn(u16, Address, u32) -> Result<u32, ()>,
+    /// 功能：向 [Address] 写入 count 个字节。
+    pub write: fn(u16, Address, u32) -> Result<u32, ()>,
+    /// 功能：设备相关的控制命令，参数为命令号与用户传入的参数。
+    pub ioctl: fn(u16, u32, usize) -> Result<usize, ()>,
+    /// 功能：返回设备当前可进行的操作，由 `POLLIN`、`POLLOUT` 等位组成。
+    pub poll: fn(u16) -> u32,
+}
+
+impl Device {
+    /// 只提供读写操作的设备，其余操作使用默认实现：
+    /// 打开与关闭总是成功，不支持任何 ioctl 命令，总是可读可写。
+    pub const fn new(
+        name: &'static str,
+        read: fn(u16, Address, u32) -> Result<u32, ()>,
+        write: fn(u16, Address, u32) -> Result<u32, ()>,
+    ) -> Self {
+        Self {
+            name,
+            open: default_open,
+            close: default_close,
+            read,
+            write,
+            ioctl: default_ioctl,
+            poll: default_poll,
+        }
+    }
+}
+
+fn default_open(_minor: u16, _flags: i32) -> Result<(), ()> {
+    Ok(())
+}
+
+fn default_close(_minor: u16) {}
+
+fn default_ioctl(_minor: u16, _cmd: u32, _arg: usize) -> Result<usize, ()> {
+    Err(())
+}
+
+fn default_poll(_minor: u16) -> u32 {
+    POLLIN | POLLOUT
+}
+
+static CHRDEVS: SpinLock<[Option<&'static Device>; NDEV]> = SpinLock::new([None; NDEV], "chrdevs");
+
+/// 在主设备号 `major` 下登记字符设备驱动
+///
+/// # 可能的错误
+/// - 主设备号超出范围
+/// - 主设备号已被其他驱动占用
+pub fn register_chrdev(major: usize, dev: &'static Device) -> Result<(), &'static str> {
+    let mut devs = CHRDEVS.lock();
+    let slot = devs.get_mut(major).ok_or("chrdev: major out of range")?;
+    if slot.is_some() {
+        return Err("chrdev: major already registered");
+    }
+    *slot = Some(dev);
+    Ok(())
+}
+
+/// 注销主设备号 `major` 下的驱动，已经打开的设备文件不受影响
+pub fn unregister_chrdev(major: usize) {
+    if let Some(slot) = CHRDEVS.lock().get_mut(major) {
+        *slot = None;
+    }
+}
+
+/// 查找主设备号 `major` 对应的驱动
+pub fn chrdev(major: usize) -> Option<&'static Device> {
+    CHRDEVS.lock().get(major).copied().flatten()
+}
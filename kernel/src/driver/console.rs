@@ -1,120 +1,54 @@
 //! Console driver for user input and output.
-
-use core::num::Wrapping;
-use core::sync::atomic::Ordering;
+//!
+//! 控制台是 0 号串口上的终端，也可以通过 `/dev/ttyS0` 访问，
+//! 输入的加工与读写由 [`super::tty`] 行规程完成，内核日志同步地从该串口输出。
 
 use crate::consts::driver::*;
 use crate::spinlock::SpinLock;
 use crate::mm::Address;
-use crate::process::{CPU_MANAGER, PROC_MANAGER};
 
+use super::chrdev::{register_chrdev, Device};
+use super::tty::Tty;
 use super::uart;
 
+static CONSOLE_DEVICE: Device = Device {
+    ioctl,
+    poll,
+    ..Device::new("console", read, write)
+};
+
 /// 初始化控制台驱动
 ///
 /// # 功能说明
-/// 调用底层UART驱动进行初始化
+/// 调用底层UART驱动发现并初始化所有串口，并登记为主设备号 `DEV_CONSOLE` 的字符设备
 ///
 /// # 安全性
 /// - 必须仅在系统启动时调用一次
 /// - 调用位置：`rmain.rs:rust_main`
 pub unsafe fn init() {
     uart::init();
+    register_chrdev(DEV_CONSOLE, &CONSOLE_DEVICE).expect("console: register");
 }
 
-/// 从控制台读取数据
-///
-/// # 功能说明
-/// 从控制台缓冲区读取最多`tot`字节到目标地址：
-/// 1. 当缓冲区无数据时阻塞进程
-/// 2. 处理特殊控制字符（EOF, 换行等）
-/// 3. 支持用户空间和内核空间地址
-///
-/// # 参数
-/// - `dst`: 目标地址（用户/内核空间）
-/// - `tot`: 请求读取的字节数
-///
-/// # 返回值
-/// - `Ok(n)`: 实际读取的字节数
-/// - `Err(())`: 进程被终止时返回错误
-///
-/// # 处理流程
-/// 1. 获取控制台锁
-/// 2. 当缓冲区为空时阻塞进程
-/// 3. 从环形缓冲区读取字符
-/// 4. 处理特殊字符（EOF, 换行）
-/// 5. 复制字符到目标地址
-pub(super) fn read(mut dst: Address, tot: u32) -> Result<u32, ()> {
-    let mut console = CONSOLE.lock();
-
-    let mut left = tot;
-    while left > 0 {
-        // 如果控制台缓冲区中没有可用数据
-        // 等待直到控制台设备写入一些数据
-        while console.ri == console.wi {
-            let process = unsafe { CPU_MANAGER.my_proc() };
-            if process.killed.load(Ordering::Relaxed) {
-                return Err(())
-            }
-            process.sleep(&console.ri as *const Wrapping<_> as usize, console);
-            console = CONSOLE.lock();
-        }
-
-        // 读取
-        let c = console.buf[console.ri.0 % CONSOLE_BUF];
-        console.ri += Wrapping(1);
-
-        // 遇到 EOF
-        // 提前返回
-        if c == CTRL_EOT {
-            if left < tot {
-                console.ri -= Wrapping(1);
-            }
-            break;
-        }
-
-        // 复制到用户 / 内核空间内存
-        if dst.copy_out(&c as *const u8, 1).is_err() {
-            break;
-        }
+fn read(_minor: u16, dst: Address, tot: u32) -> Result<u32, ()> {
+    console().read(dst, tot)
+}
 
-        // 更新
-        dst = dst.offset(1);
-        left -= 1;
+fn write(_minor: u16, src: Address, tot: u32) -> Result<u32, ()> {
+    console().write(src, tot)
+}
 
-        // 遇到换行符
-        if c == CTRL_LF {
-            break;
-        }
-    }
+fn ioctl(_minor: u16, cmd: u32, arg: usize) -> Result<usize, ()> {
+    console().ioctl(cmd, arg)
+}
 
-    Ok(tot - left)
+fn poll(_minor: u16) -> u32 {
+    console().poll()
 }
 
-/// 向控制台写入数据
-///
-/// # 功能说明
-/// 从源地址读取`tot`字节输出到控制台：
-/// 1. 逐字节读取并输出
-/// 2. 支持用户空间和内核空间地址
-///
-/// # 参数
-/// - `src`: 源地址（用户/内核空间）
-/// - `tot`: 请求写入的字节数
-///
-/// # 返回值
-/// - `Ok(n)`: 实际写入的字节数
-/// - 部分写入时返回已写入字节数
-pub(super) fn write(mut src: Address, tot: u32) -> Result<u32, ()> {
-    for i in 0..tot {
-        let mut c = 0u8;
-        if src.copy_in(&mut c as *mut u8, 1).is_err() {
-            return Ok(i)
-        }
-        uart::UART.putc(c);
-        src = src.offset(1);
-    }
-    Ok(tot)
+/// 控制台终端，即 0 号串口上的终端
+fn console() -> &'static SpinLock<Tty> {
+    &uart::port(0).expect("console: no uart").tty
 }
 
 /// 向控制台输出单个字符
@@ -135,77 +69,3 @@ pub(crate) fn putc(c: u8) {
         uart::putc_sync(c);
     }
 }
-
-/// 控制台中断处理程序
-///
-/// # 功能说明
-/// 处理UART接收到的字符：
-/// 1. 特殊控制字符处理（进程列表、删除行等）
-/// 2. 普通字符回显和缓冲区管理
-/// 3. 唤醒等待输入的进程
-///
-/// # 处理流程
-/// 1. 用户输入字符
-/// 2. UART触发中断
-/// 3. 控制台处理中断
-/// 4. 回显输入或执行控制操作
-///
-/// # 参数
-/// - `c`: 接收到的字符
-pub(super) fn intr(c: u8) {
-    let mut console = CONSOLE.lock();
-
-    match c {
-        CTRL_PRINT_PROCESS => {
-            todo!("print process list to debug")
-        },
-        CTRL_BS_LINE => {
-            while console.ei != console.wi &&
-                console.buf[(console.ei-Wrapping(1)).0 % CONSOLE_BUF] != CTRL_LF
-            {
-                console.ei -= Wrapping(1);
-                putc(CTRL_BS);
-            }
-        },
-        CTRL_BS | CTRL_DEL => {
-            if console.ei != console.wi {
-                console.ei -= Wrapping(1);
-                putc(CTRL_BS);
-            }
-        }
-        _ => {
-            // 回显
-            if c != 0 && (console.ei - console.ri).0 < CONSOLE_BUF {
-                let c = if c == CTRL_CR { CTRL_LF } else { c };
-                putc(c);
-                let ei = console.ei.0 % CONSOLE_BUF;
-                console.buf[ei] = c;
-                console.ei += Wrapping(1);
-                if c == CTRL_LF || c == CTRL_EOT || (console.ei - console.ri).0 == CONSOLE_BUF {
-                    console.wi = console.ei;
-                    unsafe { PROC_MANAGER.wakeup(&console.ri as *const Wrapping<_> as usize); }
-                }
-            }
-        },
-    }
-}
-
-static CONSOLE: SpinLock<Console> = SpinLock::new(
-    Console {
-        buf: [0; CONSOLE_BUF],
-        ri: Wrapping(0),
-        wi: Wrapping(0),
-        ei: Wrapping(0),
-    },
-    "console",
-);
-
-struct Console {
-    buf: [u8; CONSOLE_BUF],
-    // 读索引
-    ri: Wrapping<usize>,
-    // 写索引
-    wi: Wrapping<usize>,
-    // 编辑索引
-    ei: Wrapping<usize>,
-}
@@ -0,0 +1,43 @@
+//! 内存类字符设备：`/dev/null`、`/dev/zero`、`/dev/full`
+//!
+//! 三者共用主设备号 `DEV_MEM`，由次设备号区分：
+//! - `null`：读到文件结尾，写入的数据被丢弃；
+//! - `zero`：读出任意多个 0，写入的数据被丢弃；
+//! - `full`：读出任意多个 0，写入总是失败，模拟设备已满。
+
+use crate::consts::driver::{DEV_MEM, MEM_FULL, MEM_NULL, MEM_ZERO};
+use crate::mm::Address;
+
+use super::chrdev::{register_chrdev, Device};
+
+static MEM: Device = Device::new("mem", read, write);
+
+/// 登记内存类字符设备
+pub fn init() {
+    register_chrdev(DEV_MEM, &MEM).expect("mem: register");
+}
+
+fn read(minor: u16, mut dst: Address, tot: u32) -> Result<u32, ()> {
+    match minor {
+        MEM_NULL => Ok(0),
+        MEM_ZERO | MEM_FULL => {
+            let zeros = [0u8; 64];
+            let mut left = tot as usize;
+            while left > 0 {
+                let n = left.min(zeros.len());
+                dst.copy_out(zeros.as_ptr(), n)?;
+                dst = dst.offset(n);
+                left -= n;
+            }
+            Ok(tot)
+        }
+        _ => Err(()),
+    }
+}
+
+fn write(minor: u16, _src: Address, tot: u32) -> Result<u32, ()> {
+    match minor {
+        MEM_NULL | MEM_ZERO => Ok(tot),
+        _ => Err(()),
+    }
+}
@@ -0,0 +1,64 @@
+//! 内存盘驱动
+//!
+//! 把一段物理内存当作块设备使用。启动时若设备树的 `/chosen` 给出了 qemu `-initrd`
+//! 载入的映像，则由 [`INITRD`] 直接在原地提供该映像，根文件系统可以完全运行在内存中。
+
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::consts::fs::BSIZE;
+use crate::fs::{BlockDevice, BufData};
+
+/// 由 qemu `-initrd` 载入的内存盘
+pub static INITRD: RamDisk = RamDisk::uninit();
+
+/// 以一段物理内存为存储的块设备
+pub struct RamDisk {
+    base: AtomicUsize,
+    nblocks: AtomicUsize,
+}
+
+impl RamDisk {
+    const fn uninit() -> Self {
+        Self {
+            base: AtomicUsize::new(0),
+            nblocks: AtomicUsize::new(0),
+        }
+    }
+
+    /// 以物理内存 `[start, end)` 初始化内存盘，不足一块的尾部被忽略
+    ///
+    /// # 安全性
+    /// 调用者需保证该区域已映射到内核页表，且不会被页分配器或内核堆再次分配。
+    pub unsafe fn init(&self, start: usize, end: usize) {
+        self.base.store(start, Ordering::Relaxed);
+        self.nblocks.store((end - start) / BSIZE, Ordering::Release);
+        println!("ramdisk: {} blocks at [{:#x}, {:#x})", (end - start) / BSIZE, start, end);
+    }
+
+    /// 内存盘是否已初始化
+    pub fn is_present(&self) -> bool {
+        self.nblocks.load(Ordering::Acquire) != 0
+    }
+
+    /// 返回块号 `blockno` 对应的内存地址
+    fn block_addr(&self, blockno: u32) -> *mut u8 {
+        let nblocks = self.nblocks.load(Ordering::Acquire);
+        if blockno as usize >= nblocks {
+            panic!("ramdisk: block {} out of range {}", blockno, nblocks);
+        }
+        (self.base.load(Ordering::Relaxed) + blockno as usize * BSIZE) as *mut u8
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, blockno: u32, data: &mut BufData) {
+        let src = self.block_addr(blockno);
+        unsafe { ptr::copy_nonoverlapping(src, data as *mut BufData as *mut u8, BSIZE); }
+    }
+
+    fn write_block(&self, blockno: u32, data: &BufData) {
+        let dst = self.block_addr(blockno);
+        unsafe { ptr::copy_nonoverlapping(data as *const BufData as *const u8, dst, BSIZE); }
+    }
+}
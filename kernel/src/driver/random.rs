@@ -0,0 +1,83 @@
+//! 内核随机数接口
+//!
+//! 供 ASLR、pid 随机化等内核功能取随机数：
+//! - [`random_u64`] 不会阻塞，基于 xorshift 生成器，每次调用时尽量混入熵池中的新字节；
+//! - [`fill_bytes`] 直接从熵源取字节，可能睡眠，只能在进程上下文中调用。
+//!
+//! 启动时 [`init`] 用熵源设备轮询出的种子初始化生成器并登记 `/dev/random`；没有 virtio-rng 设备时
+//! 退化为以 `mtime` 为种子，此时的随机数不适合安全用途。
+
+use crate::mm::Address;
+use crate::register::clint;
+use crate::spinlock::SpinLock;
+use crate::consts::driver::DEV_RANDOM;
+use super::chrdev::register_chrdev;
+use super::virtio_rng::{RANDOM_DEVICE, RNG};
+
+/// xorshift64* 生成器的状态，不能为 0
+static STATE: SpinLock<u64> = SpinLock::new(0x9e37_79b9_7f4a_7c15, "random");
+
+/// 初始化熵源设备并为生成器播种
+///
+/// # 安全性
+/// 仅在系统启动时由 0 号核心调用一次，且 virtio 设备已探测完毕。
+pub unsafe fn init() {
+    let mut seed = [0u8; 8];
+    let mut rng = RNG.lock();
+    let present = rng.init() && rng.poll_fill(&mut seed);
+    drop(rng);
+
+    if present {
+        register_chrdev(DEV_RANDOM, &RANDOM_DEVICE).expect("random: register");
+        mix(u64::from_ne_bytes(seed));
+    } else {
+        println!("random: no virtio-rng device, seeding from mtime");
+        mix(clint::read_mtime());
+    }
+}
+
+/// 把 `value` 混入生成器状态
+fn mix(value: u64) {
+    let mut state = STATE.lock();
+    let mixed = (*state ^ value).wrapping_mul(0x2545_f491_4f6c_dd1d);
+    *state = if mixed == 0 { 0x9e37_79b9_7f4a_7c15 } else { mixed };
+}
+
+/// 返回一个 64 位随机数，不会阻塞
+pub fn random_u64() -> u64 {
+    // 熵池中有新字节就混入，没有也不等待
+    let mut fresh = [0u8; 8];
+    if RNG.lock().take(&mut fresh) == fresh.len() {
+        mix(u64::from_ne_bytes(fresh));
+    }
+
+    let mut state = STATE.lock();
+    let mut x = *state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    *state = x;
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+/// 返回 `[0, bound)` 中的一个随机数，`bound` 为 0 时返回 0
+pub fn random_below(bound: u64) -> u64 {
+    if bound == 0 {
+        return 0;
+    }
+    random_u64() % bound
+}
+
+/// 用熵源设备的随机字节填满 `buf`
+///
+/// # 返回值
+/// - `Ok(())`：已填满
+/// - `Err(())`：没有熵源设备，或等待期间进程被杀死
+///
+/// # 安全性
+/// 熵池为空时会睡眠，只能在进程上下文中调用。
+pub fn fill_bytes(buf: &mut [u8]) -> Result<(), ()> {
+    let len = buf.len();
+    let read = (RANDOM_DEVICE.read)(0, Address::KernelMut(buf.as_mut_ptr()), len as u32)?;
+    if read as usize == len { Ok(()) } else { Err(()) }
+}
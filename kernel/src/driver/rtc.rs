@@ -0,0 +1,49 @@
+//! Goldfish 实时时钟驱动（qemu virt 的 `google,goldfish-rtc`）
+//!
+//! 设备给出自 1970-01-01 UTC 起的纳秒数：读 `TIME_LOW` 时设备锁存高 32 位，随后从 `TIME_HIGH` 读出。
+//! 这里只读时间，不使用闹钟与中断；[`crate::time`] 在启动时读一次，之后用 mtime 推算墙上时间。
+
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::consts::{PAGE_SIZE, RTC0};
+use crate::fdt::boot_fdt;
+
+/// 寄存器偏移
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// 设备寄存器的物理地址，由 [`init`] 设置
+static BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// 从设备树中找到时钟设备，没有对应节点时使用 [`RTC0`]
+///
+/// 必须在 `kvm_init` 之前调用，内核页表按 [`page`] 映射设备寄存器。
+pub fn init() {
+    let mut base = usize::from(RTC0);
+    if let Some(fdt) = boot_fdt() {
+        fdt.for_each_compatible("google,goldfish-rtc", &mut |node| {
+            if let Some((reg, _)) = node.reg(0) {
+                base = reg;
+            }
+        });
+    }
+    BASE.store(base, Ordering::Release);
+}
+
+/// 设备寄存器所在的页
+pub fn page() -> usize {
+    BASE.load(Ordering::Acquire) & !(PAGE_SIZE - 1)
+}
+
+/// 读取墙上时间，单位为纳秒
+pub fn read_ns() -> u64 {
+    let base = BASE.load(Ordering::Acquire);
+    // 必须先读低位，读低位时设备锁存高位
+    let (low, high) = unsafe {
+        let low = ptr::read_volatile((base + TIME_LOW) as *const u32);
+        let high = ptr::read_volatile((base + TIME_HIGH) as *const u32);
+        (low, high)
+    };
+    (high as u64) << 32 | low as u64
+}
@@ -0,0 +1,426 @@
+//! 终端行规程
+//!
+//! 位于串口等字符设备与读写它的进程之间，按 termios 设置加工输入：
+//! - 规范模式（`ICANON`）：按行缓冲输入，处理擦除字符、擦除整行与文件结束符，一行完整后读者才能读到；
+//! - 非规范模式：每个字符立即可读，`read` 按 `VMIN`/`VTIME` 决定何时返回；
+//! - `ECHO` 控制回显，`ISIG` 打开时 `VINTR`/`VQUIT` 清空输入并中断前台进程。
+//!
+//! 用户程序通过 ioctl 的 `TCGETS`/`TCSETS` 读写设置，`TIOCGPGRP`/`TIOCSPGRP` 读写前台进程。
+//! 内核还没有信号与进程组，中断前台进程即杀死它；没有设置前台进程时信号字符只清空输入。
+
+use core::mem::size_of;
+use core::num::Wrapping;
+use core::sync::atomic::Ordering;
+
+use crate::consts::driver::*;
+use crate::mm::Address;
+use crate::process::{CPU_MANAGER, PROC_MANAGER};
+use crate::spinlock::{SpinLock, SpinLockGuard};
+use crate::consts::TIMEBASE_FREQ;
+use crate::time;
+use crate::timer;
+use crate::trap::start_timer;
+
+/// 终端设置，内存布局与用户态的 `struct termios` 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Termios {
+    /// 输入处理标志，`ICRNL` 等
+    pub iflag: u32,
+    /// 输出处理标志，`OPOST`、`ONLCR`
+    pub oflag: u32,
+    /// 硬件控制标志，波特率、数据位、停止位与校验，由设备驱动应用
+    pub cflag: u32,
+    /// 本地标志，`ICANON`、`ECHO`、`ISIG` 等
+    pub lflag: u32,
+    /// 控制字符，以 `VINTR` 等为下标，0 表示禁用该功能
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// 默认设置：规范模式、回显、响应信号字符，回车转换为换行
+    pub const fn new() -> Self {
+        // 依次为 VINTR、VQUIT、VERASE、VKILL、VEOF、VTIME、VMIN
+        let cc = [CTRL_ETX, CTRL_FS, CTRL_DEL, CTRL_BS_LINE, CTRL_EOT, 0, 1, 0];
+        Self {
+            iflag: ICRNL,
+            oflag: 0,
+            cflag: B38400 | CS8 | CREAD,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK,
+            cc,
+        }
+    }
+
+    fn canonical(&self) -> bool {
+        self.lflag & ICANON != 0
+    }
+
+    /// `c` 是否为下标 `idx` 处未被禁用的控制字符
+    fn is(&self, c: u8, idx: usize) -> bool {
+        c != 0 && self.cc[idx] == c
+    }
+}
+
+/// 终端所在设备提供的操作，第一个参数都是设备内的端口号
+pub struct TtyOps {
+    /// 在中断中回显一个字符，`CTRL_BS` 需要擦除前一个字符
+    pub echo: fn(usize, u8),
+    /// 在进程上下文中输出一个字符，可以睡眠
+    pub output: fn(usize, u8),
+    /// 按 termios 的 `cflag` 设置硬件，设备不支持该设置时返回错误
+    pub set_cflag: fn(usize, u32) -> Result<(), ()>,
+}
+
+/// 一个终端的输入缓冲区与设置
+pub struct Tty {
+    buf: [u8; CONSOLE_BUF],
+    // 读索引
+    ri: Wrapping<usize>,
+    // 写索引，`ri..wi` 为读者可以取走的字符
+    wi: Wrapping<usize>,
+    // 编辑索引，`wi..ei` 为规范模式下尚未完成的一行
+    ei: Wrapping<usize>,
+    termios: Termios,
+    /// 前台进程号，0 表示没有
+    foreground: usize,
+    /// 端口号，传给 `ops` 中的操作
+    index: usize,
+    ops: &'static TtyOps,
+}
+
+impl Tty {
+    pub const fn new(index: usize, ops: &'static TtyOps) -> Self {
+        Self {
+            buf: [0; CONSOLE_BUF],
+            ri: Wrapping(0),
+            wi: Wrapping(0),
+            ei: Wrapping(0),
+            termios: Termios::new(),
+            foreground: 0,
+            index,
+            ops,
+        }
+    }
+
+    /// 读者睡眠的通道
+    fn channel(&self) -> usize {
+        &self.ri as *const Wrapping<_> as usize
+    }
+
+    /// 让 `ri..ei` 全部可读并唤醒读者
+    fn commit(&mut self) {
+        self.wi = self.ei;
+        unsafe { PROC_MANAGER.wakeup(self.channel()); }
+    }
+
+    /// 丢弃所有尚未读取的输入
+    fn flush_input(&mut self) {
+        self.wi = self.ri;
+        self.ei = self.ri;
+    }
+
+    fn echo(&self, c: u8) {
+        (self.ops.echo)(self.index, c);
+    }
+
+    /// 擦除正在编辑的行的最后一个字符
+    fn erase(&mut self) {
+        if self.ei == self.wi {
+            return;
+        }
+        self.ei -= Wrapping(1);
+        if self.termios.lflag & ECHO != 0 && self.termios.lflag & ECHOE != 0 {
+            self.echo(CTRL_BS);
+        }
+    }
+
+    /// 以 `^X` 的形式回显控制字符
+    fn echo_ctl(&self, c: u8) {
+        self.echo(b'^');
+        self.echo(c ^ 0x40);
+        self.echo(CTRL_LF);
+    }
+
+    /// 应用新的设置，硬件不支持新的 `cflag` 时保留原设置并返回错误
+    fn set_termios(&mut self, termios: Termios) -> Result<(), ()> {
+        if termios.cflag != self.termios.cflag {
+            (self.ops.set_cflag)(self.index, termios.cflag)?;
+        }
+        self.termios = termios;
+        // 切换到非规范模式时，已经输入的半行立即可读
+        if !termios.canonical() && self.wi != self.ei {
+            self.commit();
+        }
+        Ok(())
+    }
+}
+
+impl SpinLock<Tty> {
+    /// 处理设备收到的一个字符，由设备的中断处理程序调用
+    pub fn input(&self, c: u8) {
+        let mut tty = self.lock();
+        let termios = tty.termios;
+        if termios.cflag & CREAD == 0 {
+            return;
+        }
+
+        let c = match c {
+            CTRL_CR if termios.iflag & IGNCR != 0 => return,
+            CTRL_CR if termios.iflag & ICRNL != 0 => CTRL_LF,
+            CTRL_LF if termios.iflag & INLCR != 0 => CTRL_CR,
+            c => c,
+        };
+
+        if termios.lflag & ISIG != 0 && (termios.is(c, VINTR) || termios.is(c, VQUIT)) {
+            if termios.lflag & ECHO != 0 {
+                tty.echo_ctl(c);
+            }
+            tty.flush_input();
+            let pid = tty.foreground;
+            // kill 需要获取进程锁，先释放终端锁
+            drop(tty);
+            if pid != 0 {
+                let _ = unsafe { PROC_MANAGER.kill(pid) };
+            }
+            return;
+        }
+
+        if termios.canonical() {
+            if c == CTRL_PRINT_PROCESS {
+                // 打印进程列表要获取各个进程的锁，先释放终端锁
+                drop(tty);
+                unsafe { PROC_MANAGER.procdump() };
+                return;
+            }
+            if termios.is(c, VKILL) {
+                while tty.ei != tty.wi &&
+                    tty.buf[(tty.ei - Wrapping(1)).0 % CONSOLE_BUF] != CTRL_LF
+                {
+                    tty.erase();
+                }
+                return;
+            }
+            if termios.is(c, VERASE) || c == CTRL_BS {
+                tty.erase();
+                return;
+            }
+            if c == 0 {
+                return;
+            }
+        }
+
+        if (tty.ei - tty.ri).0 >= CONSOLE_BUF {
+            return;
+        }
+        let eof = termios.canonical() && termios.is(c, VEOF);
+        if termios.lflag & ECHO != 0 && !eof {
+            tty.echo(c);
+        }
+        let ei = tty.ei.0 % CONSOLE_BUF;
+        tty.buf[ei] = c;
+        tty.ei += Wrapping(1);
+        if !termios.canonical() || c == CTRL_LF || eof || (tty.ei - tty.ri).0 == CONSOLE_BUF {
+            tty.commit();
+        }
+    }
+
+    /// 从终端读取至多 `tot` 个字节
+    ///
+    /// # 功能说明
+    /// - 规范模式：没有完整的行时阻塞，读到换行符为止；行首的文件结束符使读取返回 0；
+    /// - 非规范模式：按 `VMIN`/`VTIME` 决定何时返回，`VTIME` 以 0.1 秒为单位：
+    ///   - `VMIN > 0, VTIME = 0`：读到 `VMIN` 个字节为止；
+    ///   - `VMIN > 0, VTIME > 0`：读到第一个字节后，字节间隔超过 `VTIME` 也返回；
+    ///   - `VMIN = 0, VTIME > 0`：读到任意字节或等待超过 `VTIME` 后返回；
+    ///   - `VMIN = 0, VTIME = 0`：取走已有的字节后立即返回。
+    ///
+    /// # 返回值
+    /// - `Ok(n)`: 实际读取的字节数
+    /// - `Err(())`: 等待期间进程被杀死
+    pub fn read(&self, dst: Address, tot: u32) -> Result<u32, ()> {
+        let tty = self.lock();
+        if tty.termios.canonical() {
+            self.read_canonical(tty, dst, tot)
+        } else {
+            self.read_raw(tty, dst, tot)
+        }
+    }
+
+    fn read_canonical<'a>(
+        &'a self,
+        mut tty: SpinLockGuard<'a, Tty>,
+        mut dst: Address,
+        tot: u32,
+    ) -> Result<u32, ()> {
+        let mut left = tot;
+        while left > 0 {
+            // 缓冲区中没有完整的行，等待设备送来输入
+            while tty.ri == tty.wi {
+                let process = unsafe { CPU_MANAGER.my_proc() };
+                if process.killed.load(Ordering::Relaxed) {
+                    return Err(())
+                }
+                let channel = tty.channel();
+                process.sleep(channel, tty);
+                tty = self.lock();
+            }
+
+            let c = tty.buf[tty.ri.0 % CONSOLE_BUF];
+            tty.ri += Wrapping(1);
+
+            // 遇到文件结束符，已经读到数据时留给下一次读取
+            if tty.termios.is(c, VEOF) {
+                if left < tot {
+                    tty.ri -= Wrapping(1);
+                }
+                break;
+            }
+
+            if dst.copy_out(&c as *const u8, 1).is_err() {
+                break;
+            }
+            dst = dst.offset(1);
+            left -= 1;
+
+            if c == CTRL_LF {
+                break;
+            }
+        }
+        Ok(tot - left)
+    }
+
+    fn read_raw<'a>(
+        &'a self,
+        mut tty: SpinLockGuard<'a, Tty>,
+        mut dst: Address,
+        tot: u32,
+    ) -> Result<u32, ()> {
+        let vmin = tty.termios.cc[VMIN] as u32;
+        // VTIME 以 0.1 秒为单位，换算成 mtime 周期数
+        let vtime = tty.termios.cc[VTIME] as u64 * TIMEBASE_FREQ / 10;
+        let mut left = tot;
+        // VMIN 为 0 时从开始读取计时，否则从收到上一个字节计时
+        let mut last = time::mtime();
+        loop {
+            while left > 0 && tty.ri != tty.wi {
+                let c = tty.buf[tty.ri.0 % CONSOLE_BUF];
+                tty.ri += Wrapping(1);
+                if dst.copy_out(&c as *const u8, 1).is_err() {
+                    return Ok(tot - left);
+                }
+                dst = dst.offset(1);
+                left -= 1;
+                last = time::mtime();
+            }
+
+            let got = tot - left;
+            if left == 0 || (got > 0 && got >= vmin) {
+                break;
+            }
+            let timed = vtime > 0 && (vmin == 0 || got > 0);
+            if timed && time::mtime() - last >= vtime {
+                break;
+            }
+            if vmin == 0 && vtime == 0 {
+                break;
+            }
+
+            let process = unsafe { CPU_MANAGER.my_proc() };
+            if process.killed.load(Ordering::Relaxed) {
+                return Err(())
+            }
+            // 超时由定时器唤醒，定时器回调要获取终端锁，因此在睡眠后取消
+            let timeout = timed.then(|| {
+                start_timer(last + vtime, 0, tty_timeout, self as *const SpinLock<Tty> as usize)
+            });
+            let channel = tty.channel();
+            process.sleep(channel, tty);
+            if let Some(id) = timeout {
+                timer::cancel(id);
+            }
+            tty = self.lock();
+        }
+        Ok(tot - left)
+    }
+
+    /// 向终端写入 `tot` 个字节，`OPOST | ONLCR` 时把换行输出为回车换行
+    ///
+    /// # 返回值
+    /// - `Ok(n)`: 实际写入的字节数，读取源地址失败时为已写入的部分
+    pub fn write(&self, mut src: Address, tot: u32) -> Result<u32, ()> {
+        let (oflag, index, output) = {
+            let tty = self.lock();
+            (tty.termios.oflag, tty.index, tty.ops.output)
+        };
+        let onlcr = oflag & OPOST != 0 && oflag & ONLCR != 0;
+        for i in 0..tot {
+            let mut c = 0u8;
+            if src.copy_in(&mut c as *mut u8, 1).is_err() {
+                return Ok(i)
+            }
+            if onlcr && c == CTRL_LF {
+                output(index, CTRL_CR);
+            }
+            output(index, c);
+            src = src.offset(1);
+        }
+        Ok(tot)
+    }
+
+    /// 终端的 ioctl 命令
+    ///
+    /// # 参数
+    /// - `cmd`: `TCGETS`、`TCSETS`、`TCSETSW`、`TCSETSF`、`TIOCGPGRP` 或 `TIOCSPGRP`
+    /// - `arg`: 用户空间中 `struct termios` 或进程号（`int`）的地址
+    ///
+    /// # 可能的错误
+    /// - 不支持的命令
+    /// - `arg` 不是有效的用户地址
+    /// - 设备不支持新设置中的波特率或数据格式
+    /// - 设置的前台进程号为负数
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> Result<usize, ()> {
+        let user = Address::Virtual(arg);
+        match cmd {
+            TCGETS => {
+                let termios = self.lock().termios;
+                user.copy_out(&termios as *const Termios as *const u8, size_of::<Termios>())?;
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let mut termios = Termios::new();
+                user.copy_in(&mut termios as *mut Termios as *mut u8, size_of::<Termios>())?;
+                let mut tty = self.lock();
+                // 输出是同步的，TCSETSW 无需等待输出排空
+                if cmd == TCSETSF {
+                    tty.flush_input();
+                }
+                tty.set_termios(termios)?;
+            }
+            TIOCGPGRP => {
+                let pid = self.lock().foreground as i32;
+                user.copy_out(&pid as *const i32 as *const u8, size_of::<i32>())?;
+            }
+            TIOCSPGRP => {
+                let mut pid = 0i32;
+                user.copy_in(&mut pid as *mut i32 as *mut u8, size_of::<i32>())?;
+                if pid < 0 {
+                    return Err(())
+                }
+                self.lock().foreground = pid as usize;
+            }
+            _ => return Err(()),
+        }
+        Ok(0)
+    }
+
+    /// 有可读的输入时报告 `POLLIN`，总是可写
+    pub fn poll(&self) -> u32 {
+        let tty = self.lock();
+        if tty.ri != tty.wi { POLLIN | POLLOUT } else { POLLOUT }
+    }
+}
+
+/// 带超时读取的定时器回调，`arg` 为终端的地址，唤醒读者检查是否超时
+fn tty_timeout(arg: usize, _count: u64) {
+    let tty = unsafe { &*(arg as *const SpinLock<Tty>) }.lock();
+    unsafe { PROC_MANAGER.wakeup(tty.channel()); }
+}
@@ -1,23 +1,36 @@
-use core::{sync::atomic::Ordering, num::Wrapping, ptr};
-use core::convert::Into;
+//! 16550 UART 驱动
+//!
+//! 启动时从设备树中找出所有 `ns16550a` 串口（没有设备树时只使用 `UART0`），
+//! 按寄存器地址排序后依次编号，0 号端口是控制台，内核日志只从 0 号端口同步输出。
+//! 每个端口有自己的发送环形缓冲区与终端（[`Tty`]），接收到的字符在中断中交给终端的行规程，
+//! 由终端的输入缓冲区充当接收缓冲区。端口 `n` 以主设备号 `DEV_TTYS`、次设备号 `n` 提供给用户，
+//! 波特率与数据格式通过终端的 `TCSETS` 设置 `cflag` 修改。
 
-use crate::{consts::{UART0, driver::UART_BUF}, spinlock::SpinLock};
+use core::{sync::atomic::{AtomicUsize, Ordering}, num::Wrapping, ptr};
+
+use crate::consts::{NIRQ, NUART, PAGE_SIZE, UART0, UART0_IRQ, UART_CLOCK};
+use crate::consts::driver::*;
+use crate::fdt::boot_fdt;
+use crate::mm::Address;
+use crate::plic::{self, ALL_HARTS};
+use crate::spinlock::SpinLock;
 use crate::process::{CPU_MANAGER, PROC_MANAGER};
 use crate::process::{push_off, pop_off};
 
 use super::PANICKED;
-use super::console;
+use super::chrdev::{register_chrdev, Device};
+use super::tty::{Tty, TtyOps};
 
 /// 寄存器访问宏
 ///
 /// # 功能说明
-/// 将寄存器偏移量转换为物理地址
+/// 将端口基址与寄存器偏移量转换为物理地址
 ///
 /// # 示例
-/// `Reg!(LSR)` 返回 LSR 寄存器的物理地址
+/// `Reg!(base, LSR)` 返回基址为 `base` 的端口的 LSR 寄存器的物理地址
 macro_rules! Reg {
-    ($reg: expr) => {
-        Into::<usize>::into(UART0) + $reg
+    ($base: expr, $reg: expr) => {
+        $base + $reg
     };
 }
 
@@ -27,10 +40,10 @@ macro_rules! Reg {
 /// 从指定寄存器读取一个字节（volatile 操作）
 ///
 /// # 安全性
-/// 需要有效的寄存器偏移量
+/// 需要有效的端口基址与寄存器偏移量
 macro_rules! ReadReg {
-    ($reg: expr) => {
-        unsafe { ptr::read_volatile(Reg!($reg) as *const u8) }
+    ($base: expr, $reg: expr) => {
+        unsafe { ptr::read_volatile(Reg!($base, $reg) as *const u8) }
     };
 }
 
@@ -40,49 +53,107 @@ macro_rules! ReadReg {
 /// 向指定寄存器写入一个字节（volatile 操作）
 ///
 /// # 安全性
-/// 需要有效的寄存器偏移量
+/// 需要有效的端口基址与寄存器偏移量
 macro_rules! WriteReg {
-    ($reg: expr, $value: expr) => {
+    ($base: expr, $reg: expr, $value: expr) => {
         unsafe {
-            ptr::write_volatile(Reg!($reg) as *mut u8, $value);
+            ptr::write_volatile(Reg!($base, $reg) as *mut u8, $value);
         }
     };
 }
 
-/// 初始化 UART 设备
+/// 所有串口，`PORTS[..nports()]` 为启动时发现的端口
+static PORTS: [Port; NUART] = [Port::new(0), Port::new(1), Port::new(2), Port::new(3)];
+
+/// 启动时发现的端口数
+static NPORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// `/dev/ttyS*` 字符设备，次设备号为端口号
+static TTYS_DEVICE: Device = Device {
+    open: ttys_open,
+    ioctl: ttys_ioctl,
+    poll: ttys_poll,
+    ..Device::new("ttyS", ttys_read, ttys_write)
+};
+
+const TTY_OPS: TtyOps = TtyOps {
+    echo,
+    output,
+    set_cflag,
+};
+
+/// 发现并初始化所有串口
 ///
 /// # 功能说明
-/// 配置 UART 设备参数：
-/// 1. 禁用中断
-/// 2. 设置波特率 (38.4K)
-/// 3. 配置数据格式 (8N1)
-/// 4. 启用 FIFO 缓冲区
-/// 5. 启用接收中断
+/// 1. 从设备树中找出 `ns16550a` 串口，按寄存器地址排序，没有设备树时使用 `UART0`
+/// 2. 以 38.4K 波特率、8N1 初始化每个端口，打开接收与发送中断并登记中断处理函数
+/// 3. 登记 `/dev/ttyS*` 字符设备
+///
+/// # 注意
+/// 仅在系统启动、开启分页之前由 0 号核心调用一次，此时可以直接访问设备树与寄存器。
 pub(super) fn init() {
-    // 禁用中断
-    WriteReg!(IER, 0x00);
+    let mut found = [(0usize, 0usize, 0usize); NUART];
+    let mut n = 0;
+    let mut ignored = 0;
+    if let Some(fdt) = boot_fdt() {
+        fdt.for_each_compatible("ns16550a", &mut |node| {
+            let (base, irq) = match (node.reg(0), node.irq()) {
+                (Some((base, _)), Some(irq)) => (base, irq),
+                _ => return,
+            };
+            if n == NUART || irq >= NIRQ {
+                ignored += 1;
+                return;
+            }
+            let clock = node.prop_usize("clock-frequency").unwrap_or(UART_CLOCK);
+            found[n] = (base, irq, clock);
+            n += 1;
+        });
+    }
+    if n == 0 {
+        found[0] = (usize::from(UART0), UART0_IRQ, UART_CLOCK);
+        n = 1;
+    }
+    found[..n].sort_unstable_by_key(|&(base, _, _)| base);
 
-    // 用于设置波特率的特殊模式
-    WriteReg!(LCR, 0x80);
+    for (i, &(base, irq, clock)) in found[..n].iter().enumerate() {
+        PORTS[i].init(base, irq, clock);
+        plic::register_irq(irq, "uart", uart_intr, 1, ALL_HARTS).expect("uart: register irq");
+    }
+    NPORTS.store(n, Ordering::Release);
 
-    // 38.4K 波特率的最低有效位
-    WriteReg!(0, 0x03);
+    // 0 号端口初始化之后才能打印
+    if ignored > 0 {
+        println!("uart: {} ports ignored", ignored);
+    }
 
-    // 38.4K 波特率的最高有效位
-    WriteReg!(1, 0x00);
+    register_chrdev(DEV_TTYS, &TTYS_DEVICE).expect("uart: register");
+}
 
-    // 退出设置波特率模式，
-    // 并将字长设置为 8 位，无校验。
-    WriteReg!(LCR, 0x03);
+/// 启动时发现的端口数
+#[inline]
+pub fn nports() -> usize {
+    NPORTS.load(Ordering::Acquire)
+}
 
-    //  重置并启用 FIFO
-    WriteReg!(FCR, 0x07);
+/// 第 `index` 个端口，不存在时返回 `None`
+pub fn port(index: usize) -> Option<&'static Port> {
+    if index < nports() { Some(&PORTS[index]) } else { None }
+}
 
-    // 启用接收中断
-    WriteReg!(IER, 0x03);
+/// 所有端口的寄存器所在的页，供内核页表映射
+pub fn pages() -> impl Iterator<Item = usize> {
+    PORTS[..nports()].iter().map(|port| port.base() & !(PAGE_SIZE - 1))
 }
 
-/// 同步阻塞方式输出字符
+/// 串口中断处理函数，交给使用中断 `irq` 的端口处理
+fn uart_intr(irq: usize) {
+    if let Some(port) = PORTS[..nports()].iter().find(|port| port.irq() == irq) {
+        port.intr();
+    }
+}
+
+/// 同步阻塞方式从控制台（0 号端口）输出字符，供内核日志使用
 ///
 /// # 功能说明
 /// 1. 禁用中断 (push_off)
@@ -97,27 +168,141 @@ pub(super) fn init() {
 /// # 注意
 /// 在系统恐慌状态下会进入死循环
 pub(super) fn putc_sync(c: u8) {
+    // 串口初始化之前的输出直接写到 UART0
+    let base = if nports() == 0 { usize::from(UART0) } else { PORTS[0].base() };
+    putc_sync_at(base, c);
+}
+
+fn putc_sync_at(base: usize, c: u8) {
     push_off();
     if PANICKED.load(Ordering::Relaxed) {
         loop {}
     }
-    while !is_idle() {}
-    WriteReg!(THR, c);
+    while !is_idle(base) {}
+    WriteReg!(base, THR, c);
     pop_off();
 }
 
-/// 全局 UART 实例（自旋锁保护）
-pub static UART: SpinLock<Uart> = SpinLock::new(
-    Uart {
-        buf: [0; UART_BUF],
-        ri: Wrapping(0),
-        wi: Wrapping(0),
-    },
-    "uart",
-);
+/// 一个 16550 串口
+pub struct Port {
+    /// 寄存器基址，启动时确定
+    base: AtomicUsize,
+    irq: AtomicUsize,
+    /// 输入时钟频率，用于计算波特率除数
+    clock: AtomicUsize,
+    /// 发送缓冲区
+    tx: SpinLock<Uart>,
+    /// 端口上的终端
+    pub tty: SpinLock<Tty>,
+}
+
+impl Port {
+    const fn new(index: usize) -> Self {
+        Self {
+            base: AtomicUsize::new(0),
+            irq: AtomicUsize::new(0),
+            clock: AtomicUsize::new(0),
+            tx: SpinLock::new(
+                Uart {
+                    buf: [0; UART_BUF],
+                    ri: Wrapping(0),
+                    wi: Wrapping(0),
+                },
+                "uart",
+            ),
+            tty: SpinLock::new(Tty::new(index, &TTY_OPS), "tty"),
+        }
+    }
+
+    #[inline]
+    fn base(&self) -> usize {
+        self.base.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn irq(&self) -> usize {
+        self.irq.load(Ordering::Relaxed)
+    }
+
+    /// 初始化端口
+    ///
+    /// # 功能说明
+    /// 1. 禁用中断
+    /// 2. 设置波特率与数据格式（38.4K，8N1）
+    /// 3. 启用 FIFO 缓冲区
+    /// 4. 启用接收与发送中断
+    fn init(&self, base: usize, irq: usize, clock: usize) {
+        self.base.store(base, Ordering::Relaxed);
+        self.irq.store(irq, Ordering::Relaxed);
+        self.clock.store(clock, Ordering::Relaxed);
+
+        // 禁用中断
+        WriteReg!(base, IER, 0x00);
+
+        self.configure(B38400 | CS8 | CREAD).expect("uart: default line settings");
+
+        //  重置并启用 FIFO
+        WriteReg!(base, FCR, 0x07);
+
+        // 启用接收与发送中断
+        WriteReg!(base, IER, 0x03);
+    }
+
+    /// 按 termios 的 `cflag` 设置波特率、数据位、停止位与校验
+    ///
+    /// # 可能的错误
+    /// - 不支持的波特率
+    /// - 波特率除数超出 16 位
+    fn configure(&self, cflag: u32) -> Result<(), ()> {
+        let baud = match cflag & CBAUD {
+            B1200 => 1200,
+            B2400 => 2400,
+            B4800 => 4800,
+            B9600 => 9600,
+            B19200 => 19200,
+            B38400 => 38400,
+            B57600 => 57600,
+            B115200 => 115200,
+            _ => return Err(()),
+        };
+        let divisor = self.clock.load(Ordering::Relaxed) / (16 * baud);
+        if divisor == 0 || divisor > 0xffff {
+            return Err(())
+        }
+
+        let mut lcr = match cflag & CSIZE {
+            CS5 => 0x00,
+            CS6 => 0x01,
+            CS7 => 0x02,
+            _ => 0x03,
+        };
+        if cflag & CSTOPB != 0 {
+            lcr |= 1 << 2;
+        }
+        if cflag & PARENB != 0 {
+            lcr |= 1 << 3;
+            if cflag & PARODD == 0 {
+                lcr |= 1 << 4;
+            }
+        }
+
+        // 修改除数期间不能发送
+        let _tx = self.tx.lock();
+        let base = self.base();
+        // 用于设置波特率的特殊模式
+        WriteReg!(base, LCR, LCR_DLAB);
+        WriteReg!(base, 0, divisor as u8);
+        WriteReg!(base, 1, (divisor >> 8) as u8);
+        // 退出设置波特率模式，设置数据格式
+        WriteReg!(base, LCR, lcr);
+        Ok(())
+    }
+
+    /// 同步阻塞方式输出字符，可在中断中使用
+    fn putc_sync(&self, c: u8) {
+        putc_sync_at(self.base(), c);
+    }
 
-/// 为自旋锁保护的 UART 实例添加扩展方法
-impl SpinLock<Uart> {
     /// 异步输出字符到 UART
     ///
     /// # 功能说明
@@ -129,7 +314,7 @@ impl SpinLock<Uart> {
     /// # 参数
     /// - `c`: 要发送的字符
     pub fn putc(&self, c: u8) {
-        let mut uart = self.lock();
+        let mut uart = self.tx.lock();
 
         if PANICKED.load(Ordering::Relaxed) {
             loop {}
@@ -139,12 +324,12 @@ impl SpinLock<Uart> {
             if uart.wi == uart.ri + Wrapping(UART_BUF) {
                 let process = unsafe { CPU_MANAGER.my_proc() };
                 process.sleep(&uart.ri as *const Wrapping<_> as usize, uart);
-                uart = self.lock();
+                uart = self.tx.lock();
             } else {
                 let wi = uart.wi.0 % UART_BUF;
                 uart.buf[wi] = c;
                 uart.wi += Wrapping(1);
-                uart.transmit();
+                uart.transmit(self.base());
                 break
             }
         }
@@ -153,26 +338,22 @@ impl SpinLock<Uart> {
     /// UART 中断处理函数
     ///
     /// # 功能说明
-    /// 1. 接收数据：读取所有可用字符并传递给控制台
+    /// 1. 接收数据：读取所有可用字符并传递给端口上的终端
     /// 2. 传输数据：尝试发送缓冲区中的字符
-    pub fn intr(&self) {
+    fn intr(&self) {
+        let base = self.base();
         // receive
-        loop {
-            let c: u8;
-            if ReadReg!(LSR) & 1 > 0 {
-                c = ReadReg!(RHR);
-            } else {
-                break
-            }
-            console::intr(c);
+        while ReadReg!(base, LSR) & 1 > 0 {
+            let c = ReadReg!(base, RHR);
+            self.tty.input(c);
         }
 
         // transmit
-        self.lock().transmit();
+        self.tx.lock().transmit(base);
     }
 }
 
-/// UART 内部状态结构
+/// UART 发送缓冲区
 pub struct Uart {
     buf: [u8; UART_BUF],
     ri: Wrapping<usize>,
@@ -188,17 +369,57 @@ impl Uart {
     /// 3. 发送到 THR 寄存器
     /// 4. 更新读索引
     /// 5. 唤醒可能等待的进程
-    fn transmit(&mut self) {
-        while self.wi != self.ri && is_idle() {
+    fn transmit(&mut self, base: usize) {
+        while self.wi != self.ri && is_idle(base) {
             let ri = self.ri.0 % UART_BUF;
             let c = self.buf[ri];
             self.ri += Wrapping(1);
             unsafe { PROC_MANAGER.wakeup(&self.ri as *const Wrapping<_> as usize); }
-            WriteReg!(THR, c);
+            WriteReg!(base, THR, c);
         }
     }
 }
 
+/// 终端回显，退格时输出"退格+空格+退格"擦除前一个字符
+fn echo(index: usize, c: u8) {
+    let port = &PORTS[index];
+    if c == CTRL_BS {
+        port.putc_sync(CTRL_BS);
+        port.putc_sync(b' ');
+        port.putc_sync(CTRL_BS);
+    } else {
+        port.putc_sync(c);
+    }
+}
+
+fn output(index: usize, c: u8) {
+    PORTS[index].putc(c);
+}
+
+fn set_cflag(index: usize, cflag: u32) -> Result<(), ()> {
+    PORTS[index].configure(cflag)
+}
+
+fn ttys_open(minor: u16, _flags: i32) -> Result<(), ()> {
+    port(minor as usize).map(|_| ()).ok_or(())
+}
+
+fn ttys_read(minor: u16, dst: Address, tot: u32) -> Result<u32, ()> {
+    port(minor as usize).ok_or(())?.tty.read(dst, tot)
+}
+
+fn ttys_write(minor: u16, src: Address, tot: u32) -> Result<u32, ()> {
+    port(minor as usize).ok_or(())?.tty.write(src, tot)
+}
+
+fn ttys_ioctl(minor: u16, cmd: u32, arg: usize) -> Result<usize, ()> {
+    port(minor as usize).ok_or(())?.tty.ioctl(cmd, arg)
+}
+
+fn ttys_poll(minor: u16) -> u32 {
+    port(minor as usize).map_or(0, |port| port.tty.poll())
+}
+
 // 16550 UART 寄存器偏移量定义
 // reference: http://byterunner.com/16550.html
 const RHR: usize = 0;       // 接收保持寄存器 (读操作)
@@ -209,6 +430,9 @@ const ISR: usize = 2;       // 中断状态寄存器 (只读)
 const LCR: usize = 3;       // 线路控制寄存器
 const LSR: usize = 5;       // 线路状态寄存器
 
+/// LCR 中的除数锁存访问位，置位时寄存器 0、1 为波特率除数
+const LCR_DLAB: u8 = 0x80;
+
 /// 检查 UART 是否空闲（可发送数据）
 ///
 /// # 返回值
@@ -219,6 +443,6 @@ const LSR: usize = 5;       // 线路状态寄存器
 /// LSR[5] 位表示传输保持寄存器是否为空
 /// 当该位为 1 时，可以发送新数据
 #[inline]
-fn is_idle() -> bool {
-    ReadReg!(LSR) & (1 << 5) > 0
+fn is_idle(base: usize) -> bool {
+    ReadReg!(base, LSR) & (1 << 5) > 0
 }
@@ -0,0 +1,252 @@
+//! virtio-mmio 传输层
+//!
+//! 同时支持旧版（version 1）与新版（version 2）寄存器布局：
+//! - 旧版通过 `GuestPageSize` 与 `QueuePFN` 交出整个队列，只有低 32 位特征位；
+//! - 新版分别写入描述符表、可用环、已用环的 64 位地址，并要求协商 `VIRTIO_F_VERSION_1`。
+
+use core::ptr;
+
+use crate::consts::{PAGE_SIZE, PGSHIFT};
+use super::queue::{VirtQueue, QUEUE_SIZE};
+
+//virtio mmio 控制寄存器的偏移量，来自 qemu 的 virtio_mmio.h
+const VIRTIO_MMIO_MAGIC_VALUE: usize = 0x000;
+const VIRTIO_MMIO_VERSION: usize = 0x004;
+const VIRTIO_MMIO_DEVICE_ID: usize = 0x008;
+const VIRTIO_MMIO_VENDOR_ID: usize = 0x00c;
+const VIRTIO_MMIO_DEVICE_FEATURES: usize = 0x010;
+const VIRTIO_MMIO_DEVICE_FEATURES_SEL: usize = 0x014;
+const VIRTIO_MMIO_DRIVER_FEATURES: usize = 0x020;
+const VIRTIO_MMIO_DRIVER_FEATURES_SEL: usize = 0x024;
+const VIRTIO_MMIO_GUEST_PAGE_SIZE: usize = 0x028; // 仅旧版
+const VIRTIO_MMIO_QUEUE_SEL: usize = 0x030;
+const VIRTIO_MMIO_QUEUE_NUM_MAX: usize = 0x034;
+const VIRTIO_MMIO_QUEUE_NUM: usize = 0x038;
+const VIRTIO_MMIO_QUEUE_ALIGN: usize = 0x03c; // 仅旧版
+const VIRTIO_MMIO_QUEUE_PFN: usize = 0x040; // 仅旧版
+const VIRTIO_MMIO_QUEUE_READY: usize = 0x044; // 仅新版
+const VIRTIO_MMIO_QUEUE_NOTIFY: usize = 0x050;
+const VIRTIO_MMIO_INTERRUPT_STATUS: usize = 0x060;
+const VIRTIO_MMIO_INTERRUPT_ACK: usize = 0x064;
+const VIRTIO_MMIO_STATUS: usize = 0x070;
+const VIRTIO_MMIO_QUEUE_DESC_LOW: usize = 0x080; // 以下仅新版
+const VIRTIO_MMIO_QUEUE_DESC_HIGH: usize = 0x084;
+const VIRTIO_MMIO_QUEUE_DRIVER_LOW: usize = 0x090;
+const VIRTIO_MMIO_QUEUE_DRIVER_HIGH: usize = 0x094;
+const VIRTIO_MMIO_QUEUE_DEVICE_LOW: usize = 0x0a0;
+const VIRTIO_MMIO_QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const VIRTIO_MMIO_CONFIG: usize = 0x100;
+
+const VIRTIO_MAGIC: u32 = 0x74726976;
+
+////virtio 状态寄存器位，来自 qemu 的 virtio_config.h
+const VIRTIO_CONFIG_S_ACKNOWLEDGE: u32 = 1;
+const VIRTIO_CONFIG_S_DRIVER: u32 = 2;
+const VIRTIO_CONFIG_S_DRIVER_OK: u32 = 4;
+const VIRTIO_CONFIG_S_FEATURES_OK: u32 = 8;
+
+/// 新版设备必须协商的特征位
+const VIRTIO_F_VERSION_1: u32 = 32;
+
+/// 一个 virtio-mmio 设备槽位
+pub struct MmioTransport {
+    base: usize,
+    version: u32,
+    device_id: u32,
+    irq: usize,
+}
+
+impl MmioTransport {
+    /// 检查 `base` 处是否存在 virtio 设备
+    ///
+    /// # 返回值
+    /// - `Some(MmioTransport)`：魔数与版本合法且槽位上挂有设备
+    /// - `None`：槽位为空（设备号为 0）或不是 virtio 设备
+    ///
+    /// # 安全性
+    /// 调用者需保证 `base` 处的一页寄存器已映射。
+    pub unsafe fn probe(base: usize, irq: usize) -> Option<Self> {
+        let mut transport = Self { base, version: 0, device_id: 0, irq };
+        if transport.read(VIRTIO_MMIO_MAGIC_VALUE) != VIRTIO_MAGIC {
+            return None;
+        }
+        transport.version = transport.read(VIRTIO_MMIO_VERSION);
+        transport.device_id = transport.read(VIRTIO_MMIO_DEVICE_ID);
+        if transport.device_id == 0 || !(transport.version == 1 || transport.version == 2) {
+            return None;
+        }
+        Some(transport)
+    }
+
+    /// 设备类型号，见 [`super::DeviceType`]
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    /// 寄存器版本，1 为旧版，2 为新版
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// 厂商号，qemu 为 `QEMU`
+    pub fn vendor_id(&self) -> u32 {
+        unsafe { self.read(VIRTIO_MMIO_VENDOR_ID) }
+    }
+
+    /// 寄存器基址
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// 设备在 PLIC 上的中断号
+    pub fn irq(&self) -> usize {
+        self.irq
+    }
+
+    /// 复位设备并完成特征协商
+    ///
+    /// # 功能说明
+    /// 1. 复位设备，设置 ACKNOWLEDGE 和 DRIVER 状态位
+    /// 2. 读取设备特征位，交给 `negotiate` 决定驱动接受的子集
+    /// 3. 新版设备额外接受 `VIRTIO_F_VERSION_1`
+    /// 4. 设置 FEATURES_OK 并确认设备接受
+    ///
+    /// 之后驱动应调用 [`MmioTransport::setup_queue`] 配置队列，
+    /// 最后调用 [`MmioTransport::driver_ok`]。
+    ///
+    /// # 参数
+    /// - `negotiate`：输入设备提供的特征位，返回驱动接受的特征位
+    ///
+    /// # 可能的错误
+    /// - 设备拒绝了协商结果
+    pub fn begin_init(&self, negotiate: impl FnOnce(u64) -> u64) -> Result<(), &'static str> {
+        unsafe {
+            // 复位
+            self.write(VIRTIO_MMIO_STATUS, 0);
+            let mut status: u32 = VIRTIO_CONFIG_S_ACKNOWLEDGE;
+            self.write(VIRTIO_MMIO_STATUS, status);
+            status |= VIRTIO_CONFIG_S_DRIVER;
+            self.write(VIRTIO_MMIO_STATUS, status);
+
+            let device_features = self.read_features();
+            let mut features = negotiate(device_features) & device_features;
+            if self.version == 2 {
+                if device_features & (1 << VIRTIO_F_VERSION_1) == 0 {
+                    return Err("virtio: modern device without VERSION_1");
+                }
+                features |= 1 << VIRTIO_F_VERSION_1;
+            }
+            self.write_features(features);
+
+            // 设置 FEATURES_OK 位以告知设备特征协商已完成
+            status |= VIRTIO_CONFIG_S_FEATURES_OK;
+            self.write(VIRTIO_MMIO_STATUS, status);
+            if self.version == 2 && self.read(VIRTIO_MMIO_STATUS) & VIRTIO_CONFIG_S_FEATURES_OK == 0 {
+                return Err("virtio: device rejected features");
+            }
+
+            if self.version == 1 {
+                self.write(VIRTIO_MMIO_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+            }
+        }
+        Ok(())
+    }
+
+    /// 把队列 `index` 交给设备
+    ///
+    /// # 参数
+    /// - `index`：队列号
+    /// - `queue`：已 [`VirtQueue::reset`] 的队列，在设备使用期间不能移动
+    ///
+    /// # 可能的错误
+    /// - 设备没有该队列，或队列容量小于 [`QUEUE_SIZE`]
+    pub fn setup_queue(&self, index: u32, queue: &VirtQueue) -> Result<(), &'static str> {
+        unsafe {
+            self.write(VIRTIO_MMIO_QUEUE_SEL, index);
+            let max = self.read(VIRTIO_MMIO_QUEUE_NUM_MAX);
+            if max == 0 {
+                return Err("virtio: queue not available");
+            }
+            if max < QUEUE_SIZE as u32 {
+                return Err("virtio: queue too short");
+            }
+            self.write(VIRTIO_MMIO_QUEUE_NUM, QUEUE_SIZE as u32);
+
+            if self.version == 1 {
+                self.write(VIRTIO_MMIO_QUEUE_ALIGN, PAGE_SIZE as u32);
+                let pfn = queue.desc_addr() >> PGSHIFT;
+                self.write(VIRTIO_MMIO_QUEUE_PFN, pfn as u32);
+            } else {
+                self.write_addr(VIRTIO_MMIO_QUEUE_DESC_LOW, VIRTIO_MMIO_QUEUE_DESC_HIGH, queue.desc_addr());
+                self.write_addr(VIRTIO_MMIO_QUEUE_DRIVER_LOW, VIRTIO_MMIO_QUEUE_DRIVER_HIGH, queue.avail_addr());
+                self.write_addr(VIRTIO_MMIO_QUEUE_DEVICE_LOW, VIRTIO_MMIO_QUEUE_DEVICE_HIGH, queue.used_addr());
+                self.write(VIRTIO_MMIO_QUEUE_READY, 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// 设置 DRIVER_OK 位以告知设备驱动程序已准备就绪，此时设备处于“活动”状态
+    pub fn driver_ok(&self) {
+        unsafe {
+            let status = self.read(VIRTIO_MMIO_STATUS);
+            self.write(VIRTIO_MMIO_STATUS, status | VIRTIO_CONFIG_S_DRIVER_OK);
+        }
+    }
+
+    /// 通知设备队列 `index` 中有新的请求
+    pub fn notify(&self, index: u32) {
+        unsafe { self.write(VIRTIO_MMIO_QUEUE_NOTIFY, index); }
+    }
+
+    /// 确认并清除中断状态，返回中断原因
+    pub fn ack_interrupt(&self) -> u32 {
+        unsafe {
+            let intr_stat = self.read(VIRTIO_MMIO_INTERRUPT_STATUS);
+            self.write(VIRTIO_MMIO_INTERRUPT_ACK, intr_stat & 0x3);
+            intr_stat
+        }
+    }
+
+    /// 读取设备配置空间中偏移 `offset` 处的 32 位值
+    pub fn read_config(&self, offset: usize) -> u32 {
+        unsafe { self.read(VIRTIO_MMIO_CONFIG + offset) }
+    }
+
+    unsafe fn read_features(&self) -> u64 {
+        if self.version == 1 {
+            return self.read(VIRTIO_MMIO_DEVICE_FEATURES) as u64;
+        }
+        self.write(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
+        let high = self.read(VIRTIO_MMIO_DEVICE_FEATURES) as u64;
+        self.write(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 0);
+        let low = self.read(VIRTIO_MMIO_DEVICE_FEATURES) as u64;
+        (high << 32) | low
+    }
+
+    unsafe fn write_features(&self, features: u64) {
+        if self.version != 1 {
+            self.write(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
+            self.write(VIRTIO_MMIO_DRIVER_FEATURES, (features >> 32) as u32);
+            self.write(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
+        }
+        self.write(VIRTIO_MMIO_DRIVER_FEATURES, features as u32);
+    }
+
+    unsafe fn write_addr(&self, low: usize, high: usize, addr: usize) {
+        self.write(low, addr as u32);
+        self.write(high, (addr >> 32) as u32);
+    }
+
+    #[inline]
+    unsafe fn read(&self, offset: usize) -> u32 {
+        let src = (self.base + offset) as *const u32;
+        ptr::read_volatile(src)
+    }
+
+    #[inline]
+    unsafe fn write(&self, offset: usize, data: u32) {
+        let dst = (self.base + offset) as *mut u32;
+        ptr::write_volatile(dst, data);
+    }
+}
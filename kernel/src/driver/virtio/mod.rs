@@ -0,0 +1,97 @@
+//! virtio 设备框架
+//!
+//! - [`mmio`]：virtio-mmio 传输层，负责寄存器访问、特征协商与队列配置；
+//! - [`queue`]：分离式虚拟队列，负责描述符的分配、提交与回收。
+//!
+//! 启动时 [`probe`] 扫描 qemu virt 平台的全部 MMIO 槽位并记录每个槽位上的设备类型，
+//! 具体设备的驱动通过 [`claim`] 取得对应的传输层，再用传输层的中断号向 PLIC 登记中断处理函数。
+
+pub mod mmio;
+pub mod queue;
+
+pub use mmio::MmioTransport;
+pub use queue::{VirtBuf, VirtQueue, QUEUE_SIZE};
+
+use crate::consts::{NVIRTIO, VIRTIO0, VIRTIO0_IRQ, VIRTIO_MMIO_STRIDE};
+use crate::spinlock::SpinLock;
+
+/// virtio 设备类型号
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum DeviceType {
+    Network = 1,
+    Block = 2,
+    Console = 3,
+    Entropy = 4,
+}
+
+impl DeviceType {
+    fn name(id: u32) -> &'static str {
+        match id {
+            1 => "net",
+            2 => "block",
+            3 => "console",
+            4 => "entropy",
+            _ => "unknown",
+        }
+    }
+}
+
+/// 一个 MMIO 槽位的探测结果
+#[derive(Clone, Copy)]
+struct Slot {
+    /// 设备类型号，0 表示槽位为空
+    device_id: u32,
+    /// 是否已被驱动取走
+    claimed: bool,
+}
+
+impl Slot {
+    const fn empty() -> Self {
+        Self { device_id: 0, claimed: false }
+    }
+}
+
+static SLOTS: SpinLock<[Slot; NVIRTIO]> = SpinLock::new([Slot::empty(); NVIRTIO], "virtio slots");
+
+#[inline]
+fn slot_base(slot: usize) -> usize {
+    usize::from(VIRTIO0) + slot * VIRTIO_MMIO_STRIDE
+}
+
+/// 扫描所有 MMIO 槽位并记录其中的设备
+///
+/// # 安全性
+/// 仅在启动时由 0 号核心调用一次，且所有槽位的寄存器均已映射。
+pub unsafe fn probe() {
+    let mut slots = SLOTS.lock();
+    for (i, slot) in slots.iter_mut().enumerate() {
+        if let Some(transport) = MmioTransport::probe(slot_base(i), VIRTIO0_IRQ + i) {
+            slot.device_id = transport.device_id();
+            println!(
+                "virtio: slot {} at {:#x}: {} (id {}), version {}, irq {}",
+                i, transport.base(), DeviceType::name(slot.device_id),
+                slot.device_id, transport.version(), transport.irq(),
+            );
+        }
+    }
+}
+
+/// 取得第一个尚未被占用的 `ty` 类型设备
+///
+/// # 参数
+/// - `ty`：需要的设备类型
+///
+/// # 返回值
+/// - `Some(MmioTransport)`：设备的传输层，由调用者负责初始化并登记 [`MmioTransport::irq`] 的中断处理函数
+/// - `None`：没有这类设备或都已被占用
+pub fn claim(ty: DeviceType) -> Option<MmioTransport> {
+    let mut slots = SLOTS.lock();
+    for (i, slot) in slots.iter_mut().enumerate() {
+        if slot.device_id == ty as u32 && !slot.claimed {
+            slot.claimed = true;
+            return unsafe { MmioTransport::probe(slot_base(i), VIRTIO0_IRQ + i) };
+        }
+    }
+    None
+}
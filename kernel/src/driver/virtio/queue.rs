@@ -0,0 +1,352 @@
+//! 分离式虚拟队列（split virtqueue）
+//!
+//! 来自 virtio v1.1 规范 2.6 节：
+//!     * Descriptor Table - occupies the Descriptor Area
+//!     * Available Ring - occupies the Driver Area
+//!     * Used Ring - occupies the Device Area
+//!
+//! 队列结构按旧版（legacy）布局排列：描述符表与可用环位于第一页，已用环从下一页开始，
+//! 这样既能通过 `QueuePFN` 交给旧版设备，也能把三个区域的地址分别交给新版设备。
+//! 内核地址与物理地址恒等映射，结构中的地址可以直接写入描述符。
+//!
+//! NOTE: 4096 in #[repr(C, align(4096))] is PGSIZE
+
+use core::sync::atomic::{fence, Ordering};
+
+use crate::process::PROC_MANAGER;
+
+/// 每个队列的描述符数量，必须是 2 的幂
+pub const QUEUE_SIZE: usize = 8;
+
+// 虚拟环描述符标志位
+const VRING_DESC_F_NEXT: u16 = 1; // 与另一个描述符链接
+const VRING_DESC_F_WRITE: u16 = 2; // 设备写入（相对于读取）
+
+/// 提交给设备的一段缓冲区
+#[derive(Clone, Copy)]
+pub struct VirtBuf {
+    /// 缓冲区物理地址
+    pub addr: usize,
+    /// 缓冲区长度
+    pub len: u32,
+    /// 设备是否向该缓冲区写入
+    pub writable: bool,
+}
+
+impl VirtBuf {
+    /// 设备只读的缓冲区
+    pub fn readable(addr: usize, len: usize) -> Self {
+        Self { addr, len: len as u32, writable: false }
+    }
+
+    /// 设备写入的缓冲区
+    pub fn writable(addr: usize, len: usize) -> Self {
+        Self { addr, len: len as u32, writable: true }
+    }
+}
+
+/// 一个虚拟队列及其描述符分配状态
+#[repr(C, align(4096))]
+pub struct VirtQueue {
+    // 第一页
+    desc: [VQDesc; QUEUE_SIZE],
+    avail: VQAvail,
+    // 另一页
+    pad: Pad,
+    used: VQUsed,
+    // 结尾
+    free: [bool; QUEUE_SIZE],
+    used_idx: u16,
+}
+
+impl VirtQueue {
+    /// 创建新的空队列，描述符在 [`VirtQueue::reset`] 之后才可用
+    pub const fn new() -> Self {
+        Self {
+            desc: [VQDesc::new(); QUEUE_SIZE],
+            avail: VQAvail::new(),
+            pad: Pad::new(),
+            used: VQUsed::new(),
+            free: [false; QUEUE_SIZE],
+            used_idx: 0,
+        }
+    }
+
+    /// 释放所有描述符并清空两个环，在把队列交给设备之前调用
+    pub fn reset(&mut self) {
+        self.desc.iter_mut().for_each(|d| *d = VQDesc::new());
+        self.avail = VQAvail::new();
+        self.used = VQUsed::new();
+        self.free.iter_mut().for_each(|f| *f = true);
+        self.used_idx = 0;
+    }
+
+    /// 描述符表的物理地址
+    pub fn desc_addr(&self) -> usize {
+        &self.desc as *const _ as usize
+    }
+
+    /// 可用环的物理地址
+    pub fn avail_addr(&self) -> usize {
+        &self.avail as *const _ as usize
+    }
+
+    /// 已用环的物理地址
+    pub fn used_addr(&self) -> usize {
+        &self.used as *const _ as usize
+    }
+
+    /// 描述符不足时等待的睡眠通道，有描述符被释放时唤醒
+    pub fn free_channel(&self) -> usize {
+        &self.free[0] as *const bool as usize
+    }
+
+    /// 把一组缓冲区作为一条描述符链放入可用环
+    ///
+    /// # 功能说明
+    /// 为每段缓冲区分配一个描述符并依次链接，随后把链头写入可用环。
+    /// 调用者随后需通过传输层通知设备。
+    ///
+    /// # 返回值
+    /// - `Some(head)`：链头描述符索引，设备完成后会出现在已用环中
+    /// - `None`：空闲描述符不足，此时不占用任何描述符
+    pub fn add(&mut self, bufs: &[VirtBuf]) -> Option<usize> {
+        debug_assert!(!bufs.is_empty() && bufs.len() <= QUEUE_SIZE);
+        let mut idx = [0usize; QUEUE_SIZE];
+        for i in 0..bufs.len() {
+            match self.alloc_desc() {
+                Some(ix) => idx[i] = ix,
+                None => {
+                    for j in 0..i {
+                        self.free_desc(idx[j]);
+                    }
+                    return None;
+                }
+            }
+        }
+
+        // 格式化描述符，设备会读取它们
+        for (i, buf) in bufs.iter().enumerate() {
+            let desc = &mut self.desc[idx[i]];
+            desc.addr = buf.addr as u64;
+            desc.len = buf.len;
+            desc.flags = if buf.writable { VRING_DESC_F_WRITE } else { 0 };
+            if i + 1 < bufs.len() {
+                desc.flags |= VRING_DESC_F_NEXT;
+                desc.next = idx[i + 1] as u16;
+            } else {
+                desc.next = 0;
+            }
+        }
+
+        let i = self.avail.idx as usize % QUEUE_SIZE;
+        self.avail.ring[i] = idx[0] as u16;
+
+        fence(Ordering::SeqCst);
+
+        self.avail.idx = self.avail.idx.wrapping_add(1);
+
+        fence(Ordering::SeqCst);
+
+        Some(idx[0])
+    }
+
+    /// 取出一项设备已经处理完的描述符链
+    ///
+    /// # 返回值
+    /// - `Some((head, len))`：链头描述符索引与设备写入的字节数，描述符链仍需调用者释放
+    /// - `None`：已用环中没有新的条目
+    pub fn pop_used(&mut self) -> Option<(usize, u32)> {
+        fence(Ordering::SeqCst);
+        // 当设备向已用环添加一个条目时，它会增加 used.idx 的值
+        let used_idx = unsafe { core::ptr::read_volatile(&self.used.idx) };
+        if self.used_idx == used_idx {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let elem = &self.used.ring[self.used_idx as usize % QUEUE_SIZE];
+        let ret = (elem.id as usize, elem.len);
+        self.used_idx = self.used_idx.wrapping_add(1);
+        Some(ret)
+    }
+
+    /// 释放描述符链
+    ///
+    /// # 功能说明
+    /// 遍历并释放通过`VRING_DESC_F_NEXT`链接的描述符链
+    ///
+    /// # 参数
+    /// - `i`: 链的起始描述符索引
+    pub fn free_chain(&mut self, mut i: usize) {
+        loop {
+            let flag = self.desc[i].flags;
+            let next = self.desc[i].next;
+            self.free_desc(i);
+            if (flag & VRING_DESC_F_NEXT) != 0 {
+                i = next as usize;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// 分配单个描述符
+    ///
+    /// # 返回值
+    /// - `Some(usize)`: 分配的描述符索引
+    /// - `None`: 无可用描述符
+    fn alloc_desc(&mut self) -> Option<usize> {
+        for i in 0..QUEUE_SIZE {
+            if self.free[i] {
+                self.free[i] = false;
+                return Some(i)
+            }
+        }
+        None
+    }
+
+    /// 释放单个描述符
+    ///
+    /// # 参数
+    /// - `i`: 要释放的描述符索引
+    ///
+    /// # Panics
+    /// - 索引超出范围
+    /// - 描述符已处于空闲状态
+    fn free_desc(&mut self, i: usize) {
+        if i >= QUEUE_SIZE || self.free[i] {
+            panic!("desc index not correct");
+        }
+        self.desc[i] = VQDesc::new();
+        self.free[i] = true;
+        unsafe {
+            PROC_MANAGER.wakeup(self.free_channel());
+        }
+    }
+}
+
+/// 内存填充结构（用于对齐）
+#[repr(C, align(4096))]
+struct Pad();
+
+impl Pad {
+    /// 创建新的填充实例
+    const fn new() -> Self {
+        Self()
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct VQDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+impl VQDesc {
+    /// 创建新的未初始化描述符
+    const fn new() -> Self {
+        Self {
+            addr: 0,
+            len: 0,
+            flags: 0,
+            next: 0,
+        }
+    }
+}
+
+#[repr(C, align(2))]
+struct VQAvail {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    unused: u16,
+}
+
+impl VQAvail {
+    /// 创建新的可用环
+    const fn new() -> Self {
+        Self {
+            flags: 0,
+            idx: 0,
+            ring: [0; QUEUE_SIZE],
+            unused: 0,
+        }
+    }
+}
+
+#[repr(C, align(4))]
+struct VQUsed {
+    flags: u16,
+    idx: u16,
+    ring: [VQUsedElem; QUEUE_SIZE],
+}
+
+impl VQUsed {
+    /// 创建新的已用环
+    const fn new() -> Self {
+        Self {
+            flags: 0,
+            idx: 0,
+            ring: [VQUsedElem::new(); QUEUE_SIZE],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VQUsedElem {
+    id: u32,
+    len: u32,
+}
+
+impl VQUsedElem {
+    /// 创建新的已用元素
+    const fn new() -> Self {
+        Self {
+            id: 0,
+            len: 0,
+        }
+    }
+}
+
+#[cfg(feature = "unit_test")]
+pub mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use crate::ktest::TestHarts;
+
+    kernel_test!(layout, TestHarts::Hart(0));
+    kernel_test!(chain_alloc_and_free, TestHarts::Hart(0));
+
+    /// 队列有两页大，直接在堆上清零构造，避免占用测试栈
+    fn new_queue() -> Box<VirtQueue> {
+        unsafe { Box::<VirtQueue>::new_zeroed().assume_init() }
+    }
+
+    /// 旧版设备要求已用环从描述符表之后的下一页开始
+    pub fn layout() {
+        let q = new_queue();
+        assert_eq!(q.desc_addr() % 4096, 0);
+        assert_eq!(q.used_addr() - q.desc_addr(), 4096);
+        assert!(q.avail_addr() < q.used_addr());
+    }
+
+    /// 描述符耗尽时 add 失败且不泄漏，释放链后可以重新分配
+    pub fn chain_alloc_and_free() {
+        let mut q = new_queue();
+        q.reset();
+        let buf = VirtBuf::readable(0x1000, 16);
+        let a = q.add(&[buf; 3]).unwrap();
+        let b = q.add(&[buf; 3]).unwrap();
+        assert!(q.add(&[buf; 3]).is_none());
+        let c = q.add(&[buf; 2]).unwrap();
+        assert!(q.add(&[buf]).is_none());
+        q.free_chain(a);
+        q.free_chain(b);
+        q.free_chain(c);
+        assert!(q.add(&[buf; QUEUE_SIZE]).is_some());
+    }
+}
@@ -0,0 +1,202 @@
+//! virtio 熵源设备驱动（qemu `-device virtio-rng-device`）
+//!
+//! 设备只有一个队列：驱动放入一段设备可写的缓冲区，设备填入随机字节后在已用环中归还。
+//! 驱动把收到的字节存入熵池，`/dev/random` 的读操作与 [`super::random`] 从熵池中取用，
+//! 熵池为空时发起新的请求，读者睡眠直到中断送来新的熵。
+
+use core::sync::atomic::Ordering;
+
+use crate::consts::driver::ENTROPY_BUF;
+use crate::mm::Address;
+use crate::spinlock::SpinLock;
+use crate::plic::{self, ALL_HARTS};
+use crate::process::{PROC_MANAGER, CPU_MANAGER};
+use super::chrdev::Device;
+use super::virtio::{self, DeviceType, MmioTransport, VirtBuf, VirtQueue};
+
+pub static RNG: SpinLock<Rng> = SpinLock::new(Rng::new(), "virtio_rng");
+
+/// `/dev/random` 字符设备，熵源设备存在时由 [`super::random::init`] 登记
+pub static RANDOM_DEVICE: Device = Device::new("random", read, write);
+
+/// virtio 熵源设备及其熵池
+pub struct Rng {
+    queue: VirtQueue,
+    transport: Option<MmioTransport>,
+    /// 交给设备填写的缓冲区
+    dma: [u8; ENTROPY_BUF],
+    /// 是否有请求正由设备处理
+    pending: bool,
+    /// 熵池，`pool[..avail]` 为尚未被取用的随机字节
+    pool: [u8; ENTROPY_BUF],
+    avail: usize,
+}
+
+impl Rng {
+    const fn new() -> Self {
+        Self {
+            queue: VirtQueue::new(),
+            transport: None,
+            dma: [0; ENTROPY_BUF],
+            pending: false,
+            pool: [0; ENTROPY_BUF],
+            avail: 0,
+        }
+    }
+
+    /// 初始化熵源设备
+    ///
+    /// # 返回值
+    /// - `true`：找到并初始化了设备
+    /// - `false`：qemu 没有提供 virtio-rng 设备，`/dev/random` 不可用
+    ///
+    /// # 安全性
+    /// 仅在系统启动时调用一次，且 [`virtio::probe`] 已经执行。
+    pub unsafe fn init(&mut self) -> bool {
+        let transport = match virtio::claim(DeviceType::Entropy) {
+            Some(transport) => transport,
+            None => return false,
+        };
+        plic::register_irq(transport.irq(), "virtio-rng", rng_intr, 1, ALL_HARTS)
+            .expect("virtio rng: register irq");
+        if let Err(err) = transport.begin_init(|_| 0) {
+            panic!("virtio rng: {}", err);
+        }
+        self.queue.reset();
+        if let Err(err) = transport.setup_queue(0, &self.queue) {
+            panic!("virtio rng: {}", err);
+        }
+        transport.driver_ok();
+        self.transport = Some(transport);
+        true
+    }
+
+    /// 是否已有可用的熵源设备
+    pub fn is_present(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// 若熵池为空且没有进行中的请求，则向设备请求一批新的随机字节
+    fn refill(&mut self) {
+        if self.pending || self.avail > 0 {
+            return;
+        }
+        let transport = match self.transport.as_ref() {
+            Some(transport) => transport,
+            None => return,
+        };
+        let buf = VirtBuf::writable(self.dma.as_ptr() as usize, ENTROPY_BUF);
+        if self.queue.add(&[buf]).is_some() {
+            self.pending = true;
+            transport.notify(0);
+        }
+    }
+
+    /// 从熵池中取出至多 `dst.len()` 个字节，返回取出的字节数，不会阻塞
+    pub fn take(&mut self, dst: &mut [u8]) -> usize {
+        let n = dst.len().min(self.avail);
+        self.avail -= n;
+        dst[..n].copy_from_slice(&self.pool[self.avail..self.avail + n]);
+        self.refill();
+        n
+    }
+
+    /// 轮询方式取得 `dst.len()` 个随机字节，用于启动阶段尚未开中断时
+    ///
+    /// # 返回值
+    /// - `true`：已填满 `dst`
+    /// - `false`：没有熵源设备
+    pub fn poll_fill(&mut self, dst: &mut [u8]) -> bool {
+        if !self.is_present() {
+            return false;
+        }
+        let mut got = 0;
+        while got < dst.len() {
+            got += self.take(&mut dst[got..]);
+            if got < dst.len() {
+                self.collect();
+            }
+        }
+        true
+    }
+
+    /// 把设备已归还的随机字节放入熵池，返回是否收到了新数据
+    fn collect(&mut self) -> bool {
+        let mut collected = false;
+        while let Some((head, len)) = self.queue.pop_used() {
+            self.queue.free_chain(head);
+            let len = (len as usize).min(ENTROPY_BUF);
+            self.pool[..len].copy_from_slice(&self.dma[..len]);
+            self.avail = len;
+            self.pending = false;
+            collected = true;
+        }
+        collected
+    }
+
+    /// 熵源中断处理函数，把新数据放入熵池并唤醒等待的读者
+    pub fn intr(&mut self) {
+        match self.transport.as_ref() {
+            Some(transport) => { transport.ack_interrupt(); }
+            None => return,
+        }
+        if self.collect() {
+            unsafe { PROC_MANAGER.wakeup(self.channel()); }
+        }
+    }
+
+    /// 等待熵的睡眠通道
+    fn channel(&self) -> usize {
+        &self.avail as *const usize as usize
+    }
+}
+
+fn rng_intr(_irq: usize) {
+    RNG.lock().intr();
+}
+
+/// `/dev/random` 读操作
+///
+/// # 功能说明
+/// 读满 `tot` 个字节后返回，熵池为空时睡眠等待设备送来新的熵。
+///
+/// # 返回值
+/// - `Ok(n)`: 实际读取的字节数
+/// - `Err(())`: 没有熵源设备，或等待期间进程被杀死
+fn read(_minor: u16, mut dst: Address, tot: u32) -> Result<u32, ()> {
+    let mut rng = RNG.lock();
+    if !rng.is_present() {
+        return Err(())
+    }
+
+    let mut left = tot as usize;
+    let mut chunk = [0u8; ENTROPY_BUF];
+    while left > 0 {
+        let n = rng.take(&mut chunk[..left.min(ENTROPY_BUF)]);
+        if n == 0 {
+            let process = unsafe { CPU_MANAGER.my_proc() };
+            if process.killed.load(Ordering::Relaxed) {
+                return Err(())
+            }
+            let channel = rng.channel();
+            process.sleep(channel, rng);
+            rng = RNG.lock();
+            continue;
+        }
+
+        // 复制到用户 / 内核空间内存
+        if dst.copy_out(chunk.as_ptr(), n).is_err() {
+            break;
+        }
+        dst = dst.offset(n);
+        left -= n;
+    }
+    Ok(tot - left as u32)
+}
+
+/// `/dev/random` 写操作：接受并丢弃数据
+///
+/// 设备提供的熵已经足够，写入的数据不会混入熵池，但写操作本身总是成功。
+fn write(_minor: u16, _src: Address, tot: u32) -> Result<u32, ()> {
+    Ok(tot)
+}
@@ -0,0 +1,126 @@
+//! exec 从调用者读入的命令行参数和环境变量
+//!
+//! 与架构无关，加载 ELF 文件时把这里的字符串放到新程序的用户栈上，见 `process::proc::elf`。
+
+use alloc::boxed::Box;
+use core::mem;
+
+use crate::consts::ARG_MAX;
+
+/// exec 的参数和环境变量超过 [`ARG_MAX`]
+pub const ARGS_TOO_BIG: &str = "argument list too long";
+
+/// exec 从调用者读入的命令行参数和环境变量
+///
+/// 字符串依次存放在一块 [`ARG_MAX`] 字节的缓冲区中，参数在前、环境变量在后，各自以空字节结尾。
+/// 每个字符串在新程序的栈上还占一个指针，argv 和 envp 各有一个结尾的空指针，
+/// 字符串和这些指针一共不能超过 [`ARG_MAX`] 字节，不再限制单个参数的长度和参数的个数。
+pub struct ExecArgs {
+    strings: Box<[u8; ARG_MAX]>,
+    /// 缓冲区中已经使用的字节数，之后的字节都是零
+    len: usize,
+    argc: usize,
+    envc: usize,
+}
+
+impl ExecArgs {
+    pub fn new() -> Result<Self, ()> {
+        let strings = Box::<[u8; ARG_MAX]>::try_new_zeroed().map_err(|_| ())?;
+        Ok(Self { strings: unsafe { strings.assume_init() }, len: 0, argc: 0, envc: 0 })
+    }
+
+    /// 用 `fetch` 把下一个字符串读入缓冲区剩余的空间，`env` 表示它是环境变量，参数要在所有环境变量之前加入
+    ///
+    /// # 可能的错误
+    /// 剩余的空间放不下这个字符串时返回 [`ARGS_TOO_BIG`]，否则返回 `fetch` 的错误。
+    /// 出错时已经加入的字符串不变。
+    pub fn push(&mut self, env: bool, fetch: impl FnOnce(&mut [u8]) -> Result<(), &'static str>) -> Result<(), &'static str> {
+        debug_assert!(env || self.envc == 0);
+        // 已经加入的字符串、它们的指针、两个结尾的空指针和这个字符串自己的指针
+        let used = self.len + (self.argc + self.envc + 3) * mem::size_of::<usize>();
+        let room = ARG_MAX.saturating_sub(used);
+        if room == 0 {
+            return Err(ARGS_TOO_BIG)
+        }
+        let dst = &mut self.strings[self.len..self.len + room];
+        if let Err(s) = fetch(&mut *dst) {
+            // 没有用过的字节原来都是零，最后一个字节被写过说明字符串没有在剩余的空间内结束
+            let err = if dst[room - 1] != 0 { ARGS_TOO_BIG } else { s };
+            // 读到一半的字符串要清掉，保持 len 之后的字节都是零
+            dst.fill(0);
+            return Err(err)
+        }
+        self.len += dst.iter().position(|&c| c == 0).unwrap() + 1;
+        if env {
+            self.envc += 1;
+        } else {
+            self.argc += 1;
+        }
+        Ok(())
+    }
+
+    /// 依次排列的全部字符串，每个都以空字节结尾
+    pub fn strings(&self) -> &[u8] {
+        &self.strings[..self.len]
+    }
+
+    /// 参数的个数
+    pub fn argc(&self) -> usize {
+        self.argc
+    }
+
+    /// 环境变量的个数
+    pub fn envc(&self) -> usize {
+        self.envc
+    }
+}
+
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+
+    fn push_str(args: &mut ExecArgs, env: bool, s: &[u8]) -> Result<(), &'static str> {
+        args.push(env, |dst| {
+            // 与 copy_in_str 相同：复制到空字节为止，放不下时出错
+            for (i, &c) in s.iter().chain(core::iter::once(&0)).enumerate() {
+                *dst.get_mut(i).ok_or("dst not enough space")? = c;
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn exec_args_are_limited_by_total_size() {
+        let mut args = ExecArgs::new().unwrap();
+        push_str(&mut args, false, b"prog").unwrap();
+        // 单个参数可以远长于原来的 64 字节
+        let long = [b'a'; 1000];
+        push_str(&mut args, false, &long).unwrap();
+        push_str(&mut args, true, b"FOO=bar").unwrap();
+        assert_eq!((args.argc(), args.envc()), (2, 1));
+        assert_eq!(args.strings(), [&b"prog\0"[..], &long[..], &b"\0FOO=bar\0"[..]].concat().as_slice());
+
+        // 字符串和指针一起超过 ARG_MAX 时失败，已经加入的不变
+        let huge = [b'b'; ARG_MAX];
+        assert_eq!(push_str(&mut args, true, &huge[..ARG_MAX / 2]), Ok(()));
+        assert_eq!(push_str(&mut args, true, &huge[..ARG_MAX / 2]), Err(ARGS_TOO_BIG));
+        assert_eq!(args.envc(), 2);
+        assert!(args.strings.iter().skip(args.len).all(|&c| c == 0));
+    }
+
+    #[test]
+    fn failed_fetch_leaves_no_bytes_behind() {
+        let mut args = ExecArgs::new().unwrap();
+        push_str(&mut args, false, b"prog").unwrap();
+        // 读到一半出错，例如字符串跨入了没有映射的页
+        let ret = args.push(false, |dst| {
+            dst[..3].copy_from_slice(b"abc");
+            Err("copy_in_str: bad address")
+        });
+        assert_eq!(ret, Err("copy_in_str: bad address"));
+        assert!(args.strings.iter().skip(args.len).all(|&c| c == 0));
+
+        push_str(&mut args, false, b"x").unwrap();
+        assert_eq!(args.strings(), b"prog\0x\0");
+    }
+}
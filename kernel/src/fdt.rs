@@ -0,0 +1,417 @@
+//! 扁平设备树（Flattened Device Tree）解析
+//!
+//! qemu 在跳转到 `_entry` 时通过 `a1` 传入设备树的物理地址，`start` 将其保存在 [`DTB_ADDR`] 中。
+//! 本模块只实现内核启动时需要的只读查询：遍历节点、按名字读取属性、
+//! 按 `compatible` 查找设备以及读取 `/chosen` 中的 initrd 位置和启动参数。
+//!
+//! 设备树中的所有整数均为大端序。qemu virt 平台的 `#address-cells` 与 `#size-cells`
+//! 均为 2，`reg` 的解析按此约定进行。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 启动时由 `a1` 传入的设备树物理地址，0 表示没有设备树
+pub static DTB_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+const FDT_MAGIC: u32 = 0xd00dfeed;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// 一棵只读的设备树
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+/// 设备树中的一个节点
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    /// 节点名（含单元地址），例如 `virtio_mmio@10001000`
+    pub name: &'a [u8],
+    /// 节点深度，根节点为 0
+    pub depth: usize,
+    /// 第一个属性在结构块中的偏移
+    props: usize,
+}
+
+impl<'a> Fdt<'a> {
+    /// 从物理地址解析设备树头部
+    ///
+    /// # 返回值
+    /// - `Ok(Fdt)`：头部合法
+    /// - `Err(&str)`：魔数错误或偏移越界
+    ///
+    /// # 安全性
+    /// 调用者需保证 `addr` 处的内存可读，且在返回值的生命周期内不被修改。
+    pub unsafe fn from_raw(addr: usize) -> Result<Self, &'static str> {
+        let header = core::slice::from_raw_parts(addr as *const u8, 40);
+        if be32(header, 0) != FDT_MAGIC {
+            return Err("fdt: bad magic");
+        }
+        let total = be32(header, 4) as usize;
+        let blob = core::slice::from_raw_parts(addr as *const u8, total);
+        Self::from_bytes(blob)
+    }
+
+    /// 从完整的设备树字节序列解析
+    pub fn from_bytes(blob: &'a [u8]) -> Result<Self, &'static str> {
+        if blob.len() < 40 || be32(blob, 0) != FDT_MAGIC {
+            return Err("fdt: bad magic");
+        }
+        let total = be32(blob, 4) as usize;
+        let off_struct = be32(blob, 8) as usize;
+        let off_strings = be32(blob, 12) as usize;
+        let size_strings = be32(blob, 32) as usize;
+        let size_struct = be32(blob, 36) as usize;
+        if total > blob.len()
+            || off_struct + size_struct > total
+            || off_strings + size_strings > total
+        {
+            return Err("fdt: block out of range");
+        }
+        Ok(Self {
+            structs: &blob[off_struct..off_struct + size_struct],
+            strings: &blob[off_strings..off_strings + size_strings],
+        })
+    }
+
+    /// 按深度优先顺序对每个节点调用 `f`
+    pub fn for_each_node(&self, f: &mut dyn FnMut(&Node<'a>)) {
+        let mut off = 0;
+        let mut depth = 0;
+        while off + 4 <= self.structs.len() {
+            let token = be32(self.structs, off);
+            off += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = cstr(&self.structs[off..]);
+                    off = align4(off + name.len() + 1);
+                    f(&Node { fdt: *self, name, depth, props: off });
+                    depth += 1;
+                }
+                FDT_END_NODE => depth -= 1,
+                FDT_PROP => {
+                    let len = be32(self.structs, off) as usize;
+                    off = align4(off + 8 + len);
+                }
+                FDT_NOP => {}
+                _ => break,
+            }
+        }
+    }
+
+    /// 对所有 `compatible` 属性包含 `compat` 的节点调用 `f`
+    pub fn for_each_compatible(&self, compat: &str, f: &mut dyn FnMut(&Node<'a>)) {
+        self.for_each_node(&mut |node| {
+            if node.is_compatible(compat) {
+                f(node);
+            }
+        });
+    }
+
+    /// 查找深度为 1、名字为 `name` 的节点，例如 `chosen`
+    pub fn top_node(&self, name: &str) -> Option<Node<'a>> {
+        let mut found = None;
+        self.for_each_node(&mut |node| {
+            if found.is_none() && node.depth == 1 && node.name == name.as_bytes() {
+                found = Some(*node);
+            }
+        });
+        found
+    }
+
+    /// 读取 `/chosen` 中 `linux,initrd-start` 与 `linux,initrd-end` 给出的 initrd 物理地址范围
+    pub fn initrd(&self) -> Option<(usize, usize)> {
+        let chosen = self.top_node("chosen")?;
+        let start = chosen.prop_usize("linux,initrd-start")?;
+        let end = chosen.prop_usize("linux,initrd-end")?;
+        if end > start {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    /// 启动参数（`/chosen` 中的 `bootargs`，qemu 的 `-append`）中是否有以空格分隔的参数 `arg`
+    pub fn has_bootarg(&self, arg: &str) -> bool {
+        self.top_node("chosen")
+            .and_then(|chosen| chosen.prop("bootargs"))
+            .map_or(false, |args| cstr(args).split(|&c| c == b' ').any(|s| s == arg.as_bytes()))
+    }
+
+    /// 是否所有 cpu 节点都支持名为 `ext` 的多字母 ISA 扩展（小写，例如 `sstc`）
+    ///
+    /// 优先查看 `riscv,isa-extensions` 列表，没有时在 `riscv,isa` 字符串中以下划线分隔的部分查找。
+    pub fn cpus_have_extension(&self, ext: &str) -> bool {
+        let mut cpus = 0;
+        let mut have = 0;
+        self.for_each_node(&mut |node| {
+            if node.prop("device_type") != Some(b"cpu\0") {
+                return;
+            }
+            cpus += 1;
+            let found = match node.prop("riscv,isa-extensions") {
+                Some(list) => list.split(|&c| c == 0).any(|s| s == ext.as_bytes()),
+                None => node.prop("riscv,isa").map_or(false, |isa| {
+                    cstr(isa).split(|&c| c == b'_').skip(1).any(|s| s == ext.as_bytes())
+                }),
+            };
+            if found {
+                have += 1;
+            }
+        });
+        cpus > 0 && have == cpus
+    }
+
+    fn string_at(&self, off: usize) -> &'a [u8] {
+        cstr(&self.strings[off..])
+    }
+}
+
+impl<'a> Node<'a> {
+    /// 读取名为 `name` 的属性值
+    pub fn prop(&self, name: &str) -> Option<&'a [u8]> {
+        let structs = self.fdt.structs;
+        let mut off = self.props;
+        while off + 4 <= structs.len() {
+            match be32(structs, off) {
+                FDT_PROP => {
+                    let len = be32(structs, off + 4) as usize;
+                    let nameoff = be32(structs, off + 8) as usize;
+                    let value = &structs[off + 12..off + 12 + len];
+                    if self.fdt.string_at(nameoff) == name.as_bytes() {
+                        return Some(value);
+                    }
+                    off = align4(off + 12 + len);
+                }
+                FDT_NOP => off += 4,
+                _ => break,
+            }
+        }
+        None
+    }
+
+    /// 读取一个 32 位或 64 位的整数属性
+    pub fn prop_usize(&self, name: &str) -> Option<usize> {
+        let value = self.prop(name)?;
+        match value.len() {
+            4 => Some(be32(value, 0) as usize),
+            8 => Some(be64(value, 0) as usize),
+            _ => None,
+        }
+    }
+
+    /// 节点的 `compatible` 属性是否包含 `compat`
+    pub fn is_compatible(&self, compat: &str) -> bool {
+        match self.prop("compatible") {
+            Some(list) => list
+                .split(|&c| c == 0)
+                .any(|s| s == compat.as_bytes()),
+            None => false,
+        }
+    }
+
+    /// 第 `index` 个 `reg` 区域的 (基址, 大小)，按两个 cell 的地址与大小解析
+    pub fn reg(&self, index: usize) -> Option<(usize, usize)> {
+        let reg = self.prop("reg")?;
+        let off = index * 16;
+        if reg.len() < off + 16 {
+            return None;
+        }
+        Some((be64(reg, off) as usize, be64(reg, off + 8) as usize))
+    }
+
+    /// `interrupts` 属性中的第一个中断号
+    pub fn irq(&self) -> Option<usize> {
+        let irqs = self.prop("interrupts")?;
+        if irqs.len() < 4 {
+            return None;
+        }
+        Some(be32(irqs, 0) as usize)
+    }
+}
+
+/// 返回启动时传入的设备树
+pub fn boot_fdt() -> Option<Fdt<'static>> {
+    let addr = DTB_ADDR.load(Ordering::Relaxed);
+    if addr == 0 {
+        return None;
+    }
+    unsafe { Fdt::from_raw(addr).ok() }
+}
+
+#[inline]
+fn be32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+#[inline]
+fn be64(buf: &[u8], off: usize) -> u64 {
+    ((be32(buf, off) as u64) << 32) | be32(buf, off + 4) as u64
+}
+
+#[inline]
+fn align4(off: usize) -> usize {
+    (off + 3) & !3
+}
+
+fn cstr(buf: &[u8]) -> &[u8] {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    &buf[..len]
+}
+
+/// 宿主机单元测试，手工拼出一棵小设备树
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    struct Builder {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Self { structs: Vec::new(), strings: Vec::new() }
+        }
+
+        fn token(&mut self, t: u32) {
+            self.structs.extend_from_slice(&t.to_be_bytes());
+        }
+
+        fn pad(&mut self) {
+            while self.structs.len() % 4 != 0 {
+                self.structs.push(0);
+            }
+        }
+
+        fn begin(&mut self, name: &str) {
+            self.token(FDT_BEGIN_NODE);
+            self.structs.extend_from_slice(name.as_bytes());
+            self.structs.push(0);
+            self.pad();
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) {
+            let nameoff = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.token(FDT_PROP);
+            self.token(value.len() as u32);
+            self.token(nameoff);
+            self.structs.extend_from_slice(value);
+            self.pad();
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            let off_struct = 40;
+            let off_strings = off_struct + self.structs.len();
+            let total = off_strings + self.strings.len();
+            let mut blob = Vec::new();
+            for field in [
+                FDT_MAGIC, total as u32, off_struct as u32, off_strings as u32,
+                0, 17, 16, 0, self.strings.len() as u32, self.structs.len() as u32,
+            ] {
+                blob.extend_from_slice(&field.to_be_bytes());
+            }
+            blob.extend_from_slice(&self.structs);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut b = Builder::new();
+        b.begin("");
+        b.begin("chosen");
+        b.prop("linux,initrd-start", &0x8800_0000u64.to_be_bytes());
+        b.prop("linux,initrd-end", &0x8820_0000u64.to_be_bytes());
+        b.prop("bootargs", b"console=ttyS0 aslr\0");
+        b.token(FDT_END_NODE);
+        b.begin("soc");
+        b.begin("virtio_mmio@10001000");
+        b.prop("interrupts", &1u32.to_be_bytes());
+        let mut reg = Vec::new();
+        reg.extend_from_slice(&0x1000_1000u64.to_be_bytes());
+        reg.extend_from_slice(&0x1000u64.to_be_bytes());
+        b.prop("reg", &reg);
+        b.prop("compatible", b"virtio,mmio\0");
+        b.token(FDT_END_NODE);
+        b.begin("serial@10000000");
+        b.prop("compatible", b"ns16550a\0");
+        b.token(FDT_END_NODE);
+        b.token(FDT_END_NODE);
+        b.token(FDT_END_NODE);
+        b.finish()
+    }
+
+    #[test]
+    fn reads_initrd_from_chosen() {
+        let blob = sample();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert_eq!(fdt.initrd(), Some((0x8800_0000, 0x8820_0000)));
+    }
+
+    #[test]
+    fn reads_bootargs_from_chosen() {
+        let blob = sample();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert!(fdt.has_bootarg("aslr"));
+        assert!(fdt.has_bootarg("console=ttyS0"));
+        assert!(!fdt.has_bootarg("asl"));
+        assert!(!fdt.has_bootarg(""));
+    }
+
+    #[test]
+    fn finds_compatible_devices() {
+        let blob = sample();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        let mut found = Vec::new();
+        fdt.for_each_compatible("virtio,mmio", &mut |node| {
+            found.push((node.reg(0).unwrap(), node.irq().unwrap(), node.depth));
+        });
+        assert_eq!(found, [((0x1000_1000, 0x1000), 1, 2)]);
+        assert!(fdt.top_node("soc").is_some());
+        assert!(fdt.top_node("virtio_mmio@10001000").is_none());
+    }
+
+    #[test]
+    fn finds_isa_extensions() {
+        let mut b = Builder::new();
+        b.begin("");
+        b.begin("cpus");
+        b.begin("cpu@0");
+        b.prop("device_type", b"cpu\0");
+        b.prop("riscv,isa", b"rv64imafdc_zicsr_sstc\0");
+        b.token(FDT_END_NODE);
+        b.begin("cpu@1");
+        b.prop("device_type", b"cpu\0");
+        b.prop("riscv,isa-extensions", b"i\0m\0sstc\0");
+        b.token(FDT_END_NODE);
+        b.token(FDT_END_NODE);
+        b.token(FDT_END_NODE);
+        let blob = b.finish();
+        let fdt = Fdt::from_bytes(&blob).unwrap();
+        assert!(fdt.cpus_have_extension("sstc"));
+        assert!(!fdt.cpus_have_extension("svpbmt"));
+        // 单字母扩展写在第一个下划线之前，不会被当作多字母扩展
+        assert!(!fdt.cpus_have_extension("rv64imafdc"));
+
+        let blob = sample();
+        assert!(!Fdt::from_bytes(&blob).unwrap().cpus_have_extension("sstc"));
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut blob = sample();
+        blob[0] = 0;
+        assert!(Fdt::from_bytes(&blob).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+//! 块设备抽象
+//!
+//! 缓存层只通过 [`BlockDevice`] 访问磁盘，不依赖具体的驱动实现：
+//! 内核中由 virtio 块设备实现，宿主机测试中由磁盘映像文件实现。
+
+use super::BufData;
+
+/// 以 `BSIZE` 字节为单位读写的块设备
+///
+/// # 安全性
+/// 实现者需自行保证并发读写的正确性，缓存层保证同一块号不会被并发访问。
+pub trait BlockDevice: Sync {
+    /// 将块号为 `blockno` 的磁盘块读入 `data`
+    ///
+    /// # 参数
+    /// - `blockno`: 设备上的逻辑块号
+    /// - `data`: 目标缓冲区，大小为一个块
+    fn read_block(&self, blockno: u32, data: &mut BufData);
+
+    /// 将 `data` 写入块号为 `blockno` 的磁盘块
+    ///
+    /// # 参数
+    /// - `blockno`: 设备上的逻辑块号
+    /// - `data`: 源缓冲区，大小为一个块
+    fn write_block(&self, blockno: u32, data: &BufData);
+}
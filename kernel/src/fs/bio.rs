@@ -2,14 +2,15 @@
 
 use array_macro::array;
 
+use core::cell::UnsafeCell;
 use core::ptr;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{Ordering, AtomicBool};
 
 use crate::sleeplock::{SleepLock, SleepLockGuard};
 use crate::spinlock::SpinLock;
-use crate::driver::virtio_disk::DISK;
 use crate::consts::fs::{NBUF, BSIZE};
+use super::BlockDevice;
 
 pub static BCACHE: Bcache = Bcache::new();
 
@@ -34,16 +35,30 @@ pub struct Bcache {
     /// 每个缓冲块包含块数据和一个睡眠锁（`SleepLock`），
     /// 以支持对缓冲区数据的细粒度同步访问。缓存内容由 `ctrl` 控制字段协调管理。
     bufs: [BufInner; NBUF],
+
+    /// 缓存所服务的块设备，由 `binit` 设置一次，之后只读。
+    device: UnsafeCell<Option<&'static dyn BlockDevice>>,
 }
 
+// `device` 只在启动阶段由 `binit` 写入一次
+unsafe impl Sync for Bcache {}
+
 impl Bcache {
     const fn new() -> Self {
         Self {
             ctrl: SpinLock::new(BufLru::new(), "BufLru"),
             bufs: array![_ => BufInner::new(); NBUF],
+            device: UnsafeCell::new(None),
         }
     }
 
+    /// 返回 `binit` 注册的块设备
+    ///
+    /// 交换区不经过缓存，直接用它读写磁盘，见 [`crate::mm::swap`]。
+    pub fn device(&self) -> &'static dyn BlockDevice {
+        unsafe { (*self.device.get()).expect("bcache: block device not registered") }
+    }
+
     /// 初始化全局缓冲区缓存 `Bcache`。
     ///
     /// # 功能说明
@@ -55,10 +70,12 @@ impl Bcache {
     /// - 获取 `ctrl` 的自旋锁，确保初始化期间的独占访问；
     /// - 设置 `BufLru` 中的 `head` 和 `tail` 指针，分别指向第一个和最后一个缓冲控制块；
     /// - 初始化 LRU 链表中每个节点的 `prev` 和 `next` 指针，形成双向链表结构；
-    /// - 遍历所有 `BufCtrl` 项，设置其 `index` 字段，使其记录自身在线性数组中的位置。
+    /// - 遍历所有 `BufCtrl` 项，设置其 `index` 字段，使其记录自身在线性数组中的位置；
+    /// - 记录缓存所服务的块设备，之后所有的磁盘读写都经由该设备完成。
     ///
     /// # 参数
     /// - `&self`：`Bcache` 的共享引用，表示对全局缓冲区缓存的访问。
+    /// - `device`：缓存所服务的块设备。
     ///
     /// # 返回值
     /// - 无返回值。该函数仅执行初始化逻辑。
@@ -70,28 +87,10 @@ impl Bcache {
     /// # 安全性
     /// - 使用 `SpinLock` 保护对 `BufLru` 的修改，确保在多核或并发环境中初始化过程的原子性与互斥性；
     /// - 使用原始指针构造链表，但仅在初始化阶段使用，逻辑上是安全的，后续操作通过受控路径访问。
-    pub fn binit(&self) {
-        let mut ctrl = self.ctrl.lock();
-        let len = ctrl.inner.len();
-
-        // 初始化 LRU 列表的头部和尾部
-        ctrl.head = &mut ctrl.inner[0];
-        ctrl.tail = &mut ctrl.inner[len-1];
+    pub fn binit(&self, device: &'static dyn BlockDevice) {
+        unsafe { *self.device.get() = Some(device); }
 
-        // 初始化 prev 和 next 字段
-        ctrl.inner[0].prev = ptr::null_mut();
-        ctrl.inner[0].next = &mut ctrl.inner[1];
-        ctrl.inner[len-1].prev = &mut ctrl.inner[len-2];
-        ctrl.inner[len-1].next = ptr::null_mut();
-        for i in 1..(len-1) {
-            ctrl.inner[i].prev = &mut ctrl.inner[i-1];
-            ctrl.inner[i].next = &mut ctrl.inner[i+1];
-        }
-        
-        // 初始化索引
-        ctrl.inner.iter_mut()
-            .enumerate()
-            .for_each(|(i, b)| b.index = i);
+        self.ctrl.lock().init_links();
     }
 
     /// 获取指定设备与块号对应的缓冲块引用。
@@ -170,7 +169,7 @@ impl Bcache {
     /// # 流程解释
     /// - 调用 `bget` 获取目标块的缓冲结构，若命中缓存则直接返回；
     /// - 若该缓冲块的 `valid` 标志为 false，表示当前块数据尚未从磁盘加载；
-    ///   - 调用底层磁盘驱动 `DISK.rw` 执行一次读取；
+    ///   - 调用 `binit` 注册的块设备执行一次读取；
     ///   - 读取完成后设置该块的 `valid` 标志为 true；
     /// - 返回已准备就绪的缓冲块 `Buf` 对象。
     ///
@@ -192,7 +191,8 @@ impl Bcache {
     pub fn bread<'a>(&'a self, dev: u32, blockno: u32) -> Buf<'a> {
         let mut b = self.bget(dev, blockno);
         if !self.bufs[b.index].valid.load(Ordering::Relaxed) {
-            DISK.rw(&mut b, false);
+            let data = unsafe { &mut *b.raw_data_mut() };
+            self.device().read_block(b.blockno, data);
             self.bufs[b.index].valid.store(true, Ordering::Relaxed);
         }
         b
@@ -255,7 +255,8 @@ impl<'a> Buf<'a> {
     }
 
     pub fn bwrite(&mut self) {
-        DISK.rw(self, true);
+        let data = unsafe { &*self.raw_data() };
+        BCACHE.device().write_block(self.blockno, data);
     }
 
     /// 提供指向缓冲区数据的原始常量指针。
@@ -343,6 +344,31 @@ impl BufLru {
         }
     }
 
+    /// 将所有缓冲块按数组顺序串成 LRU 双向链表，并记录各自的索引。
+    /// 只能在缓存投入使用前调用。
+    fn init_links(&mut self) {
+        let len = self.inner.len();
+
+        // 初始化 LRU 列表的头部和尾部
+        self.head = &mut self.inner[0];
+        self.tail = &mut self.inner[len-1];
+
+        // 初始化 prev 和 next 字段
+        self.inner[0].prev = ptr::null_mut();
+        self.inner[0].next = &mut self.inner[1];
+        self.inner[len-1].prev = &mut self.inner[len-2];
+        self.inner[len-1].next = ptr::null_mut();
+        for i in 1..(len-1) {
+            self.inner[i].prev = &mut self.inner[i-1];
+            self.inner[i].next = &mut self.inner[i+1];
+        }
+
+        // 初始化索引
+        self.inner.iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| b.index = i);
+    }
+
     /// 查找是否缓存中已存在指定设备和块号的缓冲块。
     ///
     /// # 功能说明
@@ -596,3 +622,67 @@ impl  BufData {
         Self([0; BSIZE])
     }
 }
+
+/// 宿主机单元测试
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use crate::consts::fs::ROOTDEV;
+    use crate::host;
+
+    /// 链表节点之间以裸指针相连，初始化后不能再移动
+    fn new_lru() -> Box<BufLru> {
+        let mut lru = Box::new(BufLru::new());
+        lru.init_links();
+        lru
+    }
+
+    #[test]
+    fn lru_recycles_from_tail() {
+        let mut lru = new_lru();
+        let (first, _) = lru.recycle(1, 10).unwrap();
+        assert_eq!(first, NBUF - 1);
+        let (second, _) = lru.recycle(1, 11).unwrap();
+        assert_eq!(second, NBUF - 2);
+
+        // 命中缓存时引用计数增加
+        let (index, rc) = lru.find_cached(1, 10).unwrap();
+        assert_eq!(index, first);
+        assert_eq!(unsafe { *rc }, 2);
+        assert!(lru.find_cached(1, 12).is_none());
+    }
+
+    #[test]
+    fn lru_released_buffer_moves_to_head() {
+        let mut lru = new_lru();
+        let (index, _) = lru.recycle(1, 20).unwrap();
+        lru.move_if_no_ref(index);
+        assert!(ptr::eq(lru.head, &lru.inner[index]));
+
+        // 最近释放的缓冲块最后才会被回收
+        for i in 0..NBUF - 1 {
+            let (other, _) = lru.recycle(1, 100 + i as u32).unwrap();
+            assert_ne!(other, index);
+        }
+        let (last, _) = lru.recycle(1, 200).unwrap();
+        assert_eq!(last, index);
+        assert!(lru.recycle(1, 201).is_none());
+    }
+
+    #[test]
+    fn bwrite_reaches_device() {
+        let _fs = host::fs_setup();
+        let blockno = host::TEST_FS_SIZE - 1;
+
+        let mut buf = BCACHE.bread(ROOTDEV, blockno);
+        unsafe { (&mut (*buf.raw_data_mut()).0)[..4].copy_from_slice(b"bio!"); }
+        buf.bwrite();
+        drop(buf);
+
+        // 绕过缓存直接读设备
+        let mut data = BufData::new();
+        BCACHE.device().read_block(blockno, &mut data);
+        assert_eq!(&data.0[..4], b"bio!");
+    }
+}
@@ -5,18 +5,20 @@ use core::cell::UnsafeCell;
 use core::cmp::min;
 use core::convert::TryInto;
 
-use crate::consts::driver::NDEV;
 use crate::consts::fs::{MAXOPBLOCKS, BSIZE};
 use crate::consts::fs::{O_RDONLY, O_WRONLY, O_RDWR, O_CREATE, O_TRUNC};
-use crate::driver::DEVICES;
+use crate::consts::driver::{POLLIN, POLLOUT};
+use crate::driver::chrdev::{chrdev, Device};
 use crate::mm::Address;
 
 use super::{ICACHE, LOG, inode::FileStat};
 use super::{Inode, InodeType};
 
 mod pipe;
+mod timerfd;
 
 pub use pipe::Pipe;
+pub use timerfd::{TimerFd, TFD_TIMER_ABSTIME};
 
 /// 表示内核中的文件抽象结构，构建在 inode 之上。
 ///
@@ -59,7 +61,7 @@ impl File {
     /// 3. 根据 inode 类型判断处理逻辑：
     ///    - 若为 `Directory`，只允许 `O_RDONLY` 打开；
     ///    - 若为 `File`，根据 `O_TRUNC` 标志判断是否截断文件；
-    ///    - 若为 `Device`，检查 major 编号合法性并封装为设备文件；
+    ///    - 若为 `Device`，按主设备号查找已登记的驱动，调用其 `open` 后封装为设备文件；
     /// 4. 构造 `File` 结构体并返回其 `Arc` 包装；
     /// 5. 所有路径在出错时需释放 inode 并结束日志操作。
     ///
@@ -75,7 +77,7 @@ impl File {
     /// - 路径不存在且未指定 `O_CREATE`；
     /// - 创建文件失败（如目录不存在或权限问题）；
     /// - 尝试以非只读方式打开目录；
-    /// - 打开设备文件但 major 编号非法；
+    /// - 打开设备文件但主设备号下没有登记驱动，或驱动拒绝打开；
     /// - 日志事务未正确结束（通过提前 return 路径确保处理）。
     ///
     /// # 安全性
@@ -126,13 +128,20 @@ impl File {
                 inner = FileInner::Regular(FileRegular { offset: UnsafeCell::new(0), inode: Some(inode) });
             },
             InodeType::Device => {
-                let (major, _) = idata.get_devnum();
-                if major as usize >= NDEV {
-                    drop(idata); drop(inode); LOG.end_op();
+                let (major, minor) = idata.get_devnum();
+                let dev = match chrdev(major as usize) {
+                    Some(dev) => dev,
+                    None => {
+                        drop(idata); drop(inode); LOG.end_op();
+                        return None
+                    }
+                };
+                drop(idata);
+                if (dev.open)(minor, flags).is_err() {
+                    drop(inode); LOG.end_op();
                     return None
                 }
-                drop(idata);
-                inner = FileInner::Device(FileDevice { major, inode: Some(inode) });
+                inner = FileInner::Device(FileDevice { dev, minor, inode: Some(inode) });
             }
         }
 
@@ -200,9 +209,9 @@ impl File {
                 }
             },
             FileInner::Device(ref dev) => {
-                let dev_read = DEVICES[dev.major as usize].as_ref().ok_or(())?.read;
-                dev_read(Address::Virtual(addr), count)
+                (dev.dev.read)(dev.minor, Address::Virtual(addr), count)
             },
+            FileInner::TimerFd(ref tfd) => tfd.read(addr, count),
         }
     }
 
@@ -279,9 +288,9 @@ impl File {
                 Ok(count)
             },
             FileInner::Device(ref dev) => {
-                let dev_write = DEVICES[dev.major as usize].as_ref().ok_or(())?.write;
-                dev_write(Address::Virtual(addr), count)
+                (dev.dev.write)(dev.minor, Address::Virtual(addr), count)
             },
+            FileInner::TimerFd(_) => Err(()),
         }
     }
 
@@ -318,7 +327,7 @@ impl File {
     pub fn fstat(&self, stat: &mut FileStat) -> Result<(), ()> {
         let inode: &Inode;
         match self.inner {
-            FileInner::Pipe(_) => return Err(()),
+            FileInner::Pipe(_) | FileInner::TimerFd(_) => return Err(()),
             FileInner::Regular(ref file) => inode = file.inode.as_ref().unwrap(),
             FileInner::Device(ref dev) => inode = dev.inode.as_ref().unwrap(),
         }
@@ -326,6 +335,41 @@ impl File {
         idata.istat(stat);
         Ok(())
     }
+
+    /// 对设备文件执行设备相关的控制命令。
+    ///
+    /// # 参数
+    /// - `cmd`: 命令号，含义由驱动定义；
+    /// - `arg`: 命令参数，通常是用户空间地址或整数。
+    ///
+    /// # 返回值
+    /// - `Ok(n)`：驱动返回的结果；
+    /// - `Err(())`：不是设备文件，或驱动不支持该命令。
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> Result<usize, ()> {
+        match self.inner {
+            FileInner::Device(ref dev) => (dev.dev.ioctl)(dev.minor, cmd, arg),
+            _ => Err(()),
+        }
+    }
+
+    /// 查询文件当前可进行的操作，结果由 `POLLIN`、`POLLOUT` 等位组成。
+    ///
+    /// 普通文件与管道总是报告可读可写，设备文件由驱动决定，timerfd 在有未读的到期次数时可读。
+    pub fn poll(&self) -> u32 {
+        match self.inner {
+            FileInner::Device(ref dev) => (dev.dev.poll)(dev.minor),
+            FileInner::TimerFd(ref tfd) => tfd.poll(),
+            _ => POLLIN | POLLOUT,
+        }
+    }
+
+    /// 文件是 timerfd 时返回它，供 `timerfd_settime`/`timerfd_gettime` 使用
+    pub fn timerfd(&self) -> Option<&TimerFd> {
+        match self.inner {
+            FileInner::TimerFd(ref tfd) => Some(tfd),
+            _ => None,
+        }
+    }
 }
 
 impl Drop for File {
@@ -367,10 +411,12 @@ impl Drop for File {
                 LOG.end_op();
             },
             FileInner::Device(ref mut dev) => {
+                (dev.dev.close)(dev.minor);
                 LOG.begin_op();
                 drop(dev.inode.take());
                 LOG.end_op();
             },
+            FileInner::TimerFd(ref tfd) => tfd.close(),
         }
     }
 }
@@ -389,8 +435,11 @@ enum FileInner {
     /// 常规文件，包含偏移量与 inode，用于磁盘文件的读写。
     Regular(FileRegular),
 
-    /// 设备文件，包含主设备号与 inode，用于通过驱动进行 I/O。
+    /// 设备文件，包含驱动、次设备号与 inode，用于通过驱动进行 I/O。
     Device(FileDevice),
+
+    /// timerfd，定时器到期后可读，没有对应的 inode。
+    TimerFd(TimerFd),
 }
 
 
@@ -421,11 +470,14 @@ struct FileRegular {
 /// 该结构用于抽象和封装对设备节点的访问。
 #[derive(Debug)]
 struct FileDevice {
-    /// 主设备号（major device number），用于在设备表中索引对应的设备驱动。
+    /// 打开时按主设备号（major device number）在字符设备注册表中查到的驱动。
     ///
     /// 在 xv6 中，每种设备（如控制台、磁盘等）都有唯一的主设备号，
     /// 通过该编号调用统一的设备操作接口（如 `read` / `write`）。
-    major: u16,
+    dev: &'static Device,
+
+    /// 次设备号（minor device number），每次操作时交给驱动，用于区分同一驱动下的不同设备。
+    minor: u16,
 
     /// 指向设备对应的 inode 对象。
     ///
@@ -0,0 +1,212 @@
+//! timerfd：定时器到期后可读的文件
+//!
+//! 读取得到自上次读取以来的到期次数（`u64`），没有到期时阻塞。
+//! 定时器回调可能在取消之后仍在另一个硬件线程上运行，因此回调参数带上槽位的代数，
+//! 每次重设或关闭都递增代数，过期的回调什么也不做。
+
+use alloc::sync::Arc;
+use array_macro::array;
+use core::mem::size_of;
+use core::sync::atomic::Ordering;
+
+use crate::consts::driver::POLLIN;
+use crate::consts::fs::NTIMERFD;
+use crate::mm::Address;
+use crate::process::{CPU_MANAGER, PROC_MANAGER};
+use crate::spinlock::SpinLock;
+use crate::time::{self, Itimerspec, Timespec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::timer::{self, TimerId};
+use crate::trap::start_timer;
+
+use super::{File, FileInner};
+
+/// `timerfd_settime` 的标志：`it_value` 是绝对时间
+pub const TFD_TIMER_ABSTIME: usize = 1;
+
+/// 打开的 timerfd，指向全局表中的一个槽位
+#[derive(Debug)]
+pub struct TimerFd {
+    slot: usize,
+}
+
+struct TimerFdSlot {
+    used: bool,
+    /// 每次重设或关闭时递增，用于识别过期的定时器回调
+    generation: usize,
+    clock: usize,
+    timer: Option<TimerId>,
+    /// 自上次读取以来的到期次数
+    expirations: u64,
+}
+
+impl TimerFdSlot {
+    const fn new() -> Self {
+        Self {
+            used: false,
+            generation: 0,
+            clock: CLOCK_MONOTONIC,
+            timer: None,
+            expirations: 0,
+        }
+    }
+
+    /// 读者睡眠的通道
+    fn channel(&self) -> usize {
+        &self.expirations as *const u64 as usize
+    }
+
+    /// 定时器回调的参数，由槽位号与代数组成
+    fn callback_arg(&self, slot: usize) -> usize {
+        self.generation * NTIMERFD + slot
+    }
+
+    /// 剩余时间与周期
+    fn remaining(&self) -> Itimerspec {
+        match self.timer.and_then(timer::get) {
+            Some((deadline, interval)) => Itimerspec {
+                interval: Timespec::from_ns(time::mtime_to_ns(interval)),
+                value: Timespec::from_ns(time::mtime_to_ns(deadline.saturating_sub(time::mtime()).max(1))),
+            },
+            None => Itimerspec::default(),
+        }
+    }
+
+    /// 取消定时器并使尚未运行完的回调失效
+    fn disarm(&mut self) {
+        if let Some(id) = self.timer.take() {
+            timer::cancel(id);
+        }
+        self.generation = self.generation.wrapping_add(1);
+        self.expirations = 0;
+    }
+}
+
+static TIMERFDS: [SpinLock<TimerFdSlot>; NTIMERFD] =
+    array![_ => SpinLock::new(TimerFdSlot::new(), "timerfd"); NTIMERFD];
+
+/// 定时器回调，累加到期次数并唤醒读者
+fn timerfd_expire(arg: usize, count: u64) {
+    let (slot, generation) = (arg % NTIMERFD, arg / NTIMERFD);
+    let mut guard = TIMERFDS[slot].lock();
+    if guard.used && guard.generation == generation {
+        guard.expirations += count;
+        unsafe { PROC_MANAGER.wakeup(guard.channel()); }
+    }
+}
+
+impl TimerFd {
+    /// 创建一个使用时钟 `clock` 的 timerfd，初始未启动
+    ///
+    /// # 返回值
+    /// - `Some(file)`: 只读的 timerfd 文件
+    /// - `None`: 不支持的时钟，或 timerfd 已经用完
+    pub fn create(clock: usize) -> Option<Arc<File>> {
+        if clock != CLOCK_REALTIME && clock != CLOCK_MONOTONIC {
+            return None
+        }
+        let slot = TIMERFDS.iter().position(|slot| {
+            let mut guard = slot.lock();
+            if guard.used {
+                return false
+            }
+            guard.used = true;
+            guard.clock = clock;
+            guard.expirations = 0;
+            true
+        })?;
+
+        let file = Arc::try_new(File {
+            inner: FileInner::TimerFd(TimerFd { slot }),
+            readable: true,
+            writable: false,
+        });
+        if file.is_err() {
+            TIMERFDS[slot].lock().used = false;
+        }
+        file.ok()
+    }
+
+    /// 启动或停止定时器
+    ///
+    /// # 参数
+    /// - `flags`: 0 或 [`TFD_TIMER_ABSTIME`]
+    /// - `new`: `value` 为 0 时停止定时器，否则在 `value` 后到期，此后每隔 `interval` 再次到期
+    ///
+    /// # 返回值
+    /// - `Ok(old)`: 原来的剩余时间与周期
+    /// - `Err(())`: 标志或时间不合法
+    pub fn settime(&self, flags: usize, new: &Itimerspec) -> Result<Itimerspec, ()> {
+        if flags & !TFD_TIMER_ABSTIME != 0 {
+            return Err(())
+        }
+        let value = new.value.to_ns().ok_or(())?;
+        let interval = time::ns_to_mtime(new.interval.to_ns().ok_or(())?);
+
+        let mut guard = TIMERFDS[self.slot].lock();
+        let deadline = if flags & TFD_TIMER_ABSTIME != 0 {
+            time::clock_to_mtime(guard.clock, value)?
+        } else {
+            time::mtime() + time::ns_to_mtime(value)
+        };
+        let old = guard.remaining();
+        guard.disarm();
+        if value > 0 {
+            let arg = guard.callback_arg(self.slot);
+            guard.timer = Some(start_timer(deadline, interval, timerfd_expire, arg));
+        }
+        Ok(old)
+    }
+
+    /// 查询剩余时间与周期，定时器未启动时均为 0
+    pub fn gettime(&self) -> Itimerspec {
+        TIMERFDS[self.slot].lock().remaining()
+    }
+
+    /// 读取到期次数，没有到期时阻塞
+    ///
+    /// # 返回值
+    /// - `Ok(8)`: 已写入 `u64` 的到期次数并清零
+    /// - `Err(())`: 缓冲区不足 8 字节、写入失败或等待期间进程被杀死
+    pub fn read(&self, addr: usize, count: u32) -> Result<u32, ()> {
+        if (count as usize) < size_of::<u64>() {
+            return Err(())
+        }
+        let mut guard = TIMERFDS[self.slot].lock();
+        while guard.expirations == 0 {
+            let process = unsafe { CPU_MANAGER.my_proc() };
+            if process.killed.load(Ordering::Relaxed) {
+                return Err(())
+            }
+            let channel = guard.channel();
+            process.sleep(channel, guard);
+            guard = TIMERFDS[self.slot].lock();
+        }
+        // 写用户内存可能缺页睡眠，先取走到期次数并释放锁
+        let expirations = guard.expirations;
+        guard.expirations = 0;
+        let generation = guard.generation;
+        drop(guard);
+        if Address::Virtual(addr).copy_out(&expirations as *const u64 as *const u8, size_of::<u64>()).is_err() {
+            // 写入失败时把次数还回去，定时器已经重新设置过的除外
+            let mut guard = TIMERFDS[self.slot].lock();
+            if guard.generation == generation {
+                guard.expirations += expirations;
+                unsafe { PROC_MANAGER.wakeup(guard.channel()); }
+            }
+            return Err(())
+        }
+        Ok(size_of::<u64>() as u32)
+    }
+
+    /// 有未读的到期次数时报告 `POLLIN`
+    pub fn poll(&self) -> u32 {
+        if TIMERFDS[self.slot].lock().expirations > 0 { POLLIN } else { 0 }
+    }
+
+    /// 关闭时停止定时器并释放槽位
+    pub fn close(&self) {
+        let mut guard = TIMERFDS[self.slot].lock();
+        guard.disarm();
+        guard.used = false;
+    }
+}
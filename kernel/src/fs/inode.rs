@@ -2,12 +2,15 @@
 
 use array_macro::array;
 
+use alloc::vec::Vec;
 use core::{cmp::min, mem, panic, ptr};
 
-use crate::mm::Address;
+use crate::consts::PAGE_SIZE;
+use crate::mm::{frame, Address, RawPage, RawSinglePage};
 use crate::spinlock::SpinLock;
 use crate::sleeplock::{SleepLock, SleepLockGuard};
 use crate::process::CPU_MANAGER;
+use crate::time::unix_time;
 use crate::consts::fs::{NINODE, BSIZE, NDIRECT, NINDIRECT, MAX_DIR_SIZE, MAX_FILE_SIZE, ROOTDEV, ROOTINUM};
 use super::{BCACHE, BufData, superblock::SUPER_BLOCK, LOG};
 use super::block::{bm_alloc, bm_free, inode_alloc};
@@ -202,6 +205,7 @@ impl InodeCache {
             // 安全性：引用计数为 1，因此这个锁不会阻塞。
             let mut idata = self.data[i].lock();
             if idata.valid.is_none() || idata.dinode.nlink > 0 {
+                idata.drop_pages();
                 idata.valid.take();
                 drop(idata);
                 imeta.refs -= 1;
@@ -640,6 +644,10 @@ pub struct InodeData {
     /// 磁盘 inode 的实际内容副本，包括类型、链接数、文件大小及数据块地址等字段。
     /// 该字段保存的是从磁盘读入的结构体，并可被修改和写回。
     dinode: DiskInode,
+
+    /// 作为可执行文件运行时共享的只读页，元素为 `(文件偏移, 字节数, 物理页)`，
+    /// 见 [`InodeData::shared_page`]。
+    pages: Vec<(u32, u32, usize)>,
 }
 
 
@@ -648,6 +656,7 @@ impl InodeData {
         Self {
             valid: None,
             dinode: DiskInode::new(),
+            pages: Vec::new(),
         }
     }
 
@@ -669,15 +678,17 @@ impl InodeData {
         (self.dinode.major, self.dinode.minor)
     }
 
-    /// 将硬链接数增加 1。
+    /// 将硬链接数增加 1，并更新状态改变时间。
     #[inline]
     pub fn link(&mut self) {
         self.dinode.nlink += 1;
+        self.dinode.ctime = unix_time();
     }
 
-    /// 将硬链接数减少 1。
+    /// 将硬链接数减少 1，并更新状态改变时间。
     pub fn unlink(&mut self) {
         self.dinode.nlink -= 1;
+        self.dinode.ctime = unix_time();
     }
 
     /// 丢弃当前 inode 所有的数据块，并将其大小清零。
@@ -696,7 +707,7 @@ impl InodeData {
     ///     - 遍历数组并释放所有非 0 块；
     ///     - 释放间接块本身；
     ///     - 清除间接块地址；
-    /// 4. 将 inode 的文件大小字段 `size` 设置为 0；
+    /// 4. 将 inode 的文件大小字段 `size` 设置为 0，并更新修改时间与状态改变时间；
     /// 5. 调用 `update()` 将清空后的 inode 写回磁盘。
     ///
     /// # 参数
@@ -740,7 +751,11 @@ impl InodeData {
         }
 
         self.dinode.size = 0;
+        let now = unix_time();
+        self.dinode.mtime = now;
+        self.dinode.ctime = now;
         self.update();
+        self.drop_pages();
     }
 
     /// 将已修改的内存中 inode 信息写回磁盘。
@@ -798,7 +813,8 @@ impl InodeData {
     ///     - 调用 `BCACHE.bread()` 读取该块；
     ///     - 从块数据中按需偏移并读取 `read_count` 字节到 `dst` 所指定的位置；
     ///     - 更新剩余读取长度与目标地址，继续下一块；
-    /// 4. 所有块读取完毕后返回 `Ok(())`。
+    /// 4. 所有块读取完毕后更新内存中的访问时间，返回 `Ok(())`。
+    ///    访问时间不单独写回磁盘，随 inode 下一次 `update()` 一并持久化，避免读操作也要开启日志事务。
     ///
     /// # 参数
     /// - `dst`: 目标地址，表示读取结果要写入的位置，可为用户空间或内核空间地址（通过 [`Address`] 抽象）；
@@ -816,7 +832,7 @@ impl InodeData {
     /// # 安全性
     /// - 使用 `unsafe` 的指针偏移访问磁盘块数据，但该地址由 `BCACHE` 提供，确保在有效内存范围内；
     /// - 所有对目标地址 `dst` 的访问通过安全封装的 [`Address::copy_out`] 实现，调用方需保证地址有效；
-    /// - 函数未修改 inode 状态，因此可安全并发只读调用；
+    /// - 函数只修改内存中的访问时间，调用者需持有 inode 的睡眠锁；
     pub fn iread(&mut self, mut dst: Address, offset: u32, count: u32) -> Result<(), ()> {
         // 检查读取的内容是否在范围内
         let end = offset.checked_add(count).ok_or(())?;
@@ -843,6 +859,7 @@ impl InodeData {
             block_offset = 0;
             read_count = min(BSIZE, count);
         }
+        self.dinode.atime = unix_time();
         Ok(())
     }
 
@@ -945,7 +962,8 @@ impl InodeData {
     ///     - 使用 `Address::copy_in()` 从 `src` 拷贝数据到块缓冲区；
     ///     - 写入后将该块加入日志系统（`LOG.write()`）；
     ///     - 更新剩余写入量、地址偏移；
-    /// 4. 若写入过程扩展了文件大小，则更新 inode 的 `size` 并调用 `update()` 写回磁盘；
+    /// 4. 若写入过程扩展了文件大小，则更新 inode 的 `size`；写入了数据时更新修改时间与状态改变时间，
+    ///    再调用 `update()` 写回磁盘；
     /// 5. 返回成功写入的实际字节数。
     ///
     /// # 参数
@@ -1003,19 +1021,61 @@ impl InodeData {
         if size > self.dinode.size {
             self.dinode.size = size;
         }
+        if size > offset {
+            let now = unix_time();
+            self.dinode.mtime = now;
+            self.dinode.ctime = now;
+            self.drop_pages();
+        }
         self.update();
         Ok(size-offset)
     }
 
+    /// 取得可执行文件从 `offset` 开始的 `count` 字节组成的只读页，其余部分和超出文件末尾的部分为零
+    ///
+    /// # 功能说明
+    /// 同一文件的同一段内容只读入一次：页缓存在 inode 中，运行同一程序的进程映射同一个物理页。
+    /// 返回的物理页多了一个引用，由调用者映射并在解除映射时释放。
+    ///
+    /// # 可能的错误
+    /// 物理页分配失败或读取文件失败
+    ///
+    /// # 安全性
+    /// 调用者不能写入返回的页。文件被写入或截断时缓存作废，已经映射旧内容的进程保留旧页。
+    pub fn shared_page(&mut self, offset: u32, count: u32) -> Result<usize, ()> {
+        debug_assert!(count as usize <= PAGE_SIZE);
+        if let Some(&(_, _, pa)) = self.pages.iter().find(|&&(o, c, _)| o == offset && c == count) {
+            frame::get(pa);
+            return Ok(pa)
+        }
+        self.pages.try_reserve(1).map_err(|_| ())?;
+        let mem = unsafe { RawSinglePage::try_new_zeroed() }.map_err(|_| ())?;
+        if self.try_iread(Address::KernelMut(mem), offset, count).is_err() {
+            unsafe { RawSinglePage::from_raw_and_drop(mem); }
+            return Err(())
+        }
+        let pa = mem as usize;
+        self.pages.push((offset, count, pa));
+        frame::get(pa);
+        Ok(pa)
+    }
+
+    /// 放弃缓存的共享只读页，仍被进程映射的页在它们解除映射后释放
+    fn drop_pages(&mut self) {
+        for (_, _, pa) in self.pages.drain(..) {
+            unsafe { RawSinglePage::from_raw_and_drop(pa as *mut u8); }
+        }
+    }
+
     /// 填充指定的 [`FileStat`] 结构体，以反映当前 inode 的元数据信息。
     ///
     /// # 功能说明
-    /// `istat` 用于获取当前 inode 的状态信息，包括设备号、inode 编号、类型、链接数和文件大小。
+    /// `istat` 用于获取当前 inode 的状态信息，包括设备号、inode 编号、类型、链接数、文件大小与三个时间戳。
     /// 通常用于实现如 `stat` 系统调用或 `fstat` 接口，向用户空间或上层模块报告文件状态。
     ///
     /// # 流程解释
     /// 1. 解包 `valid` 字段，获取该 inode 所在设备号和编号；
-    /// 2. 从 `dinode` 中读取文件类型、硬链接数量和大小；
+    /// 2. 从 `dinode` 中读取文件类型、硬链接数量、大小与时间戳；
     /// 3. 将上述信息写入传入的 `FileStat` 结构体中；
     ///
     /// # 参数
@@ -1038,6 +1098,9 @@ impl InodeData {
         stat.itype = self.dinode.itype;
         stat.nlink = self.dinode.nlink;
         stat.size = self.dinode.size as u64;
+        stat.atime = self.dinode.atime;
+        stat.mtime = self.dinode.mtime;
+        stat.ctime = self.dinode.ctime;
     }
 
     /// 根据数据块逻辑编号返回其在磁盘中的物理块号，如有必要则分配新块。
@@ -1383,6 +1446,7 @@ pub fn locate_inode_offset(inum: u32) -> isize {
 /// 检查 inode 结构体应满足
 pub fn icheck() {
     debug_assert_eq!(mem::align_of::<BufData>() % mem::align_of::<DiskInode>(), 0);
+    debug_assert_eq!(mem::size_of::<DiskInode>(), 128);
 
     // LTODO - 定义类型别名 BlockNo 以替代部分 u32
     debug_assert_eq!(mem::align_of::<BufData>() % mem::align_of::<BlockNo>(), 0);
@@ -1400,7 +1464,7 @@ type BlockNo = u32;
 ///
 /// # 结构体用途
 /// `FileStat` 是对内核中 inode 元信息的抽象表示，通常用于实现系统调用 `stat` 或 `fstat`，
-/// 供用户程序获取文件的基本属性，如设备号、inode 编号、类型、链接数和大小。
+/// 供用户程序获取文件的基本属性，如设备号、inode 编号、类型、链接数、大小和时间戳。
 /// 它是用户空间和内核空间之间传递文件状态的标准结构体。
 #[repr(C)]
 #[derive(Debug)]
@@ -1419,6 +1483,15 @@ pub struct FileStat {
 
     /// 文件的总大小（以字节为单位）。
     size: u64,
+
+    /// 最后访问时间（自 1970-01-01 UTC 起的秒数，下同）。
+    atime: u64,
+
+    /// 最后修改内容的时间。
+    mtime: u64,
+
+    /// 最后改变 inode 状态（内容、链接数等）的时间。
+    ctime: u64,
 }
 
 
@@ -1430,6 +1503,9 @@ impl FileStat {
             itype: InodeType::Empty,
             nlink: 0,
             size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
         }
     }
 }
@@ -1438,7 +1514,8 @@ impl FileStat {
 ///
 /// # 结构体用途
 /// `DiskInode` 是文件系统中每个 inode 在磁盘上的持久化表示，
-/// 存储了文件类型、设备信息、链接计数、文件大小以及数据块地址等内容。
+/// 存储了文件类型、设备信息、链接计数、文件大小、数据块地址以及时间戳等内容。
+/// 大小为 128 字节，需与 `include/fs.h` 中的 `struct dinode` 保持一致。
 /// 它是文件系统的核心结构之一，支持普通文件、目录、设备节点等多种类型的文件。
 ///
 /// 此结构体通常通过块缓存 (`BufData`) 进行加载和修改，并由 `InodeData` 封装为内存中的表示。
@@ -1468,6 +1545,18 @@ pub struct DiskInode {
     /// - 前 `NDIRECT` 项为直接块地址；
     /// - 最后一项为一级间接块地址（若启用）；
     addrs: [u32; NDIRECT + 1],
+
+    /// 最后访问时间（自 1970-01-01 UTC 起的秒数，下同）。
+    atime: u64,
+
+    /// 最后修改内容的时间。
+    mtime: u64,
+
+    /// 最后改变 inode 状态的时间。
+    ctime: u64,
+
+    /// 保留给以后的字段，使结构体大小为 2 的幂。
+    reserved: [u32; 10],
 }
 
 impl DiskInode {
@@ -1479,14 +1568,23 @@ impl DiskInode {
             nlink: 0,
             size: 0,
             addrs: [0; NDIRECT + 1],
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            reserved: [0; 10],
         }
     }
 
-    // 如果 [DiskInode] 是空闲的（即其类型为 [InodeType::Empty]），则通过设置其 itype 来分配它。
+    // 如果 [DiskInode] 是空闲的（即其类型为 [InodeType::Empty]），则通过设置其 itype 来分配它，
+    // 三个时间戳均设为当前时间。
     pub fn try_alloc(&mut self, itype: InodeType) -> Result<(), ()> {
         if self.itype == InodeType::Empty {
             unsafe { ptr::write_bytes(self, 0, 1); }
             self.itype = itype;
+            let now = unix_time();
+            self.atime = now;
+            self.mtime = now;
+            self.ctime = now;
             Ok(())
         } else {
             Err(())
@@ -1529,3 +1627,127 @@ impl DirEntry {
         }
     }
 }
+
+/// 宿主机单元测试，在 `host::ImageDisk` 格式化出的映像上运行
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use crate::host;
+    use alloc::vec::Vec;
+
+    fn create(path: &[u8], itype: InodeType) -> Option<Inode> {
+        LOG.begin_op();
+        let inode = ICACHE.create(path, itype, 0, 0, false);
+        LOG.end_op();
+        inode
+    }
+
+    fn inum_of(path: &[u8]) -> Option<u32> {
+        let inode = ICACHE.namei(path)?;
+        let inum = inode.lock().get_dev_inum().1;
+        LOG.begin_op();
+        drop(inode);
+        LOG.end_op();
+        Some(inum)
+    }
+
+    #[test]
+    fn create_lookup_and_unlink() {
+        let _fs = host::fs_setup();
+
+        let dir = create(b"/hdir\0", InodeType::Directory).unwrap();
+        let file = create(b"/hdir/file\0", InodeType::File).unwrap();
+        let file_inum = file.lock().get_dev_inum().1;
+        LOG.begin_op();
+        drop(file);
+        drop(dir);
+        LOG.end_op();
+
+        assert_eq!(inum_of(b"/hdir/file\0"), Some(file_inum));
+        assert_eq!(inum_of(b"/hdir/./file\0"), Some(file_inum));
+        assert_eq!(inum_of(b"/hdir/../hdir/file\0"), Some(file_inum));
+        assert!(create(b"/hdir/file\0", InodeType::File).is_none());
+
+        LOG.begin_op();
+        let mut name = [0u8; MAX_DIR_SIZE];
+        let dir = ICACHE.namei_parent(b"/hdir/file\0", &mut name).unwrap();
+        let mut idata = dir.lock();
+        assert!(idata.dir_unlink(&name).is_ok());
+        assert!(idata.dir_unlink(&name).is_err());
+        drop(idata);
+        drop(dir);
+        LOG.end_op();
+
+        assert!(inum_of(b"/hdir/file\0").is_none());
+        assert!(inum_of(b"/hdir\0").is_some());
+    }
+
+    #[test]
+    fn write_and_read_through_indirect_block() {
+        let _fs = host::fs_setup();
+
+        let inode = create(b"/hbig\0", InodeType::File).unwrap();
+        let total = (NDIRECT + 4) * BSIZE;
+        let data: Vec<u8> = (0..total).map(|i| (i % 251) as u8).collect();
+
+        // 每个事务最多写入 MAXOPBLOCKS 个块，这里每次写三块
+        let chunk = 3 * BSIZE;
+        for off in (0..total).step_by(chunk) {
+            let count = min(chunk, total - off);
+            LOG.begin_op();
+            let mut idata = inode.lock();
+            idata.iwrite(Address::Kernel(data[off..].as_ptr()), off as u32, count as u32).unwrap();
+            drop(idata);
+            LOG.end_op();
+        }
+
+        let mut back = alloc::vec![0u8; total];
+        let mut idata = inode.lock();
+        idata.iread(Address::KernelMut(back.as_mut_ptr()), 0, total as u32).unwrap();
+        assert_eq!(idata.dinode.size as usize, total);
+        assert_ne!(idata.dinode.addrs[NDIRECT], 0);
+        drop(idata);
+        assert_eq!(data, back);
+
+        LOG.begin_op();
+        drop(inode);
+        LOG.end_op();
+    }
+
+    #[test]
+    fn timestamps_follow_writes() {
+        let _fs = host::fs_setup();
+
+        let before = unix_time();
+        let inode = create(b"/htime\0", InodeType::File).unwrap();
+        let mut idata = inode.lock();
+        assert!(idata.dinode.ctime >= before);
+        assert_eq!(idata.dinode.mtime, idata.dinode.ctime);
+
+        // 回拨时间戳，确认写入与读取会重新设置它们
+        idata.dinode.atime = 1;
+        idata.dinode.mtime = 1;
+        idata.dinode.ctime = 1;
+        let data = [7u8; 16];
+        LOG.begin_op();
+        idata.iwrite(Address::Kernel(data.as_ptr()), 0, data.len() as u32).unwrap();
+        LOG.end_op();
+        assert!(idata.dinode.mtime >= before);
+        assert!(idata.dinode.ctime >= before);
+        assert_eq!(idata.dinode.atime, 1);
+
+        let mut back = [0u8; 16];
+        idata.iread(Address::KernelMut(back.as_mut_ptr()), 0, back.len() as u32).unwrap();
+        assert!(idata.dinode.atime >= before);
+
+        let mut stat = FileStat::uninit();
+        idata.istat(&mut stat);
+        assert_eq!(stat.mtime, idata.dinode.mtime);
+        assert_eq!(stat.size, data.len() as u64);
+        drop(idata);
+
+        LOG.begin_op();
+        drop(inode);
+        LOG.end_op();
+    }
+}
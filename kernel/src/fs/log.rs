@@ -549,3 +549,66 @@ struct LogHeader {
     /// 总共最多可容纳 `LOGSIZE - 1` 个块号，保留一个块用于存放该日志头本身。
     blocknos: [u32; LOGSIZE - 1],
 }
+
+/// 宿主机单元测试
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use crate::consts::fs::ROOTDEV;
+    use crate::host;
+
+    #[test]
+    fn recover_installs_committed_blocks() {
+        let _fs = host::fs_setup();
+        let (start, size) = unsafe { SUPER_BLOCK.read_log() };
+        let target = host::TEST_FS_SIZE - 2;
+
+        // 伪造一个已提交但尚未安装的事务：第一个日志块存放新内容，日志头记录目标块号
+        let mut log_buf = BCACHE.bread(ROOTDEV, start + 1);
+        unsafe { ptr::write_bytes(log_buf.raw_data_mut() as *mut u8, 0x5a, BSIZE); }
+        log_buf.bwrite();
+        drop(log_buf);
+
+        let mut log = Log::uninit();
+        log.start = start;
+        log.size = size;
+        log.dev = ROOTDEV;
+        log.lh.len = 1;
+        log.lh.blocknos[0] = target;
+        log.write_head();
+
+        log.lh.len = 0;
+        log.recover();
+
+        let buf = BCACHE.bread(ROOTDEV, target);
+        let data = unsafe { core::slice::from_raw_parts(buf.raw_data() as *const u8, BSIZE) };
+        assert!(data.iter().all(|&b| b == 0x5a));
+        drop(buf);
+
+        // 恢复完成后日志头被清空，再次恢复不会重复安装
+        log.read_head();
+        assert_eq!(log.lh.len, 0);
+    }
+
+    #[test]
+    fn transaction_commits_on_last_end_op() {
+        let _fs = host::fs_setup();
+        let target = host::TEST_FS_SIZE - 3;
+
+        LOG.begin_op();
+        let mut buf = BCACHE.bread(ROOTDEV, target);
+        unsafe { ptr::write_bytes(buf.raw_data_mut() as *mut u8, 0xc3, BSIZE); }
+        LOG.write(buf);
+        assert_eq!(LOG.lock().lh.len, 1);
+        LOG.end_op();
+
+        let guard = LOG.lock();
+        assert_eq!(guard.lh.len, 0);
+        assert!(!guard.committing);
+        drop(guard);
+
+        let buf = BCACHE.bread(ROOTDEV, target);
+        let data = unsafe { core::slice::from_raw_parts(buf.raw_data() as *const u8, BSIZE) };
+        assert!(data.iter().all(|&b| b == 0xc3));
+    }
+}
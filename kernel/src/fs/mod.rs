@@ -2,30 +2,35 @@
 
 use core::ops::DerefMut;
 
+#[cfg(not(test))]
 mod file;
 mod inode;
 mod log;
 mod bio;
 mod block;
 mod superblock;
+mod bdev;
 
 // TODO - Buf 也可以?
-pub use bio::Buf;
+pub use bio::{Buf, BufData};
 // TODO - 在从 rmain.rs 中移除用法后，可简化为使用 xxx
 pub use bio::BCACHE;
 pub use inode::{ICACHE, Inode, InodeData, InodeType, FileStat};
 pub use log::LOG;
-pub use file::{File, Pipe};
+#[cfg(not(test))]
+pub use file::{File, Pipe, TimerFd, TFD_TIMER_ABSTIME};
+pub use bdev::BlockDevice;
 
 use superblock::SUPER_BLOCK;
 use log::Log;
-use bio::BufData;
 use inode::icheck;
 
 /// 初始化文件系统，读取磁盘超级块信息，并根据需要进行日志恢复
 /// 安全性：必须在系统启动时被调用一次
 pub unsafe fn init(dev: u32) {
     SUPER_BLOCK.init(dev);
+    let (swapstart, nswap) = SUPER_BLOCK.read_swap();
+    crate::mm::swap::init(swapstart, nswap);
     let log_ptr = LOG.lock().deref_mut() as *mut Log;
     log_ptr.as_mut().unwrap().init(dev);
     icheck();
@@ -105,6 +105,15 @@ impl SuperBlock {
         (sb.logstart, sb.nlog)
     }
 
+    /// 读取交换区信息
+    ///
+    /// # 返回值
+    /// 元组`(起始块号, 交换区块数量)`，没有交换区的旧映像中均为 0
+    pub fn read_swap(&self) -> (u32, u32) {
+        let sb = self.read();
+        (sb.swapstart, sb.nswap)
+    }
+
     /// 定位索引节点所在的磁盘块
     ///
     /// # 参数
@@ -174,4 +183,6 @@ struct RawSuperBlock {
     logstart: u32,   // 第一个日志块的块号
     inodestart: u32, // 第一个索引节点块的块号
     bmapstart: u32,  // 第一个位图块的块号
+    swapstart: u32,  // 第一个交换区块的块号，位于文件系统之后
+    nswap: u32,      // 交换区块数量
 }
@@ -0,0 +1,170 @@
+//! 宿主机测试支持
+//!
+//! 在 x86 Linux 上运行 `cargo test` 时，mm 与 fs 模块按原样编译，
+//! 依赖硬件的部分由本目录下的替身代替：
+//! - `printf.rs`、`process.rs`、`time.rs`、`ipi.rs` 通过 `#[path]` 替换同名的内核模块；
+//! - [`Arena`] 用宿主机分配的一段内存充当物理内存；
+//! - [`ImageDisk`] 用磁盘映像文件充当块设备，并能像 mkfs 一样格式化出一个只有根目录的文件系统。
+
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, Once};
+
+use crate::consts::PAGE_SIZE;
+use crate::consts::fs::{BPB, BSIZE, FSMAGIC, LOGSIZE, ROOTDEV, ROOTINUM};
+use crate::fs::{self, BlockDevice, BufData, BCACHE};
+
+/// 宿主机上的一段页对齐内存，充当物理内存
+pub struct Arena {
+    start: *mut u8,
+    layout: Layout,
+}
+
+impl Arena {
+    /// 分配 `size` 字节、按页对齐并清零的内存
+    pub fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, PAGE_SIZE).unwrap();
+        let start = unsafe { alloc::alloc_zeroed(layout) };
+        assert!(!start.is_null(), "arena: host allocation failed");
+        Self { start, layout }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start as usize
+    }
+
+    pub fn end(&self) -> usize {
+        self.start as usize + self.layout.size()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.start, self.layout); }
+    }
+}
+
+/// 由磁盘映像文件支持的块设备
+pub struct ImageDisk {
+    file: Mutex<File>,
+}
+
+/// 磁盘上 inode 结构的大小，与 `DiskInode` 一致
+const DINODE_SIZE: usize = 128;
+/// 每块 inode 数
+const IPB: u32 = (BSIZE / DINODE_SIZE) as u32;
+/// 目录项大小，与 `DirEntry` 一致
+const DIRENT_SIZE: usize = 16;
+
+impl ImageDisk {
+    /// 打开已有的磁盘映像
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// 创建一个共 `size` 块、`ninodes` 个 inode 的文件系统映像，其中只有根目录
+    ///
+    /// # 功能说明
+    /// 布局与 mkfs 相同：引导块 | 超级块 | 日志 | inode 块 | 位图 | 数据块，
+    /// 根目录占用第一个数据块，包含 `.` 与 `..` 两个目录项。
+    pub fn format(path: &Path, size: u32, ninodes: u32) -> io::Result<Self> {
+        let nlog = LOGSIZE as u32;
+        let ninodeblocks = ninodes / IPB + 1;
+        let nbitmap = size / BPB + 1;
+        let logstart = 2;
+        let inodestart = logstart + nlog;
+        let bmapstart = inodestart + ninodeblocks;
+        let nmeta = bmapstart + nbitmap;
+        let nblocks = size - nmeta;
+
+        let mut image = vec![0u8; size as usize * BSIZE];
+
+        // 超级块
+        let sb = [FSMAGIC, size, nblocks, ninodes, nlog, logstart, inodestart, bmapstart];
+        for (i, field) in sb.iter().enumerate() {
+            put_u32(&mut image, BSIZE + i * 4, *field);
+        }
+
+        // 根目录 inode，数据位于第一个数据块
+        let root_block = nmeta;
+        let off = (inodestart + ROOTINUM / IPB) as usize * BSIZE
+            + (ROOTINUM % IPB) as usize * DINODE_SIZE;
+        put_u16(&mut image, off, 1);                          // itype: Directory
+        put_u16(&mut image, off + 6, 1);                      // nlink
+        put_u32(&mut image, off + 8, 2 * DIRENT_SIZE as u32); // size
+        put_u32(&mut image, off + 12, root_block);            // addrs[0]
+
+        // 根目录的 . 与 ..
+        let off = root_block as usize * BSIZE;
+        for (i, name) in [&b"."[..], &b".."[..]].iter().enumerate() {
+            let de = off + i * DIRENT_SIZE;
+            put_u16(&mut image, de, ROOTINUM as u16);
+            image[de + 2..de + 2 + name.len()].copy_from_slice(name);
+        }
+
+        // 位图：元数据块与根目录数据块均已占用
+        for b in 0..=root_block {
+            let byte = bmapstart as usize * BSIZE + (b / 8) as usize;
+            image[byte] |= 1 << (b % 8);
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true).write(true).create(true).truncate(true)
+            .open(path)?;
+        file.write_all(&image)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+}
+
+impl BlockDevice for ImageDisk {
+    fn read_block(&self, blockno: u32, data: &mut BufData) {
+        let buf = unsafe { &mut *(data as *mut BufData as *mut [u8; BSIZE]) };
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(blockno as u64 * BSIZE as u64)).unwrap();
+        file.read_exact(buf).unwrap();
+    }
+
+    fn write_block(&self, blockno: u32, data: &BufData) {
+        let buf = unsafe { &*(data as *const BufData as *const [u8; BSIZE]) };
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(blockno as u64 * BSIZE as u64)).unwrap();
+        file.write_all(buf).unwrap();
+    }
+}
+
+fn put_u16(image: &mut [u8], off: usize, value: u16) {
+    image[off..off + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(image: &mut [u8], off: usize, value: u32) {
+    image[off..off + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// 测试用文件系统映像的块数
+pub const TEST_FS_SIZE: u32 = 2000;
+
+/// 格式化一个临时映像并在其上初始化全局的文件系统（整个测试进程只执行一次），
+/// 返回的守卫使文件系统测试依次执行
+///
+/// 缓存层、日志与 inode 缓存都是全局的，所有文件系统测试共享同一个映像，
+/// 因此各测试应使用互不相同的文件名与块号。
+pub fn fs_setup() -> MutexGuard<'static, ()> {
+    static INIT: Once = Once::new();
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    INIT.call_once(|| {
+        let path = image_path();
+        let disk = ImageDisk::format(&path, TEST_FS_SIZE, 200).expect("format test image");
+        BCACHE.binit(Box::leak(Box::new(disk)));
+        unsafe { fs::init(ROOTDEV); }
+    });
+    // 某个测试失败不应影响其余测试
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn image_path() -> PathBuf {
+    std::env::temp_dir().join(format!("xv6-rust-test-{}.img", std::process::id()))
+}
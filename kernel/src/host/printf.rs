@@ -0,0 +1,44 @@
+//! 宿主机测试环境下的输出宏，直接转发到标准输出
+
+use core::fmt;
+
+/// 核心打印函数（被宏调用）
+pub fn _print(args: fmt::Arguments<'_>) {
+    std::print!("{}", args);
+}
+
+/// 在终端输出一串字符
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::printf::_print(format_args!($($arg)*));
+    };
+}
+
+/// 在终端输出一行字符
+#[macro_export]
+macro_rules! println {
+    () => {$crate::print!("\n")};
+    ($fmt:expr) => {$crate::print!(concat!($fmt, "\n"))};
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::print!(concat!($fmt, "\n"), $($arg)*)
+    };
+}
+
+#[macro_export]
+macro_rules! kinfo {
+    () => {$crate::print!("\n")};
+    ($fmt:expr) => {$crate::print!(concat!($fmt, "\n"))};
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::print!(concat!($fmt, "\n"), $($arg)*)
+    };
+}
+
+#[macro_export]
+macro_rules! kerror {
+    () => {$crate::print!("\n")};
+    ($fmt:expr) => {$crate::print!(concat!($fmt, "\n"))};
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::print!(concat!($fmt, "\n"), $($arg)*)
+    };
+}
@@ -0,0 +1,83 @@
+//! 宿主机测试环境下的进程模块替身
+//!
+//! 只提供 mm 与 fs 用到的接口：每个测试线程视作一个独立的硬件线程并拥有一个进程，
+//! `sleep` 释放锁后让出线程，`wakeup` 不做任何事，等待方在下次被调度时重新检查条件。
+
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::boxed::Box;
+
+use crate::fs::Inode;
+use crate::spinlock::SpinLockGuard;
+
+pub static mut CPU_MANAGER: CpuManager = CpuManager;
+pub static mut PROC_MANAGER: ProcManager = ProcManager;
+
+/// 为每个测试线程分配的“硬件线程”编号
+static NEXT_HART: AtomicUsize = AtomicUsize::new(0);
+
+std::thread_local! {
+    static HART: usize = NEXT_HART.fetch_add(1, Ordering::Relaxed);
+    static PROC: *mut Process = Box::into_raw(Box::new(Process::new()));
+}
+
+pub struct CpuManager;
+
+impl CpuManager {
+    #[inline]
+    pub unsafe fn cpu_id() -> usize {
+        HART.with(|hart| *hart)
+    }
+
+    /// 返回当前测试线程对应的进程
+    pub unsafe fn my_proc(&self) -> &mut Process {
+        PROC.with(|p| &mut **p)
+    }
+}
+
+pub struct ProcManager;
+
+impl ProcManager {
+    pub fn wakeup(&self, _channel: usize) {}
+}
+
+/// 宿主机上没有中断，关中断嵌套计数无需维护
+pub fn push_off() {}
+
+pub fn pop_off() {}
+
+pub struct Process {
+    pub data: UnsafeCell<ProcData>,
+}
+
+impl Process {
+    fn new() -> Self {
+        Self {
+            data: UnsafeCell::new(ProcData { cwd: None }),
+        }
+    }
+
+    pub fn sleep<T>(&self, _channel: usize, guard: SpinLockGuard<'_, T>) {
+        drop(guard);
+        std::thread::yield_now();
+    }
+}
+
+pub struct ProcData {
+    pub cwd: Option<Inode>,
+}
+
+impl ProcData {
+    /// 宿主机上“用户地址”即为测试进程自身的地址
+    pub fn copy_out(&mut self, src: *const u8, dst: usize, count: usize) -> Result<(), ()> {
+        unsafe { ptr::copy(src, dst as *mut u8, count); }
+        Ok(())
+    }
+
+    pub fn copy_in(&mut self, src: usize, dst: *mut u8, count: usize) -> Result<(), ()> {
+        unsafe { ptr::copy(src as *const u8, dst, count); }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! 核间中断（IPI）
+//!
+//! 发送方在目标硬件线程的挂起掩码中登记请求，再写目标的 CLINT MSIP；
+//! 目标的 `machinevec` 把机器模式软件中断转为监督模式软件中断，由 [`handle_ipi`] 处理登记的请求：
+//! - [`IPI_RESCHEDULE`]：运行队列有了新进程，叫醒停在 WFI 中的硬件线程；
+//! - [`IPI_CALL`]：在目标上调用函数，发送方等待所有目标调用完毕，用于 TLB 击落；
+//! - [`IPI_STOP`]：某个硬件线程 panic，其余硬件线程关中断停机。
+
+use array_macro::array;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use crate::consts::NCPU;
+use crate::mm::asid;
+use crate::process::{pop_off, push_off, CpuManager};
+use crate::register::{clint, sstatus};
+use crate::spinlock::SpinLock;
+
+/// 运行队列有了新进程
+pub const IPI_RESCHEDULE: usize = 1 << 0;
+/// 有待调用的函数
+pub const IPI_CALL: usize = 1 << 1;
+/// 停机
+pub const IPI_STOP: usize = 1 << 2;
+
+/// 在其他硬件线程上调用的函数
+pub type IpiFn = fn(usize);
+
+/// 一次函数调用请求，`done` 指向发送方栈上的完成计数
+#[derive(Clone, Copy)]
+struct IpiCall {
+    func: IpiFn,
+    arg: usize,
+    done: *const AtomicUsize,
+}
+
+unsafe impl Send for IpiCall {}
+
+/// 已能处理核间中断的硬件线程的掩码
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+/// 每个硬件线程待处理的请求
+static PENDING: [AtomicUsize; NCPU] = array![_ => AtomicUsize::new(0); NCPU];
+
+/// `CALLS[目标][发送方]`：每个发送方同时至多有一个未完成的调用
+static CALLS: [[SpinLock<Option<IpiCall>>; NCPU]; NCPU] =
+    array![_ => array![_ => SpinLock::new(None, "ipicall"); NCPU]; NCPU];
+
+/// 本硬件线程开始接收核间中断，在设置好 stvec 之后调用
+pub fn init_hart() {
+    let id = unsafe { CpuManager::cpu_id() };
+    ONLINE.fetch_or(1 << id, Ordering::SeqCst);
+}
+
+/// 向 `mask` 中已上线的硬件线程（不含本硬件线程）发送请求 `kind`
+pub fn send(mask: usize, kind: usize) {
+    push_off();
+    let mask = mask & ONLINE.load(Ordering::SeqCst) & !(1 << unsafe { CpuManager::cpu_id() });
+    for hart in (0..NCPU).filter(|&i| mask & 1 << i != 0) {
+        PENDING[hart].fetch_or(kind, Ordering::SeqCst);
+        unsafe { clint::send_soft(hart); }
+    }
+    pop_off();
+}
+
+/// 在 `mask` 中的每个硬件线程上调用 `func(arg)`，等待全部完成后返回
+///
+/// # 功能说明
+/// `mask` 包含本硬件线程时直接调用。等待期间处理发给本硬件线程的调用，
+/// 两个硬件线程互相调用时不会死锁。
+///
+/// # 安全性
+/// 目标在中断关闭时无法响应，调用者不能持有目标可能在关中断时自旋等待的锁（即任何自旋锁）。
+pub fn call(mask: usize, func: IpiFn, arg: usize) {
+    push_off();
+    let id = unsafe { CpuManager::cpu_id() };
+    let targets = mask & ONLINE.load(Ordering::SeqCst) & !(1 << id);
+    let done = AtomicUsize::new(0);
+    for hart in (0..NCPU).filter(|&i| targets & 1 << i != 0) {
+        *CALLS[hart][id].lock() = Some(IpiCall { func, arg, done: &done });
+    }
+    send(targets, IPI_CALL);
+    if mask & 1 << id != 0 {
+        func(arg);
+    }
+    while done.load(Ordering::Acquire) < targets.count_ones() as usize {
+        run_calls(id);
+        core::hint::spin_loop();
+    }
+    pop_off();
+}
+
+/// 执行发给硬件线程 `id` 的函数调用
+fn run_calls(id: usize) {
+    if PENDING[id].fetch_and(!IPI_CALL, Ordering::SeqCst) & IPI_CALL == 0 {
+        return
+    }
+    for slot in CALLS[id].iter() {
+        let call = slot.lock().take();
+        if let Some(call) = call {
+            (call.func)(call.arg);
+            unsafe { &*call.done }.fetch_add(1, Ordering::Release);
+        }
+    }
+}
+
+/// 处理发给本硬件线程的核间中断，由监督模式软件中断的处理程序在清除 SSIP 后调用
+///
+/// # 返回值
+/// 是否有待处理的请求。[`IPI_RESCHEDULE`] 不需要额外处理，
+/// 中断返回后调用者检查运行队列，空闲的硬件线程从 WFI 返回后回到调度循环。
+pub fn handle_ipi() -> bool {
+    let id = unsafe { CpuManager::cpu_id() };
+    let pending = PENDING[id].load(Ordering::SeqCst);
+    if pending & IPI_STOP != 0 {
+        halt();
+    }
+    run_calls(id);
+    PENDING[id].fetch_and(!IPI_RESCHEDULE, Ordering::SeqCst);
+    pending != 0
+}
+
+/// 关中断停机，此后不再响应函数调用
+fn halt() -> ! {
+    sstatus::intr_off();
+    ONLINE.fetch_and(!(1 << unsafe { CpuManager::cpu_id() }), Ordering::SeqCst);
+    loop {
+        unsafe { core::arch::asm!("wfi"); }
+    }
+}
+
+/// 由 panic 的硬件线程调用，让其余硬件线程停机，不等待它们响应
+pub fn stop_others() {
+    send(ONLINE.load(Ordering::SeqCst), IPI_STOP);
+}
+
+/// TLB 击落的范围，位于发送方的栈上
+struct FlushRange {
+    asid: usize,
+    va: usize,
+    count: usize,
+}
+
+fn flush_tlb(arg: usize) {
+    let range = unsafe { &*(arg as *const FlushRange) };
+    asid::flush_local(range.asid, range.va, range.count);
+}
+
+/// 页表 `satp` 从 `va` 开始的 `count` 页的映射被解除或收紧后，
+/// 刷新所有可能缓存了这些表项的硬件线程（可能包括本硬件线程）的 TLB
+///
+/// 返回后被解除映射的物理页才能释放。已注销 ASID 的页表不需要击落，见 [`asid::retire`]。
+/// 调用者不能持有自旋锁，见 [`call`]。
+pub fn tlb_shootdown(satp: usize, va: usize, count: usize) {
+    // 页表项的修改先于读取各硬件线程的登记
+    fence(Ordering::SeqCst);
+    asid::for_each_holder(satp, |asid, harts| {
+        let range = FlushRange { asid, va, count };
+        call(harts, flush_tlb, &range as *const FlushRange as usize);
+    });
+}
+
+/// 单元测试模块
+#[cfg(feature = "unit_test")]
+pub mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use array_macro::array;
+
+    use crate::consts::NCPU;
+    use crate::ktest::TestHarts;
+    use crate::process::CpuManager;
+
+    kernel_test!(call_all, TestHarts::All);
+
+    static HITS: [AtomicUsize; NCPU] = array![_ => AtomicUsize::new(0); NCPU];
+
+    fn hit(_arg: usize) {
+        HITS[unsafe { CpuManager::cpu_id() }].fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// 所有硬件线程同时互相调用
+    ///
+    /// # 测试点
+    /// 1. 等待期间处理发来的调用，互相调用不会死锁
+    /// 2. 返回时每个目标都已执行过调用
+    pub fn call_all() {
+        let online = super::ONLINE.load(Ordering::SeqCst);
+        super::call(online, hit, 0);
+        for hart in (0..NCPU).filter(|&i| online & 1 << i != 0) {
+            assert!(HITS[hart].load(Ordering::SeqCst) > 0, "hart {} missed the call", hart);
+        }
+    }
+}
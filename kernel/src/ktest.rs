@@ -0,0 +1,342 @@
+//! 内核单元测试框架（仅在 `unit_test` 特性下编译）
+//!
+//! 任意模块都可以通过 [`kernel_test!`] 宏声明测试用例，宏会把一个 [`KernelTest`]
+//! 描述符放入链接段 `.ktest_array`，链接脚本用 `ktest_start`/`ktest_end` 标出该段的边界。
+//! 所有硬件线程启动完成后由 [`run_all`] 依次执行这些用例。
+//!
+//! # 输出格式
+//! 每个用例在每个参与的硬件线程上输出一行结果，最后由 0 号硬件线程输出汇总行，
+//! 便于 `grade/gradelib.py` 一类的脚本用正则解析。用例名是去掉 crate 名的模块路径。
+//! 汇总行按用例计数，在多个硬件线程上运行的用例只要有一个硬件线程失败就算失败，
+//! `harts` 是参与测试的硬件线程数：
+//! ```text
+//! ktest: PASS spinlock::tests::smoke hart=0 time_us=12
+//! ktest: FAIL mm::kalloc::tests::alloc_simo hart=1 time_us=40 msg=...
+//! ktest: SKIP some::test hart=0
+//! ktest: summary total=3 passed=1 failed=1 skipped=1 harts=2
+//! ```
+//!
+//! # panic 捕获
+//! 每个用例在独立的测试栈上运行，运行前保存调度上下文。若用例 panic，
+//! panic 处理函数会调用 [`catch_panic`]，记录失败后切换回保存的上下文继续执行后续用例。
+//! 用例 panic 时持有的锁不会被释放，因此该机制只能尽力而为。
+
+use core::fmt::Write;
+use core::mem;
+use core::panic::PanicInfo;
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::consts::{KERNEL_STACK_SIZE, NCPU, NSMP};
+use crate::process::{Context, CpuManager, CPU_MANAGER};
+use crate::register::clint;
+
+/// qemu virt 平台 mtime 的频率为 10MHz，即每微秒 10 个计数
+const MTIME_PER_US: u64 = 10;
+
+/// 失败信息的最大保存长度
+const MSG_LEN: usize = 96;
+
+/// 测试用例运行在哪些硬件线程上
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestHarts {
+    /// 仅在指定编号的硬件线程上运行
+    Hart(usize),
+    /// 在所有参与启动的硬件线程（共 `NSMP` 个）上同时运行
+    All,
+}
+
+/// 测试用例描述符，由 [`kernel_test!`] 生成并放入 `.ktest_array` 段
+#[repr(C)]
+pub struct KernelTest {
+    pub name: &'static str,
+    pub func: fn(),
+    pub harts: TestHarts,
+}
+
+impl KernelTest {
+    fn runs_on(&self, hart: usize) -> bool {
+        match self.harts {
+            TestHarts::Hart(id) => id == hart,
+            TestHarts::All => true,
+        }
+    }
+
+    fn is_skipped(&self) -> bool {
+        match self.harts {
+            TestHarts::Hart(id) => id >= NSMP,
+            TestHarts::All => false,
+        }
+    }
+
+    /// 输出用的用例名，去掉 `module_path!()` 开头的 crate 名
+    fn short_name(&self) -> &'static str {
+        self.name.split_once("::").map_or(self.name, |(_, path)| path)
+    }
+}
+
+/// 声明一个内核测试用例
+///
+/// # 用法
+/// ```
+/// kernel_test!(tests::smoke, TestHarts::Hart(0));
+/// kernel_test!(tests::println_simo, TestHarts::All);
+/// ```
+#[macro_export]
+macro_rules! kernel_test {
+    ($func:path, $harts:expr) => {
+        const _: () = {
+            #[used]
+            #[link_section = ".ktest_array"]
+            static KTEST: $crate::ktest::KernelTest = $crate::ktest::KernelTest {
+                name: concat!(module_path!(), "::", stringify!($func)),
+                func: $func,
+                harts: $harts,
+            };
+        };
+    };
+}
+
+/// 用例在单个硬件线程上的运行结果
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Running,
+    Pass,
+    Fail,
+}
+
+/// 每个硬件线程的测试运行状态
+struct HartState {
+    /// 运行器上下文，用例结束或 panic 后切换回这里
+    runner: Context,
+    /// 用例上下文，入口为 `test_entry`，栈为该硬件线程的测试栈
+    test: Context,
+    current: Option<&'static KernelTest>,
+    outcome: Outcome,
+    msg: MsgBuf,
+}
+
+impl HartState {
+    const fn new() -> Self {
+        Self {
+            runner: Context::new(),
+            test: Context::new(),
+            current: None,
+            outcome: Outcome::Running,
+            msg: MsgBuf::new(),
+        }
+    }
+}
+
+/// 定长的失败信息缓冲区，超出部分被截断
+struct MsgBuf {
+    buf: [u8; MSG_LEN],
+    len: usize,
+}
+
+impl MsgBuf {
+    const fn new() -> Self {
+        Self { buf: [0; MSG_LEN], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("<invalid utf8>")
+    }
+}
+
+impl Write for MsgBuf {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.bytes() {
+            if self.len == MSG_LEN {
+                break;
+            }
+            // 保持单行输出，便于脚本解析
+            self.buf[self.len] = if c == b'\n' { b' ' } else { c };
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+#[repr(C, align(4096))]
+struct TestStack([u8; KERNEL_STACK_SIZE]);
+
+static mut HART_STATES: [HartState; NCPU] = array_macro::array![_ => HartState::new(); NCPU];
+static mut TEST_STACKS: [TestStack; NCPU] = array_macro::array![_ => TestStack([0; KERNEL_STACK_SIZE]); NCPU];
+
+/// 当前用例是否在某个硬件线程上失败，每个用例结束后由 0 号硬件线程计入汇总并清除
+static TEST_FAILED: AtomicBool = AtomicBool::new(false);
+
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// 简单的可重用屏障，等待 `NSMP` 个硬件线程全部到达
+struct Barrier {
+    count: AtomicUsize,
+    generation: AtomicUsize,
+}
+
+impl Barrier {
+    const fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    fn wait(&self) {
+        let gen = self.generation.load(Ordering::Acquire);
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 == NSMP {
+            self.count.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+        } else {
+            while self.generation.load(Ordering::Acquire) == gen {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+static BARRIER: Barrier = Barrier::new();
+
+/// 返回链接段 `.ktest_array` 中的全部测试描述符
+fn registered() -> &'static [KernelTest] {
+    extern "C" {
+        static ktest_start: KernelTest;
+        static ktest_end: KernelTest;
+    }
+    unsafe {
+        let start = &ktest_start as *const KernelTest;
+        let end = &ktest_end as *const KernelTest;
+        let len = (end as usize - start as usize) / mem::size_of::<KernelTest>();
+        slice::from_raw_parts(start, len)
+    }
+}
+
+/// 运行所有已注册的测试用例
+///
+/// # 功能说明
+/// 必须由所有 `NSMP` 个硬件线程在进入调度器前调用。每个用例开始前与结束后
+/// 各硬件线程在屏障处同步，因此 `TestHarts::All` 的用例在各硬件线程上同时开始。
+/// 每个用例结束后由 0 号硬件线程计数一次，全部用例结束后输出汇总行，
+/// 若没有失败还会输出 `all tests pass.`。
+///
+/// # 安全性
+/// 只能在启动阶段调用一次，且调用时不得持有任何锁。
+pub unsafe fn run_all() {
+    let hart = CpuManager::cpu_id();
+    let tests = registered();
+
+    if hart == 0 {
+        println!("ktest: running {} tests on {} harts", tests.len(), NSMP);
+    }
+
+    for test in tests {
+        BARRIER.wait();
+        if test.is_skipped() {
+            if hart == 0 {
+                SKIPPED.fetch_add(1, Ordering::Relaxed);
+                println!("ktest: SKIP {} hart={}", test.short_name(), hart);
+            }
+        } else if test.runs_on(hart) && !run_one(hart, test) {
+            TEST_FAILED.store(true, Ordering::Relaxed);
+        }
+        BARRIER.wait();
+        // 其他硬件线程要在下一个用例开始前的屏障处等待 0 号硬件线程，清除标志不会与它们冲突
+        if hart == 0 && !test.is_skipped() {
+            if TEST_FAILED.swap(false, Ordering::Relaxed) {
+                FAILED.fetch_add(1, Ordering::Relaxed);
+            } else {
+                PASSED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    if hart == 0 {
+        let passed = PASSED.load(Ordering::Relaxed);
+        let failed = FAILED.load(Ordering::Relaxed);
+        let skipped = SKIPPED.load(Ordering::Relaxed);
+        println!(
+            "ktest: summary total={} passed={} failed={} skipped={} harts={}",
+            passed + failed + skipped, passed, failed, skipped, NSMP,
+        );
+        if failed == 0 {
+            println!("all tests pass.");
+        }
+    }
+    BARRIER.wait();
+}
+
+/// 在当前硬件线程的测试栈上运行单个用例，并输出结果行，返回用例是否通过
+unsafe fn run_one(hart: usize, test: &'static KernelTest) -> bool {
+    extern "C" {
+        fn switch(old: *mut Context, new: *mut Context);
+    }
+
+    let state = &mut HART_STATES[hart];
+    let stack_top = TEST_STACKS[hart].0.as_ptr() as usize + KERNEL_STACK_SIZE;
+    state.current = Some(test);
+    state.outcome = Outcome::Running;
+    state.msg.len = 0;
+    state.test.clear();
+    state.test.set_ra(test_entry as usize);
+    state.test.set_sp(stack_top);
+
+    let noff = CPU_MANAGER.my_cpu_mut().noff();
+    let start = clint::read_mtime();
+    switch(&mut state.runner, &mut state.test);
+    let elapsed = (clint::read_mtime() - start) / MTIME_PER_US;
+    // panic 的用例可能遗留未配对的 push_off
+    CPU_MANAGER.my_cpu_mut().set_noff(noff);
+
+    state.current = None;
+    match state.outcome {
+        Outcome::Pass => {
+            println!("ktest: PASS {} hart={} time_us={}", test.short_name(), hart, elapsed);
+            true
+        }
+        _ => {
+            println!(
+                "ktest: FAIL {} hart={} time_us={} msg={}",
+                test.short_name(), hart, elapsed, state.msg.as_str(),
+            );
+            false
+        }
+    }
+}
+
+/// 用例上下文的入口，运行结束后切换回运行器，不会返回
+extern "C" fn test_entry() -> ! {
+    extern "C" {
+        fn switch(old: *mut Context, new: *mut Context);
+    }
+
+    unsafe {
+        let state = &mut HART_STATES[CpuManager::cpu_id()];
+        (state.current.unwrap().func)();
+        state.outcome = Outcome::Pass;
+        switch(&mut state.test, &mut state.runner);
+    }
+    unreachable!("ktest: resumed a finished test");
+}
+
+/// 由 panic 处理函数调用，若当前硬件线程正在运行测试用例则记录失败并返回运行器
+///
+/// # 返回值
+/// 当前硬件线程没有正在运行的用例时直接返回，由 panic 处理函数继续原有流程。
+pub fn catch_panic(info: &PanicInfo<'_>) {
+    extern "C" {
+        fn switch(old: *mut Context, new: *mut Context);
+    }
+
+    unsafe {
+        let state = &mut HART_STATES[CpuManager::cpu_id()];
+        if state.current.is_none() || state.outcome != Outcome::Running {
+            return;
+        }
+        state.outcome = Outcome::Fail;
+        let _ = write!(state.msg, "{}", info);
+        switch(&mut state.test, &mut state.runner);
+    }
+}
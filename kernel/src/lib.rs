@@ -1,6 +1,6 @@
 //！ 引入汇编代码
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(slice_ptr_get)]
 #![feature(get_mut_unchecked)]
 #![feature(allocator_api)]
@@ -16,43 +16,72 @@ extern crate bitflags;
 
 extern crate alloc;
 
+#[cfg(not(test))]
 global_asm!(include_str!("asm/entry.S"));
+#[cfg(not(test))]
 global_asm!(include_str!("asm/kernelvec.S"));
+#[cfg(not(test))]
 global_asm!(include_str!("asm/switch.S"));
+#[cfg(not(test))]
 global_asm!(include_str!("asm/trampoline.S"));
 
+#[cfg(not(test))]
 #[macro_use]
 mod printf;
+#[cfg(all(feature = "unit_test", not(test)))]
+#[macro_use]
+mod ktest;
+
+// 宿主机测试用的 printf 替身要在其他模块之前声明，它们才能使用其中的宏
+#[cfg(test)]
+#[macro_use]
+#[path = "host/printf.rs"]
+mod printf;
 
 mod consts;
+mod exec_args;
+mod fdt;
 mod fs;
 mod mm;
+mod spinlock;
+mod sleeplock;
+mod timer;
+
+#[cfg(not(test))]
 mod process;
+#[cfg(not(test))]
 mod register;
+#[cfg(not(test))]
 mod rmain;
-mod spinlock;
-mod sleeplock;
+#[cfg(not(test))]
 mod start;
+#[cfg(not(test))]
 mod trap;
+#[cfg(not(test))]
 mod driver;
+#[cfg(not(test))]
 mod plic;
+#[cfg(not(test))]
+mod ipi;
+#[cfg(not(test))]
+mod time;
 
-#[cfg(feature = "unit_test")]
-fn test_main_entry() {
-    use proc::cpu_id;
-
-    let cpu_id = unsafe { cpu_id() };
-
-    // 只需要在单个硬件线程 / 内核线程上执行的测试用例
-    if cpu_id == 0 {
-        spinlock::tests::smoke();
-    }
-
-    // 需要在多个硬件线程 / 内核线程上执行的测试用例
-    printf::tests::println_simo();
-    mm::kalloc::tests::alloc_simo();
+// 在宿主机上运行 `cargo test` 时，用 host 目录下的替身模块代替
+// 依赖 RISC-V 硬件的进程与驱动模块，mm 与 fs 的逻辑保持不变
+#[cfg(test)]
+#[path = "host/process.rs"]
+mod process;
+#[cfg(test)]
+#[path = "host/time.rs"]
+mod time;
+#[cfg(test)]
+#[path = "host/ipi.rs"]
+mod ipi;
+#[cfg(test)]
+mod host;
 
-    if cpu_id == 0 {
-        println!("all tests pass.");
-    }
+#[cfg(all(feature = "unit_test", not(test)))]
+fn test_main_entry() {
+    // 各模块通过 kernel_test! 注册的用例由测试框架统一调度
+    unsafe { ktest::run_all(); }
 }
@@ -0,0 +1,181 @@
+//! 地址空间标识符（ASID）
+//!
+//! 进程返回用户态时取得一个 ASID 并写入 satp，TLB 表项按 ASID 区分，切换进程时不必刷新 TLB。
+//! ASID 按代分配：一代内每个编号只分配一次，用完后进入下一代，所有硬件线程在下次返回用户态前
+//! 刷新整个 TLB，进程发现自己的 ASID 属于旧的一代时重新分配。编号 0 留给内核页表。
+//!
+//! 每个进程槽位记录使用的页表、ASID 以及可能缓存了它的表项的硬件线程，
+//! TLB 击落只需刷新这些硬件线程上这个 ASID 的表项。被丢弃的页表（进程退出、exec）
+//! 注销后其 ASID 直到下一代才会再被分配，残留的表项无需击落。
+//!
+//! 硬件不支持 ASID 或启用 `no_asid` 特性时所有进程使用编号 0，每次返回用户态都刷新整个 TLB。
+
+use array_macro::array;
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::consts::{NCPU, NPROC, PAGE_SIZE};
+use crate::process::CpuManager;
+use crate::register::satp;
+use crate::spinlock::SpinLock;
+
+/// satp 中 ASID 字段的位置，字段最宽 16 位
+const SATP_ASID_SHIFT: usize = 44;
+const SATP_ASID_MASK: usize = 0xffff << SATP_ASID_SHIFT;
+
+/// 击落超过这么多页时按 ASID 刷新，而不是逐页刷新
+const FLUSH_PAGES_MAX: usize = 32;
+
+/// 硬件实现的 ASID 位数，0 表示不使用 ASID
+static ASID_BITS: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前的代，槽位中代数与之不同的 ASID 已失效
+static GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// 本代下一个可分配的编号
+static NEXT_ASID: SpinLock<usize> = SpinLock::new(1, "asid");
+
+/// 进入新的一代后置位，硬件线程返回用户态前刷新整个 TLB
+static FLUSH_PENDING: [AtomicBool; NCPU] = array![_ => AtomicBool::new(false); NCPU];
+
+/// 进程槽位的 ASID 状态
+struct AsidSlot {
+    /// 串行化同一进程的多个线程同时返回用户态
+    lock: SpinLock<()>,
+    /// 使用这个 ASID 的页表的 satp（不含 ASID），0 表示没有
+    root: AtomicUsize,
+    /// `(代 << 16) | 编号`，0 表示尚未分配
+    asid: AtomicU64,
+    /// 可能缓存了这个 ASID 的表项的硬件线程的掩码，换页表前只增不减
+    harts: AtomicUsize,
+}
+
+impl AsidSlot {
+    const fn new() -> Self {
+        Self {
+            lock: SpinLock::new((), "asidslot"),
+            root: AtomicUsize::new(0),
+            asid: AtomicU64::new(0),
+            harts: AtomicUsize::new(0),
+        }
+    }
+}
+
+static SLOTS: [AsidSlot; NPROC] = array![_ => AsidSlot::new(); NPROC];
+
+/// 探测硬件实现的 ASID 位数，由每个硬件线程在载入内核页表之后、刷新 TLB 之前调用
+///
+/// # 安全性
+/// 临时改写 satp，需在关中断、尚未运行进程时调用。
+pub unsafe fn init_hart() {
+    if cfg!(feature = "no_asid") {
+        return
+    }
+    // 不支持的 ASID 位写入后读回为 0
+    let kernel = satp::read();
+    satp::write(kernel | SATP_ASID_MASK);
+    let bits = ((satp::read() & SATP_ASID_MASK) >> SATP_ASID_SHIFT).count_ones();
+    satp::write(kernel);
+    ASID_BITS.store(bits as usize, Ordering::Relaxed);
+}
+
+/// 从当前的代中分配一个编号，用完时进入下一代
+fn alloc(bits: usize) -> u64 {
+    let mut next = NEXT_ASID.lock();
+    if *next >= 1 << bits {
+        GENERATION.fetch_add(1, Ordering::SeqCst);
+        for pending in FLUSH_PENDING.iter() {
+            pending.store(true, Ordering::SeqCst);
+        }
+        *next = 1;
+    }
+    let asid = GENERATION.load(Ordering::SeqCst) << 16 | *next as u64;
+    *next += 1;
+    asid
+}
+
+/// 本硬件线程即将以槽位 `slot` 的进程的页表 `root` 返回用户态，返回应写入 satp 的值
+///
+/// # 功能说明
+/// 页表与槽位记录的不同（新进程或 exec 之后）或 ASID 属于旧的一代时分配新的 ASID，
+/// 并把本硬件线程加入可能缓存它的表项的硬件线程中。进入新的一代后第一次返回用户态时刷新整个 TLB。
+///
+/// # 参数
+/// - `slot`: 进程在进程表中的槽位
+/// - `root`: 页表的 satp 值（不含 ASID），即 [`PageTable::as_satp`](super::PageTable::as_satp)
+///
+/// # 安全性
+/// 需关闭中断后调用，返回值只能在本硬件线程使用。
+pub unsafe fn activate(slot: usize, root: usize) -> usize {
+    let id = CpuManager::cpu_id();
+    let bits = ASID_BITS.load(Ordering::Relaxed);
+    if bits == 0 {
+        asm!("sfence.vma zero, zero");
+        return root
+    }
+
+    let slot = &SLOTS[slot];
+    let guard = slot.lock.lock();
+    if slot.root.load(Ordering::SeqCst) != root {
+        slot.asid.store(0, Ordering::SeqCst);
+        slot.harts.store(0, Ordering::SeqCst);
+        slot.root.store(root, Ordering::SeqCst);
+    }
+    let mut asid = slot.asid.load(Ordering::SeqCst);
+    if asid >> 16 != GENERATION.load(Ordering::SeqCst) {
+        asid = alloc(bits);
+        slot.asid.store(asid, Ordering::SeqCst);
+    }
+    // 先登记再使用：击落时没有登记的硬件线程一定还没有缓存这个 ASID 的表项
+    slot.harts.fetch_or(1 << id, Ordering::SeqCst);
+    drop(guard);
+
+    if FLUSH_PENDING[id].swap(false, Ordering::SeqCst) {
+        asm!("sfence.vma zero, zero");
+    }
+    root | (asid as usize & 0xffff) << SATP_ASID_SHIFT
+}
+
+/// 注销页表 `root` 的 ASID，在丢弃页表之前调用
+///
+/// 注销后解除映射不再需要击落：这个 ASID 的残留表项要到下一代才可能被用到，
+/// 而进入下一代时所有硬件线程都会刷新 TLB。
+pub fn retire(root: usize) {
+    for slot in SLOTS.iter().filter(|slot| slot.root.load(Ordering::Relaxed) == root) {
+        let _guard = slot.lock.lock();
+        if slot.root.load(Ordering::SeqCst) == root {
+            slot.root.store(0, Ordering::SeqCst);
+            slot.asid.store(0, Ordering::SeqCst);
+            slot.harts.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// 对使用页表 `root` 的每个 ASID 调用 `f(编号, 可能缓存了它的表项的硬件线程)`
+///
+/// 不使用 ASID 时，所有正在运行的硬件线程都可能缓存了表项。
+pub fn for_each_holder(root: usize, mut f: impl FnMut(usize, usize)) {
+    if ASID_BITS.load(Ordering::Relaxed) == 0 {
+        f(0, (1 << NCPU) - 1);
+        return
+    }
+    for slot in SLOTS.iter().filter(|slot| slot.root.load(Ordering::SeqCst) == root) {
+        let asid = slot.asid.load(Ordering::SeqCst);
+        if asid != 0 {
+            f(asid as usize & 0xffff, slot.harts.load(Ordering::SeqCst));
+        }
+    }
+}
+
+/// 刷新本硬件线程上 ASID `asid` 从 `va` 开始 `count` 页的表项，页数较多时刷新这个 ASID 的全部表项
+pub fn flush_local(asid: usize, va: usize, count: usize) {
+    unsafe {
+        if count > FLUSH_PAGES_MAX {
+            asm!("sfence.vma zero, {}", in(reg) asid);
+        } else {
+            for page in (va..va + count * PAGE_SIZE).step_by(PAGE_SIZE) {
+                asm!("sfence.vma {}, {}", in(reg) page, in(reg) asid);
+            }
+        }
+    }
+}
@@ -0,0 +1,478 @@
+//! 物理页帧分配器
+//!
+//! 管理从内核镜像末尾到 `PHYSTOP` 的全部物理内存，按伙伴算法以 2 的幂个页为单位分配，
+//! 每块按自身大小对齐（页号从按最大块对齐的基址起算）。
+//!
+//! 每个页帧有一项元数据 [`Frame`]，记录引用计数、标志与用途。块的首页带 [`FRAME_FREE`]
+//! 或 [`FRAME_HEAD`] 标志，释放时只需检查首页的标志即可发现重复释放。
+//! 内核镜像、元数据数组、initrd 与设备树所占的页帧标记为保留，永不分配。
+//!
+//! 内核堆（[`KernelHeap`](super::kalloc::KernelHeap)）按需从这里取得 slab 与小对象的分配区，
+//! 页大小及以上的分配则直接转到这里，页表、用户页、内核栈与陷阱帧都经由后者取得。
+
+use core::mem::size_of;
+use core::ptr;
+
+use crate::consts::PAGE_SIZE;
+use crate::spinlock::SpinLock;
+use super::list::List;
+
+/// 最大块为 2^MAX_ORDER 页（4 MiB）
+pub const MAX_ORDER: usize = 10;
+
+/// 空闲块的首页，位于 `order` 阶空闲链表中
+pub const FRAME_FREE: u8 = 1 << 0;
+/// 已分配块的首页
+pub const FRAME_HEAD: u8 = 1 << 1;
+
+/// 页帧的用途
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOwner {
+    /// 空闲或属于某个块的非首页
+    Free,
+    /// 内核镜像、元数据等，永不分配
+    Reserved,
+    /// 内核堆的小对象分配区
+    Arena,
+    /// slab 分配器的一个 slab
+    Slab,
+    /// 直接分配的页：页表、用户页、内核栈等
+    Page,
+}
+
+/// 每个页帧的元数据，除 `owner` 外只在块的首页有意义
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Frame {
+    refcount: u32,
+    order: u8,
+    flags: u8,
+    owner: FrameOwner,
+}
+
+impl Frame {
+    const fn reserved() -> Self {
+        Self { refcount: 0, order: 0, flags: 0, owner: FrameOwner::Reserved }
+    }
+}
+
+/// 伙伴算法页帧分配器
+///
+/// 空闲链表的表头位于结构体内，初始化之后不能再移动。
+pub struct FrameAllocator {
+    /// 0 号页帧的物理地址，按最大块对齐
+    base: usize,
+    /// 元数据数组，每个页帧一项
+    frames: *mut Frame,
+    npages: usize,
+    /// `free[k]` 链接所有 k 阶空闲块
+    free: [List; MAX_ORDER + 1],
+    nfree: usize,
+    total: usize,
+    /// `sbrk` 承诺给进程、还没有分配物理页的页数，见 [`commit`](Self::commit)
+    committed: usize,
+    initialized: bool,
+}
+
+// 因为 *mut Frame 不是 Send
+unsafe impl Send for FrameAllocator {}
+
+impl FrameAllocator {
+    pub const fn uninit() -> Self {
+        const EMPTY: List = List::empty();
+        Self {
+            base: 0,
+            frames: ptr::null_mut(),
+            npages: 0,
+            free: [EMPTY; MAX_ORDER + 1],
+            nfree: 0,
+            total: 0,
+            committed: 0,
+            initialized: false,
+        }
+    }
+
+    /// 管理物理内存 `[start, end)`，其中落在 `reserved` 各区间内的页帧不参与分配
+    ///
+    /// # 功能说明
+    /// 元数据数组放在 `start` 处，覆盖从按最大块向下对齐的基址到 `end` 的所有页帧，
+    /// 基址到元数据末尾之间的页帧标记为保留。
+    ///
+    /// # 安全性
+    /// `[start, end)` 必须是可直接访问且未被其他代码使用的内存，只能初始化一次。
+    pub unsafe fn init(&mut self, start: usize, end: usize, reserved: &[(usize, usize)]) {
+        if self.initialized {
+            panic!("frame allocator: init twice");
+        }
+        for list in self.free.iter_mut() {
+            list.init();
+        }
+
+        let end = end & !(PAGE_SIZE - 1);
+        self.base = start & !((PAGE_SIZE << MAX_ORDER) - 1);
+        self.npages = (end - self.base) / PAGE_SIZE;
+        self.frames = round_up(start, size_of::<Frame>()) as *mut Frame;
+        for i in 0..self.npages {
+            ptr::write(self.frames.add(i), Frame::reserved());
+        }
+        let meta_end = round_up(self.frames.add(self.npages) as usize, PAGE_SIZE);
+
+        for pa in (meta_end..end).step_by(PAGE_SIZE) {
+            if reserved.iter().any(|&(lo, hi)| pa + PAGE_SIZE > lo && pa < hi) {
+                continue;
+            }
+            self.total += 1;
+            self.release(self.pfn(pa), 0);
+        }
+        self.initialized = true;
+    }
+
+    /// 分配 2^`order` 个连续的页帧，返回首页的物理地址，内容未初始化
+    ///
+    /// # 可能的错误
+    /// 没有足够大的空闲块
+    pub fn alloc(&mut self, order: usize, owner: FrameOwner) -> Result<usize, ()> {
+        if order > MAX_ORDER {
+            return Err(());
+        }
+        let mut k = (order..=MAX_ORDER).find(|&k| !self.free[k].is_empty()).ok_or(())?;
+        let pa = unsafe { self.free[k].pop() };
+        let pfn = self.pfn(pa);
+
+        // 把多余的后半块逐级放回空闲链表
+        while k > order {
+            k -= 1;
+            let buddy = pfn + (1 << k);
+            self.frame_mut(buddy).order = k as u8;
+            self.frame_mut(buddy).flags = FRAME_FREE;
+            let addr = self.addr(buddy);
+            unsafe { self.free[k].push(addr); }
+        }
+
+        *self.frame_mut(pfn) = Frame { refcount: 1, order: order as u8, flags: FRAME_HEAD, owner };
+        self.nfree -= 1 << order;
+        Ok(pa)
+    }
+
+    /// 放弃 `alloc` 返回的块 `pa` 的一个引用，引用计数归零时释放
+    ///
+    /// # Panics
+    /// `pa` 不是已分配块的首页（包括重复释放），或 `order` 与分配时不同
+    pub fn free(&mut self, pa: usize, order: usize) {
+        let pfn = self.head(pa);
+        let frame = self.frame_mut(pfn);
+        if frame.order as usize != order {
+            panic!("frame allocator: free {:#x} with order {}, allocated with order {}", pa, order, frame.order);
+        }
+        frame.refcount -= 1;
+        if frame.refcount == 0 {
+            self.release(pfn, order);
+        }
+    }
+
+    /// 为已分配的块 `pa` 增加一个引用，之后需要多一次 [`free`](Self::free) 才会真正释放
+    pub fn get(&mut self, pa: usize) {
+        let pfn = self.head(pa);
+        self.frame_mut(pfn).refcount += 1;
+    }
+
+    /// 已分配的块 `pa` 的引用计数
+    pub fn refcount(&self, pa: usize) -> usize {
+        self.frame(self.head(pa)).refcount as usize
+    }
+
+    /// 页帧 `pa` 的用途
+    pub fn owner(&self, pa: usize) -> FrameOwner {
+        self.frame(self.pfn(pa)).owner
+    }
+
+    /// 空闲页帧数
+    pub fn free_pages(&self) -> usize {
+        self.nfree
+    }
+
+    /// 可分配的页帧总数
+    pub fn total_pages(&self) -> usize {
+        self.total
+    }
+
+    /// 为按需分配的 `n` 页预留空闲页帧，已承诺的页数不能超过空闲页帧数
+    ///
+    /// 预留不会真正分配页帧，只用来让 `sbrk` 在内存不足时失败，
+    /// 而不是等到缺页时再由 OOM killer 处理。
+    ///
+    /// # 可能的错误
+    /// 空闲页帧不足以兑现已有的承诺和这 `n` 页
+    pub fn commit(&mut self, n: usize) -> Result<(), ()> {
+        match self.committed.checked_add(n) {
+            Some(committed) if committed <= self.nfree => {
+                self.committed = committed;
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// 取消 [`commit`](Self::commit) 预留的 `n` 页：这些页已经分配了物理页，或者已经不在进程中
+    pub fn uncommit(&mut self, n: usize) {
+        debug_assert!(n <= self.committed, "frame allocator: uncommit {} of {} pages", n, self.committed);
+        self.committed = self.committed.saturating_sub(n);
+    }
+
+    /// 已承诺、还没有分配物理页的页数
+    pub fn committed_pages(&self) -> usize {
+        self.committed
+    }
+
+    /// 把以 `pfn` 开头的 `order` 阶块放回空闲链表，并与空闲的伙伴逐级合并
+    fn release(&mut self, mut pfn: usize, mut order: usize) {
+        self.nfree += 1 << order;
+        *self.frame_mut(pfn) = Frame { refcount: 0, order: 0, flags: 0, owner: FrameOwner::Free };
+        while order < MAX_ORDER {
+            let buddy = pfn ^ (1 << order);
+            if buddy >= self.npages {
+                break;
+            }
+            let frame = self.frame(buddy);
+            if frame.flags & FRAME_FREE == 0 || frame.order as usize != order {
+                break;
+            }
+            let addr = self.addr(buddy);
+            unsafe { (addr as *mut List).as_mut().unwrap().remove(); }
+            self.frame_mut(buddy).flags = 0;
+            pfn = pfn.min(buddy);
+            order += 1;
+        }
+        *self.frame_mut(pfn) = Frame { refcount: 0, order: order as u8, flags: FRAME_FREE, owner: FrameOwner::Free };
+        let addr = self.addr(pfn);
+        unsafe { self.free[order].push(addr); }
+    }
+
+    /// 检查 `pa` 是已分配块的首页，返回其页号
+    fn head(&self, pa: usize) -> usize {
+        let pfn = self.pfn(pa);
+        if pa % PAGE_SIZE != 0 || self.frame(pfn).flags & FRAME_HEAD == 0 {
+            panic!("frame allocator: {:#x} is not allocated (double free?)", pa);
+        }
+        pfn
+    }
+
+    fn pfn(&self, pa: usize) -> usize {
+        if pa < self.base || pa >= self.addr(self.npages) {
+            panic!("frame allocator: {:#x} out of range", pa);
+        }
+        (pa - self.base) / PAGE_SIZE
+    }
+
+    #[inline]
+    fn addr(&self, pfn: usize) -> usize {
+        self.base + pfn * PAGE_SIZE
+    }
+
+    #[inline]
+    fn frame(&self, pfn: usize) -> &Frame {
+        unsafe { &*self.frames.add(pfn) }
+    }
+
+    #[inline]
+    fn frame_mut(&mut self, pfn: usize) -> &mut Frame {
+        unsafe { &mut *self.frames.add(pfn) }
+    }
+}
+
+/// 全局页帧分配器
+pub static FRAMES: SpinLock<FrameAllocator> = SpinLock::new(FrameAllocator::uninit(), "frames");
+
+/// 把内核镜像之后到 `PHYSTOP` 的物理内存交给页帧分配器
+///
+/// 由 0 号核心在启动早期、任何堆分配之前调用。initrd 与设备树若落在这段内存中则保留。
+///
+/// # 安全性
+/// 只能调用一次。
+#[cfg(not(test))]
+pub unsafe fn init() {
+    use core::sync::atomic::Ordering;
+    use crate::consts::PHYSTOP;
+    use crate::fdt::{boot_fdt, DTB_ADDR};
+
+    extern "C" {
+        fn end();
+    }
+    let start = end as usize;
+    let stop = usize::from(PHYSTOP);
+
+    let mut reserved = [(0, 0); 2];
+    if let Some(initrd) = boot_fdt().and_then(|fdt| fdt.initrd()) {
+        reserved[0] = initrd;
+    }
+    let dtb = DTB_ADDR.load(Ordering::Relaxed);
+    if dtb != 0 {
+        reserved[1] = (dtb, dtb + u32::from_be(*((dtb + 4) as *const u32)) as usize);
+    }
+
+    let mut frames = FRAMES.lock();
+    frames.init(start, stop, &reserved);
+    #[cfg(feature = "verbose_init_info")]
+    println!("frames: {} pages free in [{:#x}, {:#x})", frames.free_pages(), start, stop);
+}
+
+/// 容纳 `size` 字节所需的块的阶数
+pub fn order_of(size: usize) -> usize {
+    size.div_ceil(PAGE_SIZE).max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+/// 为已分配的块 `pa` 增加一个引用，见 [`FrameAllocator::get`]
+pub fn get(pa: usize) {
+    FRAMES.lock().get(pa)
+}
+
+/// 放弃 [`get`] 为单页 `pa` 增加的引用，见 [`FrameAllocator::free`]
+pub fn put(pa: usize) {
+    FRAMES.lock().free(pa, 0)
+}
+
+/// 已分配的块 `pa` 的引用计数
+pub fn refcount(pa: usize) -> usize {
+    FRAMES.lock().refcount(pa)
+}
+
+/// 为按需分配的 `n` 页预留空闲页帧，见 [`FrameAllocator::commit`]
+pub fn commit(n: usize) -> Result<(), ()> {
+    FRAMES.lock().commit(n)
+}
+
+/// 取消预留的 `n` 页，见 [`FrameAllocator::uncommit`]
+pub fn uncommit(n: usize) {
+    if n != 0 {
+        FRAMES.lock().uncommit(n)
+    }
+}
+
+#[inline]
+fn round_up(n: usize, size: usize) -> usize {
+    n.div_ceil(size) * size
+}
+
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use crate::host::Arena;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    const ARENA_SIZE: usize = 8 * 1024 * 1024;
+
+    fn new_frames(arena: &Arena, reserved: &[(usize, usize)]) -> Box<FrameAllocator> {
+        let mut frames = Box::new(FrameAllocator::uninit());
+        unsafe { frames.init(arena.start(), arena.end(), reserved); }
+        frames
+    }
+
+    #[test]
+    fn blocks_are_aligned_to_their_size() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut frames = new_frames(&arena, &[]);
+
+        for order in 0..=4 {
+            let pa = frames.alloc(order, FrameOwner::Page).unwrap();
+            assert_eq!((pa - frames.base) % (PAGE_SIZE << order), 0);
+            assert!(pa >= arena.start() && pa + (PAGE_SIZE << order) <= arena.end());
+            assert_eq!(frames.owner(pa), FrameOwner::Page);
+            frames.free(pa, order);
+        }
+    }
+
+    #[test]
+    fn exhaust_then_merge_back() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut frames = new_frames(&arena, &[]);
+        let total = frames.total_pages();
+        assert_eq!(frames.free_pages(), total);
+
+        let mut pages = Vec::new();
+        while let Ok(pa) = frames.alloc(0, FrameOwner::Page) {
+            pages.push(pa);
+        }
+        assert_eq!(pages.len(), total);
+        assert_eq!(frames.free_pages(), 0);
+
+        // 全部释放后伙伴应合并，可以再次分配出大块
+        for pa in pages {
+            frames.free(pa, 0);
+        }
+        assert_eq!(frames.free_pages(), total);
+        assert!(frames.alloc(9, FrameOwner::Arena).is_ok());
+    }
+
+    #[test]
+    fn reserved_range_is_never_allocated() {
+        let arena = Arena::new(ARENA_SIZE);
+        let hole = (arena.start() + ARENA_SIZE / 2, arena.start() + ARENA_SIZE / 2 + 3 * PAGE_SIZE + 1);
+        let mut frames = new_frames(&arena, &[hole]);
+
+        while let Ok(pa) = frames.alloc(0, FrameOwner::Page) {
+            assert!(pa + PAGE_SIZE <= hole.0 || pa >= hole.1, "allocated reserved frame {:#x}", pa);
+        }
+        assert_eq!(frames.owner(hole.0), FrameOwner::Reserved);
+    }
+
+    #[test]
+    fn refcount_delays_free() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut frames = new_frames(&arena, &[]);
+        let free = frames.free_pages();
+
+        let pa = frames.alloc(1, FrameOwner::Page).unwrap();
+        frames.get(pa);
+        assert_eq!(frames.refcount(pa), 2);
+        frames.free(pa, 1);
+        assert_eq!(frames.free_pages(), free - 2);
+        frames.free(pa, 1);
+        assert_eq!(frames.free_pages(), free);
+    }
+
+    #[test]
+    fn commit_is_bounded_by_free_pages() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut frames = new_frames(&arena, &[]);
+        let free = frames.free_pages();
+
+        assert!(frames.commit(free - 1).is_ok());
+        assert!(frames.commit(2).is_err());
+        assert_eq!(frames.committed_pages(), free - 1);
+
+        // 承诺的页兑现之后空闲页帧减少，承诺也相应取消
+        let pa = frames.alloc(0, FrameOwner::Page).unwrap();
+        frames.uncommit(1);
+        assert!(frames.commit(2).is_err());
+        assert!(frames.commit(1).is_ok());
+
+        // 页帧释放回来之后又可以承诺
+        frames.free(pa, 0);
+        assert!(frames.commit(1).is_ok());
+        assert!(frames.commit(1).is_err());
+        frames.uncommit(free);
+        assert_eq!(frames.committed_pages(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free_is_caught() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut frames = new_frames(&arena, &[]);
+
+        let pa = frames.alloc(0, FrameOwner::Page).unwrap();
+        frames.free(pa, 0);
+        frames.free(pa, 0);
+    }
+
+    #[test]
+    fn order_of_sizes() {
+        assert_eq!(order_of(1), 0);
+        assert_eq!(order_of(PAGE_SIZE), 0);
+        assert_eq!(order_of(PAGE_SIZE + 1), 1);
+        assert_eq!(order_of(3 * PAGE_SIZE), 2);
+        assert_eq!(order_of(4 * PAGE_SIZE), 2);
+    }
+}
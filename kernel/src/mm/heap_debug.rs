@@ -0,0 +1,350 @@
+//! 内核堆调试模式，由 `heap_debug` 特性开启
+//!
+//! 小于一页的分配在底层分配器中多占一些空间，布局为：
+//!
+//! ```text
+//! | Header | 前红区 | 对象（size 字节） | 后红区 |
+//! ```
+//!
+//! - 新分配的对象填充 [`ALLOC_BYTE`]，红区填充 [`REDZONE_BYTE`]；
+//! - 释放时检查头部的魔数（发现重复释放与野指针）和两侧红区（发现越界写），
+//!   然后把对象与红区填充为 [`POISON_BYTE`]，放入隔离队列而不立即归还；
+//! - 隔离队列满了以后最早释放的对象出队，检查毒化的内容未被改写（发现释放后写）后才真正释放，
+//!   所以被释放的内存在重新分配之前都经过检查；
+//! - 活跃对象链入链表，头部记录分配序号与调用链上最近的几个返回地址，
+//!   [`KernelHeap::debug_dump`] 打印某个标记之后分配、至今未释放的对象，用于查找泄漏。
+//!
+//! 页大小及以上或按页对齐的分配（页表、用户页、内核栈等）直接取自页帧分配器，
+//! 页帧分配器本身能发现重复释放，这里不再包装。
+
+use core::alloc::Layout;
+use core::mem::{align_of, size_of};
+use core::ptr;
+
+use crate::consts::PAGE_SIZE;
+use super::kalloc::KernelHeap;
+use super::list::List;
+
+/// 对象两侧红区的最小字节数
+const REDZONE: usize = 16;
+/// 红区的填充
+pub const REDZONE_BYTE: u8 = 0xfd;
+/// 新分配对象的填充，便于发现使用未初始化的内存
+pub const ALLOC_BYTE: u8 = 0xcd;
+/// 已释放对象的填充
+pub const POISON_BYTE: u8 = 0x6b;
+
+const MAGIC_LIVE: usize = 0xa110_c8ed;
+const MAGIC_FREED: usize = 0xdead_f7ee;
+
+/// 记录的返回地址个数
+const DEPTH: usize = 4;
+/// 隔离队列的长度
+const QUARANTINE_MAX: usize = 512;
+
+/// 对象头部，位于底层分配的起始处
+#[repr(C)]
+struct Header {
+    /// 活跃时链入 [`DebugState::live`]，释放后链入 [`DebugState::quarantine`]
+    link: List,
+    magic: usize,
+    size: usize,
+    align: usize,
+    /// 分配序号，从 1 开始
+    seq: usize,
+    callers: [usize; DEPTH],
+}
+
+/// 调试模式的状态，由 [`KernelHeap`] 持有
+pub struct DebugState {
+    live: List,
+    quarantine: List,
+    initialized: bool,
+    nquarantine: usize,
+    nlive: usize,
+    live_bytes: usize,
+    seq: usize,
+    /// [`KernelHeap::debug_mark`] 记下的序号
+    mark: usize,
+}
+
+// 因为 List 中的 *mut List 不是 Send
+unsafe impl Send for DebugState {}
+
+impl DebugState {
+    pub const fn new() -> Self {
+        Self {
+            live: List::empty(),
+            quarantine: List::empty(),
+            initialized: false,
+            nquarantine: 0,
+            nlive: 0,
+            live_bytes: 0,
+            seq: 0,
+            mark: 0,
+        }
+    }
+
+    fn init(&mut self) {
+        if !self.initialized {
+            self.live.init();
+            self.quarantine.init();
+            self.initialized = true;
+        }
+    }
+}
+
+/// 是否绕过调试包装
+#[inline]
+pub fn bypass(layout: Layout) -> bool {
+    layout.size() >= PAGE_SIZE || layout.align() >= PAGE_SIZE
+}
+
+/// 对象 `layout` 在底层分配器中的布局，以及对象相对头部的偏移
+fn inner_layout(size: usize, align: usize) -> (Layout, usize) {
+    let align = align.max(align_of::<Header>());
+    let front = (size_of::<Header>() + REDZONE).next_multiple_of(align);
+    (Layout::from_size_align(front + size + REDZONE, align).unwrap(), front)
+}
+
+/// 调用链上最近的 `DEPTH` 个返回地址，沿帧指针回溯，不越过当前栈帧所在的页
+#[cfg(not(test))]
+#[inline(always)]
+fn callers() -> [usize; DEPTH] {
+    let mut callers = [0; DEPTH];
+    let mut fp: usize;
+    unsafe { core::arch::asm!("mv {}, fp", out(reg) fp); }
+    let barrier = (fp + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    for ra in callers.iter_mut() {
+        if fp % size_of::<usize>() != 0 || fp <= 16 || fp > barrier {
+            break;
+        }
+        unsafe {
+            *ra = *((fp - 8) as *const usize);
+            fp = *((fp - 16) as *const usize);
+        }
+    }
+    callers
+}
+
+/// 宿主机上不回溯调用链
+#[cfg(test)]
+fn callers() -> [usize; DEPTH] {
+    [0; DEPTH]
+}
+
+/// 检查 `[start, start + len)` 都是 `byte`，返回第一个不是的地址
+unsafe fn find_changed(start: usize, len: usize, byte: u8) -> Option<usize> {
+    (start..start + len).find(|&addr| *(addr as *const u8) != byte)
+}
+
+impl Header {
+    fn report(&self, what: &str, ptr: usize, addr: usize) -> ! {
+        panic!("heap_debug: {} at {:#x} in object {:#x} (size {}, seq {}) allocated from {:x?}",
+            what, addr, ptr, self.size, self.seq, self.callers)
+    }
+}
+
+impl KernelHeap {
+    /// 调试模式下的分配
+    pub(super) unsafe fn debug_alloc(&self, layout: Layout) -> *mut u8 {
+        let (inner, front) = inner_layout(layout.size(), layout.align());
+        let block = self.alloc_raw(inner) as usize;
+        if block == 0 {
+            return ptr::null_mut();
+        }
+        let obj = block + front;
+        let header_end = block + size_of::<Header>();
+        ptr::write_bytes(header_end as *mut u8, REDZONE_BYTE, front - size_of::<Header>());
+        ptr::write_bytes(obj as *mut u8, ALLOC_BYTE, layout.size());
+        ptr::write_bytes((obj + layout.size()) as *mut u8, REDZONE_BYTE, REDZONE);
+
+        let header = block as *mut Header;
+        let mut state = self.debug.lock();
+        state.init();
+        state.seq += 1;
+        ptr::write(header, Header {
+            link: List::empty(),
+            magic: MAGIC_LIVE,
+            size: layout.size(),
+            align: layout.align(),
+            seq: state.seq,
+            callers: callers(),
+        });
+        state.live.push(block);
+        state.nlive += 1;
+        state.live_bytes += layout.size();
+        obj as *mut u8
+    }
+
+    /// 调试模式下的释放：检查后毒化放入隔离队列，队列满时检查并真正释放最早的对象
+    pub(super) unsafe fn debug_dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let obj = ptr as usize;
+        let (_, front) = inner_layout(layout.size(), layout.align());
+        let block = obj - front;
+        let header = &mut *(block as *mut Header);
+        match header.magic {
+            MAGIC_LIVE => {}
+            MAGIC_FREED => header.report("double free", obj, obj),
+            _ => panic!("heap_debug: free of {:#x} (size {}): not a live object or header overwritten", obj, layout.size()),
+        }
+        if header.size != layout.size() || header.align != layout.align() {
+            panic!("heap_debug: free of {:#x} with layout {:?}, allocated with size {} align {}",
+                obj, layout, header.size, header.align);
+        }
+        let header_end = block + size_of::<Header>();
+        if let Some(addr) = find_changed(header_end, front - size_of::<Header>(), REDZONE_BYTE) {
+            header.report("underflow", obj, addr);
+        }
+        if let Some(addr) = find_changed(obj + header.size, REDZONE, REDZONE_BYTE) {
+            header.report("overflow", obj, addr);
+        }
+        ptr::write_bytes(header_end as *mut u8, POISON_BYTE, front - size_of::<Header>() + header.size + REDZONE);
+
+        let evicted = {
+            let mut state = self.debug.lock();
+            header.link.remove();
+            header.magic = MAGIC_FREED;
+            state.nlive -= 1;
+            state.live_bytes -= header.size;
+            state.quarantine.push(block);
+            state.nquarantine += 1;
+            if state.nquarantine > QUARANTINE_MAX {
+                state.nquarantine -= 1;
+                state.quarantine.pop_back()
+            } else {
+                None
+            }
+        };
+        if let Some(block) = evicted {
+            self.debug_release(block);
+        }
+    }
+
+    /// 检查隔离的对象未在释放后被改写，然后归还底层分配器
+    unsafe fn debug_release(&self, block: usize) {
+        let header = &*(block as *const Header);
+        let (inner, front) = inner_layout(header.size, header.align);
+        let header_end = block + size_of::<Header>();
+        if let Some(addr) = find_changed(header_end, front - size_of::<Header>() + header.size + REDZONE, POISON_BYTE) {
+            header.report("write after free", block + front, addr);
+        }
+        self.dealloc_raw(block as *mut u8, inner);
+    }
+
+    /// 记下当前的分配序号，此后 [`debug_dump`](Self::debug_dump) 只打印之后分配的对象
+    ///
+    /// # 返回值
+    /// 记下的序号
+    pub fn debug_mark(&self) -> usize {
+        let mut state = self.debug.lock();
+        state.mark = state.seq;
+        state.mark
+    }
+
+    /// 打印标记之后分配、至今未释放的对象，每行为序号、地址、大小和分配时的返回地址
+    ///
+    /// # 返回值
+    /// 打印的对象数
+    pub fn debug_dump(&self) -> usize {
+        let mut state = self.debug.lock();
+        state.init();
+        let mut count = 0;
+        let mut bytes = 0;
+        // 新分配的对象在链表头部，遇到标记之前的对象即可停止
+        for block in unsafe { state.live.iter() } {
+            let header = unsafe { &*(block as *const Header) };
+            if header.seq <= state.mark {
+                break;
+            }
+            let (_, front) = inner_layout(header.size, header.align);
+            println!("heap_debug: #{} {:#x} size {} from {:x?}", header.seq, block + front, header.size, header.callers);
+            count += 1;
+            bytes += header.size;
+        }
+        println!("heap_debug: {} objects ({} bytes) outstanding since #{}; {} live ({} bytes) in total",
+            count, bytes, state.mark, state.nlive, state.live_bytes);
+        count
+    }
+}
+
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use crate::host::Arena;
+    use crate::mm::frame::FrameAllocator;
+    use crate::spinlock::SpinLock;
+    use alloc::boxed::Box;
+    use core::alloc::GlobalAlloc;
+
+    const ARENA_SIZE: usize = 8 * 1024 * 1024;
+
+    fn new_heap(arena: &Arena) -> KernelHeap {
+        let frames = Box::leak(Box::new(SpinLock::new(FrameAllocator::uninit(), "frames")));
+        unsafe { frames.lock().init(arena.start(), arena.end(), &[]); }
+        KernelHeap::new(frames)
+    }
+
+    #[test]
+    fn fill_and_track() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+        let layout = Layout::from_size_align(40, 8).unwrap();
+
+        heap.debug_mark();
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(unsafe { find_changed(ptr as usize, 40, ALLOC_BYTE) }.is_none());
+        assert_eq!(heap.debug_dump(), 1);
+        unsafe { heap.dealloc(ptr, layout); }
+        assert_eq!(heap.debug_dump(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow")]
+    fn overflow_is_caught() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+        let layout = Layout::from_size_align(40, 8).unwrap();
+
+        let ptr = unsafe { heap.alloc(layout) };
+        unsafe {
+            *ptr.add(40) = 0;
+            heap.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free_is_caught() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+        let layout = Layout::from_size_align(40, 8).unwrap();
+
+        let ptr = unsafe { heap.alloc(layout) };
+        unsafe {
+            heap.dealloc(ptr, layout);
+            heap.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "write after free")]
+    fn write_after_free_is_caught() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+        let layout = Layout::from_size_align(40, 8).unwrap();
+
+        let ptr = unsafe { heap.alloc(layout) };
+        unsafe {
+            heap.dealloc(ptr, layout);
+            *ptr.add(8) = 1;
+        }
+        // 挤出隔离队列
+        for _ in 0..=QUARANTINE_MAX {
+            unsafe {
+                let other = heap.alloc(layout);
+                heap.dealloc(other, layout);
+            }
+        }
+    }
+}
@@ -1,4 +1,10 @@
-//! 内核堆内存分配器，采用伙伴算法
+//! 内核堆内存分配器
+//!
+//! 页大小及以上的分配直接交给页帧分配器（[`frame`](super::frame)），
+//! 不超过 [`SLAB_MAX`] 字节的对象交给按大小类划分的 slab 分配器（[`slab`](super::slab)），
+//! 介于两者之间的对象由伙伴算法在分配区内分配。
+//! 分配区是按需从页帧分配器取得的 2 MiB 块，按自身大小对齐，头部存放 [`HeapArena`]：
+//! 释放时由地址直接找到所属的分配区，分配区中的对象全部释放后整块归还页帧分配器。
 
 use bit_field::BitField;
 
@@ -7,9 +13,13 @@ use core::ptr::{self};
 use core::mem::{MaybeUninit, size_of};
 use core::cmp;
 
-use crate::consts::{KERNEL_HEAP_END, LEAF_SIZE, PAGE_SIZE, PHYSTOP};
+use crate::consts::{LEAF_SIZE, PAGE_SIZE};
 use crate::spinlock::SpinLock;
+use super::frame::{order_of, FrameAllocator, FrameOwner, FRAMES};
 use super::list::List;
+use super::slab::{class_of, SlabCache, SlabStat, NCLASSES, SLAB_MAX};
+#[cfg(feature = "heap_debug")]
+use super::heap_debug::{self, DebugState};
 
 /// 全局内核堆分配器。
 ///
@@ -17,96 +27,166 @@ use super::list::List;
 /// 并通过 `#[global_allocator]` 标记为全局分配器，
 /// 用于为内核中的所有堆分配请求提供支持。
 ///
-/// 它包装了一个基于伙伴系统（buddy system）的堆分配器 [`BuddySystem`]，
-/// 可在系统初始化早期通过 [`KernelHeap::kinit`] 完成堆空间的初始化，
-/// 从而管理内核可用的物理内存区间。
+/// 它从全局页帧分配器 [`FRAMES`] 按需取得内存，不需要单独初始化。
 ///
 /// # 安全性
 ///
-/// 在调用 `kinit()` 初始化之前，不应进行任何堆分配操作。
-#[global_allocator]
-pub static KERNEL_HEAP: KernelHeap = KernelHeap::uninit();
+/// 在页帧分配器初始化（[`frame::init`](super::frame::init)）之前，不应进行任何堆分配操作。
+#[cfg_attr(not(test), global_allocator)]
+pub static KERNEL_HEAP: KernelHeap = KernelHeap::new(&FRAMES);
 
+#[cfg(not(test))]
 #[alloc_error_handler]
 fn foo(layout: Layout) -> ! {
     panic!("alloc error: {:?}", layout)
 }
 
+/// 分配区为 2^ARENA_ORDER 页
+const ARENA_ORDER: usize = 9;
+const ARENA_SIZE: usize = PAGE_SIZE << ARENA_ORDER;
+
+/// 分配区的头部，位于分配区起始处，其后的内存由 `buddy` 管理
+#[repr(C)]
+struct HeapArena {
+    /// 链入 [`Arenas::list`]，必须是第一个字段
+    link: List,
+    /// 尚未释放的对象数
+    live: usize,
+    buddy: BuddySystem,
+}
+
+/// 所有分配区
+struct Arenas {
+    list: List,
+    count: usize,
+    initialized: bool,
+}
+
+// 因为 List 中的 *mut List 不是 Send
+unsafe impl Send for Arenas {}
+
+impl Arenas {
+    const fn new() -> Self {
+        Self {
+            list: List::empty(),
+            count: 0,
+            initialized: false,
+        }
+    }
+
+    /// 依次尝试各分配区，都已满时从 `frames` 取得一个新的分配区
+    unsafe fn alloc(&mut self, layout: Layout, frames: &SpinLock<FrameAllocator>) -> *mut u8 {
+        if !self.initialized {
+            self.list.init();
+            self.initialized = true;
+        }
+        for raw_addr in self.list.iter() {
+            let arena = &mut *(raw_addr as *mut HeapArena);
+            let ptr = arena.buddy.alloc(layout);
+            if !ptr.is_null() {
+                arena.live += 1;
+                return ptr
+            }
+        }
+
+        let base = match frames.lock().alloc(ARENA_ORDER, FrameOwner::Arena) {
+            Ok(base) => base,
+            Err(_) => return ptr::null_mut(),
+        };
+        let arena = &mut *(base as *mut HeapArena);
+        ptr::write(arena, HeapArena {
+            link: List::empty(),
+            live: 0,
+            buddy: BuddySystem::uninit(),
+        });
+        arena.buddy.init(base + size_of::<HeapArena>(), base + ARENA_SIZE);
+        self.list.push(base);
+        self.count += 1;
+
+        let ptr = arena.buddy.alloc(layout);
+        if !ptr.is_null() {
+            arena.live += 1;
+        }
+        ptr
+    }
+
+    /// 释放对象，所在分配区空了并且不是唯一的分配区时归还给 `frames`
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout, frames: &SpinLock<FrameAllocator>) {
+        let base = ptr as usize & !(ARENA_SIZE - 1);
+        debug_assert_eq!(frames.lock().owner(base), FrameOwner::Arena);
+        let arena = &mut *(base as *mut HeapArena);
+        arena.buddy.dealloc(ptr, layout);
+        arena.live -= 1;
+        if arena.live == 0 && self.count > 1 {
+            arena.link.remove();
+            self.count -= 1;
+            frames.lock().free(base, ARENA_ORDER);
+        }
+    }
+}
+
 /// 内核堆分配器封装结构。
 ///
 /// `KernelHeap` 是整个内核的堆内存分配器核心类型，
-/// 它通过内部封装一个加锁的 [`BuddySystem`]，
-/// 提供线程安全的堆内存分配与回收机制。
-///
-/// # 初始化
+/// 它把页大小及以上的分配转给页帧分配器，小对象交给各大小类的 [`SlabCache`]，
+/// 其余对象在加锁保护的分配区中分配，提供线程安全的堆内存分配与回收机制。
 ///
-/// 在系统启动早期，应调用 [`KernelHeap::kinit`] 来初始化堆管理区间，
-/// 否则任何堆分配行为都将引发错误。
-pub struct KernelHeap(SpinLock<BuddySystem>);
+/// 分配区与大小类的锁都先于页帧分配器的锁获取。
+pub struct KernelHeap {
+    frames: &'static SpinLock<FrameAllocator>,
+    caches: [SlabCache; NCLASSES],
+    arenas: SpinLock<Arenas>,
+    #[cfg(feature = "heap_debug")]
+    pub(super) debug: SpinLock<DebugState>,
+}
 
 impl KernelHeap {
-    const fn uninit() -> Self {
-        Self(SpinLock::new(BuddySystem::uninit(), "kernel heap"))
+    /// 创建从 `frames` 取得内存的堆分配器，`frames` 需已初始化后才能分配
+    pub const fn new(frames: &'static SpinLock<FrameAllocator>) -> Self {
+        Self {
+            frames,
+            caches: [
+                SlabCache::new(0), SlabCache::new(1), SlabCache::new(2), SlabCache::new(3),
+                SlabCache::new(4), SlabCache::new(5), SlabCache::new(6), SlabCache::new(7),
+            ],
+            arenas: SpinLock::new(Arenas::new(), "kernel heap"),
+            #[cfg(feature = "heap_debug")]
+            debug: SpinLock::new(DebugState::new(), "heap debug"),
+        }
     }
 
-    /// 初始化内核堆分配器。
-    ///
-    /// 在内核启动早期调用此函数，用于初始化整个内核堆的可用物理内存区域，
-    /// 其作用是将从链接脚本中 `_end` 符号（表示内核镜像末尾）
-    /// 到 `PHYSTOP` 之间的物理内存设置为可管理的堆空间。
-    ///
-    /// # 功能说明
-    ///
-    /// - 获取内核镜像结束地址 `_end` 作为起点，
-    ///   将区间 `[end, PHYSTOP)` 注册到内部伙伴系统中进行内存管理；
-    /// - 调用内部 `init()` 方法完成堆空间的初始化；
-    /// - 会打印可用内存区间和初始化完成提示信息。
-    ///
-    /// # 参数
-    ///
-    /// - `&self`：对当前 `KernelHeap` 分配器的不可变引用。
-    ///
-    /// # 返回值
-    ///
-    /// - 无返回值，函数执行完成后 `KernelHeap` 将处于可分配状态。
-    /// 
-    /// # 安全性
+    /// 第 `class` 个大小类的统计，大小类不存在时返回 `None`，可用于枚举大小类
+    pub fn slab_stat(&self, class: usize) -> Option<SlabStat> {
+        self.caches.get(class).map(SlabCache::stat)
+    }
+
+    /// 按布局把分配交给页帧分配器、slab 分配器或分配区，不经过调试包装
     ///
-    /// - 本函数为 `unsafe`，因为直接从裸指针读取 `_end` 符号，
-    ///   并假设其值正确无误；
-    /// - 使用者必须确保仅调用一次，并在其他堆分配操作之前调用；
-    /// - 不应在初始化前使用任何需要堆内存的结构（如 `Box`、`Vec`）
-    pub unsafe fn kinit(&self) {
-        extern "C" {
-            fn end();
+    /// slab 对象和页帧块都按自身大小对齐，对齐要求大于大小时按对齐要求选择大小类或阶数。
+    pub(super) unsafe fn alloc_raw(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size().max(layout.align());
+        if size <= SLAB_MAX {
+            return self.caches[class_of(size)].alloc(self.frames)
         }
-        let end = end as usize;
-        println!("KernelHeap: available physical memory [{:#x}, {:#x})", end, usize::from(KERNEL_HEAP_END));
-        self.init(end, usize::from(KERNEL_HEAP_END));
-        println!("KernelHeap: init memory done");
+        if size >= PAGE_SIZE {
+            return match self.frames.lock().alloc(order_of(size), FrameOwner::Page) {
+                Ok(pa) => pa as *mut u8,
+                Err(_) => ptr::null_mut(),
+            }
+        }
+        self.arenas.lock().alloc(layout, self.frames)
     }
 
-    /// 初始化内核堆分配器的实现函数。
-    ///
-    /// 在内核启动过程中调用此函数，用于初始化伙伴系统分配器，
-    /// 将 `[start, end)` 范围内的物理内存注册为可管理的堆空间，
-    /// 并建立用于内存分配的元数据结构（如分配位图、分裂标记等）。
-    ///
-    /// # 功能说明
-    ///
-    /// - 加锁访问内部 [`BuddySystem`]，并调用其 `init()` 方法；
-    /// - 设置堆起始地址、对齐边界，并初始化所有管理元信息；
-    /// 
-    /// # 参数
-    ///
-    /// - `start`: 要加入堆管理的起始物理地址，通常为内核镜像结束地址；
-    /// - `end`: 要加入堆管理的结束物理地址，通常为物理内存上限（如 `PHYSTOP`）；
-    ///
-    /// # 返回值
-    ///
-    /// - 无返回值。函数执行成功后，内核堆分配器将处于可用状态。
-    unsafe fn init(&self, start: usize, end: usize) {
-        self.0.lock().init(start, end);
+    /// 释放 [`alloc_raw`](Self::alloc_raw) 分配的内存
+    pub(super) unsafe fn dealloc_raw(&self, ptr: *mut u8, layout: Layout) {
+        let size = layout.size().max(layout.align());
+        if size <= SLAB_MAX {
+            return self.caches[class_of(size)].dealloc(ptr, self.frames)
+        }
+        if size >= PAGE_SIZE {
+            return self.frames.lock().free(ptr as usize, order_of(size))
+        }
+        self.arenas.lock().dealloc(ptr, layout, self.frames)
     }
 }
 
@@ -115,12 +195,13 @@ impl KernelHeap {
 /// 本实现使 `KernelHeap` 可以作为 Rust 全局分配器使用，
 /// 支持内核中 `Box`、`Vec` 等标准堆分配类型的底层内存管理。
 ///
-/// `alloc` 和 `dealloc` 方法会加锁访问内部的 [`BuddySystem`] 分配器，
-/// 从而保证在多核环境下的线程安全。
+/// 小对象通常只访问本硬件线程的弹匣，其余情况加锁访问分配区或页帧分配器，
+/// 从而保证在多核环境下的线程安全。开启 `heap_debug` 特性时小于一页的分配经过
+/// [`heap_debug`](super::heap_debug) 的检查。
 unsafe impl GlobalAlloc for KernelHeap {
     /// # 功能说明
     ///
-    /// - `alloc`：根据指定的内存布局分配堆空间；
+    /// - `alloc`：根据指定的内存布局分配堆空间，页大小及以上的请求按 2 的幂个页分配；
     /// 
     /// # 参数
     ///
@@ -129,7 +210,11 @@ unsafe impl GlobalAlloc for KernelHeap {
     ///
     /// - `alloc` 返回一个满足给定 `layout` 的裸指针；若内存不足返回空指针；
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.0.lock().alloc(layout)
+        #[cfg(feature = "heap_debug")]
+        if !heap_debug::bypass(layout) {
+            return self.debug_alloc(layout)
+        }
+        self.alloc_raw(layout)
     }
 
     /// # 功能说明
@@ -140,7 +225,11 @@ unsafe impl GlobalAlloc for KernelHeap {
     /// - `layout`：一个 [`Layout`] 对象，指定分配或释放的内存大小与对齐；
     /// - `ptr`：待释放的内存指针，必须来自之前分配的有效地址；
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.0.lock().dealloc(ptr, layout)
+        #[cfg(feature = "heap_debug")]
+        if !heap_debug::bypass(layout) {
+            return self.debug_dealloc(ptr, layout)
+        }
+        self.dealloc_raw(ptr, layout)
     }
 }
 
@@ -243,8 +332,11 @@ impl BuddySystem {
             self.nsizes += 1;
         }
 
+        #[cfg(feature = "verbose_init_info")]
         println!("  buddy system: useful memory is {:#x} bytes", self.actual_end - self.base);
+        #[cfg(feature = "verbose_init_info")]
         println!("  buddy system: leaf size is {} bytes", LEAF_SIZE);
+        #[cfg(feature = "verbose_init_info")]
         println!("  buddy system: free lists have {} different sizes", self.nsizes);
 
         // 分配伙伴系统信息
@@ -261,7 +353,8 @@ impl BuddySystem {
             info.free.init();
 
             // 安全性：初始化大小为 i 的 alloc 字段
-            let alloc_size = round_up(nblk, 8)/8;
+            // 管理的内存恰好是 2 的幂时，init_free 会检查末尾之后的块 nblk，位图要多留出这一位
+            let alloc_size = nblk/8 + 1;
             let alloc_slice_ptr = init_slice_empty(&mut cur, alloc_size);
             info.alloc.as_mut_ptr().write(alloc_slice_ptr);
         }
@@ -499,6 +592,7 @@ impl BuddySystem {
     /// - `usize`：表示元数据区域的大小（以字节计），即 `cur - self.base`。
     fn mark_meta(&mut self, cur: usize) -> usize {
         let meta = cur - self.base;
+        #[cfg(feature = "verbose_init_info")]
         println!("  buddy system: alloc {:#x} bytes meta data", meta);
         self.mark(self.base, cur);
         meta
@@ -526,6 +620,7 @@ impl BuddySystem {
     /// - `usize`：被标记为不可用的内存大小（以字节计）；
     fn mark_unavail(&mut self) -> usize {
         let unavail = blk_size(self.max_size()) - (self.actual_end - self.base);
+        #[cfg(feature = "verbose_init_info")]
         println!("  buddy system: {:#x} bytes unavailable", unavail);
         self.mark(self.actual_end, self.actual_end + unavail);
         unavail
@@ -789,26 +884,190 @@ fn blk_size(k: usize) -> usize {
 
 #[cfg(feature = "unit_test")]
 pub mod tests {
-    use super::*;
-    use crate::consts;
-    use crate::proc::cpu_id;
+    use alloc::boxed::Box;
+
+    use crate::ktest::TestHarts;
     use crate::mm::pagetable::PageTable;
-    use core::sync::atomic::{AtomicU8, Ordering};
+    use crate::process::CpuManager;
 
-    pub fn alloc_simo() {
-        // 使用 NSMP 来同步测试拉取请求的自旋锁
-        static NSMP: AtomicU8 = AtomicU8::new(0);
-        NSMP.fetch_add(1, Ordering::Relaxed);
-        while NSMP.load(Ordering::Relaxed) != NSMP as u8 {}
+    kernel_test!(alloc_simo, TestHarts::All);
 
-        let id = unsafe { cpu_id() };
+    /// 多核同时分配页表，验证伙伴系统在并发下的正确性
+    pub fn alloc_simo() {
+        let id = unsafe { CpuManager::cpu_id() };
 
         for _ in 0..10 {
-            let page_table = PageTable::new();
-            println!("hart {} alloc page table at {:#x}", id, page_table.addr());
+            let page_table = unsafe { Box::<PageTable>::try_new_zeroed().unwrap().assume_init() };
+            println!("hart {} alloc page table at {:#x}", id, &*page_table as *const PageTable as usize);
+        }
+    }
+}
+
+/// 宿主机单元测试，使用 `host::Arena` 充当物理内存
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use crate::host::Arena;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    const ARENA_SIZE: usize = 4 * 1024 * 1024;
+
+    fn new_buddy(arena: &Arena) -> BuddySystem {
+        let mut buddy = BuddySystem::uninit();
+        unsafe { buddy.init(arena.start(), arena.end()); }
+        buddy
+    }
+
+    #[test]
+    fn alloc_is_aligned_and_in_range() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut buddy = new_buddy(&arena);
+
+        for size in [1, 16, 100, 4096, 10000] {
+            let layout = Layout::from_size_align(size, cmp::min(size.next_power_of_two(), PAGE_SIZE)).unwrap();
+            let ptr = buddy.alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % layout.align(), 0);
+            assert!(ptr as usize >= arena.start() && ptr as usize + size <= arena.end());
+            buddy.dealloc(ptr, layout);
         }
+    }
+
+    #[test]
+    fn allocations_do_not_overlap() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut buddy = new_buddy(&arena);
+        let layout = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap();
+
+        let mut pages: Vec<usize> = (0..64).map(|_| buddy.alloc(layout) as usize).collect();
+        pages.sort();
+        for pair in pages.windows(2) {
+            assert!(pair[0] + PAGE_SIZE <= pair[1]);
+        }
+        for page in pages {
+            buddy.dealloc(page as *mut u8, layout);
+        }
+    }
 
-        NSMP.fetch_sub(1, Ordering::Relaxed);
-        while NSMP.load(Ordering::Relaxed) != 0 {}
+    #[test]
+    fn exhaust_then_merge_back() {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut buddy = new_buddy(&arena);
+        let layout = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap();
+
+        let mut pages = Vec::new();
+        loop {
+            let ptr = buddy.alloc(layout);
+            if ptr.is_null() {
+                break;
+            }
+            pages.push(ptr);
+        }
+        assert!(!pages.is_empty());
+
+        // 全部释放后伙伴应合并，可以再次分配出同样多的页
+        let count = pages.len();
+        for ptr in pages.drain(..) {
+            buddy.dealloc(ptr, layout);
+        }
+        for _ in 0..count {
+            let ptr = buddy.alloc(layout);
+            assert!(!ptr.is_null());
+            pages.push(ptr);
+        }
+        assert!(buddy.alloc(layout).is_null());
+    }
+
+    /// 从宿主机内存 `arena` 取得页帧的堆，页帧分配器泄漏以获得 `'static` 生命周期
+    fn new_heap(arena: &Arena) -> KernelHeap {
+        let frames = Box::leak(Box::new(SpinLock::new(FrameAllocator::uninit(), "frames")));
+        unsafe { frames.lock().init(arena.start(), arena.end(), &[]); }
+        KernelHeap::new(frames)
+    }
+
+    #[test]
+    fn kernel_heap_wrapper() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(!ptr.is_null());
+        unsafe {
+            ptr::write_bytes(ptr, 0xab, 64);
+            heap.dealloc(ptr, layout);
+        }
+    }
+
+    // 以下两个测试绕过 heap_debug 的包装，它会改变分配的大小并推迟释放
+    #[test]
+    fn small_objects_come_from_slabs() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let ptr = unsafe { heap.alloc_raw(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % 128, 0);
+        let stat = heap.slab_stat(class_of(100)).unwrap();
+        assert_eq!(stat.size, 128);
+        assert_eq!(stat.slabs, 1);
+        unsafe { heap.dealloc_raw(ptr, layout); }
+        assert!(heap.slab_stat(NCLASSES).is_none());
+    }
+
+    #[test]
+    fn alignment_larger_than_size_is_honoured() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+
+        for align in [64, 512, PAGE_SIZE, 4 * PAGE_SIZE] {
+            let layout = Layout::from_size_align(8, align).unwrap();
+            let ptrs: Vec<*mut u8> = (0..4).map(|_| unsafe { heap.alloc_raw(layout) }).collect();
+            assert!(ptrs.iter().all(|&ptr| !ptr.is_null() && ptr as usize % align == 0));
+            for ptr in ptrs {
+                unsafe { heap.dealloc_raw(ptr, layout); }
+            }
+        }
+    }
+
+    #[test]
+    fn pages_come_from_frames() {
+        let arena = Arena::new(ARENA_SIZE);
+        let heap = new_heap(&arena);
+        let free = heap.frames.lock().free_pages();
+
+        let layout = Layout::from_size_align(3 * PAGE_SIZE, PAGE_SIZE).unwrap();
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(heap.frames.lock().owner(ptr as usize), FrameOwner::Page);
+        assert_eq!(heap.frames.lock().free_pages(), free - 4);
+        unsafe { heap.dealloc(ptr, layout); }
+        assert_eq!(heap.frames.lock().free_pages(), free);
+    }
+
+    #[test]
+    fn arenas_grow_and_shrink() {
+        let arena = Arena::new(16 * 1024 * 1024);
+        let heap = new_heap(&arena);
+        let free = heap.frames.lock().free_pages();
+
+        // 超过一个分配区的、slab 分配器不接受的对象
+        let layout = Layout::from_size_align(SLAB_MAX + 1, 8).unwrap();
+        let count = super::ARENA_SIZE / PAGE_SIZE * 2;
+        let ptrs: Vec<*mut u8> = (0..count).map(|_| unsafe { heap.alloc_raw(layout) }).collect();
+        assert!(ptrs.iter().all(|ptr| !ptr.is_null()));
+        assert!(heap.arenas.lock().count > 2);
+        for &ptr in ptrs.iter() {
+            assert_eq!(heap.frames.lock().owner(ptr as usize & !(super::ARENA_SIZE - 1)), FrameOwner::Arena);
+        }
+
+        // 全部释放后只保留一个分配区
+        for ptr in ptrs {
+            unsafe { heap.dealloc_raw(ptr, layout); }
+        }
+        assert_eq!(heap.arenas.lock().count, 1);
+        assert_eq!(heap.frames.lock().free_pages(), free - (1 << ARENA_ORDER));
     }
 }
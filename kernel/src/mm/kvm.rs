@@ -4,12 +4,19 @@ use core::arch::asm;
 use core::convert::{TryFrom, Into};
 use core::mem;
 use core::ops::DerefMut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use array_macro::array;
 
 use crate::consts::{
-    CLINT, CLINT_MAP_SIZE, KERNBASE, KERNEL_HEAP_END, PAGE_SIZE, PHYSTOP, PLIC, PLIC_MAP_SIZE, TRAMPOLINE, UART0, UART0_MAP_SIZE, VIRTIO0, VIRTIO0_MAP_SIZE
+    CLINT, CLINT_MAP_SIZE, KERNBASE, NCPU, PAGE_SIZE, PHYSTOP, PLIC, PLIC_MAP_SIZE, TRAMPOLINE, VIRTIO0, VIRTIO0_MAP_SIZE
 };
+use crate::driver::{rtc, uart};
+use crate::fdt::{boot_fdt, DTB_ADDR};
+use crate::process::CpuManager;
 use crate::register::satp;
 use crate::spinlock::SpinLock;
+use super::asid;
 use super::{Addr, PageTable, PhysAddr, PteFlag, VirtAddr, RawSinglePage, RawDoublePage, RawQuadPage};
 
 /// 内核页表（Kernel Page Table）
@@ -28,17 +35,36 @@ use super::{Addr, PageTable, PhysAddr, PteFlag, VirtAddr, RawSinglePage, RawDoub
 static mut KERNEL_PAGE_TABLE: PageTable = PageTable::empty();
 
 /// 初始化当前处理器核心的内核虚拟内存页表。  
-/// 该函数将内核页表的物理页号写入 `satp` 寄存器，启用分页机制，  
+/// 该函数将内核页表的物理页号写入 `satp` 寄存器，启用分页机制，探测硬件支持的 ASID 位数，  
 /// 并执行 `sfence.vma zero, zero` 指令刷新地址转换缓存（TLB），  
-/// 保证页表修改立即生效。
+/// 保证页表修改立即生效。内核页表使用 ASID 0。
 pub unsafe fn kvm_init_hart() {
     satp::write(KERNEL_PAGE_TABLE.as_satp());
+    asid::init_hart();
     asm!("sfence.vma zero, zero");
 }
 
+/// 启动后内核页表增加映射（线程的内核栈）的次数
+static KVM_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// 每个硬件线程最近一次刷新 TLB 时看到的 [`KVM_GENERATION`]
+static KVM_SEEN: [AtomicUsize; NCPU] = array![_ => AtomicUsize::new(0); NCPU];
+
+/// 内核页表在本硬件线程上次检查之后增加过映射时刷新 TLB
+///
+/// 进出用户态不再刷新 TLB，硬件线程可能缓存了新映射处原先无效的表项。
+/// 新映射只有内核栈，由调度器在切换到进程之前调用。
+pub fn kvm_sync_hart() {
+    let id = unsafe { CpuManager::cpu_id() };
+    let generation = KVM_GENERATION.load(Ordering::Acquire);
+    if KVM_SEEN[id].swap(generation, Ordering::Relaxed) != generation {
+        unsafe { asm!("sfence.vma zero, zero"); }
+    }
+}
+
 /// # 功能说明
 /// 初始化内核虚拟内存页表的映射，建立内核空间的虚拟地址到物理地址的映射关系。  
-/// 包括对设备寄存器（UART0、VIRTIO0、CLINT、PLIC）、内核代码段、内核数据段、以及陷阱跳板（trampoline）  
+/// 包括对设备寄存器（所有串口、实时时钟、VIRTIO0、CLINT、PLIC）、内核代码段、内核数据段、以及陷阱跳板（trampoline）  
 /// 等关键内存区域的映射，并设置对应的访问权限（只读、可写、可执行）。  
 /// 还通过断言验证了原始页结构（RawSinglePage、RawDoublePage、RawQuadPage）与页表结构的内存布局一致性。
 ///
@@ -69,15 +95,20 @@ pub unsafe fn kvm_init() {
     debug_assert_eq!(mem::size_of::<RawQuadPage>(), PAGE_SIZE*4);
     debug_assert_eq!(mem::align_of::<RawQuadPage>(), PAGE_SIZE);
 
-    // UART 寄存器
-    kvm_map(
-        VirtAddr::from(UART0),
-        PhysAddr::from(UART0),
-        UART0_MAP_SIZE,
-        PteFlag::R | PteFlag::W,
-    );
+    // 所有串口的寄存器，同一页中的多个端口只映射一次
+    for (i, page) in uart::pages().enumerate() {
+        if uart::pages().take(i).any(|mapped| mapped == page) {
+            continue;
+        }
+        kvm_map(
+            VirtAddr::try_from(page).unwrap(),
+            PhysAddr::try_from(page).unwrap(),
+            PAGE_SIZE,
+            PteFlag::R | PteFlag::W,
+        );
+    }
 
-    // virtio 内存映射 I/O 磁盘接
+    // virtio 内存映射 I/O，全部槽位
     kvm_map(
         VirtAddr::from(VIRTIO0),
         PhysAddr::from(VIRTIO0),
@@ -85,6 +116,14 @@ pub unsafe fn kvm_init() {
         PteFlag::R | PteFlag::W,
     );
 
+    // 实时时钟
+    kvm_map(
+        VirtAddr::try_from(rtc::page()).unwrap(),
+        PhysAddr::try_from(rtc::page()).unwrap(),
+        PAGE_SIZE,
+        PteFlag::R | PteFlag::W,
+    );
+
     // CLINT
     kvm_map(
         VirtAddr::from(CLINT),
@@ -120,16 +159,37 @@ pub unsafe fn kvm_init() {
     kvm_map(
         VirtAddr::try_from(etext).unwrap(),
         PhysAddr::try_from(etext).unwrap(),
-        usize::from(KERNEL_HEAP_END) - etext,
+        usize::from(PHYSTOP) - etext,
         PteFlag::R | PteFlag::W,
     );
 
-    kvm_map(
-        VirtAddr::from(KERNEL_HEAP_END),
-        PhysAddr::from(KERNEL_HEAP_END),
-        usize::from(PHYSTOP) - usize::from(KERNEL_HEAP_END),
-        PteFlag::R | PteFlag::W,
-    );
+    // qemu 把设备树放在内存末尾，映射为只读以便开启分页后仍能查询设备
+    let dtb = DTB_ADDR.load(Ordering::Relaxed);
+    if dtb != 0 {
+        let size = u32::from_be(*((dtb + 4) as *const u32)) as usize;
+        let start = dtb & !(PAGE_SIZE - 1);
+        if start < usize::from(KERNBASE) || dtb + size > usize::from(PHYSTOP) {
+            kvm_map(
+                VirtAddr::try_from(start).unwrap(),
+                PhysAddr::try_from(start).unwrap(),
+                dtb + size - start,
+                PteFlag::R,
+            );
+        }
+    }
+
+    // initrd 若落在上面映射的物理内存之外，单独映射给内存盘使用
+    if let Some((start, end)) = boot_fdt().and_then(|fdt| fdt.initrd()) {
+        let start = start & !(PAGE_SIZE - 1);
+        if start < usize::from(KERNBASE) || end > usize::from(PHYSTOP) {
+            kvm_map(
+                VirtAddr::try_from(start).unwrap(),
+                PhysAddr::try_from(start).unwrap(),
+                end - start,
+                PteFlag::R | PteFlag::W,
+            );
+        }
+    }
 
     // 将用于陷阱进入 / 退出的跳板页映射到内核中最高的虚拟地址。
     extern "C" {
@@ -162,7 +222,10 @@ pub unsafe fn kvm_map(va: VirtAddr, pa: PhysAddr, size: usize, perm: PteFlag) {
     drop(spin_lock_guard);
 }
 
-pub unsafe fn kvm_task_kstack_map(va: VirtAddr, pa: PhysAddr, tid : usize,size: usize, perm: PteFlag) {
+/// 把线程 `tid` 的内核栈映射到内核页表，返回是否建立了映射
+///
+/// 内核栈的映射与 tid 绑定，tid 被重用时栈已经映射，`pa` 没有被使用，由调用者释放。
+pub unsafe fn kvm_task_kstack_map(va: VirtAddr, pa: PhysAddr, tid : usize,size: usize, perm: PteFlag) -> bool {
     #[cfg(feature = "verbose_init_info")]
     println!(
         "kvm_map: va={:#x}, pa={:#x}, size={:#x}",
@@ -177,8 +240,11 @@ pub unsafe fn kvm_task_kstack_map(va: VirtAddr, pa: PhysAddr, tid : usize,size:
             panic!("kvm_map: {}", err);
         }
         *spin_lock_guard.deref_mut() += 1;
+        KVM_GENERATION.fetch_add(1, Ordering::Release);
+        return true;
     }
     drop(spin_lock_guard);
+    false
 }
 /// # 功能说明
 /// 将内核虚拟地址 `va` 转换为对应的物理地址。  
@@ -18,6 +18,14 @@ pub struct List {
 }
 
 impl List {
+    /// 尚未初始化的表头，可用于静态变量，使用前需调用 [`init`](Self::init)
+    pub const fn empty() -> Self {
+        Self {
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+        }
+    }
+
     /// 初始化链表节点
     ///
     /// # 功能说明
@@ -75,6 +83,22 @@ impl List {
         raw_addr
     }
 
+    /// 从链表尾部弹出节点，与 [`push`](Self::push) 配合即为先进先出队列
+    ///
+    /// # 返回值
+    /// 被移除节点的内存地址，链表为空时返回 `None`
+    ///
+    /// # 安全性
+    /// 同 [`pop`](Self::pop)
+    pub unsafe fn pop_back(&mut self) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        let raw_addr = self.prev as usize;
+        self.prev.as_mut().unwrap().remove();
+        Some(raw_addr)
+    }
+
     /// 将当前节点从链表中移除
     ///
     /// # 功能说明
@@ -99,4 +123,31 @@ impl List {
     pub fn is_empty(&self) -> bool {
         ptr::eq(self.next, self)
     }
+
+    /// 从头到尾依次返回各节点的地址（不含表头）
+    ///
+    /// # 安全性
+    /// 遍历期间不能修改链表。
+    pub unsafe fn iter(&self) -> Iter {
+        Iter { head: self, cur: self.next }
+    }
+}
+
+/// [`List::iter`] 返回的迭代器
+pub struct Iter {
+    head: *const List,
+    cur: *mut List,
+}
+
+impl Iterator for Iter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if ptr::eq(self.cur, self.head) {
+            return None;
+        }
+        let raw_addr = self.cur as usize;
+        self.cur = unsafe { (*self.cur).next };
+        Some(raw_addr)
+    }
 }
@@ -7,15 +7,23 @@ use crate::consts::ConstAddr;
 use crate::process::CPU_MANAGER;
 use crate::consts::{TRAPFRAME,PAGE_SIZE,USER_STACK_SIZE};
 pub use addr::{Addr, PhysAddr, VirtAddr};
-pub use kvm::{kvm_init, kvm_init_hart, kvm_map, kvm_task_kstack_map,kvm_pa};
+#[cfg(not(test))]
+pub use kvm::{kvm_init, kvm_init_hart, kvm_map, kvm_sync_hart, kvm_task_kstack_map,kvm_pa};
 pub use pagetable::{PageTable, PteFlag};
 
 pub mod addr;
+#[cfg(not(test))]
+pub mod asid;
+pub mod frame;
+#[cfg(feature = "heap_debug")]
+pub mod heap_debug;
 pub mod kalloc;
+#[cfg(not(test))]
 mod kvm;
 pub mod pagetable;
 mod list;
-pub mod page_allocator;
+pub mod slab;
+pub mod swap;
 
 /// 定义物理页帧分配接口，用于分配页大小对齐的内存块。
 ///
@@ -24,7 +32,8 @@ pub mod page_allocator;
 /// - 分配归零的物理页
 /// - 分配未初始化的物理页
 /// - 安全释放已分配的物理页
-/// 实现通常使用`Box::new_zeroed()`和`Box::into_raw()`完成内存分配。
+/// 实现通常使用`Box::new_zeroed()`和`Box::into_raw()`完成内存分配，
+/// 内核堆把这些页大小的分配直接转给页帧分配器（见 [`frame`]）。
 ///
 /// # 类型参数
 /// - `Self`：实现trait的具体类型，需满足页大小对齐要求
@@ -2,20 +2,26 @@
 
 use array_macro::array;
 
-use super::{pg_round_up, Addr, PhysAddr, RawPage, RawSinglePage, VirtAddr};
-use crate::consts::{ConstAddr, MAX_TASKS_PER_PROC, USER_STACK_SIZE};
-use crate::consts::{PAGE_SIZE, PGSHIFT, SATP_SV39, SV39FLAGLEN, TRAMPOLINE, USERTEXT};
-use crate::mm::page_allocator::{page_alloc, PAGE_ALLOCATOR};
-use crate::mm::{pagetable, trapframe_from_pid, RawQuadPage};
-
 use alloc::boxed::Box;
-use core::sync::atomic::AtomicBool;
-use core::{ptr};
 use core::{cmp::min, convert::TryFrom};
-use core::ptr::{self, NonNull};
+use core::ptr;
+use core::sync::atomic::AtomicBool;
+
+use super::{pg_round_down, pg_round_up, Addr, PhysAddr, RawPage, RawSinglePage, VirtAddr};
+use crate::consts::{ConstAddr, MAX_TASKS_PER_PROC, USER_STACK_SIZE};
+use crate::consts::{PAGE_SIZE, PGSHIFT, PGSIZE, SATP_SV39, SV39FLAGLEN, TRAMPOLINE, TRAPFRAME, USERTEXT, USYSCALL};
+use crate::mm::{frame, swap, trapframe_from_pid, RawQuadPage};
+
+/// `uvm_unmap` 每击落一次 TLB 最多释放的页数
+const UNMAP_BATCH: usize = 32;
+
+/// 软件保留位中的换出标记：V 位为 0 且带这个标记的页表项表示页已换出到交换区，
+/// 物理页号的位置保存槽位号，读写执行与用户权限保持不变
+const PTE_SWAPPED: usize = 1 << 8;
 
-use crate::consts::{PGSHIFT, PGSIZE, SATP_SV39, SV39FLAGLEN, TRAMPOLINE, TRAPFRAME, USERTEXT, USYSCALL};
-use super::{Addr, PhysAddr, RawPage, RawSinglePage, VirtAddr, pg_round_up};
+/// 软件保留位中的保护页标记：V 位为 0 且带这个标记的页表项是用户栈下方的保护页，
+/// 不会按需分配，访问它是栈溢出
+const PTE_GUARD: usize = 1 << 9;
 
 bitflags! {
     /// 内存页表项权限标志（Page Table Entry Flags）
@@ -85,6 +91,11 @@ impl PageTableEntry {
         (self.data & (PteFlag::V.bits())) > 0
     }
 
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        (self.data & (PteFlag::W.bits())) > 0
+    }
+
     #[inline]
     pub fn is_access(&self) -> bool {
         (self.data & (PteFlag::A.bits())) > 0
@@ -95,6 +106,44 @@ impl PageTableEntry {
         self.data &= !PteFlag::A.bits()
     }
 
+    /// 页是否已换出到交换区
+    #[inline]
+    pub fn is_swapped(&self) -> bool {
+        !self.is_valid() && (self.data & PTE_SWAPPED) != 0
+    }
+
+    /// 页是否是用户栈下方的保护页
+    #[inline]
+    pub fn is_guard(&self) -> bool {
+        !self.is_valid() && (self.data & PTE_GUARD) != 0
+    }
+
+    /// 把没有映射的页表项标记为保护页
+    #[inline]
+    fn write_guard(&mut self) {
+        self.data = PTE_GUARD;
+    }
+
+    /// 换出的页所在的交换区槽位
+    #[inline]
+    pub fn swap_slot(&self) -> usize {
+        self.data >> SV39FLAGLEN
+    }
+
+    /// 把映射用户页的页表项改为换出到槽位 `slot`
+    #[inline]
+    fn write_swapped(&mut self, slot: usize) {
+        let perm = self.read_perm() & (PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U);
+        self.data = (slot << SV39FLAGLEN) | perm.bits() | PTE_SWAPPED;
+    }
+
+    /// 把换出的页表项重新映射到换入的物理页 `pa`，并视为刚被访问过
+    #[inline]
+    fn write_swapped_in(&mut self, pa: PhysAddr) {
+        let perm = self.read_perm() & (PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U);
+        self.write_perm(pa, perm | PteFlag::A);
+    }
+
     #[inline]
     fn is_leaf(&self) -> bool {
         let flag_bits = self.data & (PteFlag::R | PteFlag::W | PteFlag::X).bits();
@@ -268,7 +317,7 @@ impl PageTable {
         while va != last {
             match self.find_pte_create(va) {
                 Some(pte) => {
-                    if pte.is_valid() {
+                    if pte.is_valid() || pte.is_swapped() || pte.is_guard() {
                         println!(
                             "va: {:#x}, pa: {:#x}, pte: {:#x}",
                             va.as_usize(),
@@ -325,7 +374,8 @@ impl PageTable {
             } else {
                 let zerod_pgt = unsafe { Box::<Self>::try_new_zeroed().ok()?.assume_init() };
                 pagetable = Box::into_raw(zerod_pgt);
-                pte.write(PhysAddr::try_from(pagetable as usize).unwrap());
+                // 新页表由内核堆分配，一定页对齐且位于可访问的物理内存中
+                pte.write(unsafe { PhysAddr::from_raw(pagetable as usize) });
             }
         }
         unsafe { Some(&mut pagetable.as_mut().unwrap().data[va.page_num(0)]) }
@@ -333,7 +383,7 @@ impl PageTable {
 
     /// 与 [walk_alloc] 功能相同，
     /// 但如果页表不存在时不会分配新的页表。
-    pub pub fn find_pte_mut(&mut self, va: VirtAddr) -> Option<&mut PageTableEntry> {
+    pub fn find_pte_mut(&mut self, va: VirtAddr) -> Option<&mut PageTableEntry> {
         let mut pagetable = self as *mut PageTable;
         for level in (1..=2).rev() {
             let pte = unsafe { &mut pagetable.as_mut().unwrap().data[va.page_num(level)] };
@@ -372,6 +422,9 @@ impl PageTable {
                     Err("pte not valid")
                 } else if !pte.is_user() {
                     Err("pte not mapped for user")
+                } else if !pte.is_writable() {
+                    // 只读页可能与其他进程共享，见 `uvm_copy`
+                    Err("pte not writable")
                 } else {
                     Ok(pte.as_phys_addr())
                 }
@@ -532,52 +585,90 @@ impl PageTable {
             self.uvm_unmap(0, pg_round_up(proc_size)/PAGE_SIZE, true);
         }
     }
-        /// 根据当前线程数量和ustack_base分配用户栈
+    /// 为第 `pos` 个线程分配用户栈，并在栈下方设置保护页
+    ///
+    /// 每个线程的栈槽从 `ustack_base` 开始依次排列，由一个保护页和 `USER_STACK_SIZE` 字节的栈组成，
+    /// 见 [`ustack_bottom_by_pos`]。栈可读写、不可执行，保护页不映射物理页，栈溢出时缺页并报告段错误。
+    ///
     /// # 返回值
-    /// - `Ok(usize)`：返回实际分配后的ustack(栈向下生长)起始位置（字节）。
-    pub fn uvm_alloc_ustack(&mut self, ustack_base: usize, pos: usize) -> Result<usize, &str> {
-        if pos >= MAX_TASKS_PER_PROC {
+    /// - `Ok(usize)`：返回栈顶（栈向下生长）的地址。
+    ///
+    /// # 可能的错误
+    /// 线程序号超出范围、内存不足或栈槽已经被映射时返回错误，已映射的页被回滚。
+    pub fn uvm_alloc_ustack(&mut self, ustack_base: usize, pos: usize) -> Result<usize, &'static str> {
+        if pos == 0 || pos > MAX_TASKS_PER_PROC {
             return Err("too many tasks");
         }
-        let ustack_top = ustack_bottom_by_pos(ustack_base, pos);
-        let ustack_bottom = ustack_top - USER_STACK_SIZE;
+        let ustack_bottom = ustack_bottom_by_pos(ustack_base, pos);
+        let ustack_top = ustack_bottom + USER_STACK_SIZE;
 
+        self.uvm_guard(ustack_bottom - PAGE_SIZE).map_err(|_| "no enough virtual memory")?;
         for vaddr in (ustack_bottom..ustack_top).step_by(PAGE_SIZE) {
-            match unsafe { RawSinglePage::try_new_zeroed() } {
-                Err(_) => return Err("no enough physical memory"),
-                Ok(mem) => {
-                    match self.map_pages(
-                        unsafe { VirtAddr::from_raw(vaddr) },
-                        PAGE_SIZE,
-                        unsafe { PhysAddr::from_raw(mem as usize) },
-                        PteFlag::R | PteFlag::W | PteFlag::U,
-                    ) {
-                        Err(s) => {
-                            unsafe {
-                                RawSinglePage::from_raw_and_drop(mem);
-                            }
-                            return Err("no enough virtual memory");
-                        }
-                        Ok(_) => {
-                            // the mem raw pointer is leaked
-                            // but recorded in the pagetable at virtual address cur_size
-                        }
-                    }
+            let mem = match unsafe { RawSinglePage::try_new_zeroed() } {
+                Ok(mem) => mem,
+                Err(_) => {
+                    self.dealloc_ustack_by_pos(ustack_base, pos);
+                    return Err("no enough physical memory");
                 }
+            };
+            if self.map_pages(
+                unsafe { VirtAddr::from_raw(vaddr) },
+                PAGE_SIZE,
+                unsafe { PhysAddr::from_raw(mem as usize) },
+                PteFlag::R | PteFlag::W | PteFlag::U,
+            ).is_err() {
+                unsafe { RawSinglePage::from_raw_and_drop(mem); }
+                self.dealloc_ustack_by_pos(ustack_base, pos);
+                return Err("no enough virtual memory");
             }
         }
         Ok(ustack_top)
     }
+
+    /// 释放第 `pos` 个线程的用户栈及其保护页，见 [`PageTable::uvm_alloc_ustack`]
     pub fn dealloc_ustack_by_pos(&mut self, ustack_base: usize, pos: usize) {
-        let ustack_top = ustack_bottom_by_pos(ustack_base, pos);
-        let ustack_bottom = ustack_top - USER_STACK_SIZE;
-        self.uvm_unmap(ustack_bottom, 4, false);
+        let ustack_bottom = ustack_bottom_by_pos(ustack_base, pos);
+        self.uvm_unmap(ustack_bottom - PAGE_SIZE, USER_STACK_SIZE / PAGE_SIZE + 1, true);
+    }
+
+    /// 用户访问 `va` 缺页而又不能调入时，说明访问为什么非法，`access` 是访问需要的权限（R、W 或 X）
+    pub fn fault_reason(&self, va: usize, access: PteFlag) -> &'static str {
+        let pte = VirtAddr::try_from(pg_round_down(va)).ok().and_then(|va| self.find_pte(va));
+        match pte {
+            Some(pte) if pte.is_guard() => "stack overflow into guard page",
+            Some(pte) if pte.is_valid() && !pte.is_user() => "page not accessible from user mode",
+            Some(pte) if pte.is_valid() && pte.data & access.bits() == 0 => {
+                if access.contains(PteFlag::W) {
+                    "page not writable"
+                } else if access.contains(PteFlag::X) {
+                    "page not executable"
+                } else {
+                    "page not readable"
+                }
+            }
+            _ => "address not mapped",
+        }
+    }
+
+    /// 把用户地址 `va` 所在的页设为保护页，它必须还没有映射
+    ///
+    /// # 可能的错误
+    /// 页已经映射，或页表页分配失败
+    pub fn uvm_guard(&mut self, va: usize) -> Result<(), ()> {
+        let va = VirtAddr::try_from(pg_round_down(va)).map_err(|_| ())?;
+        match self.find_pte_create(va) {
+            Some(pte) if !pte.is_valid() && !pte.is_swapped() => {
+                pte.write_guard();
+                Ok(())
+            }
+            _ => Err(()),
+        }
     }
 
     /// # 功能说明
     /// 初始化进程用户空间的第一个内存页（代码页），  
     /// 将传入的程序代码 `code` 拷贝到用户空间的固定起始位置（`USERTEXT`），  
-    /// 并建立对应虚拟地址到物理页的映射，权限包括读、写、执行和用户访问。
+    /// 并建立对应虚拟地址到物理页的映射，权限包括读、执行和用户访问，不可写。
     ///
     /// # 参数
     /// - `&mut self`：进程页表的可变引用。  
@@ -606,7 +697,7 @@ impl PageTable {
             VirtAddr::from(USERTEXT),
             PAGE_SIZE,
             PhysAddr::try_from(mem as usize).unwrap(),
-            PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U,
+            PteFlag::R | PteFlag::X | PteFlag::U,
         )
         .expect("map_page error");
         unsafe {
@@ -616,7 +707,8 @@ impl PageTable {
 
     /// # 功能说明
     /// 为进程用户空间从 `old_size` 扩展到 `new_size` 分配新的内存页，
-    /// 并建立对应的虚拟地址到物理页的映射，权限包括读、写、执行和用户访问。  
+    /// 并建立对应的虚拟地址到物理页的映射，权限包括读、写和用户访问。  
+    /// 堆和栈上的数据不可执行，可执行的页只来自可执行文件的代码段。  
     /// 如果 `new_size` 小于或等于 `old_size`，则不做任何操作直接返回。  
     /// 该函数负责为进程用户空间分配新的页并初始化映射，支持动态扩展内存。
     ///
@@ -656,7 +748,7 @@ impl PageTable {
                         unsafe { VirtAddr::from_raw(cur_size) },
                         PAGE_SIZE,
                         unsafe { PhysAddr::from_raw(mem as usize) },
-                        PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U,
+                        PteFlag::R | PteFlag::W | PteFlag::U,
                     ) {
                         Err(s) => {
                             #[cfg(feature = "kernel_warning")]
@@ -732,45 +824,150 @@ impl PageTable {
     ///
     /// # 可能的错误
     /// - `va` 非页对齐时触发 panic。  
-    /// - 找不到对应虚拟地址的页表项时触发 panic。  
-    /// - 页表项无效或非叶子页表项时触发 panic。
+    /// - 没有映射的页被跳过，按需分配的堆中可能有这样的页。  
+    /// - 换出的页释放其交换区槽位。  
+    /// - 映射到非叶子页表项时触发 panic。
     ///
     /// # 安全性
     /// - 使用了 `unsafe` 代码释放裸指针指向的物理页内存，调用者需确保内存安全。  
     /// - 解除映射和释放操作需在单线程或同步环境下执行，避免竞态条件。  
-    /// - 解除映射后页表项会清零，防止悬挂指针访问。
+    /// - 解除映射后页表项会清零，并击落所有正在使用该页表的硬件线程的 TLB 之后才释放物理页，
+    ///   因此调用时不能持有自旋锁。
     pub fn uvm_unmap(&mut self, va: usize, count: usize, freeing: bool) {
         if va % PAGE_SIZE != 0 {
             panic!("va not page aligned");
         }
 
+        // 释放路径上不分配内存，内存耗尽时也能回收；攒满一批页就击落一次 TLB 并释放
+        let mut freed = [0usize; UNMAP_BATCH];
+        let mut nfreed = 0;
+        let mut start = va;
         for ca in (va..(va+PGSIZE*count)).step_by(PGSIZE) {
-            //println!("unmap! freeing:{}", freeing);
-            let pte;
-            let mut null = PageTableEntry { data: 0 };
-            let pte_option = unsafe { self.walk_mut( VirtAddr::from_raw(ca)) };
-            if let Some(inn) = pte_option {
-                pte = inn;
-            }
-            else {
-                return;
-            }
-            if !pte.is_valid() {
-                return;
-                //panic!("this pte is not valid");
-            }
+            // 按需分配的堆中可能有尚未访问、没有映射的页
+            let pte = match unsafe { self.find_pte_mut(VirtAddr::from_raw(ca)) } {
+                Some(pte) if pte.is_valid() => pte,
+                Some(pte) if pte.is_swapped() => {
+                    // 换出的页只属于这个页表项，没有物理页需要击落或释放
+                    swap::release(pte.swap_slot());
+                    pte.write_zero();
+                    continue
+                }
+                Some(pte) if pte.is_guard() => {
+                    pte.write_zero();
+                    continue
+                }
+                _ => continue,
+            };
             if !pte.is_leaf() {
-                //return;
                 panic!("this pte is not a leaf");
             }
             if freeing {
-                let pa = pte.as_phys_addr();
-                unsafe { RawSinglePage::from_raw_and_drop(pa.into_raw() as *mut u8); }
+                freed[nfreed] = pte.as_phys_addr().into_raw();
+                nfreed += 1;
             }
             pte.write_zero();
-            //println!("unmap finish!");
+            if nfreed == UNMAP_BATCH {
+                self.unmap_flush(start, ca + PGSIZE, &freed[..nfreed]);
+                nfreed = 0;
+                start = ca + PGSIZE;
+            }
         }
+        self.unmap_flush(start, va + PGSIZE*count, &freed[..nfreed]);
     }
+
+    /// 击落 `[start, end)` 的 TLB 之后释放物理页 `freed`
+    ///
+    /// 其他硬件线程可能仍缓存着刚解除的映射，刷新它们的 TLB 之后才能释放物理页
+    fn unmap_flush(&self, start: usize, end: usize, freed: &[usize]) {
+        if start >= end {
+            return
+        }
+        crate::ipi::tlb_shootdown(self.as_satp(), start, (end - start) / PGSIZE);
+        for &pa in freed {
+            unsafe { RawSinglePage::from_raw_and_drop(pa as *mut u8); }
+        }
+    }
+
+    /// 统计页表中映射的用户页数，即进程常驻内存的页数
+    ///
+    /// 只读取页表项，其他硬件线程可以在页表所有者运行时调用，
+    /// 但调用者需保证期间页表页不被释放（见 `exec` 与进程回收）。
+    pub fn user_pages(&self) -> usize {
+        self.data.iter()
+            .filter(|pte| pte.is_valid())
+            .map(|pte| if pte.is_leaf() {
+                pte.is_user() as usize
+            } else {
+                unsafe { &*pte.as_page_table() }.user_pages()
+            })
+            .sum()
+    }
+    /// 时钟算法扫描 `[start, end)` 的用户页，把最近没有访问过的页换出到交换区
+    ///
+    /// # 功能说明
+    /// 访问过的页清除访问位后跳过，得到第二次机会；没有访问过、且只被这个页表映射
+    /// （页帧引用计数为 1）的页改为换出状态并登记槽位，槽位号依次写入 `slots`。
+    /// 换出 `slots.len()` 页或交换区已满时停止。
+    ///
+    /// # 返回值
+    /// `(换出的页数, 扫描停止的地址)`，停止的地址之前的页都已扫描过。
+    ///
+    /// # 安全性
+    /// 换出的页在击落 `[start, 停止的地址)` 的 TLB 之前仍可能被访问，
+    /// 击落之后才能用 [`swap::write_out`] 写入磁盘。调用者需保证期间没有别人使用或修改这个页表。
+    pub fn swap_out(&mut self, start: usize, end: usize, slots: &mut [usize]) -> (usize, usize) {
+        let mut n = 0;
+        let mut va = pg_round_down(start);
+        while va < end && n < slots.len() {
+            let pte = match unsafe { self.find_pte_mut(VirtAddr::from_raw(va)) } {
+                Some(pte) => pte,
+                None => {
+                    // 整个末级页表都不存在，跳到下一个末级页表覆盖的范围
+                    va = (va | (PGSIZE * 512 - 1)) + 1;
+                    continue
+                }
+            };
+            if pte.is_valid() && pte.is_leaf() && pte.is_user() {
+                if pte.is_access() {
+                    pte.clear_access();
+                } else {
+                    let pa = pte.as_phys_addr().into_raw();
+                    if frame::refcount(pa) == 1 {
+                        match swap::reserve(pa) {
+                            Some(slot) => {
+                                pte.write_swapped(slot);
+                                slots[n] = slot;
+                                n += 1;
+                            }
+                            None => break,
+                        }
+                    }
+                }
+            }
+            va += PGSIZE;
+        }
+        (n, va)
+    }
+
+    /// 把 `va` 所在的换出的页读回内存并重新映射
+    ///
+    /// # 可能的错误
+    /// - `va` 所在的页没有换出
+    /// - 物理页分配失败，内存已经耗尽
+    ///
+    /// # 安全性
+    /// 需要读磁盘，调用者不能持有自旋锁。页从无效变为有效，不需要击落 TLB。
+    pub fn swap_in(&mut self, va: usize) -> Result<(), ()> {
+        let va = VirtAddr::try_from(pg_round_down(va)).map_err(|_| ())?;
+        let pte = match self.find_pte_mut(va) {
+            Some(pte) if pte.is_swapped() => pte,
+            _ => return Err(()),
+        };
+        let pa = swap::swap_in(pte.swap_slot())?;
+        pte.write_swapped_in(unsafe { PhysAddr::from_raw(pa) });
+        Ok(())
+    }
+
     pub fn kvm_unmap(&mut self, va: usize, count: usize, freeing: bool) {
         if va % PAGE_SIZE != 0 {
             panic!("va not page aligned");
@@ -817,6 +1014,7 @@ impl PageTable {
     /// 复制当前页表所管理的用户空间内存到子进程的页表 `child_pgt`，  
     /// 实现用户空间内存的逐页深拷贝，常用于进程创建（fork）时的地址空间复制。  
     /// 对每个页表项执行内存页克隆，建立子页表对应的映射关系，权限保持一致。
+    /// 只读页不会再改变，增加引用计数后与子进程共享，不复制。
     ///
     /// # 参数
     /// - `&mut self`：当前（父）进程的页表可变引用。  
@@ -828,9 +1026,9 @@ impl PageTable {
     /// - `Err(())`：复制过程中出现错误，且已回滚部分已映射的页。
     ///
     /// # 可能的错误
-    /// - 当前页表项不存在时触发 panic（`expect("pte not exist")`）。  
-    /// - 物理页克隆失败或子页表映射失败时，函数会清理已映射的页并返回错误。  
-    /// - 内存分配失败导致 `try_clone()` 返回错误。
+    /// - 没有映射的页（按需分配的堆中尚未访问的页）被跳过，换出的页先换入父进程。  
+    /// - 换入或物理页克隆失败或子页表映射失败时，函数会清理已映射的页并返回错误，
+    ///   调用者据此返回 ENOMEM。
     ///
     /// # 安全性
     /// - 函数中多处使用 `unsafe` 代码，调用裸指针操作内存，调用者需保证上下文安全。  
@@ -839,38 +1037,52 @@ impl PageTable {
     pub fn uvm_copy(&mut self, child_pgt: &mut Self, size: usize) -> Result<(), ()> {
         for i in (0..size).step_by(PAGE_SIZE) {
             let va = unsafe { VirtAddr::from_raw(i) };
-            //println!("copy");
-            let pte;
-            let pte_option = self.find_pte(va);
-            if let Some(inn) = pte_option {
-                pte = inn;
-            }
-            else {
+            // 换出的页先换入，再和其他页一样复制
+            if self.find_pte(va).map_or(false, |pte| pte.is_swapped()) && self.swap_in(i).is_err() {
+                child_pgt.uvm_unmap(0, i / PAGE_SIZE, true);
                 return Err(());
             }
-            let mem = unsafe { pte.try_clone() };
-            if let Ok(mem) = mem {
-                let perm = pte.read_perm();
-                if child_pgt
-                    .map_pages(
-                        va,
-                        PAGE_SIZE,
-                        unsafe { PhysAddr::from_raw(mem as usize) },
-                        perm,
-                    )
-                    .is_ok()
-                {
-                    continue;
+            // 按需分配的堆中尚未访问的页不需要复制，保护页在子进程中同样是保护页
+            let pte = match self.find_pte(va) {
+                Some(pte) if pte.is_valid() => pte,
+                Some(pte) if pte.is_guard() => {
+                    if child_pgt.uvm_guard(i).is_err() {
+                        child_pgt.uvm_unmap(0, i / PAGE_SIZE, true);
+                        return Err(());
+                    }
+                    continue
                 }
+                _ => continue,
+            };
+            let perm = pte.read_perm();
+            if !pte.is_writable() {
+                // 只读页（如可执行文件的代码页）不会再改变，父子进程共享同一个物理页
+                let pa = pte.as_phys_addr();
+                frame::get(pa.as_usize());
+                if child_pgt.map_pages(va, PAGE_SIZE, pa, perm).is_err() {
+                    frame::put(pa.as_usize());
+                    child_pgt.uvm_unmap(0, i / PAGE_SIZE, true);
+                    return Err(());
+                }
+                continue
+            }
+            let mem = match unsafe { pte.try_clone() } {
+                Ok(mem) => mem,
+                Err(_) => {
+                    child_pgt.uvm_unmap(0, i / PAGE_SIZE, true);
+                    return Err(());
+                }
+            };
+            if child_pgt
+                .map_pages(va, PAGE_SIZE, unsafe { PhysAddr::from_raw(mem as usize) }, perm)
+                .is_err()
+            {
                 unsafe {
                     RawSinglePage::from_raw_and_drop(mem);
                 }
+                child_pgt.uvm_unmap(0, i / PAGE_SIZE, true);
+                return Err(());
             }
-            else {
-                return Ok(());
-            }
-            child_pgt.uvm_unmap(0, i / PAGE_SIZE, true);
-            return Err(());
         }
         Ok(())
     }
@@ -963,7 +1175,7 @@ impl PageTable {
     /// # 可能的错误
     /// - 当 `count` 为 0 时，直接返回成功。  
     /// - 目标虚拟地址转换为物理地址失败时返回错误。  
-    /// - 跨页复制时如遇无效页表映射也会返回错误。
+    /// - 跨页复制时如遇无效页表映射或只读页也会返回错误。
     ///
     /// # 安全性
     /// - 使用了大量 `unsafe` 代码访问裸指针，调用者需保证源地址有效且目标内存可写。  
@@ -991,7 +1203,6 @@ impl PageTable {
                 Err(s) => {
                     #[cfg(feature = "kernel_warning")]
                     println!("kernel warning: {} when pagetable copy_out", s);
-                    panic!("no map {:x}",va.into_raw());
                     return Err(());
                 }
             }
@@ -1139,3 +1350,194 @@ impl Drop for PageTable {
 pub fn ustack_bottom_by_pos(ustack_base: usize, pos: usize) -> usize {
     ustack_base + (pos - 1) * (PAGE_SIZE + USER_STACK_SIZE) + PAGE_SIZE
 }
+
+/// 宿主机单元测试，页表与物理页都由宿主机的堆分配
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use core::mem::ManuallyDrop;
+    use std::sync::Once;
+    use crate::host::Arena;
+    use crate::mm::frame::FrameOwner;
+
+    fn new_pagetable() -> Box<PageTable> {
+        unsafe { Box::<PageTable>::new_zeroed().assume_init() }
+    }
+
+    #[test]
+    fn map_and_walk() {
+        let mut pgt = new_pagetable();
+        let page = unsafe { RawSinglePage::new_zeroed() };
+        let va = VirtAddr::try_from(0x4000_0000usize).unwrap();
+        let pa = unsafe { PhysAddr::from_raw(page as usize) };
+
+        pgt.map_pages(va, PAGE_SIZE, pa, PteFlag::R | PteFlag::W | PteFlag::U).unwrap();
+        assert_eq!(pgt.find_pa(va).unwrap().as_usize(), page as usize);
+        assert!(pgt.find_pa(VirtAddr::try_from(0x4000_1000usize).unwrap()).is_err());
+
+        // 重复映射应报错
+        assert_eq!(pgt.map_pages(va, PAGE_SIZE, pa, PteFlag::R | PteFlag::U), Err("remap"));
+
+        pgt.uvm_unmap(va.as_usize(), 1, true);
+        assert!(pgt.find_pa(va).is_err());
+    }
+
+    #[test]
+    fn kernel_only_mapping_is_hidden_from_user_walk() {
+        let mut pgt = new_pagetable();
+        let page = unsafe { RawSinglePage::new_zeroed() };
+        let va = VirtAddr::try_from(0x2000usize).unwrap();
+        let pa = unsafe { PhysAddr::from_raw(page as usize) };
+
+        pgt.map_pages(va, PAGE_SIZE, pa, PteFlag::R | PteFlag::W).unwrap();
+        assert_eq!(pgt.find_pa(va), Err("pte not mapped for user"));
+        assert_eq!(pgt.find_pa_by_kernel(va).unwrap().as_usize(), page as usize);
+
+        pgt.uvm_unmap(va.as_usize(), 1, true);
+    }
+
+    #[test]
+    fn uvm_alloc_copy_and_dealloc() {
+        let mut pgt = new_pagetable();
+        let size = pgt.uvm_alloc(0, 3 * PAGE_SIZE).unwrap();
+        assert_eq!(size, 3 * PAGE_SIZE);
+
+        // 跨页拷贝
+        let src: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let va = PAGE_SIZE - 32;
+        pgt.copy_out(src.as_ptr(), va, src.len()).unwrap();
+        let mut dst = [0u8; 64];
+        pgt.copy_in(va, dst.as_mut_ptr(), dst.len()).unwrap();
+        assert_eq!(src, dst);
+
+        assert_eq!(pgt.uvm_dealloc(size, 0), 0);
+        for i in 0..3 {
+            assert!(pgt.find_pa(VirtAddr::try_from(i * PAGE_SIZE).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn holes_are_skipped() {
+        let mut pgt = new_pagetable();
+        // 模拟按需分配的堆：只访问过第 0、2、40 页
+        for page in [0, 2, 40] {
+            pgt.uvm_alloc(page * PAGE_SIZE, (page + 1) * PAGE_SIZE).unwrap();
+        }
+        let size = 41 * PAGE_SIZE;
+        assert_eq!(pgt.user_pages(), 3);
+
+        let mut child = new_pagetable();
+        pgt.uvm_copy(&mut child, size).unwrap();
+        assert_eq!(child.user_pages(), 3);
+        assert!(child.find_pa(VirtAddr::try_from(PAGE_SIZE).unwrap()).is_err());
+
+        // 空洞之后的页也要释放
+        child.uvm_dealloc(size, 0);
+        assert_eq!(child.user_pages(), 0);
+        pgt.uvm_dealloc(size, 0);
+        assert_eq!(pgt.user_pages(), 0);
+
+        // 超过一批的页分几次击落与释放
+        let size = (2 * UNMAP_BATCH + 1) * PAGE_SIZE;
+        pgt.uvm_alloc(0, size).unwrap();
+        assert_eq!(pgt.user_pages(), 2 * UNMAP_BATCH + 1);
+        pgt.uvm_dealloc(size, 0);
+        assert_eq!(pgt.user_pages(), 0);
+    }
+
+    /// 共享只读页要用全局的页帧分配器计数，用一段不释放的宿主机内存初始化它一次
+    fn init_global_frames() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let arena = ManuallyDrop::new(Arena::new(64 * PAGE_SIZE));
+            unsafe { frame::FRAMES.lock().init(arena.start(), arena.end(), &[]); }
+        });
+    }
+
+    #[test]
+    fn failed_share_drops_only_the_extra_reference() {
+        init_global_frames();
+        let mut pgt = new_pagetable();
+        let pa = frame::FRAMES.lock().alloc(0, FrameOwner::Page).unwrap();
+        let va = VirtAddr::try_from(0usize).unwrap();
+        pgt.map_pages(va, PAGE_SIZE, unsafe { PhysAddr::from_raw(pa) }, PteFlag::R | PteFlag::X | PteFlag::U).unwrap();
+
+        // 子进程在同一地址已有映射，共享只读页时映射失败
+        let mut child = new_pagetable();
+        let page = unsafe { RawSinglePage::new_zeroed() };
+        child.map_pages(va, PAGE_SIZE, unsafe { PhysAddr::from_raw(page as usize) }, PteFlag::R | PteFlag::U).unwrap();
+        assert!(pgt.uvm_copy(&mut child, PAGE_SIZE).is_err());
+
+        // 父进程的页仍然映射，只剩它自己的引用
+        assert_eq!(pgt.find_pa(va).unwrap().as_usize(), pa);
+        assert_eq!(frame::refcount(pa), 1);
+
+        child.uvm_unmap(0, 1, true);
+        pgt.uvm_unmap(0, 1, false);
+        frame::put(pa);
+    }
+
+    #[test]
+    fn swapped_pte_keeps_slot_and_permissions() {
+        let mut pgt = new_pagetable();
+        let page = unsafe { RawSinglePage::new_zeroed() };
+        let va = VirtAddr::try_from(0x3000usize).unwrap();
+        let pa = unsafe { PhysAddr::from_raw(page as usize) };
+        pgt.map_pages(va, PAGE_SIZE, pa, PteFlag::R | PteFlag::W | PteFlag::U).unwrap();
+
+        let pte = pgt.find_pte_mut(va).unwrap();
+        pte.write_swapped(1234);
+        assert!(!pte.is_valid());
+        assert!(pte.is_swapped());
+        assert_eq!(pte.swap_slot(), 1234);
+
+        // 换出的页不属于常驻内存，也不能再次映射
+        assert_eq!(pgt.user_pages(), 0);
+        assert!(pgt.find_pa(va).is_err());
+        assert_eq!(pgt.map_pages(va, PAGE_SIZE, pa, PteFlag::R | PteFlag::U), Err("remap"));
+
+        let pte = pgt.find_pte_mut(va).unwrap();
+        pte.write_swapped_in(pa);
+        assert!(!pte.is_swapped());
+        assert!(pte.is_access());
+        assert_eq!(pte.read_perm() & (PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U),
+            PteFlag::R | PteFlag::W | PteFlag::U);
+        assert_eq!(pgt.find_pa(va).unwrap().as_usize(), page as usize);
+
+        pgt.uvm_unmap(va.as_usize(), 1, true);
+    }
+
+    #[test]
+    fn user_stack_has_guard_page() {
+        let mut pgt = new_pagetable();
+        let base = 0x10000;
+        let top = pgt.uvm_alloc_ustack(base, 1).unwrap();
+        let bottom = ustack_bottom_by_pos(base, 1);
+        assert_eq!(top, bottom + USER_STACK_SIZE);
+        assert_eq!(pgt.user_pages(), USER_STACK_SIZE / PAGE_SIZE);
+
+        // 栈不可执行，栈下方的保护页不映射也不能再映射
+        let pte = pgt.find_pte(VirtAddr::try_from(bottom).unwrap()).unwrap();
+        assert_eq!(pte.read_perm() & (PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U),
+            PteFlag::R | PteFlag::W | PteFlag::U);
+        assert_eq!(pgt.fault_reason(bottom, PteFlag::X), "page not executable");
+        let guard = VirtAddr::try_from(bottom - PAGE_SIZE).unwrap();
+        assert!(pgt.find_pte(guard).unwrap().is_guard());
+        assert_eq!(pgt.fault_reason(bottom - 1, PteFlag::W), "stack overflow into guard page");
+        let page = unsafe { RawSinglePage::new_zeroed() };
+        let pa = unsafe { PhysAddr::from_raw(page as usize) };
+        assert_eq!(pgt.map_pages(guard, PAGE_SIZE, pa, PteFlag::R | PteFlag::U), Err("remap"));
+        unsafe { RawSinglePage::from_raw_and_drop(page) };
+
+        // 子进程的栈同样有保护页
+        let mut child = new_pagetable();
+        pgt.uvm_copy(&mut child, top).unwrap();
+        assert!(child.find_pte(guard).unwrap().is_guard());
+        child.uvm_dealloc(top, 0);
+        assert!(!child.find_pte(guard).unwrap().is_guard());
+
+        pgt.dealloc_ustack_by_pos(base, 1);
+        assert_eq!(pgt.user_pages(), 0);
+        assert!(!pgt.find_pte(guard).unwrap().is_guard());
+    }
+}
@@ -0,0 +1,444 @@
+//! 小对象的 slab 分配器
+//!
+//! 不超过 [`SLAB_MAX`] 字节的堆分配按 2 的幂大小类交给一个 [`SlabCache`]。
+//! 每个大小类从页帧分配器取得按自身大小对齐的块（slab），头部存放 [`Slab`]，其余部分切成等大的对象，
+//! 空闲对象的第一个字存放下一个空闲对象的地址。
+//!
+//! 每个硬件线程为每个大小类持有一个弹匣（magazine），缓存至多 [`MAG_SIZE`] 个空闲对象。
+//! 分配与释放通常只在关中断后操作本地弹匣，不需要加锁；弹匣空了从 slab 层一次取回半个弹匣，
+//! 满了一次还回半个，只有这时才获取大小类的锁。
+
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::consts::{NCPU, PAGE_SIZE};
+use crate::process::{pop_off, push_off, CpuManager};
+use crate::spinlock::SpinLock;
+use super::frame::{FrameAllocator, FrameOwner};
+use super::list::List;
+
+/// 交给 slab 分配器的最大对象大小
+pub const SLAB_MAX: usize = 2048;
+/// 最小的大小类为 2^MIN_SHIFT 字节，需放得下空闲链表指针
+const MIN_SHIFT: usize = 4;
+/// 大小类的个数：16、32、……、2048 字节
+pub const NCLASSES: usize = 8;
+/// 每个 slab 至少容纳的对象数，决定 slab 的阶数
+const OBJS_PER_SLAB: usize = 8;
+/// 弹匣的容量
+pub const MAG_SIZE: usize = 32;
+
+/// 大小为 `size` 的对象所属的大小类
+pub fn class_of(size: usize) -> usize {
+    size.max(1 << MIN_SHIFT).next_power_of_two().trailing_zeros() as usize - MIN_SHIFT
+}
+
+/// 一个 slab 的头部，位于 slab 起始处
+#[repr(C)]
+struct Slab {
+    /// 有空闲对象时链入 [`SlabLists::partial`]，必须是第一个字段
+    link: List,
+    /// 第一个空闲对象的地址，0 表示已满
+    free: usize,
+    /// 已分配出去（包括在弹匣中）的对象数
+    inuse: usize,
+}
+
+/// 大小类的 slab 层，由大小类的锁保护
+struct SlabLists {
+    partial: List,
+    initialized: bool,
+    slabs: usize,
+    /// 所有 slab 中空闲对象的总数
+    free: usize,
+    /// 弹匣从 slab 层取回对象的次数
+    refills: usize,
+    /// 弹匣把对象还回 slab 层的次数
+    flushes: usize,
+}
+
+// 因为 List 中的 *mut List 不是 Send
+unsafe impl Send for SlabLists {}
+
+/// 一个硬件线程的弹匣，只由该硬件线程在关中断时修改
+///
+/// 计数器用原子变量只是为了让统计能从其他硬件线程读取，本硬件线程按普通变量读写。
+struct Magazine {
+    rounds: [usize; MAG_SIZE],
+    count: AtomicUsize,
+    allocs: AtomicUsize,
+    frees: AtomicUsize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            rounds: [0; MAG_SIZE],
+            count: AtomicUsize::new(0),
+            allocs: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn bump(counter: &AtomicUsize) {
+        counter.store(counter.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+    }
+}
+
+/// 一个大小类的统计，内存布局与用户态的 `struct slabstat` 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SlabStat {
+    /// 对象大小
+    pub size: u64,
+    /// slab 个数
+    pub slabs: u64,
+    /// 所有 slab 能容纳的对象数
+    pub objects: u64,
+    /// slab 中的空闲对象数
+    pub free: u64,
+    /// 各硬件线程弹匣中缓存的对象数
+    pub cached: u64,
+    pub allocs: u64,
+    pub frees: u64,
+    /// 弹匣从 slab 层取回对象的次数，即获取大小类的锁的次数的一部分
+    pub refills: u64,
+    /// 弹匣把对象还回 slab 层的次数
+    pub flushes: u64,
+}
+
+/// 一个大小类的对象缓存
+pub struct SlabCache {
+    size: usize,
+    /// slab 的阶数
+    order: usize,
+    lists: SpinLock<SlabLists>,
+    magazines: [UnsafeCell<Magazine>; NCPU],
+}
+
+// 弹匣只由所属的硬件线程在关中断时修改
+unsafe impl Sync for SlabCache {}
+
+impl SlabCache {
+    /// 第 `class` 个大小类的缓存
+    pub const fn new(class: usize) -> Self {
+        const EMPTY: UnsafeCell<Magazine> = UnsafeCell::new(Magazine::new());
+        let size = 1 << (class + MIN_SHIFT);
+        // 与 order_of(size * OBJS_PER_SLAB) 相同，const fn 中不能调用它
+        let pages = (size * OBJS_PER_SLAB).div_ceil(PAGE_SIZE);
+        Self {
+            size,
+            order: pages.next_power_of_two().trailing_zeros() as usize,
+            lists: SpinLock::new(SlabLists {
+                partial: List::empty(),
+                initialized: false,
+                slabs: 0,
+                free: 0,
+                refills: 0,
+                flushes: 0,
+            }, "slab"),
+            magazines: [EMPTY; NCPU],
+        }
+    }
+
+    /// slab 的字节数
+    #[inline]
+    fn slab_size(&self) -> usize {
+        PAGE_SIZE << self.order
+    }
+
+    /// slab 中第一个对象相对 slab 起始处的偏移，保证对象按自身大小对齐
+    #[inline]
+    fn first_offset(&self) -> usize {
+        size_of::<Slab>().div_ceil(self.size) * self.size
+    }
+
+    /// 每个 slab 容纳的对象数
+    #[inline]
+    fn per_slab(&self) -> usize {
+        (self.slab_size() - self.first_offset()) / self.size
+    }
+
+    /// 分配一个对象，内存不足时返回空指针
+    ///
+    /// # 安全性
+    /// `frames` 需已初始化，并且始终是同一个页帧分配器。
+    pub unsafe fn alloc(&self, frames: &SpinLock<FrameAllocator>) -> *mut u8 {
+        push_off();
+        let obj = match self.magazines.get(CpuManager::cpu_id()) {
+            Some(mag) => {
+                let mag = &mut *mag.get();
+                if mag.count() == 0 {
+                    self.refill(mag, frames);
+                }
+                match mag.count() {
+                    0 => 0,
+                    n => {
+                        mag.count.store(n - 1, Ordering::Relaxed);
+                        Magazine::bump(&mag.allocs);
+                        mag.rounds[n - 1]
+                    }
+                }
+            }
+            // 宿主机测试线程的编号可能超出 NCPU，没有本地弹匣时直接使用 slab 层
+            None => self.lists.lock().take(self, frames).unwrap_or(0),
+        };
+        pop_off();
+        obj as *mut u8
+    }
+
+    /// 释放 [`alloc`](Self::alloc) 分配的对象
+    ///
+    /// # 安全性
+    /// `ptr` 必须由本缓存分配且尚未释放。
+    pub unsafe fn dealloc(&self, ptr: *mut u8, frames: &SpinLock<FrameAllocator>) {
+        push_off();
+        match self.magazines.get(CpuManager::cpu_id()) {
+            Some(mag) => {
+                let mag = &mut *mag.get();
+                if mag.count() == MAG_SIZE {
+                    self.flush(mag, frames);
+                }
+                let n = mag.count();
+                mag.rounds[n] = ptr as usize;
+                mag.count.store(n + 1, Ordering::Relaxed);
+                Magazine::bump(&mag.frees);
+            }
+            None => self.lists.lock().put(self, ptr as usize, frames),
+        }
+        pop_off();
+    }
+
+    /// 从 slab 层取回至多半个弹匣的对象
+    fn refill(&self, mag: &mut Magazine, frames: &SpinLock<FrameAllocator>) {
+        let mut lists = self.lists.lock();
+        lists.refills += 1;
+        let mut n = mag.count();
+        while n < MAG_SIZE / 2 {
+            match lists.take(self, frames) {
+                Some(obj) => mag.rounds[n] = obj,
+                None => break,
+            }
+            n += 1;
+        }
+        mag.count.store(n, Ordering::Relaxed);
+    }
+
+    /// 把弹匣中最早放入的半个弹匣的对象还回 slab 层
+    fn flush(&self, mag: &mut Magazine, frames: &SpinLock<FrameAllocator>) {
+        let mut lists = self.lists.lock();
+        lists.flushes += 1;
+        for &obj in mag.rounds[..MAG_SIZE / 2].iter() {
+            lists.put(self, obj, frames);
+        }
+        mag.rounds.copy_within(MAG_SIZE / 2.., 0);
+        mag.count.store(mag.count() - MAG_SIZE / 2, Ordering::Relaxed);
+    }
+
+    /// 本大小类的统计
+    pub fn stat(&self) -> SlabStat {
+        let mut stat = {
+            let lists = self.lists.lock();
+            SlabStat {
+                size: self.size as u64,
+                slabs: lists.slabs as u64,
+                objects: (lists.slabs * self.per_slab()) as u64,
+                free: lists.free as u64,
+                refills: lists.refills as u64,
+                flushes: lists.flushes as u64,
+                ..Default::default()
+            }
+        };
+        // 弹匣可能正被所属的硬件线程修改，只通过裸指针读取其中的原子计数器
+        for mag in self.magazines.iter().map(UnsafeCell::get) {
+            unsafe {
+                stat.cached += (*ptr::addr_of!((*mag).count)).load(Ordering::Relaxed) as u64;
+                stat.allocs += (*ptr::addr_of!((*mag).allocs)).load(Ordering::Relaxed) as u64;
+                stat.frees += (*ptr::addr_of!((*mag).frees)).load(Ordering::Relaxed) as u64;
+            }
+        }
+        stat
+    }
+}
+
+impl SlabLists {
+    /// 从有空闲对象的 slab 中取出一个对象，没有时从 `frames` 取得一个新的 slab
+    fn take(&mut self, cache: &SlabCache, frames: &SpinLock<FrameAllocator>) -> Option<usize> {
+        if !self.initialized {
+            self.partial.init();
+            self.initialized = true;
+        }
+        if self.partial.is_empty() {
+            self.grow(cache, frames)?;
+        }
+        unsafe {
+            let base = self.partial.iter().next().unwrap();
+            let slab = &mut *(base as *mut Slab);
+            let obj = slab.free;
+            slab.free = *(obj as *const usize);
+            slab.inuse += 1;
+            self.free -= 1;
+            if slab.free == 0 {
+                slab.link.remove();
+            }
+            Some(obj)
+        }
+    }
+
+    /// 把对象 `obj` 还给所在的 slab，slab 空了并且本大小类还有至少一个 slab 的空闲对象时释放它
+    fn put(&mut self, cache: &SlabCache, obj: usize, frames: &SpinLock<FrameAllocator>) {
+        let base = obj & !(cache.slab_size() - 1);
+        unsafe {
+            let slab = &mut *(base as *mut Slab);
+            let was_full = slab.free == 0;
+            *(obj as *mut usize) = slab.free;
+            slab.free = obj;
+            slab.inuse -= 1;
+            self.free += 1;
+            if was_full {
+                self.partial.push(base);
+            }
+            if slab.inuse == 0 && self.free >= 2 * cache.per_slab() {
+                slab.link.remove();
+                self.slabs -= 1;
+                self.free -= cache.per_slab();
+                frames.lock().free(base, cache.order);
+            }
+        }
+    }
+
+    /// 取得一个新的 slab，把其中的对象串成空闲链表
+    fn grow(&mut self, cache: &SlabCache, frames: &SpinLock<FrameAllocator>) -> Option<()> {
+        let base = frames.lock().alloc(cache.order, FrameOwner::Slab).ok()?;
+        let first = base + cache.first_offset();
+        let end = first + cache.per_slab() * cache.size;
+        unsafe {
+            let mut obj = first;
+            while obj < end {
+                let next = obj + cache.size;
+                *(obj as *mut usize) = if next < end { next } else { 0 };
+                obj = next;
+            }
+            ptr::write(base as *mut Slab, Slab {
+                link: List::empty(),
+                free: first,
+                inuse: 0,
+            });
+            self.partial.push(base);
+        }
+        self.slabs += 1;
+        self.free += cache.per_slab();
+        Some(())
+    }
+}
+
+/// 单元测试模块
+#[cfg(feature = "unit_test")]
+pub mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use crate::ktest::TestHarts;
+    use crate::mm::kalloc::KERNEL_HEAP;
+
+    kernel_test!(magazines_all_harts, TestHarts::All);
+
+    /// 所有硬件线程同时分配并交错释放小对象
+    ///
+    /// # 测试点
+    /// 1. 弹匣的取回与归还在多个硬件线程上并发进行时对象不重复分配
+    /// 2. 统计的分配数不少于本测试的分配数
+    pub fn magazines_all_harts() {
+        let before = KERNEL_HEAP.slab_stat(super::class_of(64)).unwrap().allocs;
+        let mut objs: Vec<Box<[u64; 8]>> = Vec::new();
+        for round in 0..200u64 {
+            objs.push(Box::new([round; 8]));
+            if round % 3 == 0 {
+                objs.swap_remove(0);
+            }
+        }
+        for obj in objs.iter() {
+            assert!(obj.iter().all(|&x| x == obj[0]), "slab object corrupted");
+        }
+        let after = KERNEL_HEAP.slab_stat(super::class_of(64)).unwrap().allocs;
+        assert!(after - before >= 200);
+    }
+}
+
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+    use crate::host::Arena;
+    use crate::mm::frame::order_of;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    const ARENA_SIZE: usize = 8 * 1024 * 1024;
+
+    fn new_frames(arena: &Arena) -> &'static SpinLock<FrameAllocator> {
+        let frames = Box::leak(Box::new(SpinLock::new(FrameAllocator::uninit(), "frames")));
+        unsafe { frames.lock().init(arena.start(), arena.end(), &[]); }
+        frames
+    }
+
+    #[test]
+    fn size_classes() {
+        assert_eq!(class_of(1), 0);
+        assert_eq!(class_of(16), 0);
+        assert_eq!(class_of(17), 1);
+        assert_eq!(class_of(SLAB_MAX), NCLASSES - 1);
+        for class in 0..NCLASSES {
+            let cache = SlabCache::new(class);
+            assert_eq!(cache.order, order_of(cache.size * OBJS_PER_SLAB));
+            assert!(cache.per_slab() >= OBJS_PER_SLAB - 1);
+        }
+    }
+
+    #[test]
+    fn objects_are_aligned_and_distinct() {
+        let arena = Arena::new(ARENA_SIZE);
+        let frames = new_frames(&arena);
+
+        for class in [0, 3, NCLASSES - 1] {
+            let cache = SlabCache::new(class);
+            let mut objs: Vec<usize> = (0..100).map(|_| unsafe { cache.alloc(frames) } as usize).collect();
+            assert!(objs.iter().all(|&obj| obj != 0 && obj % cache.size == 0));
+            assert_eq!(frames.lock().owner(objs[0] & !(cache.slab_size() - 1)), FrameOwner::Slab);
+            objs.sort();
+            objs.dedup();
+            assert_eq!(objs.len(), 100);
+            for obj in objs {
+                unsafe { cache.dealloc(obj as *mut u8, frames); }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_slabs_go_back_to_frames() {
+        let arena = Arena::new(ARENA_SIZE);
+        let frames = new_frames(&arena);
+        let free_pages = frames.lock().free_pages();
+        let cache = SlabCache::new(class_of(256));
+
+        let objs: Vec<*mut u8> = (0..1000).map(|_| unsafe { cache.alloc(frames) }).collect();
+        let stat = cache.stat();
+        assert!(stat.slabs as usize >= 1000 / cache.per_slab());
+        assert_eq!(stat.objects - stat.free - stat.cached, 1000);
+
+        for obj in objs {
+            unsafe { cache.dealloc(obj, frames); }
+        }
+        // 空了的 slab 已还回，剩下的页帧都属于仍在的 slab
+        let stat = cache.stat();
+        assert!((stat.slabs as usize) < 1000 / cache.per_slab());
+        assert_eq!(frames.lock().free_pages() + ((stat.slabs as usize) << cache.order), free_pages);
+    }
+}
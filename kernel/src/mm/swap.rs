@@ -0,0 +1,245 @@
+//! 交换区
+//!
+//! mkfs 在文件系统之后保留一段块作为交换区（见超级块的 `swapstart`、`nswap`），
+//! 每个槽位占连续的 `PAGE_SIZE / BSIZE` 块，保存一个换出的用户页。
+//! 换出的页表项 V 位为 0，物理页号的位置记录槽位号，见 [`PageTableEntry::is_swapped`]。
+//!
+//! 换出分两步：持有页表所有者的锁把页表项改为换出状态并登记槽位（[`reserve`]），
+//! 击落 TLB 之后再把页写入磁盘（[`write_out`]）。写入完成之前页仍留在槽位中，
+//! 这期间缺页直接取回这个页；页表项被销毁时槽位等写入完成才释放。
+//! 因此同一槽位的磁盘块不会被并发读写，交换区绕过块缓存直接访问块设备。
+//!
+//! [`PageTableEntry::is_swapped`]: super::pagetable::PageTableEntry::is_swapped
+
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::consts::PAGE_SIZE;
+use crate::consts::fs::BSIZE;
+use crate::fs::{BufData, BCACHE};
+use crate::spinlock::SpinLock;
+use super::{RawPage, RawSinglePage};
+
+/// 每个槽位占用的磁盘块数
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BSIZE;
+
+pub static SWAP: SpinLock<SwapArea> = SpinLock::new(SwapArea::new(), "swap");
+
+#[derive(Clone, Copy)]
+struct Slot {
+    /// 被一个换出的页表项引用
+    used: bool,
+    /// 页正在写入磁盘
+    writing: bool,
+    /// 写入完成之前页所在的物理页，之后为 0
+    frame: usize,
+}
+
+impl Slot {
+    const FREE: Self = Self { used: false, writing: false, frame: 0 };
+
+    fn is_free(&self) -> bool {
+        !self.used && !self.writing
+    }
+}
+
+/// 交换区的槽位表
+pub struct SwapArea {
+    /// 第一个槽位的块号
+    start: u32,
+    slots: Vec<Slot>,
+    nfree: usize,
+    /// 下一次从这里开始找空闲槽位
+    next: usize,
+}
+
+impl SwapArea {
+    const fn new() -> Self {
+        Self {
+            start: 0,
+            slots: Vec::new(),
+            nfree: 0,
+            next: 0,
+        }
+    }
+
+    /// 为即将写入磁盘的物理页 `pa` 分配槽位，交换区已满时返回 `None`
+    fn reserve(&mut self, pa: usize) -> Option<usize> {
+        if self.nfree == 0 {
+            return None
+        }
+        let n = self.slots.len();
+        let slot = (0..n).map(|i| (self.next + i) % n).find(|&i| self.slots[i].is_free())?;
+        self.slots[slot] = Slot { used: true, writing: true, frame: pa };
+        self.nfree -= 1;
+        self.next = (slot + 1) % n;
+        Some(slot)
+    }
+
+    /// 槽位写入完成，返回仍由槽位持有、需要释放的物理页
+    fn finish_write(&mut self, slot: usize) -> Option<usize> {
+        let s = &mut self.slots[slot];
+        debug_assert!(s.writing);
+        s.writing = false;
+        if !s.used {
+            self.nfree += 1;
+        }
+        Some(mem::replace(&mut s.frame, 0)).filter(|&pa| pa != 0)
+    }
+
+    /// 页表项不再引用槽位；写入完成之前页仍在内存中，取走并返回这个物理页
+    fn take(&mut self, slot: usize) -> Option<usize> {
+        let s = &mut self.slots[slot];
+        assert!(s.used, "swap: slot {} is not in use", slot);
+        s.used = false;
+        if !s.writing {
+            self.nfree += 1;
+        }
+        Some(mem::replace(&mut s.frame, 0)).filter(|&pa| pa != 0)
+    }
+
+    /// 页表项不再引用槽位，页不再需要；写入中的页由写入者释放
+    fn release(&mut self, slot: usize) {
+        let s = &mut self.slots[slot];
+        assert!(s.used, "swap: slot {} is not in use", slot);
+        s.used = false;
+        if !s.writing {
+            self.nfree += 1;
+        }
+    }
+
+    /// 槽位第一块的块号
+    fn blockno(&self, slot: usize) -> u32 {
+        self.start + (slot * BLOCKS_PER_SLOT) as u32
+    }
+}
+
+/// 按超级块记录的交换区 `[start, start + nblocks)` 初始化槽位表
+///
+/// # 安全性
+/// 只能在文件系统初始化时调用一次，此时还没有页被换出。
+pub unsafe fn init(start: u32, nblocks: u32) {
+    let nslots = nblocks as usize / BLOCKS_PER_SLOT;
+    if nslots == 0 {
+        println!("swap: no swap area");
+        return
+    }
+    let mut swap = SWAP.lock();
+    swap.start = start;
+    swap.slots = alloc::vec![Slot::FREE; nslots];
+    swap.nfree = nslots;
+    println!("swap: {} slots at block {}", nslots, start);
+}
+
+/// 空闲槽位数
+pub fn free_slots() -> usize {
+    SWAP.lock().nfree
+}
+
+/// 为正在换出的物理页 `pa` 分配槽位，见 [`SwapArea::reserve`]
+pub fn reserve(pa: usize) -> Option<usize> {
+    SWAP.lock().reserve(pa)
+}
+
+/// 把槽位 `slot` 登记的页写入磁盘，然后释放物理页
+///
+/// # 安全性
+/// 引用该槽位的页表项已经换出，且已经击落了所有可能缓存旧映射的 TLB。
+/// 调用者不能持有自旋锁：写磁盘会睡眠。
+pub fn write_out(slot: usize) {
+    let (blockno, pa) = {
+        let swap = SWAP.lock();
+        (swap.blockno(slot), swap.slots[slot].frame)
+    };
+    // 页可能在写入期间被缺页取走，写入的内容随之作废，槽位由 `finish_write` 回收
+    if pa != 0 {
+        let device = BCACHE.device();
+        for i in 0..BLOCKS_PER_SLOT {
+            let data = unsafe { &*((pa + i * BSIZE) as *const BufData) };
+            device.write_block(blockno + i as u32, data);
+        }
+    }
+    if let Some(pa) = SWAP.lock().finish_write(slot) {
+        unsafe { RawSinglePage::from_raw_and_drop(pa as *mut u8); }
+    }
+}
+
+/// 取回槽位 `slot` 中的页并释放槽位，返回存放该页的物理页
+///
+/// # 可能的错误
+/// 需要从磁盘读回时物理页分配失败，此时槽位保持不变。
+///
+/// # 安全性
+/// 调用者是唯一引用该槽位的页表项的所有者，且不能持有自旋锁：读磁盘会睡眠。
+pub fn swap_in(slot: usize) -> Result<usize, ()> {
+    let blockno = {
+        let mut swap = SWAP.lock();
+        if swap.slots[slot].frame != 0 {
+            return Ok(swap.take(slot).unwrap())
+        }
+        swap.blockno(slot)
+    };
+    let mem = unsafe { RawSinglePage::try_new_uninit() }.map_err(|_| ())?;
+    let device = BCACHE.device();
+    for i in 0..BLOCKS_PER_SLOT {
+        let data = unsafe { &mut *(mem.add(i * BSIZE) as *mut BufData) };
+        device.read_block(blockno + i as u32, data);
+    }
+    SWAP.lock().take(slot);
+    Ok(mem as usize)
+}
+
+/// 释放换出的页表项引用的槽位，见 [`SwapArea::release`]
+pub fn release(slot: usize) {
+    SWAP.lock().release(slot)
+}
+
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+
+    fn new_area(nslots: usize) -> SwapArea {
+        let mut area = SwapArea::new();
+        area.slots = alloc::vec![Slot::FREE; nslots];
+        area.nfree = nslots;
+        area
+    }
+
+    #[test]
+    fn slots_are_reused_after_write() {
+        let mut area = new_area(2);
+        let a = area.reserve(0x1000).unwrap();
+        let b = area.reserve(0x2000).unwrap();
+        assert_ne!(a, b);
+        assert!(area.reserve(0x3000).is_none());
+
+        // 写入完成后页由槽位释放，数据只留在磁盘上
+        assert_eq!(area.finish_write(a), Some(0x1000));
+        assert_eq!(area.take(a), None);
+        assert_eq!(area.nfree, 1);
+        assert_eq!(area.reserve(0x3000), Some(a));
+    }
+
+    #[test]
+    fn fault_during_write_takes_the_page() {
+        let mut area = new_area(1);
+        let slot = area.reserve(0x1000).unwrap();
+
+        // 写入完成之前缺页，页直接回到页表；槽位等写入完成才空闲
+        assert_eq!(area.take(slot), Some(0x1000));
+        assert!(area.reserve(0x2000).is_none());
+        assert_eq!(area.finish_write(slot), None);
+        assert_eq!(area.nfree, 1);
+    }
+
+    #[test]
+    fn release_during_write_leaves_the_page_to_the_writer() {
+        let mut area = new_area(1);
+        let slot = area.reserve(0x1000).unwrap();
+
+        area.release(slot);
+        assert_eq!(area.nfree, 0);
+        assert_eq!(area.finish_write(slot), Some(0x1000));
+        assert_eq!(area.nfree, 1);
+    }
+}
@@ -1,40 +1,126 @@
 //! RISC-V PLIC（平台级中断控制器）驱动模块
 //!
 //! 负责管理外部设备中断，包括：
-//! - 中断优先级配置
+//! - 中断处理函数的登记，以及每个中断源的优先级与目标核心（HART）
 //! - 中断使能控制
-//! - 中断声明与完成处理
+//! - 中断声明、分发与完成处理
+//! - 按中断号与核心统计中断次数
 //!
 //! PLIC 是 RISC-V 系统中处理外部设备中断的核心组件，
 //! 支持多级优先级和多个中断目标（HART）。
 //!
 //! # 主要功能
-//! 1. 全局初始化 (`init`)
+//! 1. 登记与注销中断处理函数 (`register_irq`、`unregister_irq`)
 //! 2. 单核初始化 (`init_hart`)
-//! 3. 中断声明 (`claim`)
-//! 4. 中断完成 (`complete`)
+//! 3. 外部中断分发 (`handle_irq`)，由用户态与内核态的陷阱处理共用
+//! 4. 中断统计 (`irq_count`、`dump`)
 
 use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::process::CpuManager;
-use crate::consts::{PLIC, UART0_IRQ, VIRTIO0_IRQ};
+use crate::consts::{NCPU, NIRQ, PLIC, PLIC_MAX_PRIORITY};
+use crate::spinlock::SpinLock;
 
-/// 初始化 PLIC 全局设置
+/// 中断可以发往所有核心
+pub const ALL_HARTS: usize = usize::MAX;
+
+/// 一个中断源上登记的处理函数
+#[derive(Clone, Copy)]
+struct IrqAction {
+    /// 驱动名称，用于统计输出
+    name: &'static str,
+    /// 处理函数，参数为中断号
+    handler: fn(usize),
+    priority: u32,
+    /// 接收该中断的核心掩码，第 i 位对应 i 号核心
+    harts: usize,
+}
+
+struct Irqs {
+    actions: [Option<IrqAction>; NIRQ],
+    /// 已执行 `init_hart` 的核心掩码，只有这些核心的使能寄存器会被修改
+    online: usize,
+}
+
+static IRQS: SpinLock<Irqs> = SpinLock::new(
+    Irqs { actions: [None; NIRQ], online: 0 },
+    "irqs",
+);
+
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+const HART_COUNTS: [AtomicUsize; NCPU] = [ZERO; NCPU];
+
+/// 每个中断源在每个核心上被处理的次数
+static COUNTS: [[AtomicUsize; NCPU]; NIRQ] = [HART_COUNTS; NIRQ];
+
+/// 每个核心上收到的没有处理函数的中断次数
+static SPURIOUS: [AtomicUsize; NCPU] = [ZERO; NCPU];
+
+/// 为中断源 `irq` 登记处理函数
 ///
-/// # 功能说明
-/// 设置关键设备中断的优先级（非零值启用中断）：
-/// - UART0 (串口)：优先级 1
-/// - VIRTIO0 (磁盘)：优先级 1
+/// # 参数
+/// - `irq`: 中断号
+/// - `name`: 驱动名称
+/// - `handler`: 中断到来时调用的函数，在关中断的陷阱上下文中执行
+/// - `priority`: 优先级，1 到 `PLIC_MAX_PRIORITY`
+/// - `harts`: 接收该中断的核心掩码，`ALL_HARTS` 表示所有核心
 ///
-/// # 安全性
-/// - 直接操作硬件寄存器
-/// - 应在系统启动时调用一次
-pub unsafe fn init() {
-    // 设置UART中断优先级
-    write(UART0_IRQ*4, 1);
+/// # 可能的错误
+/// - 中断号超出范围或为 0
+/// - 优先级超出范围
+/// - 核心掩码中没有存在的核心
+/// - 中断源已被其他驱动占用
+pub fn register_irq(
+    irq: usize,
+    name: &'static str,
+    handler: fn(usize),
+    priority: u32,
+    harts: usize,
+) -> Result<(), &'static str> {
+    if irq == 0 || irq >= NIRQ {
+        return Err("plic: irq out of range");
+    }
+    if priority == 0 || priority > PLIC_MAX_PRIORITY {
+        return Err("plic: priority out of range");
+    }
+    let harts = harts & ((1 << NCPU) - 1);
+    if harts == 0 {
+        return Err("plic: no target hart");
+    }
+
+    let mut irqs = IRQS.lock();
+    if irqs.actions[irq].is_some() {
+        return Err("plic: irq already registered");
+    }
+    irqs.actions[irq] = Some(IrqAction { name, handler, priority, harts });
+    write(PRIORITY + irq * 4, priority);
+    for hart in 0..NCPU {
+        if irqs.online & (1 << hart) != 0 {
+            set_enable(hart, irq, harts & (1 << hart) != 0);
+        }
+    }
+    Ok(())
+}
+
+/// 注销中断源 `irq` 的处理函数，并在所有核心上禁用该中断
+pub fn unregister_irq(irq: usize) {
+    if irq == 0 || irq >= NIRQ {
+        return;
+    }
+    let mut irqs = IRQS.lock();
+    irqs.actions[irq] = None;
+    disable(&irqs, irq);
+}
 
-    // 设置虚拟磁盘中断优先级
-    write(VIRTIO0_IRQ*4, 1);
+/// 在所有在线核心上禁用 `irq`，并把优先级设为 0
+fn disable(irqs: &Irqs, irq: usize) {
+    write(PRIORITY + irq * 4, 0);
+    for hart in 0..NCPU {
+        if irqs.online & (1 << hart) != 0 {
+            set_enable(hart, irq, false);
+        }
+    }
 }
 
 /// 初始化特定 CPU 核心的 PLIC 设置
@@ -43,20 +129,100 @@ pub unsafe fn init() {
 /// - `hart`: 目标 CPU 核心 ID
 ///
 /// # 功能说明
-/// 1. 启用当前核心的 UART 和 VIRTIO 中断
+/// 1. 启用已登记且目标包含当前核心的中断
 /// 2. 设置核心中断优先级阈值为 0（接收所有优先级中断）
+/// 3. 之后登记的中断也会在当前核心上按目标掩码启用
 ///
 /// # 安全性
 /// - 直接操作硬件寄存器
 /// - 应在每个核心启动时调用
 pub unsafe fn init_hart(hart: usize) {
-    // 启用当前核心的特定中断源
-    write(SENABLE+SENABLE_HART*hart, (1<<UART0_IRQ)|(1<<VIRTIO0_IRQ));
+    let mut irqs = IRQS.lock();
+    for irq in 1..NIRQ {
+        let enable = matches!(irqs.actions[irq], Some(action) if action.harts & (1 << hart) != 0);
+        set_enable(hart, irq, enable);
+    }
+    irqs.online |= 1 << hart;
 
     // 设置核心优先级阈值为0（接收所有中断）
     write(SPRIORITY+SPRIORITY_HART*hart, 0);
 }
 
+/// 设置 `hart` 的 S 模式上下文是否接收 `irq`
+fn set_enable(hart: usize, irq: usize, enable: bool) {
+    let offset = SENABLE + SENABLE_HART * hart + irq / 32 * 4;
+    let bit = 1u32 << (irq % 32);
+    let old = read(offset);
+    write(offset, if enable { old | bit } else { old & !bit });
+}
+
+/// 外部中断分发
+///
+/// # 功能说明
+/// 1. 从 PLIC 声明待处理的中断
+/// 2. 调用登记的处理函数并计数
+/// 3. 没有处理函数的中断视为伪中断：报告并禁用该中断源，避免反复触发
+/// 4. 通知 PLIC 处理完成
+///
+/// # 调用时机
+/// 用户态与内核态的陷阱处理收到 S 模式外部中断时调用
+pub fn handle_irq() {
+    let irq = claim() as usize;
+    // 中断已被其他核心声明
+    if irq == 0 {
+        return;
+    }
+    let hart = unsafe { CpuManager::cpu_id() };
+
+    // 先取出处理函数再调用，处理函数内部会获取驱动自己的锁
+    let action = if irq < NIRQ { IRQS.lock().actions[irq] } else { None };
+    match action {
+        Some(action) => {
+            COUNTS[irq][hart].fetch_add(1, Ordering::Relaxed);
+            (action.handler)(irq);
+        }
+        None => {
+            SPURIOUS[hart].fetch_add(1, Ordering::Relaxed);
+            println!("plic: spurious irq {} on hart {}, disabled", irq, hart);
+            if irq < NIRQ {
+                disable(&IRQS.lock(), irq);
+            }
+        }
+    }
+    complete(irq as u32);
+}
+
+/// 中断源 `irq` 在所有核心上被处理的总次数
+pub fn irq_count(irq: usize) -> usize {
+    COUNTS.get(irq).map_or(0, |harts| {
+        harts.iter().map(|count| count.load(Ordering::Relaxed)).sum()
+    })
+}
+
+/// 打印每个已登记中断在各核心上的处理次数，以及各核心上的伪中断次数
+pub fn dump() {
+    let actions = IRQS.lock().actions;
+    print!("irq ");
+    for hart in 0..NCPU {
+        print!(" {:>8}", hart);
+    }
+    println!();
+    for (irq, action) in actions.iter().enumerate() {
+        if let Some(action) = action {
+            print!("{:>3}:", irq);
+            for count in COUNTS[irq].iter() {
+                print!(" {:>8}", count.load(Ordering::Relaxed));
+            }
+            println!("  prio {} {}", action.priority, action.name);
+        }
+    }
+    print!("ERR:");
+    for count in SPURIOUS.iter() {
+        print!(" {:>8}", count.load(Ordering::Relaxed));
+    }
+    println!("  spurious");
+}
+
 /// 声明当前待处理的中断
 ///
 /// # 功能说明
@@ -137,3 +303,30 @@ fn write(offset: usize, value: u32) {
         ptr::write_volatile(dst, value);
     }
 }
+
+#[cfg(feature = "unit_test")]
+pub mod tests {
+    use super::*;
+    use crate::ktest::TestHarts;
+
+    kernel_test!(register, TestHarts::Hart(0));
+
+    fn nop(_irq: usize) {}
+
+    /// 登记与注销处理函数，使用一个不会有设备的中断号
+    pub fn register() {
+        let irq = NIRQ - 1;
+        assert!(register_irq(0, "test", nop, 1, ALL_HARTS).is_err());
+        assert!(register_irq(NIRQ, "test", nop, 1, ALL_HARTS).is_err());
+        assert!(register_irq(irq, "test", nop, 0, ALL_HARTS).is_err());
+        assert!(register_irq(irq, "test", nop, PLIC_MAX_PRIORITY + 1, ALL_HARTS).is_err());
+        assert!(register_irq(irq, "test", nop, 1, 0).is_err());
+
+        assert_eq!(register_irq(irq, "test", nop, 1, 1), Ok(()));
+        assert!(register_irq(irq, "test", nop, 1, 1).is_err());
+        unregister_irq(irq);
+        assert_eq!(register_irq(irq, "test", nop, PLIC_MAX_PRIORITY, ALL_HARTS), Ok(()));
+        unregister_irq(irq);
+        assert_eq!(irq_count(irq), 0);
+    }
+}
@@ -103,8 +103,14 @@ macro_rules! kerror {
 
 #[panic_handler]
 fn panic(info: &panic::PanicInfo<'_>) -> ! {
+    // 正在运行的测试用例 panic 时不会返回，而是回到测试运行器
+    #[cfg(feature = "unit_test")]
+    crate::ktest::catch_panic(info);
+
     crate::kerror!("{}\n", info);
     PANICKED.store(true, Ordering::Relaxed);
+    // 其余硬件线程关中断停机，不再改动内核状态或输出
+    crate::ipi::stop_others();
     loop {}
 }
 
@@ -120,31 +126,24 @@ fn abort() -> ! {
 /// 单元测试模块
 #[cfg(feature = "unit_test")]
 pub mod tests {
-    use crate::consts::NSMP;
-    use crate::proc::cpu_id;
-    use core::sync::atomic::{AtomicU8, Ordering};
+    use crate::ktest::TestHarts;
+    use crate::process::CpuManager;
+
+    // printf 在 ktest 之前声明，宏要按路径引用
+    crate::kernel_test!(println_simo, TestHarts::All);
 
     /// 多核同步打印测试
     ///
     /// # 测试点
     /// 验证多核环境下println!的同步输出能力：
-    /// 1. 使用原子计数器确保所有核心同时开始测试
+    /// 1. 测试框架保证所有核心同时开始测试
     /// 2. 每个核心连续输出10行带核心ID的信息
-    /// 3. 使用原子计数器确保所有核心完成测试
     pub fn println_simo() {
-        let cpu_id = unsafe { cpu_id() };
-
-        // 使用 NSMP 来同步测试 pr 的自旋锁
-        static NSMP: AtomicU8 = AtomicU8::new(0);
-        NSMP.fetch_add(1, Ordering::Relaxed);
-        while NSMP.load(Ordering::Relaxed) != NSMP as u8 {}
+        let cpu_id = unsafe { CpuManager::cpu_id() };
 
         for i in 0..10 {
             println!("println_mul_hart{}: hart {}", i, cpu_id);
         }
-
-        NSMP.fetch_sub(1, Ordering::Relaxed);
-        while NSMP.load(Ordering::Relaxed) != 0 {}
     }
 }
 
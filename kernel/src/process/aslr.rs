@@ -0,0 +1,69 @@
+//! 用户地址空间布局随机化（ASLR）
+//!
+//! 打开时 exec 把位置无关可执行文件（ET_DYN）的加载地址、用户栈和堆的起点各自随机后移若干页，
+//! 随机数来自内核随机数接口（见 [`random_below`]）。移开后留下的空洞不属于任何区域，访问时报告段错误。
+//! 还没有 mmap，没有映射区的基址需要随机化。
+//!
+//! 默认关闭。启动参数中有 `aslr`（`make qemu BOOTARGS=aslr`）时第一个进程打开，
+//! 子进程继承父进程的设置；进程用 `setaslr` 系统调用改变自己的设置，在下一次 exec 时生效。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::consts::PAGE_SIZE;
+use crate::driver::random::random_below;
+use crate::fdt::boot_fdt;
+
+/// ET_DYN 程序加载地址的随机范围（页数），256 MiB
+const LOAD_RANDOM_PAGES: usize = 1 << 16;
+/// 用户栈起点的随机范围（页数），64 MiB
+const STACK_RANDOM_PAGES: usize = 1 << 14;
+/// 堆起点的随机范围（页数），64 MiB
+const HEAP_RANDOM_PAGES: usize = 1 << 14;
+
+/// 第一个进程是否打开 ASLR
+static BOOT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 读取启动参数中的 ASLR 开关
+///
+/// # 安全性
+/// 仅在系统启动时由 0 号核心调用一次，在内核随机数初始化之后、创建第一个进程之前。
+pub unsafe fn init() {
+    if boot_fdt().map_or(false, |fdt| fdt.has_bootarg("aslr")) {
+        BOOT_ENABLED.store(true, Ordering::Relaxed);
+        println!("aslr: enabled");
+    }
+}
+
+/// 第一个进程的 ASLR 设置
+pub fn boot_enabled() -> bool {
+    BOOT_ENABLED.load(Ordering::Relaxed)
+}
+
+/// exec 时一个进程的随机偏移，均按页对齐；关闭 ASLR 时全部为 0
+#[derive(Clone, Copy, Default)]
+pub struct Layout {
+    /// ET_DYN 程序的加载地址
+    pub load_bias: usize,
+    /// 程序段末尾与用户栈之间的空洞
+    pub stack_gap: usize,
+    /// 用户栈与堆之间的空洞
+    pub heap_gap: usize,
+}
+
+impl Layout {
+    /// 为打开了 ASLR 的进程取一组新的随机偏移
+    pub fn new(enabled: bool) -> Self {
+        if !enabled {
+            return Self::default();
+        }
+        Self {
+            load_bias: random_pages(LOAD_RANDOM_PAGES),
+            stack_gap: random_pages(STACK_RANDOM_PAGES),
+            heap_gap: random_pages(HEAP_RANDOM_PAGES),
+        }
+    }
+}
+
+fn random_pages(pages: usize) -> usize {
+    random_below(pages as u64) as usize * PAGE_SIZE
+}
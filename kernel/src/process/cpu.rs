@@ -5,11 +5,14 @@ use alloc::sync::Arc;
 
 use super::{proc::ProcExcl, Context, ProcState, Process, PROC_MANAGER};
 use crate::consts::NCPU;
+use crate::mm::kvm_sync_hart;
 use crate::process::proc::manager::fetch_task;
 use crate::process::task::task::{Task, TaskStatus};
-use crate::process::PROCFIFO;
+use crate::process::RUNQUEUES;
 use crate::register::{sstatus, tp};
 use crate::spinlock::SpinLockGuard;
+use crate::time;
+use crate::trap::set_next_timer;
 
 /// 全局 CPU 管理器实例
 ///
@@ -126,14 +129,16 @@ impl CpuManager {
     /// # 流程解释
     /// 1. 调用 `my_cpu_mut()` 获取当前 CPU 的可变引用。
     /// 2. 进入无限循环，确保设备中断打开以允许硬件中断响应。
-    /// 3. 通过 `PROC_MANAGER.alloc_runnable()` 尝试获取一个可运行的进程。
+    /// 3. 从本硬件线程的运行队列取一个可运行的进程，队列为空时从最忙的队列窃取。
     ///    - 若成功，设置当前 CPU 的 `proc` 指针指向该进程。
     ///    - 获取该进程的排他锁，修改进程状态为 `RUNNING`。
     ///    - 调用外部汇编函数 `swtch`，完成从调度器上下文切换到进程上下文。
     ///    - 上下文切换返回后，检查 `proc` 是否为空，
     ///      若为空则触发 panic，说明调度异常。
     ///    - 清空 `proc` 指针，释放进程锁。
-    /// 4. 若无可运行进程，继续循环等待。
+    /// 4. 若无可运行进程，关中断后登记为空闲并再确认一次，把时钟设为最早的定时器到期时间
+    ///    并执行 `wfi`，直到时钟中断、设备中断或其他硬件线程放入进程后发来的核间中断到来，
+    ///    空闲时间计入调度统计；此后选中进程时重新打开周期性的 tick。
     ///
     /// # 参数
     /// - `&mut self`：`CpuManager` 的可变引用，允许修改 CPU 相关状态。
@@ -160,17 +165,20 @@ impl CpuManager {
         }
 
         let cpu: &mut Cpu = self.my_cpu_mut();
+        let id = Self::cpu_id();
+        RUNQUEUES.set_online(id);
 
         loop {
             //  确保设备能够中断
             sstatus::intr_on();
 
-            // 使用 ProcManager 查找一个可运行的进程
-            match {
-                let res = PROCFIFO.lock().fetch();
-                res
-            } {
+            // 从本硬件线程的运行队列取一个进程，队列为空时从其他硬件线程窃取
+            match RUNQUEUES.fetch(id) {
                 Some(process) => {
+                    if cpu.tickless {
+                        set_next_timer(true);
+                        cpu.tickless = false;
+                    }
                     cpu.process = Some(process as _);
                     let process = process as *mut Process;
                     let task = &mut *process;
@@ -179,6 +187,9 @@ impl CpuManager {
                     let old_context = &mut cpu.scheduler as *mut Context;
                     let new_context = task.data.get_mut().get_context();
 
+                    // 进程的内核栈可能是其他硬件线程新映射的
+                    kvm_sync_hart();
+
                     switch(old_context, new_context);
                     if cpu.process.is_none() {
                         panic!("context switch back with no process reference");
@@ -186,7 +197,18 @@ impl CpuManager {
                     cpu.process = None;
                     drop(guard);
                 }
-                None => {}
+                None => {
+                    // 关中断后登记为空闲再检查一次，避免刚放入本队列的进程被忽略后睡过头
+                    sstatus::intr_off();
+                    if RUNQUEUES.enter_idle(id) {
+                        set_next_timer(false);
+                        cpu.tickless = true;
+                        // 中断关闭时 wfi 仍会因待处理的中断返回，回到循环开头打开中断后处理它
+                        let start = time::mtime();
+                        core::arch::asm!("wfi");
+                        RUNQUEUES.exit_idle(id, time::mtime_to_ns(time::mtime() - start));
+                    }
+                }
             }
         }
     }
@@ -218,6 +240,9 @@ pub struct Cpu {
     /// 中断使能标志，记录关闭中断之前的中断使能状态，
     /// 用于恢复中断使能。
     intena: bool,
+
+    /// 空闲时停止了周期性的 tick，再次运行进程前需重新设定时钟
+    tickless: bool,
 }
 
 impl Cpu {
@@ -228,9 +253,25 @@ impl Cpu {
             scheduler: Context::new(),
             noff: 0,
             intena: false,
+            tickless: false,
         }
     }
 
+    /// 读取关闭中断的嵌套计数
+    #[cfg(feature = "unit_test")]
+    pub fn noff(&self) -> u8 {
+        self.noff
+    }
+
+    /// 直接设置关闭中断的嵌套计数
+    ///
+    /// # 安全性
+    /// 仅供测试框架在 panic 的用例返回后回滚计数，调用者需保证计数与实际的中断状态一致。
+    #[cfg(feature = "unit_test")]
+    pub unsafe fn set_noff(&mut self, noff: u8) {
+        self.noff = noff;
+    }
+
     /// # 功能说明
     /// 从当前运行的进程上下文切换回调度器上下文。
     /// 该函数在切换期间保持进程的锁（`SpinLockGuard`），
@@ -1,7 +1,6 @@
 //! 进程控制模块
 
 use array_macro::array;
-use lazy_static::lazy_static;
 
 use core::convert::TryFrom;
 use core::mem;
@@ -10,25 +9,29 @@ use core::sync::atomic::Ordering;
 
 use crate::consts::KERNEL_STACK_SIZE;
 use crate::consts::PAGE_SIZE;
-use crate::consts::{NPROC, TRAMPOLINE, fs::ROOTDEV};
+use crate::consts::{NCPU, NPROC, TRAMPOLINE, fs::ROOTDEV};
 use crate::mm::{kvm_map, PhysAddr, PteFlag, VirtAddr, RawPage, RawSinglePage, PageTable, RawQuadPage};
 use crate::process::trapframe::UsysPage;
 use crate::process::proc::pid::PID_ALLOCATOR;
 use crate::process::proc::ProcData;
 use crate::spinlock::SpinLock;
 use crate::trap::user_trap_ret;
-use crate::process::proc::manager::ProcessFIFO;
+use crate::process::proc::manager::{RunQueues, ALL_HARTS};
+pub use crate::process::proc::manager::SchedStat;
 pub use cpu::{pop_off, push_off};
 pub use cpu::{CpuManager, CPU_MANAGER};
 pub use proc::Process;
 
+pub mod aslr;
 mod context;
 mod cpu;
+mod oom;
 mod proc;
+mod swap;
 pub mod trapframe;
 pub mod task;
 
-use context::Context;
+pub use context::Context;
 use proc::ProcState;
 use trapframe::TrapFrame;
 
@@ -83,10 +86,8 @@ pub struct ProcManager {
 
 }
 
-lazy_static!{
-    /// 可运行进程FIFO队列
-    pub static ref PROCFIFO: SpinLock<ProcessFIFO> = SpinLock::new(ProcessFIFO::new(), "pid");
-}
+/// 各硬件线程的可运行进程队列
+pub static RUNQUEUES: RunQueues = RunQueues::new();
 
 impl ProcManager {
     const fn new() -> Self {
@@ -194,8 +195,7 @@ impl ProcManager {
     ///
     /// - 分配陷阱帧或页表失败时，会返回 `None`，
     ///   表示无法完成新进程的分配。
-    /// - 该函数当前未实现OOM（内存不足）恢复机制，
-    ///   资源分配失败直接放弃。
+    /// - 内存不足时释放已分配的页并返回 `None`，不会 panic。
     ///
     /// # 安全性
     ///
@@ -210,25 +210,36 @@ impl ProcManager {
                 ProcState::UNUSED => {
                     // 持有进程的排他锁，因此管理器可以修改其私有数据
                     let pdata = process.data.get_mut();
-                    let pa = p.alarm.get_mut();
-
-                    // 分配陷阱帧
-                    pdata.trapframe = unsafe { RawSinglePage::try_new_zeroed().ok()? as *mut TrapFrame };
-                    pd.up = unsafe { RawSinglePage::try_new_zeroed().ok()? as *mut UsysPage };
-                    pa.alarm_frame = unsafe { RawSinglePage::try_new_zeroed().ok()? as *mut TrapFrame };
+                    let palarm = process.alarm.get_mut();
+
+                    // 分配陷阱帧等页，内存不足时释放已分配的页，进程槽位保持空闲
+                    let mut pages = [ptr::null_mut(); 3];
+                    for page in pages.iter_mut() {
+                        match unsafe { RawSinglePage::try_new_zeroed() } {
+                            Ok(pa) => *page = pa,
+                            Err(_) => {
+                                free_pages(&pages);
+                                return None;
+                            }
+                        }
+                    }
                     let new_pid = PID_ALLOCATOR.lock().pid_alloc();
-                    (unsafe { &mut *pd.up }).pid = new_pid as u32;
                     debug_assert!(pdata.pagetable.is_none());
-                    match PageTable::alloc_proc_pagetable(pdata.trapframe as usize, pd.up as usize, new_pid) {
+                    match PageTable::alloc_proc_pagetable(pages[0] as usize, pages[1] as usize, new_pid) {
                         Some(pgt) => pdata.pagetable = Some(pgt),
                         None => {
-                            unsafe {
-                                RawSinglePage::from_raw_and_drop(pdata.trapframe as *mut u8);
-                            }
+                            PID_ALLOCATOR.lock().pid_dealloc(new_pid);
+                            free_pages(&pages);
                             return None;
                         }
                     }
+                    pdata.trapframe = pages[0] as *mut TrapFrame;
+                    pdata.up = pages[1] as *mut UsysPage;
+                    palarm.alarm_frame = pages[2] as *mut TrapFrame;
+                    (unsafe { &mut *pdata.up }).pid = new_pid as u32;
                     pdata.init_context();
+                    process.cpu.store(NCPU, Ordering::Relaxed);
+                    process.affinity.store(ALL_HARTS, Ordering::Relaxed);
                     guard.pid = new_pid;
                     guard.priority = 255;
                     guard.state = ProcState::ALLOCATED;
@@ -360,7 +371,7 @@ impl ProcManager {
         process.user_init();
         let mut guard = process.excl.lock();
         guard.state = ProcState::RUNNABLE;
-        PROCFIFO.lock().add(process as *const Process);
+        RUNQUEUES.enqueue(process);
     }
 
     /// 检查给定的进程是否是init
@@ -401,7 +412,7 @@ impl ProcManager {
             let mut guard = process.excl.lock();
             if guard.state == ProcState::SLEEPING && guard.channel == channel {
                 guard.state = ProcState::RUNNABLE;
-                PROCFIFO.lock().add(process as *const Process);
+                RUNQUEUES.enqueue(process);
             }
             drop(guard);
         }
@@ -487,6 +498,20 @@ impl ProcManager {
                 .as_mut()
                 .unwrap()
                 .close_files();
+            // 进程不再接收 SIGALRM
+            self.table[exit_index]
+                .data
+                .get()
+                .as_mut()
+                .unwrap()
+                .cancel_itimer();
+            // 立即归还用户内存，被 OOM killer 杀死的进程的父进程可能正在等待内存而没有回收它
+            self.table[exit_index]
+                .data
+                .get()
+                .as_mut()
+                .unwrap()
+                .free_user_memory();
         }
         let pid = self.table[exit_index].excl.lock().pid;
         let mut parent_map = self.parents.lock();
@@ -568,9 +593,13 @@ impl ProcManager {
     /// - 调用 `sleep` 使调用进程阻塞，等待唤醒重新检测，
     ///   需保证唤醒机制和锁释放顺序正确避免死锁。
     fn waiting(&self, pi: usize, addr: usize) -> Result<usize, ()> {
-        let mut parent_map = self.parents.lock();
         let process = unsafe { CPU_MANAGER.my_proc() };
         let pdata = unsafe { process.data.get().as_mut().unwrap() };
+        // 退出状态在持有锁时写入，调入需要读磁盘，先调入目标页
+        if addr != 0 {
+            pdata.page_in_range(addr, mem::size_of::<i32>())?;
+        }
+        let mut parent_map = self.parents.lock();
 
         loop {
             let mut have_child = false;
@@ -618,9 +647,13 @@ impl ProcManager {
         }
     }
     fn waiting_pid(&self, current_pid: usize, child_pid: usize, addr: usize) -> Result<usize, ()> {
-        let mut parent_map = self.parents.lock();
         let process = unsafe { CPU_MANAGER.my_proc() };
         let pdata = unsafe { process.data.get().as_mut().unwrap() };
+        // 退出状态在持有锁时写入，调入需要读磁盘，先调入目标页
+        if addr != 0 {
+            pdata.page_in_range(addr, mem::size_of::<i32>())?;
+        }
+        let mut parent_map = self.parents.lock();
         let mut child_index = 0usize;
         for i in 0..NPROC {
             if self.table[i].excl.lock().pid == child_pid  {
@@ -708,6 +741,7 @@ impl ProcManager {
                 self.table[i].killed.store(true, Ordering::Relaxed);
                 if guard.state == ProcState::SLEEPING {
                     guard.state = ProcState::RUNNABLE;
+                    RUNQUEUES.enqueue(&self.table[i]);
                 }
                 return Ok(());
             }
@@ -715,6 +749,51 @@ impl ProcManager {
 
         Err(())
     }
+
+    /// 在控制台打印进程列表（进程号、状态和名字），终端收到 Ctrl-P 时调用，用于调试
+    ///
+    /// 只持有进程锁读取状态，名字不加锁读取，可能与正在 exec 的进程不一致。
+    pub fn procdump(&self) {
+        println!();
+        for process in self.table.iter() {
+            let guard = process.excl.lock();
+            if guard.state == ProcState::UNUSED {
+                continue;
+            }
+            let (pid, state) = (guard.pid, guard.state);
+            drop(guard);
+            let name = unsafe { (*process.data.get()).name() };
+            println!("{} {:?} {}", pid, state, core::str::from_utf8(name).unwrap_or("???"));
+        }
+    }
+
+    /// 查找进程号为 `pid` 的进程
+    fn find(&self, pid: usize) -> Option<&Process> {
+        self.table.iter().find(|process| {
+            let guard = process.excl.lock();
+            guard.pid == pid && guard.state != ProcState::UNUSED
+        })
+    }
+
+    /// 设置进程 `pid` 的亲和性掩码，此后它只在掩码中的硬件线程上运行
+    ///
+    /// 正在其他硬件线程上运行或排队的进程在下次入队或被取出时迁移。
+    ///
+    /// # 可能的错误
+    /// 进程不存在，或掩码中没有已启动的硬件线程
+    pub fn set_affinity(&self, pid: usize, mask: usize) -> Result<(), ()> {
+        let mask = mask & ALL_HARTS;
+        if mask & RUNQUEUES.online_mask() == 0 {
+            return Err(())
+        }
+        self.find(pid).ok_or(())?.affinity.store(mask, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 读取进程 `pid` 的亲和性掩码
+    pub fn get_affinity(&self, pid: usize) -> Result<usize, ()> {
+        Ok(self.find(pid).ok_or(())?.affinity.load(Ordering::Relaxed))
+    }
 }
 
 /// fork 创建的子进程首次被调度器调度时，
@@ -740,6 +819,13 @@ unsafe fn fork_ret() -> ! {
     user_trap_ret();
 }
 
+/// 释放 `alloc_proc` 中已分配的页，空指针表示尚未分配
+fn free_pages(pages: &[*mut u8]) {
+    for &page in pages.iter().filter(|page| !page.is_null()) {
+        unsafe { RawSinglePage::from_raw_and_drop(page); }
+    }
+}
+
 /// # 功能说明
 ///
 /// 根据进程索引 `pos` 计算该进程的内核栈虚拟地址起始位置。
@@ -0,0 +1,59 @@
+//! 内存耗尽（OOM）处理
+//!
+//! 系统调用中的分配失败以 ENOMEM 返回给用户程序；缺页时没有调用者可以返回错误，
+//! 只能腾出内存：先把用户页换出到交换区（见 `swap`），没有页可以换出时
+//! 杀死常驻内存最多的进程，它退出时立即归还用户内存（见 `exiting`），
+//! 缺页的进程让出 CPU，之后重新执行出错的指令。
+
+use core::sync::atomic::Ordering;
+
+use crate::mm::frame::FRAMES;
+
+use super::{ProcManager, ProcState};
+
+impl ProcManager {
+    /// 缺页时物理内存耗尽，选择并杀死一个进程
+    ///
+    /// # 功能说明
+    /// 在 init 以外的活跃进程中选择常驻内存页数最多的进程杀死。
+    /// 已经被杀死但还没有归还内存的进程优先，此时不再杀死别的进程。
+    ///
+    /// # 返回值
+    /// 被选中的进程在进程表中的索引，可能就是缺页的进程。
+    ///
+    /// # 可能的错误
+    /// 缺页的是 init 且没有其他进程可杀时 panic。
+    ///
+    /// # 安全性
+    /// 持有每个进程的锁读取它的页表，页表在进程回收和 `exec` 替换时都要持有这个锁，
+    /// 读取期间页表页不会被释放。调用者不能持有自旋锁。
+    pub fn oom_kill(&self) -> usize {
+        let mut victim: Option<(usize, usize, usize)> = None;
+        for process in self.table.iter() {
+            if self.is_init_proc(process) {
+                continue;
+            }
+            let guard = process.excl.lock();
+            if !matches!(guard.state, ProcState::RUNNABLE | ProcState::RUNNING | ProcState::SLEEPING) {
+                continue;
+            }
+            let pages = unsafe { (*process.data.get()).resident_pages() };
+            if process.killed.load(Ordering::Relaxed) && pages > 0 {
+                // 上一个被杀死的进程还没有退出，等它归还内存
+                return process.index();
+            }
+            if victim.map_or(true, |(_, _, most)| pages > most) {
+                victim = Some((process.index(), guard.pid, pages));
+            }
+        }
+
+        // 缺页的进程本身正在运行，只有它是 init 时才可能没有可选的进程
+        let (index, pid, pages) = victim.expect("oom: out of memory and no process to kill");
+        println!(
+            "oom: killed pid {} ({} pages resident), {} pages free",
+            pid, pages, FRAMES.lock().free_pages()
+        );
+        let _ = self.kill(pid);
+        index
+    }
+}
@@ -1,21 +1,211 @@
 //! 从文件系统加载ELF文件开始执行
+//!
+//! 程序段不在 exec 时读入，只记录在进程的 [`ExecImage`] 中，第一次访问缺页时才从文件读入
+//! （见 [`ExecImage::page_in`]）。不可写的段（代码）的页缓存在 inode 中，
+//! 运行同一程序的进程共享这些只读页。
+//!
+//! 段的页按程序头的读、写、执行标志映射，可写的段不能同时可执行（W^X）。
+//! 每个线程的用户栈下方有一个保护页，栈溢出时缺页并报告段错误。
+//!
+//! 位置无关程序（ET_DYN）整体后移到 `ELF_DYN_BASE` 之上加载，exec 处理它 `.rela.dyn` 中的
+//! `R_RISCV_RELATIVE` 重定位，不支持需要动态链接器的程序。进程打开 ASLR 时，
+//! 加载地址、用户栈和堆的起点随机后移，见 [`aslr`]。
+//!
+//! 用户栈上依次是参数和环境变量字符串、`AT_RANDOM` 的 16 个随机字节、（没有被加载时）程序头表的副本，
+//! 栈指针处是 argc，之后是以空指针结尾的 argv 和 envp 数组以及辅助向量。
+//! 新程序从 a0、a1、a2 得到 argc、argv 和 envp。
 use alloc::{sync::Arc, task};
-use alloc::boxed::Box;
 use alloc::str;
+use alloc::vec::Vec;
 use core::{cmp::min, convert::TryFrom, mem::{self, MaybeUninit}};
+use core::sync::atomic::Ordering;
 
+use crate::process::aslr;
 use crate::process::proc::manager::add_task;
-use crate::process::PROCFIFO;
 use crate::{consts::MAX_TASKS_PER_PROC, mm::pagetable::ustack_bottom_by_pos, process::task::task::Task};
-use crate::{consts::{MAXARG, MAXARGLEN, MAXVA, PAGE_SIZE, USER_STACK_SIZE}, sleeplock::SleepLockGuard};
-use crate::mm::{Address, PageTable, Addr, VirtAddr, pg_round_up};
-use crate::fs::{ICACHE, Inode, LOG, InodeData};
+use crate::consts::{MAXVA, PAGE_SIZE, USER_STACK_SIZE};
+use crate::exec_args::ExecArgs;
+use crate::mm::{Address, PageTable, PhysAddr, PteFlag, RawPage, RawSinglePage, VirtAddr, pg_round_down, pg_round_up};
+use crate::mm::asid;
+use crate::fs::{ICACHE, Inode, LOG};
+use crate::driver::random::random_u64;
 
 use super::Process;
+use super::syscall::ENOMEM;
+
+/// 进程正在运行的可执行文件映像
+pub struct ExecImage {
+    /// 可执行文件的 inode，释放最后一个引用需要在文件系统事务中，见 [`put_image`]
+    inode: Inode,
+    /// 可加载的程序段
+    segments: Vec<Segment>,
+}
+
+/// 可加载的程序段：`[start, end)` 的前 `filesz` 字节来自文件偏移 `offset`，其余为零
+struct Segment {
+    /// 起始虚拟地址，按页对齐
+    start: usize,
+    end: usize,
+    offset: u32,
+    filesz: u32,
+    /// 映射段的页使用的权限，来自程序头的标志
+    perm: PteFlag,
+    /// 段不可写，它的页只读映射并在进程间共享
+    shared: bool,
+}
+
+impl ExecImage {
+    /// 用户地址 `va` 是否属于某个程序段
+    pub fn contains(&self, va: usize) -> bool {
+        self.segment(va).is_some()
+    }
+
+    /// 从可执行文件读入 `va` 所在的页并映射到页表 `pgt`
+    ///
+    /// # 功能说明
+    /// 不可写的段从 inode 的页缓存取得共享的只读页（见 [`InodeData::shared_page`]），
+    /// 可写的段读入进程私有的页。页按段的权限映射。文件在运行期间被截断时，超出文件末尾的部分读作零。
+    ///
+    /// # 可能的错误
+    /// 物理页或页表页分配失败，内存已经耗尽
+    ///
+    /// # 安全性
+    /// 读文件会睡眠，调用者不能持有自旋锁。
+    ///
+    /// [`InodeData::shared_page`]: crate::fs::InodeData::shared_page
+    pub fn page_in(&self, pgt: &mut PageTable, va: usize) -> Result<(), ()> {
+        let va = pg_round_down(va);
+        let seg = self.segment(va).ok_or(())?;
+        let i = va - seg.start;
+        let count = min((seg.filesz as usize).saturating_sub(i), PAGE_SIZE) as u32;
+        let offset = seg.offset + i as u32;
+
+        let mut idata = self.inode.lock();
+        let pa = if seg.shared && count > 0 {
+            idata.shared_page(offset, count)?
+        } else {
+            let mem = unsafe { RawSinglePage::try_new_zeroed() }.map_err(|_| ())?;
+            if count > 0 {
+                // 只会因为超出文件末尾少读，不会失败
+                let _ = idata.try_iread(Address::KernelMut(mem), offset, count);
+            }
+            mem as usize
+        };
+        drop(idata);
+
+        let va = VirtAddr::try_from(va).map_err(|_| ())?;
+        if pgt.map_pages(va, PAGE_SIZE, unsafe { PhysAddr::from_raw(pa) }, seg.perm).is_err() {
+            unsafe { RawSinglePage::from_raw_and_drop(pa as *mut u8); }
+            return Err(())
+        }
+        Ok(())
+    }
+
+    /// 处理位置无关程序的重定位，`dynamic` 是 `PT_DYNAMIC` 段在文件中的 (偏移, 大小)
+    ///
+    /// # 功能说明
+    /// 从 `.dynamic` 中找到 `.rela.dyn`，把每个 `R_RISCV_RELATIVE` 重定位的位置改写为
+    /// `load_bias + addend`。被改写的页在这里读入并成为进程私有的页，之后不会再从文件读入。
+    ///
+    /// # 可能的错误
+    /// 动态段或重定位表不合法、有不支持的重定位类型或要改写只读的段，
+    /// 以及内存不足（[`RELOC_NOMEM`]）时返回错误。
+    ///
+    /// # 安全性
+    /// 读文件会睡眠，调用者不能持有自旋锁，也不能持有可执行文件 inode 的锁。
+    fn relocate(&self, pgt: &mut PageTable, dynamic: (u64, u64), load_bias: usize) -> Result<(), &'static str> {
+        let (mut rela, mut relasz, mut relaent) = (0u64, 0u64, 0u64);
+        let dyn_size = mem::size_of::<Dyn>() as u64;
+        let (dyn_off, dyn_filesz) = dynamic;
+        for i in 0..dyn_filesz / dyn_size {
+            let mut entry = Dyn::default();
+            let off = u32::try_from(dyn_off + i * dyn_size).map_err(|_| "bad dynamic section")?;
+            self.read(&mut entry as *mut Dyn as *mut u8, off, dyn_size as u32)
+                .map_err(|_| "bad dynamic section")?;
+            match entry.tag {
+                DT_NULL => break,
+                DT_RELA => rela = entry.val,
+                DT_RELASZ => relasz = entry.val,
+                DT_RELAENT => relaent = entry.val,
+                _ => {}
+            }
+        }
+        if relasz == 0 {
+            return Ok(())
+        }
+        if relaent != mem::size_of::<Rela>() as u64 || relasz % relaent != 0 {
+            return Err("bad relocation table")
+        }
+
+        // 重定位表的地址是链接时的虚拟地址，找到它在文件中的位置
+        let start = (rela as usize).checked_add(load_bias).ok_or("bad relocation table")?;
+        let seg = self.segment(start).ok_or("bad relocation table")?;
+        if start + relasz as usize > seg.start + seg.filesz as usize {
+            return Err("bad relocation table")
+        }
+        let table = seg.offset + (start - seg.start) as u32;
+
+        let mut batch = [Rela::default(); RELA_BATCH];
+        let count = (relasz / relaent) as usize;
+        for first in (0..count).step_by(RELA_BATCH) {
+            let n = min(RELA_BATCH, count - first);
+            let off = table + (first * mem::size_of::<Rela>()) as u32;
+            self.read(batch.as_mut_ptr() as *mut u8, off, (n * mem::size_of::<Rela>()) as u32)
+                .map_err(|_| "bad relocation table")?;
+            for rel in &batch[..n] {
+                match rel.info as u32 {
+                    R_RISCV_NONE => continue,
+                    R_RISCV_RELATIVE => {}
+                    _ => return Err("unsupported relocation type"),
+                }
+                let va = (rel.offset as usize).checked_add(load_bias).ok_or("bad relocation")?;
+                let value = (load_bias as u64).wrapping_add(rel.addend as u64);
+                self.write_word(pgt, va, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 重定位时把 `value` 写到可写段中的用户地址 `va`，必要时先读入所在的页
+    fn write_word(&self, pgt: &mut PageTable, va: usize, value: u64) -> Result<(), &'static str> {
+        let last = va.checked_add(mem::size_of::<u64>() - 1).ok_or("bad relocation")?;
+        for addr in [va, last] {
+            let seg = self.segment(addr).ok_or("relocation outside the program")?;
+            if !seg.perm.contains(PteFlag::W) {
+                return Err("relocation in read-only segment")
+            }
+            let page = VirtAddr::try_from(pg_round_down(addr)).map_err(|_| "bad relocation")?;
+            if pgt.find_pa(page).is_err() {
+                self.page_in(pgt, addr).map_err(|_| RELOC_NOMEM)?;
+            }
+        }
+        pgt.copy_out(&value as *const u64 as *const u8, va, mem::size_of::<u64>())
+            .map_err(|_| "bad relocation")
+    }
+
+    /// 从可执行文件的 `off` 处读 `count` 字节到内核地址 `dst`，不能超出文件末尾
+    fn read(&self, dst: *mut u8, off: u32, count: u32) -> Result<(), ()> {
+        self.inode.lock().iread(Address::KernelMut(dst), off, count)
+    }
+
+    /// `va` 所在的页属于的段，段最后一页中超出段末尾的部分也算
+    fn segment(&self, va: usize) -> Option<&Segment> {
+        self.segments.iter().find(|seg| seg.start <= va && va < pg_round_up(seg.end))
+    }
+}
+
+/// 在文件系统事务中释放进程对映像的引用
+///
+/// 最后一个引用释放时放下可执行文件的 inode，文件已被删除时会在这里截断。
+pub fn put_image(image: Arc<ExecImage>) {
+    LOG.begin_op();
+    drop(image);
+    LOG.end_op();
+}
 
 /// 功能说明
 /// 该函数用于将指定路径（path）对应的 ELF 可执行文件加载到进程（Proc）的用户空间中，
-/// 并将传入的命令行参数（argv）准备好放入用户栈，最终完成进程的内存映射、栈初始化及入口点设置。
+/// 并将传入的命令行参数（argv）和环境变量（envp）准备好放入用户栈，最终完成进程的内存映射、栈初始化及入口点设置。
 ///
 /// 流程解释
 /// 1. 根据给定路径查找并获取对应的文件 inode。
@@ -23,20 +213,21 @@ use super::Process;
 /// 3. 为进程分配新的页表（PageTable），尚未替换进程当前页表。
 /// 4. 依次读取 ELF 程序头表的每个段信息，
 ///    - 验证程序段的合法性（大小、地址对齐等）
-///    - 为程序段分配用户虚拟内存空间
-///    - 加载程序段数据到相应虚拟地址
-/// 5. 在程序段末尾分配两页用户栈空间（一页作为栈，另一页作为栈保护页）。
-/// 6. 将传入的命令行参数逐个拷贝进用户栈，构造用户栈上的 argv 数组。
-/// 7. 更新进程数据结构中的页表、地址空间大小、程序入口点（epc）和栈指针（sp）。
-/// 8. 释放旧的页表对应资源，返回命令行参数数量。
+///    - 把程序段记录到新的 [`ExecImage`] 中，段的内容在第一次访问缺页时才读入
+///    - 位置无关程序的段整体后移加载，之后按动态段处理重定位
+/// 5. 在程序段末尾为每个线程预留栈槽并设置保护页，为第一个线程分配用户栈；
+///    打开 ASLR 时栈槽和之后的堆各自随机后移。
+/// 6. 将传入的参数和环境变量拷贝进用户栈，构造用户栈上的 argc、argv 和 envp 数组以及之后的辅助向量。
+/// 7. 更新进程数据结构中的页表、映像、地址空间大小、程序入口点（epc）和栈指针（sp）。
+/// 8. 释放旧的页表与映像，返回命令行参数数量。
 ///
 /// 参数
 /// - `p: &mut Proc`
 ///   目标进程的可变引用，用于加载可执行文件及更新进程状态。
 /// - `path: &[u8]`
 ///   ELF 文件路径的字节切片表示，需满足文件系统格式。
-/// - `argv: &[Option<Box<[u8; MAXARGLEN]>>]`
-///   命令行参数数组，元素为可选的固定大小字节数组，参数内容必须以空字节结尾。
+/// - `args: &ExecArgs`
+///   从调用者读入的命令行参数和环境变量，见 [`ExecArgs`]。
 ///
 /// 返回值
 /// - `Result<usize, &'static str>`
@@ -47,23 +238,28 @@ use super::Process;
 /// - 无法定位到指定路径对应的 inode（"cannot name inode"）
 /// - 读取 ELF 文件头失败（"cannot read elf inode"）
 /// - ELF 魔数校验失败（"bad elf magic number"）
+/// - 既不是 ET_EXEC 也不是 ET_DYN，或程序头大小不对（"not an executable"）
+/// - 需要动态链接器（"dynamically linked executable"）
 /// - 内存不足，无法分配新页表（"mem not enough"）
 /// - 读取程序头失败（"cannot read elf program header"）
 /// - 程序头元数据不合法（"one program header meta not correct"）
-/// - 用户虚拟内存不足，无法为程序段分配空间（"not enough uvm for program header"）
-/// - 程序段加载失败（"load program section error"）
+/// - 程序段同时可写和可执行（"segment is writable and executable"）
+/// - 位置无关程序的动态段或重定位不合法，或重定位时内存不足，见 [`ExecImage::relocate`]
+/// - 内存不足，无法记录程序段（"not enough memory for exec image"）
 /// - 用户虚拟内存不足，无法分配用户栈（"not enough uvm for user stack"）
 /// - 命令行参数拷贝失败或超出栈空间限制（"cmd args too much for stack" / "copy cmd args to pagetable go wrong"）
+/// - 内存不足，无法构造 argv 和 envp 数组（"not enough memory for cmd args"）
 ///
 /// 安全性
 /// - 该函数通过严格校验 ELF 头与程序段元数据保证加载的合法性，避免内存越界和地址不对齐的问题。
 /// - 加载过程使用 Rust 的所有权机制和显式资源释放（drop）确保 inode、页表等资源及时释放，防止内存泄漏。
-/// - 参数字符串长度和堆栈空间均有严格限制，防止栈溢出。
+///   映像持有可执行文件的 inode，在事务之外只通过 [`put_image`] 释放。
+/// - 参数和环境变量的总大小不超过 [`ARG_MAX`](crate::consts::ARG_MAX)，只占用户栈的一半，防止栈溢出。
 /// - 该函数中存在大量 `unsafe` 操作（如 `assume_init` 和裸指针转换），
 ///   调用时必须保证输入路径和 ELF 文件的完整正确性，否则可能引发未定义行为。
 /// - 新页表替换旧页表时保证旧资源释放，避免内存泄漏或悬挂指针。
 /// - 不允许中断或异步信号干扰该过程，确保加载一致性。
-pub fn load(process: &mut Process, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) -> Result<usize, &'static str> {
+pub fn load(process: &mut Process, path: &[u8], args: &ExecArgs) -> Result<usize, &'static str> {
     // get relevant inode using path
     let inode: Inode;
     LOG.begin_op();
@@ -92,6 +288,12 @@ pub fn load(process: &mut Process, path: &[u8], argv: &[Option<Box<[u8; MAXARGLE
         drop(idata); drop(inode); LOG.end_op();
         return Err("bad elf magic number")
     }
+    if (elf.elf_type != ELF_TYPE_EXEC && elf.elf_type != ELF_TYPE_DYN)
+        || elf.phentsize as usize != mem::size_of::<ProgHeader>()
+    {
+        drop(idata); drop(inode); LOG.end_op();
+        return Err("not an executable")
+    }
 
     let pid = process.excl.lock().pid;
     let process_ptr = process as *mut Process;
@@ -102,13 +304,28 @@ pub fn load(process: &mut Process, path: &[u8], argv: &[Option<Box<[u8; MAXARGLE
         Some(res) => pgt = res,
         None => {
             drop(idata); drop(inode); LOG.end_op();
+            pdata.set_errno(ENOMEM);
             return Err("mem not enough")
         },
     }
 
+    // 位置无关程序整体后移 load_bias 加载
+    let layout = aslr::Layout::new(pdata.aslr);
+    let load_bias = if elf.elf_type == ELF_TYPE_DYN { ELF_DYN_BASE + layout.load_bias } else { 0 };
+    // 动态段在文件中的 (偏移, 大小)，以及加载后程序头表的地址
+    let mut dynamic = None;
+    let mut phdr = None;
+
     let mut proc_size = 0usize;
+    let mut segments = Vec::new();
+    if segments.try_reserve_exact(elf.phnum as usize).is_err() {
+        pgt.dealloc_proc_pagetable(proc_size,pid);
+        drop(pgt); drop(idata); drop(inode); LOG.end_op();
+        pdata.set_errno(ENOMEM);
+        return Err("not enough memory for exec image")
+    }
 
-    // load each program section
+    // record each program section, its pages are read in on first access
     let ph_size = mem::size_of::<ProgHeader>() as u32;
     let mut off = elf.phoff as u32;
     for _ in 0..elf.phnum {
@@ -119,96 +336,117 @@ pub fn load(process: &mut Process, path: &[u8], argv: &[Option<Box<[u8; MAXARGLE
             return Err("cannot read elf program header")
         }
         let ph = unsafe { ph.assume_init() };
-        
+
+        match ph.pg_type {
+            ELF_PROG_INTERP => {
+                pgt.dealloc_proc_pagetable(proc_size,pid);
+                drop(pgt); drop(idata); drop(inode); LOG.end_op();
+                return Err("dynamically linked executable")
+            }
+            ELF_PROG_DYNAMIC => dynamic = Some((ph.off, ph.filesz)),
+            _ => {}
+        }
         if ph.pg_type != ELF_PROG_LOAD {
             off += ph_size;
             continue;
         }
 
-        if ph.memsz < ph.filesz || ph.vaddr + ph.memsz < ph.vaddr || ph.vaddr % (PAGE_SIZE as u64) != 0 {
+        // 段必须位于堆可以增长到的范围之内，文件中的内容必须能用 u32 偏移访问
+        let vaddr = ph.vaddr.wrapping_add(load_bias as u64);
+        if ph.memsz < ph.filesz || vaddr < ph.vaddr || vaddr + ph.memsz < vaddr || vaddr % (PAGE_SIZE as u64) != 0
+            || vaddr + ph.memsz > (Into::<usize>::into(MAXVA) / 2) as u64
+            || ph.off.checked_add(ph.filesz).map_or(true, |end| end > u32::MAX as u64)
+            || ph.flags & (ELF_PROG_FLAG_READ | ELF_PROG_FLAG_WRITE | ELF_PROG_FLAG_EXEC) == 0
+        {
             pgt.dealloc_proc_pagetable(proc_size,pid);
             drop(pgt); drop(idata); drop(inode); LOG.end_op();
             return Err("one program header meta not correct")
         }
-
-        match pgt.uvm_alloc(proc_size, (ph.vaddr + ph.memsz) as usize) {
-            Ok(cur_size) => proc_size = cur_size,
-            Err(_) => {
-                pgt.dealloc_proc_pagetable(proc_size,pid);
-                drop(pgt); drop(idata); drop(inode); LOG.end_op();
-                return Err("not enough uvm for program header")
-            }
-        }
-
-        if load_seg(pgt.as_mut(), ph.vaddr as usize, &mut idata, ph.off as u32, ph.filesz as u32).is_err() {
+        if ph.flags & ELF_PROG_FLAG_WRITE != 0 && ph.flags & ELF_PROG_FLAG_EXEC != 0 {
             pgt.dealloc_proc_pagetable(proc_size,pid);
             drop(pgt); drop(idata); drop(inode); LOG.end_op();
-            return Err("load program section error")
+            return Err("segment is writable and executable")
+        }
+
+        segments.push(Segment {
+            start: vaddr as usize,
+            end: (vaddr + ph.memsz) as usize,
+            offset: ph.off as u32,
+            filesz: ph.filesz as u32,
+            perm: segment_perm(ph.flags),
+            shared: ph.flags & ELF_PROG_FLAG_WRITE == 0,
+        });
+        proc_size = proc_size.max((vaddr + ph.memsz) as usize);
+        let phdrs_end = elf.phoff + elf.phnum as u64 * ph_size as u64;
+        if ph.off <= elf.phoff && phdrs_end <= ph.off + ph.filesz {
+            phdr = Some((vaddr + elf.phoff - ph.off) as usize);
         }
 
         off += ph_size;
     }
     drop(idata);
-    drop(inode);
+    // 分配失败时 inode 随映像一起在事务中释放
+    let image = Arc::try_new(ExecImage { inode, segments });
     LOG.end_op();
+    let image = match image {
+        Ok(image) => image,
+        Err(_) => {
+            pgt.dealloc_proc_pagetable(proc_size,pid);
+            pdata.set_errno(ENOMEM);
+            return Err("not enough memory for exec image")
+        }
+    };
+    if let Some(dynamic) = dynamic {
+        if let Err(s) = image.relocate(&mut pgt, dynamic, load_bias) {
+            pgt.dealloc_proc_pagetable(proc_size,pid);
+            put_image(image);
+            if s == RELOC_NOMEM {
+                pdata.set_errno(ENOMEM);
+            }
+            return Err(s)
+        }
+    }
+    let entry = elf.entry as usize + load_bias;
 
-    // allocate two page for user stack
-    // one for usage, the other for guarding
-    proc_size = pg_round_up(proc_size);
-    // 准备最多64个线程空间（后续可以优化）
+    // 准备最多64个线程的栈槽，每个槽由保护页和栈组成
+    // 只为第一个线程分配栈，其他槽的栈在访问时按需分配，保护页始终不映射
+    proc_size = pg_round_up(proc_size) + layout.stack_gap;
     pdata.set_ustack_base(proc_size);
     let ustack_base = proc_size;
-
-    match pgt.uvm_alloc(proc_size,  proc_size + MAX_TASKS_PER_PROC*(USER_STACK_SIZE + PAGE_SIZE)) {
-        Ok(ret_size) => proc_size = ret_size,
-        Err(_) => {
-            pgt.dealloc_proc_pagetable(proc_size,pid);
+    let ustack_end = ustack_base + MAX_TASKS_PER_PROC*(USER_STACK_SIZE + PAGE_SIZE);
+    let guarded = (2..=MAX_TASKS_PER_PROC)
+        .all(|pos| pgt.uvm_guard(ustack_bottom_by_pos(ustack_base, pos) - PAGE_SIZE).is_ok());
+    let stack_top = match pgt.uvm_alloc_ustack(ustack_base, 1) {
+        Ok(top) if guarded => top,
+        _ => {
+            pgt.dealloc_proc_pagetable(ustack_end,pid);
+            put_image(image);
+            pdata.set_errno(ENOMEM);
             return Err("not enough uvm for user stack")
         },
-    }
-    for i in 1..=MAX_TASKS_PER_PROC {
-        pgt.uvm_clear(proc_size - i*(USER_STACK_SIZE + PAGE_SIZE));
-    }
-    let first_task_ustack_bottom: usize= ustack_bottom_by_pos(pdata.ustack_base, 1);
-    let mut stack_pointer = first_task_ustack_bottom + USER_STACK_SIZE;
-    let stack_base = first_task_ustack_bottom;
-
-    // prepare command line content in the user stack
-    let argc = argv.len();
-    debug_assert!(argc < MAXARG);
-    let mut ustack = [0usize; MAXARG+1];
-    for i in 0..argc {
-        let arg_slice = argv[i].as_deref().unwrap();
-        let max_pos = arg_slice.iter().position(|x| *x==0).unwrap();
-        let count = max_pos + 1;    // counting the ending zero
-        stack_pointer -= count;
-        stack_pointer = align_sp(stack_pointer);
-        if stack_pointer < stack_base {
-            pgt.dealloc_proc_pagetable(proc_size,pid);
-            return Err("cmd args too much for stack")
-        }
-        if pgt.copy_out(arg_slice.as_ptr(), stack_pointer, count).is_err() {
+    };
+    // 栈槽之后是堆，sbrk 从 heap_start 开始增长
+    let heap_start = ustack_end + layout.heap_gap;
+    proc_size = heap_start;
+
+    // 参数、环境变量、随机字节和辅助向量放入用户栈
+    let (stack_pointer, envp) = match init_stack(&image, &mut pgt, stack_top, args, &elf, phdr, entry) {
+        Ok(res) => res,
+        Err(s) => {
             pgt.dealloc_proc_pagetable(proc_size,pid);
-            return Err("copy cmd args to pagetable go wrong")
+            put_image(image);
+            if s == STACK_NOMEM {
+                pdata.set_errno(ENOMEM);
+            }
+            return Err(s)
         }
-        ustack[i] = stack_pointer;
-    }
-    debug_assert!(argc == 0 || ustack[argc-1] != 0);    // ustack[argc-1] should not be zero
-    debug_assert_eq!(ustack[argc], 0);                  // ustack[argc] should be zero
-    stack_pointer -= (argc+1) * mem::size_of::<usize>();
-    stack_pointer = align_sp(stack_pointer);
-    if stack_pointer < stack_base {
-        pgt.dealloc_proc_pagetable(proc_size,pid);
-        return Err("cmd args too much for stack")
-    }
-    if pgt.copy_out(ustack.as_ptr() as *const u8, stack_pointer, (argc+1)*mem::size_of::<usize>()).is_err() {
-        pgt.dealloc_proc_pagetable(proc_size,pid);
-        return Err("copy cmd args to pagetable go wrong")
-    }
+    };
+    let argv = stack_pointer + mem::size_of::<usize>();
 
     // update the process's info
     let trapframe = unsafe { pdata.trapframe.as_mut().unwrap() };
-    trapframe.a1 = stack_pointer;
+    trapframe.a1 = argv;
+    trapframe.a2 = envp;
     let off = path.iter().position(|x| *x!=b'/').unwrap();
     let count = min(path.len()-off, pdata.name.len());
     for i in 0..count {
@@ -219,95 +457,46 @@ pub fn load(process: &mut Process, path: &[u8], argv: &[Option<Box<[u8; MAXARGLE
         drop(item);
     }
 
+    // 持有进程锁替换页表，OOM killer 持锁遍历其他进程的页表，不会遇到释放了的页表
+    let guard = process.excl.lock();
     let mut old_pgt = pdata.pagetable.replace(pgt).unwrap();
+    drop(guard);
+    if let Some(old_image) = pdata.image.replace(image) {
+        put_image(old_image);
+    }
     let old_size = pdata.size;
     pdata.size = proc_size;
-    trapframe.epc = elf.entry as usize;
+    pdata.heap_start = heap_start;
+    trapframe.epc = entry;
     trapframe.sp = stack_pointer;
-    
-    let task = Task::new(Some(process_ptr), 1, ustack_base ,elf.entry as usize);
-    let task = Arc::new(task);
-    pdata.tasks.push(Some(Arc::clone(&task)));
-    
-    //add_task(Arc::clone(&task));
-    
-    // 清理旧的pagetable
+
+    // 清理旧的pagetable，它的 ASID 作废，下次返回用户态时为新页表分配
+    asid::retire(old_pgt.as_satp());
     old_pgt.dealloc_proc_pagetable(old_size,pid);
     drop(old_pgt);
+    // 旧的堆已经释放，新映像的堆还是空的
+    pdata.uncommit_heap();
 
-    Ok(argc)
-}
-
-/// 功能说明
-/// 将 ELF 程序段的数据加载到指定进程的用户虚拟内存中。
-/// 函数会根据给定的虚拟地址 `va` 和文件偏移 `offset`，
-/// 将文件中 `size` 字节的数据读入用户空间对应的物理页面。
-/// 该函数假设虚拟地址已经按页大小对齐，且对应的虚拟内存页已经被映射。
-///
-/// 流程解释
-/// 1. 检查传入的虚拟地址 `va` 是否页对齐，若不对齐则直接 panic。
-/// 2. 将 `va` 转换为 `VirtAddr` 类型便于地址操作。
-/// 3. 以页为单位循环遍历整个段大小 `size`：
-///    - 使用页表 `pgt` 查询当前虚拟页对应的物理地址，若未映射则 panic。
-///    - 计算本页需要读取的数据字节数（最后一页可能不足一页）。
-///    - 从 inode 数据 `idata` 读取对应偏移位置的数据到物理地址。
-///    - 虚拟地址前进一页，继续加载下一页。
-/// 4. 所有数据加载成功则返回 Ok(())。
-///
-/// 参数
-/// - `pgt: &mut PageTable`
-///   目标进程的页表引用，用于虚拟地址到物理地址的转换。
-/// - `va: usize`
-///   程序段加载的起始虚拟地址，要求页对齐。
-/// - `idata: &mut SleepLockGuard<'_, InodeData>`
-///   文件 inode 数据的锁保护引用，用于读取文件内容。
-/// - `offset: u32`
-///   程序段在文件中的偏移量。
-/// - `size: u32`
-///   程序段的文件大小，表示需要加载的数据字节数。
-///
-/// 返回值
-/// - `Result<(), ()>`
-///   成功时返回 `Ok(())`，
-///   读取文件数据失败时返回 `Err(())`。
-///
-/// 可能的错误
-/// - 虚拟地址 `va` 未按页大小对齐时触发 panic。
-/// - 虚拟地址对应的页未映射时触发 panic。
-/// - 从 inode 读取数据失败时返回 `Err(())`。
-///
-/// 安全性
-/// - 该函数依赖调用者保证虚拟地址已正确映射，
-///   否则会通过 panic 明确提示，避免后续不可控行为。
-/// - 读取文件时持有 inode 数据锁，防止并发访问导致数据竞态。
-/// - 读写操作均使用内核态地址转换，保证内存访问合法。
-/// - 不包含任何 unsafe 代码，符合 Rust 安全编码规范。
-fn load_seg(pgt: &mut PageTable, va: usize, idata: &mut SleepLockGuard<'_, InodeData>, offset: u32, size: u32)
-    -> Result<(), ()>
-{
-    if va % PAGE_SIZE != 0 {
-        panic!("va={} is not page aligned", va);
-    }
-    let mut va = VirtAddr::try_from(va).unwrap();
-
-    for i in (0..size).step_by(PAGE_SIZE) {
-        let pa: usize;
-        match pgt.find_pa_mut(va) {
-            Ok(phys_addr) => pa = phys_addr.into_raw(),
-            Err(s) => panic!("va={} should already be mapped, {}", va.into_raw(), s),
+    // 旧的映像已经不在了，线程创建失败时只能杀死进程
+    let task = Task::new(Some(process_ptr), 1, ustack_base, entry)
+        .and_then(|task| Arc::try_new(task).map_err(|_| ()));
+    match task {
+        Ok(task) => {
+            // 栈顶放着命令行参数，线程从参数下方开始使用栈
+            let task_trapframe = task.inner.lock().get_trap_frame();
+            task_trapframe.sp = stack_pointer;
+            task_trapframe.a1 = argv;
+            task_trapframe.a2 = envp;
+            pdata.tasks.push(Some(task))
         }
-        let count = if size - i < (PAGE_SIZE as u32) {
-            size - i
-        } else {
-            PAGE_SIZE as u32
-        };
-        if idata.iread(Address::KernelMut(pa as *mut u8), offset+i, count).is_err() {
-            return Err(())
+        Err(()) => {
+            pdata.set_errno(ENOMEM);
+            process.killed.store(true, Ordering::Relaxed);
+            return Err("not enough memory for task")
         }
-        va.add_page();
     }
 
-    Ok(())
+    Ok(args.argc())
 }
 
 #[inline(always)]
@@ -315,6 +504,85 @@ fn align_sp(sp: usize) -> usize {
     sp - (sp % 16)
 }
 
+/// 把 `count` 字节压入新程序的用户栈，返回它们在栈上的地址
+fn push_stack(pgt: &mut PageTable, sp: &mut usize, stack_base: usize, src: *const u8, count: usize) -> Result<usize, &'static str> {
+    let addr = align_sp(sp.checked_sub(count).ok_or("cmd args too much for stack")?);
+    if addr < stack_base {
+        return Err("cmd args too much for stack")
+    }
+    pgt.copy_out(src, addr, count).map_err(|_| "copy cmd args to pagetable go wrong")?;
+    *sp = addr;
+    Ok(addr)
+}
+
+/// 在栈顶为 `stack_top` 的新用户栈上依次放入参数和环境变量字符串、`AT_RANDOM` 的随机字节、
+/// （没有被加载时）程序头表的副本，最后是栈指针处的 argc、以空指针结尾的 argv 和 envp 数组以及辅助向量。
+///
+/// 返回新的栈指针和 envp 数组的地址。字符串放不下时返回错误，内存不足时返回 [`STACK_NOMEM`]。
+fn init_stack(
+    image: &ExecImage, pgt: &mut PageTable, stack_top: usize, args: &ExecArgs,
+    elf: &ElfHeader, phdr: Option<usize>, entry: usize,
+) -> Result<(usize, usize), &'static str> {
+    let stack_base = stack_top - USER_STACK_SIZE;
+    let mut sp = stack_top;
+    let strings = push_stack(pgt, &mut sp, stack_base, args.strings().as_ptr(), args.strings().len())?;
+    let random = [random_u64(), random_u64()];
+    let random_addr = push_stack(pgt, &mut sp, stack_base,
+        random.as_ptr() as *const u8, mem::size_of_val(&random))?;
+    let phdr = match phdr {
+        Some(phdr) => phdr,
+        None => copy_phdrs(image, pgt, &mut sp, stack_base, elf)?,
+    };
+    let auxv: [(usize, usize); AUXV_LEN] = [
+        (AT_PHDR, phdr),
+        (AT_PHENT, mem::size_of::<ProgHeader>()),
+        (AT_PHNUM, elf.phnum as usize),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, entry),
+        (AT_RANDOM, random_addr),
+        (AT_NULL, 0),
+    ];
+
+    let mut words = Vec::new();
+    words.try_reserve_exact(args.argc() + args.envc() + 3 + 2 * AUXV_LEN).map_err(|_| STACK_NOMEM)?;
+    let mut addr = strings;
+    let mut pointers = args.strings().split_inclusive(|&c| c == 0).map(|s| {
+        addr += s.len();
+        addr - s.len()
+    });
+    words.push(args.argc());
+    words.extend(pointers.by_ref().take(args.argc()));
+    words.push(0);
+    words.extend(pointers);
+    words.push(0);
+    for &(key, value) in auxv.iter() {
+        words.push(key);
+        words.push(value);
+    }
+    let sp = push_stack(pgt, &mut sp, stack_base,
+        words.as_ptr() as *const u8, words.len() * mem::size_of::<usize>())?;
+    Ok((sp, sp + (args.argc() + 2) * mem::size_of::<usize>()))
+}
+
+/// 程序头表不在任何可加载的段中时，把它复制到新程序的用户栈上，返回它的地址
+fn copy_phdrs(image: &ExecImage, pgt: &mut PageTable, sp: &mut usize, stack_base: usize, elf: &ElfHeader) -> Result<usize, &'static str> {
+    let ph_size = mem::size_of::<ProgHeader>();
+    let size = elf.phnum as usize * ph_size;
+    let addr = align_sp(sp.checked_sub(size).ok_or("cmd args too much for stack")?);
+    if addr < stack_base {
+        return Err("cmd args too much for stack")
+    }
+    for i in 0..elf.phnum as usize {
+        let mut ph = MaybeUninit::<ProgHeader>::uninit();
+        image.read(ph.as_mut_ptr() as *mut u8, elf.phoff as u32 + (i * ph_size) as u32, ph_size as u32)
+            .map_err(|_| "cannot read elf program header")?;
+        pgt.copy_out(ph.as_ptr() as *const u8, addr + i * ph_size, ph_size)
+            .map_err(|_| "copy cmd args to pagetable go wrong")?;
+    }
+    *sp = addr;
+    Ok(addr)
+}
+
 /// ELF 文件头结构体，表示 ELF 可执行文件的起始元信息。
 /// 用于解析 ELF 文件格式，获取程序入口、段表偏移、节表偏移等关键数据，
 /// 是加载 ELF 文件到进程地址空间的基础数据结构。
@@ -376,6 +644,78 @@ struct ProgHeader {
     align: u64,
 }
 
+/// `.dynamic` 中的一项
+#[repr(C)]
+#[derive(Default)]
+struct Dyn {
+    /// 项的类型，如 `DT_RELA`
+    tag: i64,
+    /// 项的值或地址
+    val: u64,
+}
+
+/// `.rela.dyn` 中带加数的重定位项
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Rela {
+    /// 要改写的位置（链接时的虚拟地址）
+    offset: u64,
+    /// 低 32 位是重定位类型，高 32 位是符号序号
+    info: u64,
+    addend: i64,
+}
 
 const ELF_MAGIC: u32 = 0x464C457F;
+const ELF_TYPE_EXEC: u16 = 2;
+const ELF_TYPE_DYN: u16 = 3;
 const ELF_PROG_LOAD: u32 = 1;
+const ELF_PROG_DYNAMIC: u32 = 2;
+const ELF_PROG_INTERP: u32 = 3;
+
+/// 位置无关程序的默认加载地址，低于它的地址不映射，空指针访问会缺页
+const ELF_DYN_BASE: usize = 0x10_0000;
+
+const DT_NULL: i64 = 0;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_RELATIVE: u32 = 3;
+
+/// 每次从文件读入的重定位项数
+const RELA_BATCH: usize = 32;
+
+/// 重定位时内存不足
+const RELOC_NOMEM: &str = "not enough memory for relocation";
+
+/// 构造用户栈时内存不足
+const STACK_NOMEM: &str = "not enough memory for cmd args";
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+/// 辅助向量的项数，包括结尾的 `AT_NULL`
+const AUXV_LEN: usize = 7;
+const ELF_PROG_FLAG_EXEC: u32 = 1;
+const ELF_PROG_FLAG_WRITE: u32 = 2;
+const ELF_PROG_FLAG_READ: u32 = 4;
+
+/// 按程序头的标志得到映射段的页使用的权限，可写的页总是可读（RISC-V 不允许只写的页表项）
+fn segment_perm(flags: u32) -> PteFlag {
+    let mut perm = PteFlag::U;
+    if flags & (ELF_PROG_FLAG_READ | ELF_PROG_FLAG_WRITE) != 0 {
+        perm |= PteFlag::R;
+    }
+    if flags & ELF_PROG_FLAG_WRITE != 0 {
+        perm |= PteFlag::W;
+    }
+    if flags & ELF_PROG_FLAG_EXEC != 0 {
+        perm |= PteFlag::X;
+    }
+    perm
+}
@@ -1,8 +1,14 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use array_macro::array;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
+use crate::consts::NCPU;
+use crate::ipi::{self, IPI_RESCHEDULE};
 use crate::process::task::task::{Task, TaskStatus};
+use crate::process::{pop_off, push_off, CpuManager};
 use crate::spinlock::SpinLock;
+use crate::time::TICK_INTERVAL;
 use super::Process;
 
 use lazy_static::lazy_static;
@@ -28,6 +34,236 @@ impl ProcessFIFO {
     pub fn fetch(&mut self) -> Option<*const Process> {
         self.ready_queue.pop_front()
     }
+    /// 是否有进程等待运行
+    pub fn has_ready(&self) -> bool {
+        !self.ready_queue.is_empty()
+    }
+    /// 等待运行的进程数
+    pub fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+    /// 从队列中移除指定进程，返回它是否在队列中
+    pub fn remove(&mut self, process: *const Process) -> bool {
+        match self.ready_queue.iter().position(|&p| p == process) {
+            Some(i) => self.ready_queue.remove(i).is_some(),
+            None => false,
+        }
+    }
+    /// 取出最早入队的、满足 `pred` 的进程
+    pub fn take_where(&mut self, pred: impl Fn(&Process) -> bool) -> Option<*const Process> {
+        let i = self.ready_queue.iter().position(|&p| pred(unsafe { &*p }))?;
+        self.ready_queue.remove(i)
+    }
+}
+
+/// 所有硬件线程的亲和性掩码
+pub const ALL_HARTS: usize = (1 << NCPU) - 1;
+
+/// 忙碌的硬件线程每隔多少 mtime 周期检查一次负载是否均衡
+pub const BALANCE_INTERVAL: u64 = TICK_INTERVAL * 4;
+
+/// 单个硬件线程的调度统计，内存布局与用户态的 `struct schedstat` 一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedStat {
+    /// 切换到进程的次数
+    pub switches: u64,
+    /// 停在 WFI 中的时间，单位为纳秒
+    pub idle_ns: u64,
+    /// 从其他硬件线程的队列取来的进程数
+    pub steals: u64,
+    /// 上次在其他硬件线程运行、这次在本硬件线程运行的次数
+    pub migrations: u64,
+    /// 当前等待运行的进程数
+    pub queued: u64,
+}
+
+/// 单个硬件线程的运行队列
+struct HartQueue {
+    fifo: SpinLock<ProcessFIFO>,
+    /// 队列长度，供其他硬件线程不加锁地比较负载
+    len: AtomicUsize,
+    /// 已进入调度器
+    online: AtomicBool,
+    /// 停在 WFI 中，放入进程后需用核间中断叫醒
+    idle: AtomicBool,
+    /// 下次检查负载的 mtime
+    next_balance: AtomicU64,
+    switches: AtomicU64,
+    idle_time: AtomicU64,
+    steals: AtomicU64,
+    migrations: AtomicU64,
+}
+
+impl HartQueue {
+    const fn new() -> Self {
+        Self {
+            fifo: SpinLock::new(ProcessFIFO::new(), "runqueue"),
+            len: AtomicUsize::new(0),
+            online: AtomicBool::new(false),
+            idle: AtomicBool::new(false),
+            next_balance: AtomicU64::new(0),
+            switches: AtomicU64::new(0),
+            idle_time: AtomicU64::new(0),
+            steals: AtomicU64::new(0),
+            migrations: AtomicU64::new(0),
+        }
+    }
+
+    fn push(&self, process: *const Process) {
+        let mut fifo = self.fifo.lock();
+        fifo.add(process);
+        self.len.store(fifo.len(), Ordering::SeqCst);
+    }
+
+    fn take_where(&self, pred: impl Fn(&Process) -> bool) -> Option<*const Process> {
+        let mut fifo = self.fifo.lock();
+        let process = fifo.take_where(pred);
+        self.len.store(fifo.len(), Ordering::SeqCst);
+        process
+    }
+}
+
+/// 每个硬件线程一个的运行队列
+///
+/// - 入队时放到负载（排队与正在运行的进程数）最轻的允许（亲和性）的硬件线程，
+///   负载相同时优先放回进程上次运行的硬件线程以利用它的缓存，其次是本硬件线程；
+///   目标停在 WFI 中时用核间中断叫醒它；
+/// - 硬件线程只从自己的队列取进程，队列为空时从最忙的队列窃取；
+/// - 忙碌的硬件线程每隔 [`BALANCE_INTERVAL`] 检查一次，比最忙的队列短两个以上时取来一个。
+pub struct RunQueues {
+    harts: [HartQueue; NCPU],
+}
+
+impl RunQueues {
+    pub const fn new() -> Self {
+        Self {
+            harts: array![_ => HartQueue::new(); NCPU],
+        }
+    }
+
+    /// 硬件线程 `cpu` 进入调度器，此后可以接收进程
+    pub fn set_online(&self, cpu: usize) {
+        self.harts[cpu].online.store(true, Ordering::SeqCst);
+    }
+
+    /// 已进入调度器的硬件线程的掩码
+    pub fn online_mask(&self) -> usize {
+        self.harts.iter().enumerate()
+            .filter(|(_, hart)| hart.online.load(Ordering::Relaxed))
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+
+    /// 把可运行的进程放入某个硬件线程的队列
+    pub fn enqueue(&self, process: &Process) {
+        push_off();
+        let cur = unsafe { CpuManager::cpu_id() };
+        let target = self.pick(process, cur);
+        self.harts[target].push(process);
+        // 与 enter_idle 配对：目标要么在睡下前看到这个进程，要么在这里被看到已空闲
+        if target != cur && self.harts[target].idle.load(Ordering::SeqCst) {
+            ipi::send(1 << target, IPI_RESCHEDULE);
+        }
+        pop_off();
+    }
+
+    /// 为进程选择硬件线程
+    fn pick(&self, process: &Process, cur: usize) -> usize {
+        let mask = process.affinity.load(Ordering::Relaxed) & self.online_mask();
+        if mask == 0 {
+            return cur
+        }
+        // 负载为排队的进程数，醒着的硬件线程还要算上正在运行的进程
+        let load = |i: usize| {
+            let hart = &self.harts[i];
+            hart.len.load(Ordering::Relaxed) + !hart.idle.load(Ordering::Relaxed) as usize
+        };
+        let last = process.cpu.load(Ordering::Relaxed);
+        (0..NCPU).filter(|&i| mask & 1 << i != 0)
+            .min_by_key(|&i| (load(i), i != last, i != cur))
+            .unwrap()
+    }
+
+    /// 从硬件线程 `cpu` 的队列取出一个进程，队列为空时从其他队列窃取
+    ///
+    /// 入队后亲和性被改为不含 `cpu` 的进程转到允许的硬件线程。
+    pub fn fetch(&self, cpu: usize) -> Option<*const Process> {
+        let allowed = |p: &Process| p.affinity.load(Ordering::Relaxed) & 1 << cpu != 0;
+        while let Some(process) = self.harts[cpu].take_where(|p| !allowed(p)) {
+            self.enqueue(unsafe { &*process });
+        }
+        let process = self.harts[cpu].take_where(allowed).or_else(|| self.steal(cpu))?;
+        let last = unsafe { &*process }.cpu.swap(cpu, Ordering::Relaxed);
+        if last < NCPU && last != cpu {
+            self.harts[cpu].migrations.fetch_add(1, Ordering::Relaxed);
+        }
+        self.harts[cpu].switches.fetch_add(1, Ordering::Relaxed);
+        Some(process)
+    }
+
+    /// 从最忙的队列取来一个允许在 `cpu` 上运行的进程
+    fn steal(&self, cpu: usize) -> Option<*const Process> {
+        let busiest = (0..NCPU).filter(|&i| i != cpu)
+            .max_by_key(|&i| self.harts[i].len.load(Ordering::Relaxed))?;
+        if self.harts[busiest].len.load(Ordering::Relaxed) == 0 {
+            return None
+        }
+        let process = self.harts[busiest].take_where(|p| p.affinity.load(Ordering::Relaxed) & 1 << cpu != 0)?;
+        self.harts[cpu].steals.fetch_add(1, Ordering::Relaxed);
+        Some(process)
+    }
+
+    /// 由忙碌的硬件线程在时钟中断时调用，到了检查时间且本队列比最忙的队列短两个以上时取来一个进程
+    pub fn balance(&self, cpu: usize, now: u64) {
+        let hart = &self.harts[cpu];
+        if now < hart.next_balance.load(Ordering::Relaxed) {
+            return
+        }
+        hart.next_balance.store(now + BALANCE_INTERVAL, Ordering::Relaxed);
+        let busiest = (0..NCPU).max_by_key(|&i| self.harts[i].len.load(Ordering::Relaxed)).unwrap();
+        if self.harts[busiest].len.load(Ordering::Relaxed) < hart.len.load(Ordering::Relaxed) + 2 {
+            return
+        }
+        if let Some(process) = self.steal(cpu) {
+            hart.push(process);
+        }
+    }
+
+    /// 硬件线程 `cpu` 的队列中是否有进程等待运行
+    pub fn has_ready(&self, cpu: usize) -> bool {
+        self.harts[cpu].len.load(Ordering::SeqCst) > 0
+    }
+
+    /// 硬件线程 `cpu` 即将进入 WFI，返回 `false` 表示期间有进程入队，不应睡眠
+    pub fn enter_idle(&self, cpu: usize) -> bool {
+        self.harts[cpu].idle.store(true, Ordering::SeqCst);
+        if self.has_ready(cpu) {
+            self.harts[cpu].idle.store(false, Ordering::SeqCst);
+            return false
+        }
+        true
+    }
+
+    /// 硬件线程 `cpu` 从 WFI 返回，`ns` 为停在 WFI 中的时间
+    pub fn exit_idle(&self, cpu: usize, ns: u64) {
+        self.harts[cpu].idle.store(false, Ordering::SeqCst);
+        self.harts[cpu].idle_time.fetch_add(ns, Ordering::Relaxed);
+    }
+
+    /// 硬件线程 `cpu` 的调度统计，硬件线程不存在或未启动时返回 `None`
+    pub fn stat(&self, cpu: usize) -> Option<SchedStat> {
+        let hart = self.harts.get(cpu)?;
+        if !hart.online.load(Ordering::Relaxed) {
+            return None
+        }
+        Some(SchedStat {
+            switches: hart.switches.load(Ordering::Relaxed),
+            idle_ns: hart.idle_time.load(Ordering::Relaxed),
+            steals: hart.steals.load(Ordering::Relaxed),
+            migrations: hart.migrations.load(Ordering::Relaxed),
+            queued: hart.len.load(Ordering::Relaxed) as u64,
+        })
+    }
 }
 
 pub struct TaskManager {
@@ -7,28 +7,32 @@ use alloc::boxed::Box;
 use alloc::sync::Arc;
 use core::alloc::{GlobalAlloc, Layout};
 use core::mem;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::option::Option;
 use core::ptr;
 use core::cell::UnsafeCell;
-use crate::consts::KERNEL_STACK_SIZE;
-use crate::process::proc::manager::{add_task, ProcessFIFO};
+use crate::consts::{KERNEL_STACK_SIZE, MAXVA, MAX_TASKS_PER_PROC, NCPU, USER_STACK_SIZE};
+use crate::process::proc::manager::{add_task, ProcessFIFO, ALL_HARTS};
 use crate::process::task::task::Task;
-use crate::process::PROCFIFO;
+use crate::process::RUNQUEUES;
 use crate::consts::{PAGE_SIZE, fs::{NFILE, ROOTIPATH}};
-use crate::mm::{pg_round_down, PageTable, PhysAddr, PteFlag, RawPage, RawSinglePage, VirtAddr};
+use crate::mm::{pg_round_down, pg_round_up, PageTable, PhysAddr, PteFlag, RawPage, RawSinglePage, VirtAddr};
+use crate::mm::{asid, frame};
 use crate::process::trapframe::UsysPage;
 use crate::register::{satp, sepc, sstatus, stval};
 use crate::spinlock::{SpinLock, SpinLockGuard};
+use crate::timer::{self, TimerId};
 use crate::trap::user_trap;
 use crate::fs::{Inode, ICACHE, LOG, File};
 
 use super::CpuManager;
+use super::aslr;
 use super::PROC_MANAGER;
 use super::cpu::CPU_MANAGER;
 use super::{fork_ret, Context, TrapFrame};
 
-use self::syscall::Syscall;
+use self::elf::ExecImage;
+use self::syscall::{Syscall, ENOMEM};
 
 mod syscall;
 mod elf;
@@ -138,6 +142,12 @@ pub struct ProcData {
     ustack_base: usize,
     /// 进程使用的内存大小（字节数）。
     size: usize,
+    /// 堆的起始地址，`sbrk` 从这里向上增长；为 0 时整个 `[0, size)` 都可以按需分配
+    heap_start: usize,
+    /// 堆中 `sbrk` 向页帧分配器预留、还没有分配物理页的页数，见 [`frame::commit`]
+    heap_committed: usize,
+    /// 下一次 exec 时是否随机化地址空间布局，fork 时继承，见 [`aslr`](crate::process::aslr)
+    pub aslr: bool,
     /// 进程上下文（寄存器状态等），用于上下文切换。
     context: Context,
     /// 进程名称，最长16字节，通常用于调试和显示。
@@ -151,10 +161,14 @@ pub struct ProcData {
     pub pagetable: Option<Box<PageTable>>,
     /// 进程当前工作目录的 inode。
     pub cwd: Option<Inode>,
+    /// 正在运行的可执行文件，程序段的页在缺页时从这里读入，fork 时共享。
+    image: Option<Arc<ExecImage>>,
 
     pub tracemask: usize,
     /// 当前进程中的线程
-    pub tasks: Vec<Option<Arc<Task>>>
+    pub tasks: Vec<Option<Arc<Task>>>,
+    /// `setitimer(ITIMER_REAL)` 设定的定时器，不被 fork 继承
+    pub itimer: Option<TimerId>,
 }
 
 
@@ -164,6 +178,9 @@ impl ProcData {
             kstack: 0,
             ustack_base: 0,
             size: 0,
+            heap_start: 0,
+            heap_committed: 0,
+            aslr: false,
             context: Context::new(),
             name: [0; 16],
             open_files: array![_ => None; NFILE],
@@ -171,8 +188,10 @@ impl ProcData {
             up: ptr::null_mut(),
             pagetable: None,
             cwd: None,
+            image: None,
             tracemask: 0,
-            tasks: Vec::new()
+            tasks: Vec::new(),
+            itimer: None,
         }
     }
     /// 获取进程中的线程数量
@@ -290,19 +309,207 @@ impl ProcData {
         }
     }
 
+    /// 进程名，不含结尾的空字节
+    pub fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(self.name.len());
+        &self.name[..len]
+    }
+
+    /// 记下系统调用失败的原因，用户程序用 `geterrno()` 读取
+    pub fn set_errno(&mut self, errno: i32) {
+        if let Some(up) = unsafe { self.up.as_mut() } {
+            up.errno = errno;
+        }
+    }
+
+    /// 缺页地址 `va` 是否位于按需分配的内存中：在进程大小之内且还没有映射
+    ///
+    /// 已映射的页缺页是权限错误，不能按需分配；换出的页要换入；栈下方的保护页永远不分配。
+    pub fn is_lazy(&self, va: usize) -> bool {
+        if !self.in_region(va) {
+            return false
+        }
+        match VirtAddr::try_from(pg_round_down(va)) {
+            Ok(va) => self.pagetable.as_ref().unwrap().find_pte(va)
+                .map_or(true, |pte| !pte.is_valid() && !pte.is_swapped() && !pte.is_guard()),
+            Err(_) => false,
+        }
+    }
+
+    /// `va` 是否在进程的某个内存区域中：程序段、用户栈槽或堆，ASLR 在它们之间留下的空洞不算
+    fn in_region(&self, va: usize) -> bool {
+        let ustack_end = self.ustack_base + MAX_TASKS_PER_PROC * (USER_STACK_SIZE + PAGE_SIZE);
+        va < self.size
+            && (va >= self.heap_start
+                || (self.ustack_base..ustack_end).contains(&va)
+                || self.image.as_ref().map_or(false, |image| image.contains(va)))
+    }
+
+    /// 缺页地址 `va` 所在的页是否属于可执行文件中还没有读入的程序段
+    fn is_unread(&self, va: usize) -> bool {
+        self.is_lazy(va) && self.image.as_ref().map_or(false, |image| image.contains(va))
+    }
+
+    /// 访问 `va` 出现不能处理的缺页时，说明访问为什么非法，见 [`PageTable::fault_reason`]
+    pub fn fault_reason(&self, va: usize, access: PteFlag) -> &'static str {
+        if !self.in_region(va) {
+            return "address outside process memory"
+        }
+        self.pagetable.as_ref().unwrap().fault_reason(va, access)
+    }
+
+    /// 缺页地址 `va` 所在的页是否已换出到交换区
+    pub fn is_swapped(&self, va: usize) -> bool {
+        if va >= self.size {
+            return false
+        }
+        match VirtAddr::try_from(pg_round_down(va)) {
+            Ok(va) => self.pagetable.as_ref().unwrap().find_pte(va).map_or(false, |pte| pte.is_swapped()),
+            Err(_) => false,
+        }
+    }
+
+    /// 处理 `va` 的缺页：换入已换出的页，按需分配或从可执行文件读入尚未访问的页，其他情况什么也不做
+    ///
+    /// # 可能的错误
+    /// 物理页或页表页分配失败，内存已经耗尽
+    ///
+    /// # 安全性
+    /// 换入和读入需要读磁盘，调用者不能持有自旋锁，见 [`ProcData::page_in_range`]。
+    pub fn handle_fault(&mut self, va: usize) -> Result<(), ()> {
+        if self.is_swapped(va) {
+            self.pagetable.as_mut().unwrap().swap_in(va)
+        } else if self.is_lazy(va) {
+            self.lazy_alloc(va)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 调入用户内存 `[start, start + count)` 中需要读磁盘的页：换出的页和可执行文件中还没有读入的页
+    ///
+    /// 文件读写、`wait` 等在持有自旋锁时复制用户内存的系统调用要在加锁之前调用。
+    /// 进程在系统调用的其余部分不会被换出（见 `ProcManager::reclaim`），调入的页会一直留在内存中。
+    /// 内存不足时先换出一批最近没有访问过的页（可能包括本进程的）再重试，
+    /// 仍然不足时返回错误，错误码为 ENOMEM。
+    pub fn page_in_range(&mut self, start: usize, count: usize) -> Result<(), ()> {
+        self.read_in_range(start, count, |pdata, va| pdata.is_swapped(va) || pdata.is_unread(va))
+    }
+
+    /// 只换入用户内存 `[start, start + count)` 中换出的页，见 [`ProcData::page_in_range`]
+    ///
+    /// fork 复制内存之前调用：还没有读入的程序段不需要复制，子进程缺页时自己读入。
+    pub fn swap_in_range(&mut self, start: usize, count: usize) -> Result<(), ()> {
+        self.read_in_range(start, count, Self::is_swapped)
+    }
+
+    fn read_in_range(&mut self, start: usize, count: usize, needs_read: fn(&Self, usize) -> bool) -> Result<(), ()> {
+        let end = start.saturating_add(count).min(self.size);
+        let current = unsafe { CPU_MANAGER.my_proc() }.index();
+        for va in (pg_round_down(start)..end).step_by(PAGE_SIZE) {
+            if !needs_read(self, va) {
+                continue
+            }
+            if self.handle_fault(va).is_err()
+                && (unsafe { PROC_MANAGER.reclaim(current) } == 0 || self.handle_fault(va).is_err())
+            {
+                self.set_errno(ENOMEM);
+                return Err(());
+            }
+        }
+        // 为后面的页回收内存时可能又换出了前面刚调入的页
+        if (pg_round_down(start)..end).step_by(PAGE_SIZE).any(|va| needs_read(self, va)) {
+            self.set_errno(ENOMEM);
+            return Err(());
+        }
+        Ok(())
+    }
+
+    /// 从用户地址 `start` 开始换出一批最近没有访问过的页，见 [`PageTable::swap_out`]
+    ///
+    /// # 返回值
+    /// `(换出的页数, 扫描停止的地址, 是否已扫描到用户内存的末尾)`
+    pub fn swap_out(&mut self, start: usize, slots: &mut [usize]) -> (usize, usize, bool) {
+        let size = self.size;
+        match self.pagetable.as_mut() {
+            Some(pgt) => {
+                let (n, stop) = pgt.swap_out(start, size, slots);
+                (n, stop, stop >= size)
+            }
+            None => (0, start, true),
+        }
+    }
+
+    /// 为 `va` 所在的页分配并映射物理页：属于程序段的页从可执行文件读入，其他页归零
+    ///
+    /// # 可能的错误
+    /// 物理页或页表页分配失败，内存已经耗尽
+    pub fn lazy_alloc(&mut self, va: usize) -> Result<(), ()> {
+        let pgt = self.pagetable.as_mut().unwrap();
+        match self.image.as_ref() {
+            Some(image) if image.contains(va) => image.page_in(pgt, va),
+            _ => {
+                let va = pg_round_down(va);
+                pgt.uvm_alloc(va, va + PAGE_SIZE)?;
+                // 物理页已经分配，兑现 sbrk 预留的一页
+                if va >= self.heap_start && self.heap_committed > 0 {
+                    self.heap_committed -= 1;
+                    frame::uncommit(1);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 取消堆中全部还没有兑现的预留，用户内存整个释放时调用
+    pub fn uncommit_heap(&mut self) {
+        frame::uncommit(mem::take(&mut self.heap_committed));
+    }
+
+    /// 进程常驻内存的页数，供 OOM killer 选择进程
+    pub fn resident_pages(&self) -> usize {
+        self.pagetable.as_ref().map_or(0, |pgt| pgt.user_pages())
+    }
+
+    /// 进程退出时释放用户内存，不必等到父进程回收；页表本身在回收时释放
+    pub fn free_user_memory(&mut self) {
+        if let Some(pgt) = self.pagetable.as_mut() {
+            pgt.uvm_dealloc(self.size, 0);
+        }
+        self.size = 0;
+        self.uncommit_heap();
+    }
+
+    /// 复制前调入用户内存 `[start, start + count)` 中的页，见 [`ProcData::handle_fault`]
+    ///
+    /// 内存不足时返回错误，错误码为 ENOMEM。
+    fn fault_in(&mut self, start: usize, count: usize) -> Result<(), ()> {
+        for va in (pg_round_down(start)..start.saturating_add(count)).step_by(PAGE_SIZE) {
+            if self.handle_fault(va).is_err() {
+                self.set_errno(ENOMEM);
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+
     /// 将内容从 src 复制到用户的目标虚拟地址 dst。
     /// 总共复制 count 字节。
     /// 实际操作会转发调用到页表的对应方法。
+    /// 目标中按需分配、还没有访问过的页先分配，换出的页先换入，内存不足时返回错误，错误码为 ENOMEM。
     #[inline]
     pub fn copy_out(&mut self, src: *const u8, dst: usize, count: usize) -> Result<(), ()> {
+        self.fault_in(dst, count)?;
         self.pagetable.as_mut().unwrap().copy_out(src, dst, count)
     }
 
     /// 将内容从用户的源虚拟地址 src 复制到内核空间的目标地址 dst。
     /// 总共复制 count 字节。
     /// 实际操作会转发调用到页表的对应方法。
+    /// 与 [`ProcData::copy_out`] 一样先调入源地址中的页。
     #[inline]
-    pub fn copy_in(&self, src: usize, dst: *mut u8, count: usize) -> Result<(), ()> {
+    pub fn copy_in(&mut self, src: usize, dst: *mut u8, count: usize) -> Result<(), ()> {
+        self.fault_in(src, count)?;
         self.pagetable.as_ref().unwrap().copy_in(src, dst, count)
     }
 
@@ -369,20 +576,29 @@ impl ProcData {
         }
         let pgt = self.pagetable.take();
         if let Some(mut pgt) = pgt {
+            asid::retire(pgt.as_satp());
             pgt.dealloc_proc_pagetable(self.size, pid);
         }
         self.size = 0;
+        self.heap_start = 0;
+        self.uncommit_heap();
+        self.cancel_itimer();
+    }
+
+    /// 取消进程的 `ITIMER_REAL` 定时器，返回它的 (下次到期时间, 周期)
+    pub fn cancel_itimer(&mut self) -> Option<(u64, u64)> {
+        self.itimer.take().and_then(timer::cancel)
     }
 
     /// # 功能说明
-    /// 关闭进程打开的所有文件，并释放当前工作目录的引用。
+    /// 关闭进程打开的所有文件，并释放当前工作目录和可执行文件的引用。
     /// 该函数通常在进程退出时调用，用于清理进程的文件资源和目录引用。
     ///
     /// # 流程解释
     /// 1. 遍历进程打开的文件句柄数组 `open_files`，逐个取出并释放文件引用。
     /// 2. 调用日志系统 `LOG` 的 `begin_op()`，开始一次文件系统操作。
     /// 3. 使用断言确保当前工作目录 `cwd` 不为空。
-    /// 4. 释放当前工作目录的引用（调用 `take()` 后立即 drop）。
+    /// 4. 释放当前工作目录与可执行文件映像的引用（调用 `take()` 后立即 drop）。
     /// 5. 调用 `LOG.end_op()` 结束日志操作。
     ///
     /// # 参数
@@ -406,6 +622,7 @@ impl ProcData {
         LOG.begin_op();
         debug_assert!(self.cwd.is_some());
         drop(self.cwd.take());
+        drop(self.image.take());
         LOG.end_op();
     }
 
@@ -416,9 +633,9 @@ impl ProcData {
     ///
     /// # 流程解释
     /// 1. 记录当前内存大小 `old_size` 以备返回。
-    /// 2. 若 `increment` 大于 0，计算新的堆大小 `new_size`，
-    ///    调用页表的 `uvm_alloc` 分配对应内存区域，更新进程内存大小。
-    /// 3. 若 `increment` 小于 0，计算减少后的堆大小 `new_size`，
+    /// 2. 若 `increment` 大于 0，计算新的堆大小 `new_size`，向页帧分配器预留新增的页并更新进程内存大小，
+    ///    物理页在第一次访问缺页时分配（见 [`lazy_alloc`](Self::lazy_alloc)）。
+    /// 3. 若 `increment` 小于 0，计算减少后的堆大小 `new_size`，取消其中还没有访问过的页的预留，
     ///    调用页表的 `uvm_dealloc` 释放对应内存区域，更新进程内存大小。
     /// 4. 返回调整前的内存大小 `old_size`。
     ///
@@ -431,8 +648,8 @@ impl ProcData {
     /// - `Err(())`：当内存分配失败时返回错误。
     ///
     /// # 可能的错误
-    /// - 当调用 `uvm_alloc` 分配新内存失败时，返回 `Err(())`。
-    /// - 负数缩减堆空间时未显式检查边界，可能出现内存越界或非法释放。
+    /// - 进程大小超过用户地址空间的一半，或空闲页帧不足以兑现新增的页时返回 `Err(())`，错误码为 ENOMEM。
+    /// - 缩减到 0 以下时返回 `Err(())`。
     ///
     /// # 安全性
     /// - 依赖 `pagetable` 正确初始化和有效性，`unwrap()` 可能引发 panic。
@@ -441,11 +658,34 @@ impl ProcData {
     fn sbrk(&mut self, increment: i32) -> Result<usize, ()> {
         let old_size = self.size;
         if increment > 0 {
-            let new_size = old_size + (increment as usize);
-            // self.pagetable.as_mut().unwrap().uvm_alloc(old_size, new_size)?;
+            let increment = increment as usize;
+            // 地址空间的上半部分留给按 pid、tid 排列的陷阱帧
+            let new_size = match old_size.checked_add(increment) {
+                Some(new_size) if new_size <= Into::<usize>::into(MAXVA) / 2 => new_size,
+                _ => {
+                    self.set_errno(ENOMEM);
+                    return Err(());
+                }
+            };
+            // 堆按需分配，物理页在第一次访问时才分配，这里先预留，保证缺页时有页帧可用
+            let pages = (pg_round_up(new_size) - pg_round_up(old_size)) / PAGE_SIZE;
+            if frame::commit(pages).is_err() {
+                self.set_errno(ENOMEM);
+                return Err(());
+            }
+            self.heap_committed += pages;
             self.size = new_size;
         } else if increment < 0 {
-            let new_size = old_size - ((-increment) as usize);
+            // 堆不能缩小到程序段和用户栈中
+            let new_size = old_size.checked_sub(increment.unsigned_abs() as usize)
+                .filter(|&new_size| new_size >= self.heap_start)
+                .ok_or(())?;
+            let untouched = (pg_round_up(new_size)..old_size).step_by(PAGE_SIZE)
+                .filter(|&va| self.is_lazy(va))
+                .count()
+                .min(self.heap_committed);
+            self.heap_committed -= untouched;
+            frame::uncommit(untouched);
             self.pagetable.as_mut().unwrap().uvm_dealloc(old_size, new_size);
             self.size = new_size;
         }
@@ -471,6 +711,13 @@ pub struct Process {
     /// 标识进程是否被杀死的原子布尔变量，用于调度和信号处理。
     pub killed: AtomicBool,
     pub alarm: UnsafeCell<ProcAlarm>,
+    /// 上次运行该进程的硬件线程，`NCPU` 表示还没有运行过，入队时优先放回这个硬件线程。
+    pub cpu: AtomicUsize,
+    /// 允许运行该进程的硬件线程掩码，由 `sched_setaffinity` 设置，fork 时继承。
+    pub affinity: AtomicUsize,
+    /// 进程在用户态被时钟中断抢占、正在等待调度。此时内核没有在访问它的用户内存，
+    /// 它的页可以被换出，见 `ProcManager::reclaim`。
+    pub user_preempted: AtomicBool,
 }
 
 impl Process {
@@ -481,9 +728,18 @@ impl Process {
             data: UnsafeCell::new(ProcData::new()),
             killed: AtomicBool::new(false),
             alarm: UnsafeCell::new(ProcAlarm::new()),
+            cpu: AtomicUsize::new(NCPU),
+            affinity: AtomicUsize::new(ALL_HARTS),
+            user_preempted: AtomicBool::new(false),
         }
     }
 
+    /// 进程在进程表中的槽位
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
     /// # 功能说明
     /// 初始化第一个用户进程的相关数据，包括加载初始化代码到用户页表、
     /// 设置用户程序计数器（PC）和栈指针（SP），
@@ -522,6 +778,7 @@ impl Process {
         pdata.pagetable.as_mut().unwrap().uvm_init(&INITCODE);
         pdata.ustack_base = PAGE_SIZE;
         pdata.size = PAGE_SIZE;
+        pdata.aslr = aslr::boot_enabled();
 
         // 准备返回程序计数器和栈指针
         let trapframe = unsafe { pdata.trapframe.as_mut().unwrap() };
@@ -589,6 +846,8 @@ impl Process {
         //let trapframe = unsafe { self.data.get_mut().trapframe.as_mut().unwrap() };
         let a7 = trapframe.a7;
         trapframe.admit_ecall();
+        // 失败的系统调用按需设置错误码
+        self.data.get_mut().set_errno(0);
         let sys_result = match a7 {
             1 => self.sys_fork(),
             2 => self.sys_exit(),
@@ -619,12 +878,32 @@ impl Process {
             23 => self.sys_sigreturn(),
             22 => self.sys_getmtime(),
             23 => self.sys_waitpid(),
+            24 => self.sys_ioctl(),
+            25 => self.sys_clock_gettime(),
+            26 => self.sys_nanosleep(),
+            27 => self.sys_setitimer(),
+            28 => self.sys_timerfd_create(),
+            29 => self.sys_timerfd_settime(),
+            30 => self.sys_timerfd_gettime(),
+            31 => self.sys_sched_setaffinity(),
+            32 => self.sys_sched_getaffinity(),
+            33 => self.sys_schedstat(),
+            34 => self.sys_slabstat(),
+            35 => self.sys_heapdump(),
+            36 => self.sys_setaslr(),
+            37 => self.sys_execve(),
             99 => self.sys_test(),
             _ => {
                 panic!("unknown syscall num: {}", a7);
             }
         };
-        
+
+        // exec 换掉了进程的线程，返回值（argc）要写入新线程的陷阱帧；
+        // exec 创建线程失败时进程已被杀死，没有线程可以返回
+        let trapframe = match self.data.get_mut().tasks.first().and_then(Option::as_ref) {
+            Some(task) => task.get_trap_frame(),
+            None => return,
+        };
         trapframe.a0 = match sys_result {
             Ok(ret) => ret,
             Err(()) => -1isize as usize,
@@ -634,7 +913,7 @@ impl Process {
         let pid = guard.pid;
         let tracemask = self.data.get_mut().tracemask;
         if tracemask & (1 << a7) != 0 {
-            println!("{}: syscall {} -> {}", pid, syscall::SYSCALL_NAME[a7], tf.a0 as isize);
+            println!("{}: syscall {} -> {}", pid, syscall::SYSCALL_NAME[a7], trapframe.a0 as isize);
         }
         drop(guard);
     }
@@ -671,7 +950,7 @@ impl Process {
         let mut guard = self.excl.lock();
         assert_eq!(guard.state, ProcState::RUNNING);
         guard.state = ProcState::RUNNABLE;
-        PROCFIFO.lock().add(self as *const Process);
+        RUNQUEUES.enqueue(self);
         guard = unsafe { CPU_MANAGER.my_cpu_mut().sched(guard,
             self.data.get_mut().get_context()) };
         drop(guard);
@@ -758,7 +1037,7 @@ impl Process {
     ///
     /// # 可能的错误
     /// - 子进程分配失败（如进程表满），返回 `Err(())`。
-    /// - 复制父进程内存失败时，清理子进程并返回错误。
+    /// - 复制父进程内存或创建子进程的线程时内存不足，清理子进程并返回错误，错误码为 ENOMEM。
     /// - 若 TrapFrame 指针无效，`unsafe` 操作可能导致未定义行为。
     ///
     /// # 安全性
@@ -769,12 +1048,18 @@ impl Process {
     fn fork(&mut self) -> Result<usize, ()> {
         let pdata = self.data.get_mut();
         let palarm = self.alarm.get_mut();
+        // 复制内存时持有子进程的锁，换入需要读磁盘，先换入全部换出的页
+        pdata.swap_in_range(0, pdata.size)?;
         let child = unsafe { PROC_MANAGER.alloc_proc().ok_or(())? };
+        // 子进程的线程要保存进程的裸指针，在加锁借用 child 之前取出
+        let child_ptr = child as *mut Process;
         let mut cexcl = child.excl.lock();
         let cpid = cexcl.pid;
         let cdata = unsafe { child.data.get().as_mut().unwrap() };
         let calarm = unsafe { child.alarm.get().as_mut().unwrap() };
         cdata.ustack_base = pdata.ustack_base;
+        cdata.heap_start = pdata.heap_start;
+        cdata.aslr = pdata.aslr;
         // 克隆内存
         let cpgt = cdata.pagetable.as_mut().unwrap();
         let size = pdata.size;
@@ -784,10 +1069,36 @@ impl Process {
             cdata.cleanup(cpid);
             calarm.cleanup();
             cexcl.cleanup();
+            pdata.set_errno(ENOMEM);
             return Err(())
         }
         cdata.size = size;
 
+        // 子进程的堆同样有还没有访问过的页，为它另外预留
+        if frame::commit(pdata.heap_committed).is_err() {
+            cdata.cleanup(cpid);
+            calarm.cleanup();
+            cexcl.cleanup();
+            pdata.set_errno(ENOMEM);
+            return Err(())
+        }
+        cdata.heap_committed = pdata.heap_committed;
+
+        // 子进程的线程，失败时子进程还未对外可见，直接回收
+        let task = Task::from(Some(child_ptr), 1, cdata.ustack_base, unsafe { pdata.tasks[0].as_ref().unwrap().get_trap_frame()})
+            .and_then(|task| Arc::try_new(task).map_err(|_| ()));
+        let task = match task {
+            Ok(task) => task,
+            Err(()) => {
+                cdata.cleanup(cpid);
+                calarm.cleanup();
+                cexcl.cleanup();
+                pdata.set_errno(ENOMEM);
+                return Err(())
+            }
+        };
+        cdata.tasks.push(Some(task));
+
         // 克隆陷阱帧并在 a0 寄存器上返回 0
         unsafe {
             ptr::copy_nonoverlapping(pdata.trapframe, cdata.trapframe, 1);
@@ -798,7 +1109,9 @@ impl Process {
         // 克隆已打开的文件和当前工作目录
         cdata.open_files.clone_from(&pdata.open_files);
         cdata.cwd.clone_from(&pdata.cwd);
+        cdata.image.clone_from(&pdata.image);
         cdata.tracemask.clone_from(&pdata.tracemask);
+        child.affinity.store(self.affinity.load(Ordering::Relaxed), Ordering::Relaxed);
         
         // 复制进程名称
         cdata.name.copy_from_slice(&pdata.name);
@@ -812,11 +1125,7 @@ impl Process {
         let mut cexcl = child.excl.lock();
         cexcl.state = ProcState::RUNNABLE;
         drop(cexcl);
-        let task = Task::from(Some(child as *mut Process), 1, cdata.ustack_base, unsafe { pdata.tasks[0].as_ref().unwrap().get_trap_frame()});
-        let task = Arc::new(task);
-        cdata.tasks.push(Some(Arc::clone(&task)));
-        //add_task(task);
-        PROCFIFO.lock().add(child as *const Process);
+        RUNQUEUES.enqueue(child);
     
         Ok(cpid)
     }
@@ -932,8 +1241,9 @@ impl Process {
     /// - 需要保证缓冲区 `buf` 大小足够存放用户字符串。
     fn arg_str(&mut self, n: usize, buf: &mut [u8]) -> Result<(), &'static str> {
         let addr: usize = self.arg_raw(n);
-        let pagetable = unsafe { self.data.get().as_ref().unwrap().pagetable.as_ref().unwrap() };
-        pagetable.copy_in_str(addr, buf)?;
+        let pdata = self.data.get_mut();
+        pdata.page_in_range(addr, buf.len()).map_err(|_| "arg_str: not enough memory")?;
+        pdata.pagetable.as_ref().unwrap().copy_in_str(addr, buf)?;
         Ok(())
     }
 
@@ -966,7 +1276,7 @@ impl Process {
     /// - 通过页表安全复制数据，避免直接裸指针访问用户空间，符合内核安全规范。
     /// - 调用者需保证地址合法且缓冲区足够存储数据。
     fn fetch_addr(&self, addr: usize) -> Result<usize, &'static str> {
-        let pd = unsafe { self.data.get().as_ref().unwrap() };
+        let pd = unsafe { self.data.get().as_mut().unwrap() };
         if addr + mem::size_of::<usize>() > pd.size {
             Err("input addr > proc's mem size")
         } else {
@@ -983,9 +1293,25 @@ impl Process {
     }
 
     ///从虚拟地址addr获取一个以空字符结尾的字符串到内核缓冲区中。
+    ///
+    /// 一页一页地调入并复制，遇到空字节就停下，`dst` 很大时也不会调入字符串之后的页。
     fn fetch_str(&self, addr: usize, dst: &mut [u8]) -> Result<(), &'static str>{
-        let pd = unsafe { self.data.get().as_ref().unwrap() };
-        pd.pagetable.as_ref().unwrap().copy_in_str(addr, dst)
+        let pd = unsafe { self.data.get().as_mut().unwrap() };
+        let mut i = 0;
+        while i < dst.len() {
+            let va = addr.checked_add(i).ok_or("fetch_str: bad address")?;
+            let n = (pg_round_down(va) + PAGE_SIZE - va).min(dst.len() - i);
+            pd.page_in_range(va, n).map_err(|_| "fetch_str: not enough memory")?;
+            let chunk = &mut dst[i..i + n];
+            chunk[n - 1] = 0;
+            match pd.pagetable.as_ref().unwrap().copy_in_str(va, chunk) {
+                Ok(()) => return Ok(()),
+                // 这一页里没有空字节，整页都复制过来了，继续下一页
+                Err(_) if chunk[n - 1] != 0 => i += n,
+                Err(s) => return Err(s),
+            }
+        }
+        Err("copy_in_str: dst not enough space")
     }
 }
 
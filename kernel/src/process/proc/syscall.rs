@@ -1,29 +1,41 @@
 //! 所有系统调用接口实现
 
-use array_macro::array;
 
 use alloc::string::String;
-use alloc::boxed::Box;
 use alloc::sync::Arc;
 use core::convert::TryInto;
 use core::fmt::Display;
 use core::mem;
 
 use crate::consts::PGSIZE;
-use crate::consts::{MAXPATH, MAXARG, MAXARGLEN, fs::MAX_DIR_SIZE};
+use crate::consts::{MAXPATH, fs::MAX_DIR_SIZE};
 use crate::mm::VirtAddr;
-use crate::process::{PROC_MANAGER};
-use crate::fs::{ICACHE, Inode, InodeType, LOG, File, Pipe, FileStat};
+use crate::mm::kalloc::KERNEL_HEAP;
+use crate::mm::slab::SlabStat;
+use crate::process::{CpuManager, PROC_MANAGER, RUNQUEUES, SchedStat, pop_off, push_off};
+use crate::fs::{ICACHE, Inode, InodeType, LOG, File, Pipe, FileStat, TimerFd};
 use crate::register::clint;
 use crate::trap;
+use crate::time::{self, Itimerspec, Itimerval, Timespec, Timeval};
 
 use super::{Process, elf};
+use crate::exec_args::{ARGS_TOO_BIG, ExecArgs};
 
 /// 系统调用结果类型
 pub type SysResult = Result<usize, ()>;
 
-pub static SYSCALL_NAME: [&str; 24] = ["","fork","exit","wait","pipe","read","kill","exec","fstat","chdir","dup",
-"getpid","sbrk","sleep","uptime","open","write","mknod","unlink","link","mkdir","close","trace","sysinfo"];
+pub static SYSCALL_NAME: [&str; 38] = ["","fork","exit","wait","pipe","read","kill","exec","fstat","chdir","dup",
+"getpid","sbrk","sleep","uptime","open","write","mknod","unlink","link","mkdir","close","trace","sysinfo","ioctl","clock_gettime",
+"nanosleep","setitimer","timerfd_create","timerfd_settime","timerfd_gettime","sched_setaffinity","sched_getaffinity",
+"schedstat","slabstat","heapdump","setaslr","execve"];
+
+/// `setitimer` 支持的定时器，与 `include/time.h` 一致
+const ITIMER_REAL: usize = 0;
+
+/// 参数太长，与 `include/errno.h` 一致
+pub const E2BIG: i32 = 7;
+/// 内存不足，与 `include/errno.h` 一致
+pub const ENOMEM: i32 = 12;
 
 pub trait Syscall {
     fn sys_fork(&mut self) -> SysResult;
@@ -47,6 +59,20 @@ pub trait Syscall {
     fn sys_link(&mut self) -> SysResult;
     fn sys_mkdir(&mut self) -> SysResult;
     fn sys_close(&mut self) -> SysResult;
+    fn sys_ioctl(&mut self) -> SysResult;
+    fn sys_clock_gettime(&mut self) -> SysResult;
+    fn sys_nanosleep(&mut self) -> SysResult;
+    fn sys_setitimer(&mut self) -> SysResult;
+    fn sys_timerfd_create(&mut self) -> SysResult;
+    fn sys_timerfd_settime(&mut self) -> SysResult;
+    fn sys_timerfd_gettime(&mut self) -> SysResult;
+    fn sys_sched_setaffinity(&mut self) -> SysResult;
+    fn sys_sched_getaffinity(&mut self) -> SysResult;
+    fn sys_schedstat(&mut self) -> SysResult;
+    fn sys_slabstat(&mut self) -> SysResult;
+    fn sys_heapdump(&mut self) -> SysResult;
+    fn sys_setaslr(&mut self) -> SysResult;
+    fn sys_execve(&mut self) -> SysResult;
     fn sys_setpri(&mut self) -> SysResult;
     fn sys_getpri(&mut self) -> SysResult;
     fn sys_sigalarm(&mut self) -> SysResult;
@@ -198,7 +224,9 @@ impl Syscall for Process {
             return Err(())
         }
         let count = count as u32;
-        
+        // 管道、终端等在持有自旋锁时复制用户内存，调入需要读磁盘，先调入缓冲区
+        self.data.get_mut().page_in_range(user_addr, count as usize)?;
+
         let file = self.data.get_mut().open_files[fd].as_ref().unwrap();
         let ret = file.fread(user_addr, count);
 
@@ -239,77 +267,43 @@ impl Syscall for Process {
     /// 执行新程序
     ///
     /// # 功能说明
-    /// 替换当前进程的内存空间，加载并执行指定路径的ELF可执行文件。
+    /// 替换当前进程的内存空间，加载并执行指定路径的ELF可执行文件，新程序没有环境变量。
     ///
     /// # 参数
     /// - `path`: 可执行文件路径
-    /// - `argv`: 命令行参数数组
+    /// - `argv`: 以空指针结尾的命令行参数数组
     ///
     /// # 返回值
-    /// - 成功：不会返回（新程序开始执行）
-    /// - 错误：返回 Err(())
-    ///
-    /// # 流程
-    /// 1. 读取可执行文件路径
-    /// 2. 读取命令行参数
-    /// 3. 加载ELF文件
-    /// 4. 设置新程序的初始状态
+    /// - 成功：不会返回（新程序开始执行，a0 为参数个数）
+    /// - 错误：返回 Err(())，见 [`Syscall::sys_execve`]
     fn sys_exec(&mut self) -> SysResult {
-        let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
-
-        let mut result: SysResult = Err(());
-        let mut error = "too many arguments";
-        let mut uarg: usize;
         let uargv = self.arg_addr(1);
-        let mut argv: [Option<Box<[u8; MAXARGLEN]>>; MAXARG] = array![_ => None; MAXARG];
-        for i in 0..MAXARG {
-            // 获取第i个参数的地址
-            match self.fetch_addr(uargv+i*mem::size_of::<usize>()) {
-                Ok(addr) => uarg = addr,
-                Err(s) => {
-                    error = s;
-                    break
-                },
-            }
-            if uarg == 0 {
-                match elf::load(self, &path, &argv[..i]) {
-                    Ok(ret) => result = Ok(ret),
-                    Err(s) => error = s,
-                }
-                let guard = self.excl.lock();
-                if guard.pid == 1 {
-                    let data = self.data.get_mut();
-                    data.pagetable.as_ref().unwrap().vm_print(0);
-                }
-                drop(guard);
-                break       
-            }
-
-            // 为参数分配内核空间
-            match Box::try_new_zeroed() {
-                Ok(b) => unsafe { argv[i] = Some(b.assume_init()) },
-                Err(_) => {
-                    error = "not enough kernel memory";
-                    break
-                },
-            }
-
-            // 将用户空间参数复制到内核
-            if let Err(s) = self.fetch_str(uarg, argv[i].as_deref_mut().unwrap()) {
-                error = s;
-                break
-            }
-        }
-
-        #[cfg(feature = "trace_syscall")]
-        println!("[{}].exec({}, {:#x}) = {:?}", self.excl.lock().pid, String::from_utf8_lossy(&path), uargv, result);
+        exec(self, "exec", uargv, 0)
+    }
 
-        if result.is_err() {
-            syscall_warning(error);
-        }
-        //manager.lock().add(self as *const Process);
-        result
+    /// 执行新程序并传入环境变量
+    ///
+    /// # 功能说明
+    /// 与 `exec` 相同，另外把 `envp` 中的环境变量传给新程序。
+    /// 参数和环境变量一起复制到内核，总大小（包括新程序栈上的指针数组）不能超过 `ARG_MAX`。
+    ///
+    /// # 参数
+    /// - `path`: 可执行文件路径
+    /// - `argv`: 以空指针结尾的命令行参数数组
+    /// - `envp`: 以空指针结尾的环境变量数组，为空指针时没有环境变量
+    ///
+    /// # 返回值
+    /// - 成功：不会返回（新程序开始执行，a0、a1、a2 为 argc、argv 和 envp）
+    /// - 错误：返回 Err(())
+    ///
+    /// # 可能的错误
+    /// - 参数和环境变量超过 `ARG_MAX`，错误码为 E2BIG
+    /// - 内核内存不足，错误码为 ENOMEM
+    /// - 读取用户空间的指针或字符串失败，或加载 ELF 文件失败
+    fn sys_execve(&mut self) -> SysResult {
+        let uargv = self.arg_addr(1);
+        let uenvp = self.arg_addr(2);
+        exec(self, "execve", uargv, uenvp)
     }
 
     /// 获取文件状态信息
@@ -332,8 +326,8 @@ impl Syscall for Process {
         let ret = if file.fstat(&mut stat).is_err() {
             Err(())
         } else {
-            let pgt = self.data.get_mut().pagetable.as_mut().unwrap();
-            if pgt.copy_out(&stat as *const FileStat as *const u8, addr, mem::size_of::<FileStat>()).is_err() {
+            let pdata = self.data.get_mut();
+            if pdata.copy_out(&stat as *const FileStat as *const u8, addr, mem::size_of::<FileStat>()).is_err() {
                 Err(())
             } else {
                 Ok(0)
@@ -560,6 +554,8 @@ impl Syscall for Process {
             return Err(())
         }
         let count = count as u32;
+        // 管道、终端等在持有自旋锁时复制用户内存，调入需要读磁盘，先调入缓冲区
+        self.data.get_mut().page_in_range(user_addr, count as usize)?;
 
         let file = self.data.get_mut().open_files[fd].as_ref().unwrap();
         let ret = file.fwrite(user_addr, count);
@@ -776,6 +772,446 @@ impl Syscall for Process {
         drop(file);
         Ok(0)
     }
+
+    /// 设备控制
+    ///
+    /// # 功能说明
+    /// 把命令交给文件描述符对应设备的驱动处理。
+    ///
+    /// # 参数
+    /// - `fd`: 设备文件的文件描述符
+    /// - `cmd`: 命令号，含义由驱动定义
+    /// - `arg`: 命令参数，通常是用户空间地址
+    ///
+    /// # 返回值
+    /// - 成功：返回驱动给出的结果
+    /// - 错误：返回 Err(())，例如不是设备文件或驱动不支持该命令
+    fn sys_ioctl(&mut self) -> SysResult {
+        let fd = self.arg_fd(0)?;
+        let cmd = self.arg_i32(1) as u32;
+        let arg = self.arg_raw(2);
+        let file = self.data.get_mut().open_files[fd].as_ref().unwrap();
+        let ret = file.ioctl(cmd, arg);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].ioctl(fd={}, cmd={:#x}, arg={:#x}) = {:?}", self.excl.lock().pid, fd, cmd, arg, ret);
+
+        ret
+    }
+
+    /// 读取时钟
+    ///
+    /// # 功能说明
+    /// 读取指定时钟的当前值，以 `struct timespec` 复制到用户空间。
+    ///
+    /// # 参数
+    /// - `clock`: 时钟编号，`CLOCK_REALTIME` 为墙上时间，`CLOCK_MONOTONIC` 为启动以来的时间
+    /// - `addr`: 用户空间地址（用于存储 timespec 结构）
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，例如时钟编号无效或地址不可写
+    fn sys_clock_gettime(&mut self) -> SysResult {
+        let clock = self.arg_raw(0);
+        let addr = self.arg_addr(1);
+        let ret = time::clock_gettime(clock).and_then(|ts| {
+            let pdata = self.data.get_mut();
+            pdata.copy_out(&ts as *const Timespec as *const u8, addr, mem::size_of::<Timespec>())
+                .map(|()| 0)
+        });
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].clock_gettime(clock={}, addr={:#x}) = {:?}", self.excl.lock().pid, clock, addr, ret);
+
+        ret
+    }
+
+    /// 高精度睡眠
+    ///
+    /// # 功能说明
+    /// 睡眠 `struct timespec` 给出的时长，由一次性内核定时器唤醒，精度不受 tick 限制。
+    ///
+    /// # 参数
+    /// - `req`: 用户空间的 timespec，睡眠时长
+    /// - `rem`: 用户空间的 timespec，可为 0；睡眠被打断时写入剩余时长
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，时长不合法或睡眠期间进程被杀死
+    fn sys_nanosleep(&mut self) -> SysResult {
+        let req_addr = self.arg_addr(0);
+        let rem_addr = self.arg_addr(1);
+        let pdata = self.data.get_mut();
+        let mut req = Timespec::default();
+        pdata.copy_in(req_addr, &mut req as *mut Timespec as *mut u8, mem::size_of::<Timespec>())?;
+        let ns = req.to_ns().ok_or(())?;
+
+        let deadline = time::mtime() + time::ns_to_mtime(ns);
+        let ret = trap::sleep_until(self, deadline);
+        if ret.is_err() && rem_addr != 0 {
+            let rem = Timespec::from_ns(time::mtime_to_ns(deadline.saturating_sub(time::mtime())));
+            let pdata = self.data.get_mut();
+            pdata.copy_out(&rem as *const Timespec as *const u8, rem_addr, mem::size_of::<Timespec>())?;
+        }
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].nanosleep(sec={}, nsec={}) = {:?}", self.excl.lock().pid, req.sec, req.nsec, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// 设定进程的间隔定时器
+    ///
+    /// # 功能说明
+    /// 只支持 `ITIMER_REAL`：定时器到期时进程收到 SIGALRM。内核还没有信号，
+    /// 按 SIGALRM 的默认动作直接杀死进程。定时器不被 fork 继承，进程退出时取消。
+    ///
+    /// # 参数
+    /// - `which`: 定时器种类，必须为 `ITIMER_REAL`
+    /// - `new`: 用户空间的 itimerval，`value` 为 0 时停止定时器
+    /// - `old`: 用户空间的 itimerval，可为 0；写入原来的剩余时间与周期
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，例如不支持的定时器或时间不合法
+    fn sys_setitimer(&mut self) -> SysResult {
+        let which = self.arg_raw(0);
+        let new_addr = self.arg_addr(1);
+        let old_addr = self.arg_addr(2);
+        if which != ITIMER_REAL {
+            return Err(())
+        }
+        let pid = self.excl.lock().pid;
+        let pdata = self.data.get_mut();
+        let mut new = Itimerval::default();
+        pdata.copy_in(new_addr, &mut new as *mut Itimerval as *mut u8, mem::size_of::<Itimerval>())?;
+        let value = new.value.to_ns().ok_or(())?;
+        let interval = new.interval.to_ns().ok_or(())?;
+
+        let now = time::mtime();
+        let old = match pdata.cancel_itimer() {
+            Some((deadline, interval)) => Itimerval {
+                interval: Timeval::from_ns(time::mtime_to_ns(interval)),
+                value: Timeval::from_ns(time::mtime_to_ns(deadline.saturating_sub(now)).max(1000)),
+            },
+            None => Itimerval::default(),
+        };
+        if value > 0 {
+            let deadline = now + time::ns_to_mtime(value);
+            pdata.itimer = Some(trap::start_timer(deadline, time::ns_to_mtime(interval), itimer_expire, pid));
+        }
+        if old_addr != 0 {
+            pdata.copy_out(&old as *const Itimerval as *const u8, old_addr, mem::size_of::<Itimerval>())?;
+        }
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setitimer(which={}, value={}ns, interval={}ns)", pid, which, value, interval);
+
+        Ok(0)
+    }
+
+    /// 创建 timerfd
+    ///
+    /// # 参数
+    /// - `clock`: `CLOCK_REALTIME` 或 `CLOCK_MONOTONIC`，决定绝对时间按哪个时钟解释
+    /// - `flags`: 保留，必须为 0
+    ///
+    /// # 返回值
+    /// - 成功：返回文件描述符，定时器初始未启动
+    /// - 错误：返回 Err(())，例如不支持的时钟、文件描述符或 timerfd 用完
+    fn sys_timerfd_create(&mut self) -> SysResult {
+        let clock = self.arg_raw(0);
+        let flags = self.arg_raw(1);
+        if flags != 0 {
+            return Err(())
+        }
+        let pdata = self.data.get_mut();
+        let fd = pdata.alloc_fd().ok_or(())?;
+        let file = TimerFd::create(clock).ok_or(())?;
+        let none_file = pdata.open_files[fd].replace(file);
+        debug_assert!(none_file.is_none());
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].timerfd_create(clock={}) = {}(fd)", self.excl.lock().pid, clock, fd);
+
+        Ok(fd)
+    }
+
+    /// 启动或停止 timerfd 的定时器
+    ///
+    /// # 参数
+    /// - `fd`: timerfd 的文件描述符
+    /// - `flags`: 0 或 `TFD_TIMER_ABSTIME`，后者表示 `value` 是所用时钟上的绝对时间
+    /// - `new`: 用户空间的 itimerspec，`value` 为 0 时停止定时器
+    /// - `old`: 用户空间的 itimerspec，可为 0；写入原来的剩余时间与周期
+    ///
+    /// # 返回值
+    /// - 成功：返回 0，未读的到期次数清零
+    /// - 错误：返回 Err(())，例如不是 timerfd 或时间不合法
+    fn sys_timerfd_settime(&mut self) -> SysResult {
+        let fd = self.arg_fd(0)?;
+        let flags = self.arg_raw(1);
+        let new_addr = self.arg_addr(2);
+        let old_addr = self.arg_addr(3);
+        let pdata = self.data.get_mut();
+        let mut new = Itimerspec::default();
+        pdata.copy_in(new_addr, &mut new as *mut Itimerspec as *mut u8, mem::size_of::<Itimerspec>())?;
+        let file = pdata.open_files[fd].as_ref().unwrap();
+        let ret = file.timerfd().ok_or(())?.settime(flags, &new);
+        let ret = ret.and_then(|old| match old_addr {
+            0 => Ok(()),
+            _ => pdata.copy_out(&old as *const Itimerspec as *const u8, old_addr, mem::size_of::<Itimerspec>()),
+        });
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].timerfd_settime(fd={}, flags={}) = {:?}", self.excl.lock().pid, fd, flags, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// 查询 timerfd 的剩余时间与周期
+    ///
+    /// # 参数
+    /// - `fd`: timerfd 的文件描述符
+    /// - `cur`: 用户空间的 itimerspec，定时器未启动时写入全 0
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，例如不是 timerfd
+    fn sys_timerfd_gettime(&mut self) -> SysResult {
+        let fd = self.arg_fd(0)?;
+        let cur_addr = self.arg_addr(1);
+        let pdata = self.data.get_mut();
+        let file = pdata.open_files[fd].as_ref().unwrap();
+        let cur = file.timerfd().ok_or(())?.gettime();
+        pdata.copy_out(&cur as *const Itimerspec as *const u8, cur_addr, mem::size_of::<Itimerspec>())?;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].timerfd_gettime(fd={}) = {:?}", self.excl.lock().pid, fd, cur);
+
+        Ok(0)
+    }
+
+    /// 设置进程的亲和性
+    ///
+    /// # 功能说明
+    /// 此后进程只在掩码中的硬件线程上运行。设置的是当前进程且当前硬件线程不在掩码中时，
+    /// 立即让出 CPU，由允许的硬件线程继续运行。
+    ///
+    /// # 参数
+    /// - `pid`: 进程号，0 表示当前进程
+    /// - `mask`: 硬件线程掩码，第 i 位表示允许在 i 号硬件线程上运行
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，进程不存在或掩码中没有已启动的硬件线程
+    fn sys_sched_setaffinity(&mut self) -> SysResult {
+        let mypid = self.excl.lock().pid;
+        let pid = match self.arg_raw(0) {
+            0 => mypid,
+            pid => pid,
+        };
+        let mask = self.arg_raw(1);
+        let ret = unsafe { PROC_MANAGER.set_affinity(pid, mask) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sched_setaffinity(pid={}, mask={:#x}) = {:?}", mypid, pid, mask, ret);
+
+        ret?;
+        if pid == mypid {
+            push_off();
+            let cpu = unsafe { CpuManager::cpu_id() };
+            pop_off();
+            if mask & 1 << cpu == 0 {
+                self.yielding();
+            }
+        }
+        Ok(0)
+    }
+
+    /// 读取进程的亲和性
+    ///
+    /// # 参数
+    /// - `pid`: 进程号，0 表示当前进程
+    ///
+    /// # 返回值
+    /// - 成功：返回硬件线程掩码
+    /// - 错误：返回 Err(())，进程不存在
+    fn sys_sched_getaffinity(&mut self) -> SysResult {
+        let pid = match self.arg_raw(0) {
+            0 => self.excl.lock().pid,
+            pid => pid,
+        };
+        let ret = unsafe { PROC_MANAGER.get_affinity(pid) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sched_getaffinity(pid={}) = {:?}", self.excl.lock().pid, pid, ret);
+
+        ret
+    }
+
+    /// 读取硬件线程的调度统计
+    ///
+    /// # 参数
+    /// - `hart`: 硬件线程编号
+    /// - `addr`: 用户空间地址（用于存储 schedstat 结构）
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，硬件线程不存在或未启动，可用于枚举硬件线程
+    fn sys_schedstat(&mut self) -> SysResult {
+        let hart = self.arg_raw(0);
+        let addr = self.arg_addr(1);
+        let stat = RUNQUEUES.stat(hart).ok_or(())?;
+        let pdata = self.data.get_mut();
+        pdata.copy_out(&stat as *const SchedStat as *const u8, addr, mem::size_of::<SchedStat>())?;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].schedstat(hart={}) = {:?}", self.excl.lock().pid, hart, stat);
+
+        Ok(0)
+    }
+
+    /// 读取内核堆一个大小类的 slab 统计
+    ///
+    /// # 参数
+    /// - `class`: 大小类编号，从 0 开始对应 16 字节的对象
+    /// - `addr`: 用户空间地址（用于存储 slabstat 结构）
+    ///
+    /// # 返回值
+    /// - 成功：返回 0
+    /// - 错误：返回 Err(())，大小类不存在，可用于枚举大小类
+    fn sys_slabstat(&mut self) -> SysResult {
+        let class = self.arg_raw(0);
+        let addr = self.arg_addr(1);
+        let stat = KERNEL_HEAP.slab_stat(class).ok_or(())?;
+        let pdata = self.data.get_mut();
+        pdata.copy_out(&stat as *const SlabStat as *const u8, addr, mem::size_of::<SlabStat>())?;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].slabstat(class={}) = {:?}", self.excl.lock().pid, class, stat);
+
+        Ok(0)
+    }
+
+    /// 标记或打印内核堆的活跃对象，需要 `heap_debug` 特性
+    ///
+    /// # 参数
+    /// - `mode`: 0 记下当前的分配序号，1 打印此后分配且仍未释放的对象
+    ///
+    /// # 返回值
+    /// - 成功：mode 0 返回记下的序号，mode 1 返回打印的对象数
+    /// - 错误：返回 Err(())，未开启 `heap_debug` 特性或 `mode` 无效
+    fn sys_heapdump(&mut self) -> SysResult {
+        #[cfg(any(feature = "heap_debug", feature = "trace_syscall"))]
+        let mode = self.arg_raw(0);
+        #[cfg(feature = "heap_debug")]
+        let ret = match mode {
+            0 => Ok(KERNEL_HEAP.debug_mark()),
+            1 => Ok(KERNEL_HEAP.debug_dump()),
+            _ => Err(()),
+        };
+        #[cfg(not(feature = "heap_debug"))]
+        let ret = Err(());
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].heapdump(mode={}) = {:?}", self.excl.lock().pid, mode, ret);
+
+        ret
+    }
+
+    /// 打开或关闭进程的地址空间布局随机化，在下一次 exec 时生效，子进程继承
+    ///
+    /// # 参数
+    /// - `on`: 1 打开，0 关闭，负数只查询
+    ///
+    /// # 返回值
+    /// - 成功：返回原来的设置，1 为打开
+    /// - 错误：返回 Err(())，`on` 无效
+    fn sys_setaslr(&mut self) -> SysResult {
+        let on = self.arg_i32(0);
+        let pdata = self.data.get_mut();
+        let old = pdata.aslr as usize;
+        let ret = match on {
+            0 | 1 => {
+                pdata.aslr = on == 1;
+                Ok(old)
+            }
+            _ if on < 0 => Ok(old),
+            _ => Err(()),
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setaslr(on={}) = {:?}", self.excl.lock().pid, on, ret);
+
+        ret
+    }
+}
+
+/// `ITIMER_REAL` 到期时的定时器回调，`pid` 为设定定时器的进程
+///
+/// 按 SIGALRM 的默认动作杀死进程；进程已经退出时什么也不做。
+fn itimer_expire(pid: usize, _count: u64) {
+    let _ = unsafe { PROC_MANAGER.kill(pid) };
+}
+
+/// `exec` 和 `execve` 的实现
+///
+/// # 流程
+/// 1. 读取可执行文件路径
+/// 2. 读取命令行参数和环境变量（`uenvp` 为零时没有）
+/// 3. 加载ELF文件，设置新程序的初始状态
+///
+/// `name` 是跟踪系统调用时打印的名字。
+#[cfg_attr(not(feature = "trace_syscall"), allow(unused_variables))]
+fn exec(p: &mut Process, name: &str, uargv: usize, uenvp: usize) -> SysResult {
+    let mut path: [u8; MAXPATH] = [0; MAXPATH];
+    p.arg_str(0, &mut path).map_err(syscall_warning)?;
+
+    let mut args = match ExecArgs::new() {
+        Ok(args) => args,
+        Err(()) => {
+            p.data.get_mut().set_errno(ENOMEM);
+            syscall_warning("not enough kernel memory");
+            return Err(())
+        }
+    };
+    let result = fetch_strs(p, &mut args, uargv, false)
+        .and_then(|()| if uenvp == 0 { Ok(()) } else { fetch_strs(p, &mut args, uenvp, true) })
+        .and_then(|()| elf::load(p, &path, &args));
+    let guard = p.excl.lock();
+    if result.is_ok() && guard.pid == 1 {
+        let data = p.data.get_mut();
+        data.pagetable.as_ref().unwrap().vm_print(0);
+    }
+    drop(guard);
+
+    #[cfg(feature = "trace_syscall")]
+    {
+        // 只打印路径结尾空字节之前的部分
+        let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+        println!("[{}].{}({}, {:#x}, {:#x}) = {:?}", p.excl.lock().pid, name, String::from_utf8_lossy(&path[..len]), uargv, uenvp, result);
+    }
+
+    result.map_err(|s| {
+        if s == ARGS_TOO_BIG {
+            p.data.get_mut().set_errno(E2BIG);
+        }
+        syscall_warning(s)
+    })
+}
+
+/// 把用户空间中以空指针结尾的字符串指针数组 `uarray` 指向的字符串依次加入 `args`
+fn fetch_strs(p: &Process, args: &mut ExecArgs, uarray: usize, env: bool) -> Result<(), &'static str> {
+    let mut uptr = uarray;
+    loop {
+        let uarg = p.fetch_addr(uptr)?;
+        if uarg == 0 {
+            return Ok(())
+        }
+        args.push(env, |dst| p.fetch_str(uarg, dst))?;
+        uptr += mem::size_of::<usize>();
+    }
 }
 
 /// 系统调用警告函数
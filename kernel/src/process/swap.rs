@@ -0,0 +1,85 @@
+//! 页面回收：把用户页换出到交换区
+//!
+//! 时钟指针依次扫过各进程的用户内存：最近访问过的页清除访问位、得到第二次机会，
+//! 再次扫到时仍没有访问过的页换出（见 [`PageTable::swap_out`](crate::mm::PageTable::swap_out)）。
+//!
+//! 只换出在用户态被抢占、正在等待调度的进程的页，以及正在缺页或换入的进程自己的页：
+//! 这时内核没有在访问它们的用户内存，不会持有指向这些页的地址，也不会在持有自旋锁时需要换入。
+//! 进程在系统调用的其余部分（包括睡眠时）不会被换出，系统调用只需在开始时换入要访问的页。
+
+use core::sync::atomic::Ordering;
+
+use crate::consts::{NPROC, PGSIZE};
+use crate::ipi;
+use crate::mm::swap;
+use crate::spinlock::SpinLock;
+
+use super::{ProcManager, ProcState, Process};
+
+/// 每次回收最多换出的页数
+const RECLAIM_BATCH: usize = 32;
+
+/// 时钟指针：下一次扫描的进程在进程表中的索引与用户地址
+static CLOCK: SpinLock<(usize, usize)> = SpinLock::new((0, 0), "clock");
+
+impl ProcManager {
+    /// 物理内存耗尽时换出一批用户页
+    ///
+    /// # 功能说明
+    /// 从时钟指针处开始扫描可以换出的进程，换出至多 `RECLAIM_BATCH` 页。
+    /// 每个进程换出的页先击落 TLB，再写入交换区并释放物理页。
+    /// 最多扫过所有进程两圈，第一圈清除的访问位没有再被设置的页在第二圈换出。
+    ///
+    /// # 参数
+    /// - `current`: 缺页或正在换入的进程在进程表中的索引，它自己的页也可以换出
+    ///
+    /// # 返回值
+    /// 换出的页数，为 0 表示没有可以换出的页或交换区已满
+    ///
+    /// # 安全性
+    /// 调用者不能持有自旋锁：换出要击落 TLB 并写磁盘。
+    pub fn reclaim(&self, current: usize) -> usize {
+        let mut slots = [0usize; RECLAIM_BATCH];
+        let mut evicted = 0;
+        for _ in 0..2 * NPROC {
+            if evicted == RECLAIM_BATCH || swap::free_slots() == 0 {
+                break
+            }
+            let (index, start) = *CLOCK.lock();
+            let process = &self.table[index];
+
+            let guard = process.excl.lock();
+            let (n, stop, done, satp) = if Self::swappable(process, guard.state, current) {
+                let pdata = unsafe { &mut *process.data.get() };
+                let (n, stop, done) = pdata.swap_out(start, &mut slots[..RECLAIM_BATCH - evicted]);
+                (n, stop, done, pdata.pagetable.as_ref().unwrap().as_satp())
+            } else {
+                (0, start, true, 0)
+            };
+            drop(guard);
+
+            *CLOCK.lock() = if done { ((index + 1) % NPROC, 0) } else { (index, stop) };
+            if n == 0 {
+                continue
+            }
+            // 其他硬件线程缓存的旧映射击落之后，页的内容才不会再变
+            ipi::tlb_shootdown(satp, start, (stop - start).div_ceil(PGSIZE));
+            for &slot in &slots[..n] {
+                swap::write_out(slot);
+            }
+            evicted += n;
+        }
+        evicted
+    }
+
+    /// 进程的页此时能否换出，调用者持有进程的锁
+    fn swappable(process: &Process, state: ProcState, current: usize) -> bool {
+        let pdata = unsafe { &*process.data.get() };
+        if pdata.pagetable.is_none() || pdata.thread_count() > 1 {
+            // 多线程进程的其他线程可能正在内核中访问共享的用户内存
+            return false
+        }
+        process.index() == current
+            || (state == ProcState::RUNNABLE && process.user_preempted.load(Ordering::Relaxed))
+    }
+}
@@ -4,6 +4,7 @@ use crate::consts::{KERNEL_STACK_SIZE, PAGE_SIZE, USER_STACK_SIZE, TRAMPOLINE, C
 use crate::mm::{PhysAddr, RawPage, RawQuadPage, RawSinglePage, VirtAddr};
 use crate::process::{fork_ret};
 use crate::mm::{kvm_task_kstack_map};
+use crate::mm::pagetable::ustack_bottom_by_pos;
 use crate::process::proc::Process;
 use crate::process::trapframe::TrapFrame;
 use crate::process::Context;
@@ -35,21 +36,24 @@ impl KernelStack {
 
 }
 
-pub unsafe fn kstack_alloc(tid: usize) -> KernelStack {
+/// 为线程 `tid` 分配内核栈并映射到内核空间，内存不足时返回错误
+pub unsafe fn kstack_alloc(tid: usize) -> Result<KernelStack, ()> {
     let (kstack_bottom, kstack_top) = kernel_stack_position_by_tid(tid);
-    let pa = RawQuadPage::new_zeroed() as usize;
+    let pa = RawQuadPage::try_new_zeroed().map_err(|_| ())? as usize;
     kerror!("map kstack {:?} in kernel space",VirtAddr::try_from(kstack_bottom).unwrap());
     
-    kvm_task_kstack_map(
+    if !kvm_task_kstack_map(
         VirtAddr::try_from(kstack_bottom).unwrap(),
         PhysAddr::try_from(pa).unwrap(),
         tid,
         KERNEL_STACK_SIZE,
         PteFlag::R | PteFlag::W,
-    );
-    KernelStack {
-        kstack_base: kstack_bottom
+    ) {
+        RawQuadPage::from_raw_and_drop(pa as *mut u8);
     }
+    Ok(KernelStack {
+        kstack_base: kstack_bottom
+    })
 }
 
 impl Drop for KernelStack {
@@ -82,7 +86,7 @@ impl TaskUserRes {
         self.ustack_base
     }
     pub fn ustack_top(&self) -> usize {
-        ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_SIZE
+        ustack_bottom_by_pos(self.ustack_base, self.tid) + USER_STACK_SIZE
     }
 
     pub fn alloc_user_res(&self) {}
@@ -129,25 +133,19 @@ impl TaskControlInner {
     }
 }
 impl Task {
-    pub fn new(process: Option<*mut Process>, pos:usize, ustack_base: usize, entry: usize) -> Self {
+    /// 创建进程的第一个线程，从 `entry` 开始执行，内存不足时返回错误
+    pub fn new(process: Option<*mut Process>, pos:usize, ustack_base: usize, entry: usize) -> Result<Self, ()> {
         let tid = TID_ALLOCATOR.lock().tid_alloc();
         kinfo!("new alloc tid:{}", tid);
-        let kstack = unsafe { kstack_alloc(tid) };
         let proc = unsafe { process.unwrap().as_mut().unwrap() };
-        let procdata = proc.data.get_mut();
-        let trapframe_pa = unsafe { RawSinglePage::try_new_zeroed().unwrap() as usize};
-        kinfo!("set trapframe map {:?} {:x}",VirtAddr::from(trapframe_from_tid(tid)),procdata.pagetable.as_mut().unwrap().as_satp());
-        match procdata.pagetable.as_mut().unwrap()
-                    .map_pages(
-                        VirtAddr::from(trapframe_from_tid(tid)),
-                        PAGE_SIZE,
-                        PhysAddr::try_from(trapframe_pa).unwrap(),
-                        PteFlag::R | PteFlag::W,
-                    ) {
-            Ok(_) => {},
-            Err(_) => {panic!("task trapframe error")},
+        let (kstack, trapframe_pa) = match unsafe { alloc_task_res(proc, tid) } {
+            Ok(res) => res,
+            Err(()) => {
+                TID_ALLOCATOR.lock().tid_dealloc(tid);
+                return Err(());
+            }
         };
-        let ustack_bottom =ustack_bottom_from_tid(ustack_base, pos);
+        let ustack_bottom = ustack_bottom_by_pos(ustack_base, pos);
         let trapframe =unsafe {&mut *(trapframe_pa as *mut TrapFrame)};
         trapframe.epc = entry;
         trapframe.sp = ustack_bottom + USER_STACK_SIZE;
@@ -162,7 +160,7 @@ impl Task {
         context.set_ra(fork_ret as usize);
         context.set_sp(kstack.kstack_base + KERNEL_STACK_SIZE);
 
-        Self {
+        Ok(Self {
             process,
             kstack,
             ustack_base,
@@ -174,26 +172,19 @@ impl Task {
                 task_status: TaskStatus::Ready,
                 exit_code: None,
             },""),
-        }
+        })
     }
-    pub fn from(process: Option<*mut Process>, pos:usize, ustack_base: usize, ptrapframe: *mut TrapFrame) -> Self {
+    /// 创建 fork 出的子进程的线程，复制父线程的陷阱帧 `ptrapframe`，内存不足时返回错误
+    pub fn from(process: Option<*mut Process>, pos:usize, ustack_base: usize, ptrapframe: *mut TrapFrame) -> Result<Self, ()> {
         let tid = TID_ALLOCATOR.lock().tid_alloc();
         kinfo!("new alloc tid:{}", tid);
-        let kstack = unsafe { kstack_alloc(tid) };
         let proc = unsafe { &mut *process.unwrap() };
-        let procdata = proc.data.get_mut();
-        kinfo!("set trapframe");
-        let ctrapframe = unsafe { RawSinglePage::try_new_zeroed().unwrap() as usize};
-        kinfo!("map {:?} {:x}",VirtAddr::from(trapframe_from_tid(tid)),procdata.pagetable.as_mut().unwrap().as_satp());
-        match procdata.pagetable.as_mut().unwrap()
-                    .map_pages(
-                        VirtAddr::from(trapframe_from_tid(tid)),
-                        PAGE_SIZE,
-                        PhysAddr::try_from(ctrapframe).unwrap(),
-                        PteFlag::R | PteFlag::W,
-                    ) {
-            Ok(_) => {},
-            Err(_) => {panic!("task trapframe error")},
+        let (kstack, ctrapframe) = match unsafe { alloc_task_res(proc, tid) } {
+            Ok(res) => res,
+            Err(()) => {
+                TID_ALLOCATOR.lock().tid_dealloc(tid);
+                return Err(());
+            }
         };
         let trapframe =unsafe {(ctrapframe as *mut TrapFrame).as_mut().unwrap()};
         unsafe {
@@ -206,7 +197,7 @@ impl Task {
         context.set_ra(fork_ret as usize);
         context.set_sp(kstack.kstack_base + KERNEL_STACK_SIZE);
 
-        Self {
+        Ok(Self {
             process,
             kstack,
             ustack_base,
@@ -218,7 +209,7 @@ impl Task {
                 task_status: TaskStatus::Ready,
                 exit_code: None,
             },""),
-        }
+        })
     }
     pub fn get_context(&mut self) -> *mut Context {
         &mut self.inner.lock().task_context as *mut Context
@@ -247,6 +238,30 @@ impl Drop for Task {
     }
 }
 
+/// 为线程 `tid` 分配内核栈与陷阱帧，并把陷阱帧映射到进程 `proc` 的页表
+///
+/// # 可能的错误
+/// 内存不足，已分配的陷阱帧被释放；内核栈的映射与 tid 绑定，留给下一个使用这个 tid 的线程
+unsafe fn alloc_task_res(proc: &mut Process, tid: usize) -> Result<(KernelStack, usize), ()> {
+    let kstack = kstack_alloc(tid)?;
+    let procdata = proc.data.get_mut();
+    let trapframe = RawSinglePage::try_new_zeroed().map_err(|_| ())? as usize;
+    kinfo!("set trapframe map {:?} {:x}",VirtAddr::from(trapframe_from_tid(tid)),procdata.pagetable.as_mut().unwrap().as_satp());
+    if procdata.pagetable.as_mut().unwrap()
+        .map_pages(
+            VirtAddr::from(trapframe_from_tid(tid)),
+            PAGE_SIZE,
+            PhysAddr::try_from(trapframe).unwrap(),
+            PteFlag::R | PteFlag::W,
+        )
+        .is_err()
+    {
+        RawSinglePage::from_raw_and_drop(trapframe as *mut u8);
+        return Err(());
+    }
+    Ok((kstack, trapframe))
+}
+
 /// Return (bottom, top) of a kernel stack in kernel space.
 #[inline]
 fn kernel_stack_position_by_tid(tid: usize) -> (usize,usize) {
@@ -255,10 +270,6 @@ fn kernel_stack_position_by_tid(tid: usize) -> (usize,usize) {
     (kstack_bottom,kstack_top)
 }
 
-#[inline]
-fn ustack_bottom_from_tid(ustack_base: usize, pos: usize) -> usize {
-    ustack_base + pos * (PAGE_SIZE + USER_STACK_SIZE)
-}
 /// get the trapframe ptr in user space by tid
 #[inline]
 pub fn trapframe_from_tid(tid: usize) -> ConstAddr {
@@ -75,4 +75,6 @@ impl TrapFrame {
 #[derive(Debug)]
 pub struct UsysPage {
     pub pid: u32,
+    /// 上一个失败的系统调用的错误码，见 `include/errno.h`，0 表示未说明原因
+    pub errno: i32,
 }
\ No newline at end of file
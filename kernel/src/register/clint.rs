@@ -1,11 +1,12 @@
 //! CLINT（核心本地中断器）操作模块
 //!
-//! 提供对 RISC-V 平台中 CLINT 组件的访问接口，主要用于处理定时器中断。
+//! 提供对 RISC-V 平台中 CLINT 组件的访问接口，用于处理定时器中断与发送核间中断。
 //! 参考文档：doc/FU540-C000-v1.0.pdf
 //!
 //! # 关键寄存器
 //! - `mtime`: 64位全局计时器（所有核心共享）
 //! - `mtimecmp`: 每个核心独立的64位计时器比较寄存器
+//! - `msip`: 每个核心独立的32位软件中断挂起寄存器，写 1 向该核心发送机器模式软件中断
 //!
 //! # 工作原理
 //! 当 `mtime` 的值大于或等于某个核心的 `mtimecmp` 时，
@@ -15,7 +16,7 @@
 use core::ptr;
 use core::convert::Into;
 
-use crate::consts::{CLINT_MTIME, CLINT_MTIMECMP};
+use crate::consts::{CLINT_MSIP, CLINT_MTIME, CLINT_MTIMECMP};
 
 /// 读取全局计时器值 (mtime)
 ///
@@ -43,7 +44,7 @@ pub unsafe fn read_mtime() -> u64 {
 /// - 直接访问内存映射寄存器
 /// - 需确保核心ID有效
 #[inline]
-unsafe fn write_mtimecmp(mhartid: usize, value: u64) {
+pub unsafe fn write_mtimecmp(mhartid: usize, value: u64) {
     let offset = Into::<usize>::into(CLINT_MTIMECMP) + 8 * mhartid;
     ptr::write_volatile(offset as *mut u64, value);
 }
@@ -87,3 +88,20 @@ pub unsafe fn read_mtimecmp(mhartid: usize) -> u64 {
     let offset = Into::<usize>::into(CLINT_MTIMECMP) + 8 * mhartid;
     ptr::read_volatile(offset as *const u64)
 }
+
+/// 向核心发送机器模式软件中断
+///
+/// # 功能说明
+/// 置位目标核心的 `msip`，目标核心的 `machinevec` 清除它并转为监督模式软件中断。
+///
+/// # 参数
+/// - `mhartid`: 目标核心ID
+///
+/// # 安全性
+/// - 直接访问内存映射寄存器
+/// - 需确保核心ID有效
+#[inline]
+pub unsafe fn send_soft(mhartid: usize) {
+    let offset = Into::<usize>::into(CLINT_MSIP) + 4 * mhartid;
+    ptr::write_volatile(offset as *mut u32, 1);
+}
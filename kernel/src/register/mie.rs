@@ -50,3 +50,17 @@ pub unsafe fn set_mtie() {
     mie.set_bit(7, true);
     write(mie);
 }
+
+/// 使能机器模式软件中断 (MSIE)
+///
+/// # 功能说明
+/// 设置 mie 寄存器的第 3 位 (MSIE)，允许机器模式接收其他核心通过 CLINT 发送的软件中断。
+///
+/// # 安全性
+/// - 修改特权寄存器状态
+/// - 应在 mtvec 指向能处理软件中断的程序后调用
+pub unsafe fn set_msie() {
+    let mut mie = read();
+    mie.set_bit(3, true);
+    write(mie);
+}
@@ -121,6 +121,51 @@ pub mod mtvec {
     }
 }
 
+/// 机器计数器使能寄存器 (mcounteren) 操作
+///
+/// # 功能说明
+/// 控制监督模式能否读取 `cycle`、`time`、`instret` 计数器
+pub mod mcounteren {
+    /// `time` 计数器的使能位
+    const TM: usize = 1 << 1;
+
+    /// 允许监督模式读取 `time`，Sstc 下访问 `stimecmp` 也需要它
+    pub unsafe fn set_tm() {
+        core::arch::asm!("csrs mcounteren, {}", in(reg)TM);
+    }
+}
+
+/// 机器环境配置寄存器 (menvcfg) 操作
+///
+/// # 功能说明
+/// 配置监督模式可用的扩展，使用 CSR 编号以兼容不认识该寄存器名的汇编器
+pub mod menvcfg {
+    /// Sstc 扩展的使能位
+    const STCE: usize = 1 << 63;
+
+    /// 允许监督模式使用 `stimecmp`
+    ///
+    /// # 安全性
+    /// 仅在硬件线程支持 Sstc 扩展时调用，否则访问该寄存器会触发非法指令异常
+    pub unsafe fn set_stce() {
+        core::arch::asm!("csrs 0x30a, {}", in(reg)STCE);
+    }
+}
+
+/// 监督模式定时器比较寄存器 (stimecmp) 操作，由 Sstc 扩展提供
+///
+/// # 功能说明
+/// `time` 大于等于该值时产生监督模式定时器中断，写入更大的值即清除中断
+pub mod stimecmp {
+    /// 设置 stimecmp 寄存器值
+    ///
+    /// # 安全性
+    /// 需要 Sstc 扩展，且机器模式已设置 `menvcfg.STCE` 与 `mcounteren.TM`
+    pub unsafe fn write(stimecmp: u64) {
+        core::arch::asm!("csrw 0x14d, {}", in(reg)stimecmp);
+    }
+}
+
 /// 线程指针寄存器 (tp) 操作
 ///
 /// # 功能说明
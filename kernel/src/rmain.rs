@@ -2,15 +2,16 @@
 
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::driver::{virtio_disk::DISK, console};
+use crate::driver::{virtio, virtio_disk::DISK, ramdisk::INITRD, console, mem, random, rtc};
 use crate::register::tp;
+use crate::fdt::boot_fdt;
 use crate::fs::BCACHE;
-use crate::mm::kalloc::KERNEL_HEAP;
 use crate::mm::{kvm_init, kvm_init_hart};
 use crate::plic;
-use crate::process::{PROC_MANAGER, CPU_MANAGER};
+use crate::time;
+use crate::process::{aslr, PROC_MANAGER, CPU_MANAGER};
 use crate::trap::trap_init_hart;
-use crate::mm::page_allocator::init_page_allocator;
+use crate::mm::frame;
 /// 用于多核启动同步的全局原子布尔变量。
 ///
 /// `STARTED` 表示主核（cpuid == 0）是否完成了内核的全局初始化。
@@ -53,16 +54,29 @@ pub unsafe fn rust_main() -> ! {
         println!();
         println!("xv6-rust is booting");
         println!();
-        init_page_allocator();
-        KERNEL_HEAP.kinit();
+        rtc::init();
+        time::init();               // 启动时的墙上时间
+        frame::init();              // 物理页帧，内核堆从这里按需取得内存
         kvm_init(); // 初始化内核页表
         PROC_MANAGER.proc_init(); // 进程表
         kvm_init_hart(); // 开启分页
         trap_init_hart(); // 安装内核陷阱向量
-        plic::init();
         plic::init_hart(cpuid);
-        BCACHE.binit();             // 缓冲区缓存
-        DISK.lock().init();         // 仿真硬盘
+        virtio::probe();            // 扫描 virtio 设备
+        mem::init();                // /dev/null、/dev/zero、/dev/full
+        random::init();             // 熵源与内核随机数
+        aslr::init();               // 启动参数中的 ASLR 开关
+        // 缓冲区缓存：有 initrd 时根文件系统完全运行在内存盘上，否则使用仿真硬盘
+        match boot_fdt().and_then(|fdt| fdt.initrd()) {
+            Some((start, end)) => {
+                INITRD.init(start, end);
+                BCACHE.binit(&INITRD);
+            }
+            None => {
+                BCACHE.binit(&DISK);
+                DISK.lock().init();
+            }
+        }
         PROC_MANAGER.user_init();   //  第一个用户进程
 
         STARTED.store(true, Ordering::SeqCst);
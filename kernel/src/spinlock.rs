@@ -260,6 +260,9 @@ impl<'a, T> SpinLockGuard<'a, T> {
 #[cfg(feature = "unit_test")]
 pub mod tests {
     use super::*;
+    use crate::ktest::TestHarts;
+
+    kernel_test!(smoke, TestHarts::Hart(0));
 
     /// 基础功能测试：验证锁的获取和释放。
     ///
@@ -2,25 +2,39 @@
 
 
 use core::{arch::asm, convert::Into};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::{consts::{CLINT_MTIMECMP, NCPU}, register::sie};
+use crate::{consts::{CLINT_MSIP, CLINT_MTIMECMP, NCPU}, register::sie};
 use crate::register::{
-    clint, medeleg, mepc, mhartid, mideleg, mie, mscratch, mstatus, mtvec, satp, tp,
+    clint, mcounteren, medeleg, menvcfg, mepc, mhartid, mideleg, mie, mscratch, mstatus, mtvec,
+    satp, stimecmp, tp,
 };
+use crate::fdt::{DTB_ADDR, Fdt};
 use crate::rmain::rust_main;
+use crate::time::SSTC;
 
 /// 每个CPU的机器模式上下文存储区
 ///
-/// 该数组为每个CPU核心提供32个usize大小的存储空间，但仅使用前6个元素：
-/// - [0..3]：为timervec保存寄存器的空间
+/// 该数组为每个CPU核心提供32个usize大小的存储空间，但仅使用前7个元素：
+/// - [0..3]：为machinevec保存寄存器的空间
 /// - [4]：CLINT MTIMECMP寄存器的地址
-/// - [5]：定时器中断的期望间隔（周期数）
+/// - [5]：CLINT MSIP寄存器的地址
+/// - [6]：machinevec 转发时钟中断时置 1，见 [`take_timer_forwarded`]
 /// 
 /// # 安全性
 /// - 使用`static mut`声明，访问需在`unsafe`块中
 /// - 索引计算：`offset = 32 * hartid`
 static mut MSCRATCH0: [usize; NCPU * 32] = [0; NCPU * 32];
 
+/// 取出并清除硬件线程 `id` 的时钟中断转发标志
+///
+/// 机器模式中断可能在任何时候置位标志，用原子交换读取并清除，不会丢失其间的转发。
+pub fn take_timer_forwarded(id: usize) -> bool {
+    let flag = unsafe { &*(ptr::addr_of!(MSCRATCH0[32 * id + 6]) as *const AtomicUsize) };
+    flag.swap(0, Ordering::Relaxed) != 0
+}
+
 /// RISC-V机器模式入口点
 ///
 /// # 功能说明
@@ -34,9 +48,14 @@ static mut MSCRATCH0: [usize; NCPU * 32] = [0; NCPU * 32];
 /// 3. 禁用分页（初始阶段）
 /// 4. 委托所有异常和中断给监督者模式
 /// 5. 配置物理内存保护(PMP)
-/// 6. 初始化定时器中断
-/// 7. 将核心ID(hartid)存储在tp寄存器
-/// 8. 执行mret切换到监督者模式
+/// 6. 设置机器模式陷阱处理程序，接收核间中断
+/// 7. 初始化定时器中断
+/// 8. 将核心ID(hartid)存储在tp寄存器
+/// 9. 执行mret切换到监督者模式
+///
+/// # 参数
+/// - `_hartid`：qemu 通过 `a0` 传入的核心ID，与 `mhartid` 相同
+/// - `dtb`：qemu 通过 `a1` 传入的设备树物理地址，由 0 号核心保存到 [`DTB_ADDR`]
 ///
 /// # 注意事项
 /// - 此函数标记为`#[no_mangle]`确保链接器能正确找到入口点
@@ -46,7 +65,11 @@ static mut MSCRATCH0: [usize; NCPU * 32] = [0; NCPU * 32];
 /// - 直接操作硬件寄存器，需确保正确配置
 /// - 访问全局数组MSCRATCH0需unsafe
 #[no_mangle]
-pub unsafe fn start() -> ! {
+pub unsafe extern "C" fn start(_hartid: usize, dtb: usize) -> ! {
+    if mhartid::read() == 0 {
+        DTB_ADDR.store(dtb, Ordering::Relaxed);
+    }
+
     // 设置mstatus.MPP为监督者模式，确保mret后进入监督者模式
     mstatus::set_mpp(mstatus::MPP::Supervisor);
 
@@ -68,8 +91,9 @@ pub unsafe fn start() -> ! {
         csrw pmpcfg0, t0
     ");
 
-    // 请求时钟中断
-    timerinit();
+    // 配置机器模式陷阱处理程序与时钟中断
+    mtrapinit();
+    timerinit(dtb);
 
     // 将每个 CPU 的 hartid 保持在其 tp 寄存器中，以供 cpuid () 使用。
     let id = mhartid::read();
@@ -82,56 +106,88 @@ pub unsafe fn start() -> ! {
     loop {}
 }
 
-/// 初始化机器模式定时器中断
+/// 初始化机器模式的陷阱处理
 ///
 /// # 功能说明
-/// 配置每个CPU核心的定时器中断，使其定期触发。
-/// 中断将由`timervec`处理（在汇编中定义），
-/// 该处理程序将机器模式中断转换为监督者模式的软件中断。
+/// 机器模式只处理两种中断，都由`machinevec`（在汇编中定义）转为监督模式的软件中断：
+/// - 未启用 Sstc 时的定时器中断；
+/// - 其他核心写本核心的 CLINT MSIP 发来的软件中断，即核间中断。
 ///
 /// # 流程解释
-/// 1. 获取当前核心ID(hartid)
-/// 2. 通过CLINT设置MTIMECMP寄存器（触发中断的时间点）
-/// 3. 在MSCRATCH0中准备定时器中断处理所需信息
-/// 4. 设置mscratch寄存器指向当前核心的上下文存储区
-/// 5. 设置机器模式陷阱处理程序为timervec
-/// 6. 启用机器模式中断(MIE)和定时器中断(MTIE)
-///
-/// # 参数
-/// 无
-///
-/// # 返回值
-/// 无
+/// 1. 在MSCRATCH0中准备`machinevec`所需信息，设置mscratch寄存器指向它
+/// 2. 设置机器模式陷阱处理程序为machinevec
+/// 3. 启用机器模式中断(MIE)和软件中断(MSIE)
 ///
 /// # 安全性
 /// - 访问全局数组MSCRATCH0需unsafe
 /// - 直接操作硬件中断寄存器
-unsafe fn timerinit() {
-    // 每个 CPU 都有一个独立的定时器中断源。
+unsafe fn mtrapinit() {
     let id = mhartid::read();
 
-    // 向 CLINT 请求一个定时器中断。
-    let interval: u64 = 1000000; // 时钟周期；在 qemu 中大约0.1秒。
-    clint::add_mtimecmp(id, interval);
-
-    // 为 timervec 在 scratch [] 中准备信息。
-    // scratch [0..3]：供 timervec 保存寄存器的空间。
+    // 为 machinevec 在 scratch [] 中准备信息。
+    // scratch [0..3]：供 machinevec 保存寄存器的空间。
     // scratch [4]：CLINT 的 MTIMECMP 寄存器的地址。
-    // scratch [5]：定时器中断之间的期望间隔（以时钟周期为单位）。
+    // scratch [5]：CLINT 的 MSIP 寄存器的地址。
+    // scratch [6]：时钟中断转发标志，由 machinevec 置位。
     let offset = 32 * id;
     MSCRATCH0[offset + 4] = 8 * id + Into::<usize>::into(CLINT_MTIMECMP);
-    MSCRATCH0[offset + 5] = interval as usize;
+    MSCRATCH0[offset + 5] = 4 * id + Into::<usize>::into(CLINT_MSIP);
     mscratch::write((MSCRATCH0.as_ptr() as usize) + offset * core::mem::size_of::<usize>());
 
     // 设置机器模式的陷阱处理程序。
     extern "C" {
-        fn timervec();
+        fn machinevec();
     }
-    mtvec::write(timervec as usize);
+    mtvec::write(machinevec as usize);
 
     // 启用机器模式中断。
     mstatus::set_mie();
 
+    // 启用机器模式软件中断。
+    mie::set_msie();
+}
+
+/// 初始化定时器中断
+///
+/// # 功能说明
+/// 配置每个CPU核心的定时器，开始时不产生中断，由监督模式的内核按需设定下一次中断的时间。
+/// - 设备树表明支持 Sstc 扩展时，开放 `stimecmp` 给监督模式，定时器中断直接交给监督模式；
+/// - 否则监督模式写 CLINT 的 MTIMECMP，中断由`machinevec`处理，
+///   该处理程序将机器模式中断转换为监督者模式的软件中断。
+///
+/// # 流程解释
+/// 1. 获取当前核心ID(hartid)，允许监督模式读取 `time`
+/// 2. 支持 Sstc 时设置 `menvcfg.STCE` 并把 `stimecmp` 设为最大值，结束
+/// 3. 否则把 MTIMECMP 设为最大值，启用机器模式定时器中断(MTIE)
+///
+/// # 参数
+/// - `dtb`：设备树物理地址，用于检查 Sstc 扩展
+///
+/// # 返回值
+/// 无
+///
+/// # 安全性
+/// - 需在 [`mtrapinit`] 之后调用
+/// - 直接操作硬件中断寄存器
+unsafe fn timerinit(dtb: usize) {
+    // 每个 CPU 都有一个独立的定时器中断源。
+    let id = mhartid::read();
+    mcounteren::set_tm();
+
+    let sstc = match Fdt::from_raw(dtb) {
+        Ok(fdt) => fdt.cpus_have_extension("sstc"),
+        Err(_) => false,
+    };
+    if sstc {
+        SSTC.store(true, Ordering::Relaxed);
+        menvcfg::set_stce();
+        stimecmp::write(u64::MAX);
+        return;
+    }
+
+    // 第一次中断由内核在 trap_init_hart 中设定。
+    clint::write_mtimecmp(id, u64::MAX);
+
     // 启用机器模式定时器中断。
     mie::set_mtie();
 }
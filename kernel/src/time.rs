@@ -0,0 +1,190 @@
+//! 内核时间
+//!
+//! - 单调时钟：mtime 换算成的纳秒数，从启动开始计时；
+//! - 实时时钟：启动时从 Goldfish RTC 读出墙上时间，记下它与单调时钟之差，之后由单调时钟推算。
+//!
+//! 文件系统用 [`unix_time`] 给 inode 打时间戳，`clock_gettime` 系统调用由 [`clock_gettime`] 实现。
+//!
+//! 时钟中断由 [`set_timer`] 按需设定：支持 Sstc 扩展时直接写 `stimecmp`，产生监督模式定时器中断；
+//! 否则写 CLINT 的 `mtimecmp`，由机器模式的 `machinevec` 转为监督模式软件中断。
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::consts::{HZ, TIMEBASE_FREQ};
+use crate::driver::rtc;
+use crate::process::CpuManager;
+use crate::register::{clint, stimecmp};
+use crate::start;
+
+/// 时钟编号，与 `include/time.h` 一致
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// 一个 tick 的 mtime 周期数
+pub const TICK_INTERVAL: u64 = TIMEBASE_FREQ / HZ;
+
+/// 硬件线程是否支持 Sstc 扩展，由 `start` 在机器模式下根据设备树设置
+pub static SSTC: AtomicBool = AtomicBool::new(false);
+
+/// 墙上时间减去单调时钟，单位为纳秒
+static REALTIME_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// 用户态的 `struct timespec`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timespec {
+    pub sec: i64,
+    pub nsec: i64,
+}
+
+impl Timespec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            sec: (ns / NSEC_PER_SEC) as i64,
+            nsec: (ns % NSEC_PER_SEC) as i64,
+        }
+    }
+
+    /// 换算成纳秒，负数或 `nsec` 越界时返回 `None`
+    pub fn to_ns(&self) -> Option<u64> {
+        if self.sec < 0 || !(0..NSEC_PER_SEC as i64).contains(&self.nsec) {
+            return None;
+        }
+        (self.sec as u64).checked_mul(NSEC_PER_SEC)?.checked_add(self.nsec as u64)
+    }
+}
+
+/// 用户态的 `struct timeval`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeval {
+    pub sec: i64,
+    pub usec: i64,
+}
+
+impl Timeval {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            sec: (ns / NSEC_PER_SEC) as i64,
+            usec: (ns % NSEC_PER_SEC / 1000) as i64,
+        }
+    }
+
+    /// 换算成纳秒，负数或 `usec` 越界时返回 `None`
+    pub fn to_ns(&self) -> Option<u64> {
+        Timespec { sec: self.sec, nsec: self.usec.checked_mul(1000)? }.to_ns()
+    }
+}
+
+/// 用户态的 `struct itimerval`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Itimerval {
+    pub interval: Timeval,
+    pub value: Timeval,
+}
+
+/// 用户态的 `struct itimerspec`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Itimerspec {
+    pub interval: Timespec,
+    pub value: Timespec,
+}
+
+/// 从实时时钟读取启动时的墙上时间
+///
+/// 在 [`rtc::init`] 之后、开启分页之前由 0 号核心调用。
+pub fn init() {
+    let now = rtc::read_ns();
+    REALTIME_OFFSET.store(now.saturating_sub(monotonic_ns()), Ordering::Relaxed);
+
+    #[cfg(feature = "verbose_init_info")]
+    println!("time: {}s since the epoch", now / NSEC_PER_SEC);
+}
+
+/// 当前的 mtime 值
+#[inline]
+pub fn mtime() -> u64 {
+    unsafe { clint::read_mtime() }
+}
+
+/// 启动以来的 tick 数
+pub fn ticks() -> usize {
+    (mtime() / TICK_INTERVAL) as usize
+}
+
+/// 在 mtime 到达 `deadline` 时向本硬件线程发出时钟中断，`u64::MAX` 表示不再中断
+///
+/// # 安全性
+/// 需关闭中断后调用，以免在读取核心编号与写寄存器之间被迁移到别的硬件线程。
+pub unsafe fn set_timer(deadline: u64) {
+    if SSTC.load(Ordering::Relaxed) {
+        stimecmp::write(deadline);
+    } else {
+        clint::write_mtimecmp(CpuManager::cpu_id(), deadline);
+    }
+}
+
+/// 本硬件线程的监督模式软件中断是否是 `machinevec` 转发的时钟中断
+///
+/// `machinevec` 转发时钟中断时置位本硬件线程的转发标志，这里取出并清除它。
+/// 空闲的硬件线程的 `mtimecmp` 也是最大值，不能用它与核间中断区分。
+/// 使用 Sstc 时时钟中断不经过软件中断，总是返回 `false`。
+pub fn timer_forwarded() -> bool {
+    !SSTC.load(Ordering::Relaxed) && start::take_timer_forwarded(unsafe { CpuManager::cpu_id() })
+}
+
+/// 把 mtime 周期数换算成纳秒
+pub fn mtime_to_ns(ticks: u64) -> u64 {
+    // 整秒与余数分开换算，避免乘法溢出
+    ticks / TIMEBASE_FREQ * NSEC_PER_SEC + ticks % TIMEBASE_FREQ * NSEC_PER_SEC / TIMEBASE_FREQ
+}
+
+/// 把纳秒换算成 mtime 周期数，向上取整，保证定时器不会提前到期
+pub fn ns_to_mtime(ns: u64) -> u64 {
+    let rem = ns % NSEC_PER_SEC;
+    (ns / NSEC_PER_SEC).saturating_mul(TIMEBASE_FREQ)
+        .saturating_add((rem * TIMEBASE_FREQ).div_ceil(NSEC_PER_SEC))
+}
+
+/// 把编号为 `clock` 的时钟上的绝对时间（纳秒）换算成 mtime 值，早于启动的时间换算为 0
+///
+/// # 可能的错误
+/// 不支持的时钟编号
+pub fn clock_to_mtime(clock: usize, ns: u64) -> Result<u64, ()> {
+    match clock {
+        CLOCK_REALTIME => Ok(ns_to_mtime(ns.saturating_sub(REALTIME_OFFSET.load(Ordering::Relaxed)))),
+        CLOCK_MONOTONIC => Ok(ns_to_mtime(ns)),
+        _ => Err(()),
+    }
+}
+
+/// 启动以来的纳秒数
+pub fn monotonic_ns() -> u64 {
+    mtime_to_ns(mtime())
+}
+
+/// 自 1970-01-01 UTC 起的纳秒数
+pub fn realtime_ns() -> u64 {
+    REALTIME_OFFSET.load(Ordering::Relaxed) + monotonic_ns()
+}
+
+/// 自 1970-01-01 UTC 起的秒数
+pub fn unix_time() -> u64 {
+    realtime_ns() / NSEC_PER_SEC
+}
+
+/// 读取编号为 `clock` 的时钟
+///
+/// # 可能的错误
+/// 不支持的时钟编号
+pub fn clock_gettime(clock: usize) -> Result<Timespec, ()> {
+    match clock {
+        CLOCK_REALTIME => Ok(Timespec::from_ns(realtime_ns())),
+        CLOCK_MONOTONIC => Ok(Timespec::from_ns(monotonic_ns())),
+        _ => Err(()),
+    }
+}
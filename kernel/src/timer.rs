@@ -0,0 +1,215 @@
+//! 内核定时器
+//!
+//! 定时器按到期时间（mtime 值）排序保存，时钟中断时调用到期定时器的回调 `callback(arg, n)`，
+//! `n` 是自上次回调以来到期的次数：一次性定时器总是 1，周期定时器错过若干周期时大于 1。
+//! 周期定时器在调用回调前就按周期重新排入队列，重新排入时不分配内存，可以在中断中进行。
+//!
+//! 回调在不持有队列锁时调用，因此可以获取其他锁并唤醒进程，但不能假设取消之后回调不再运行：
+//! 另一个硬件线程可能已经取出了定时器、正在执行它的回调。回调的参数若指向会被释放的对象，
+//! 需要自行校验（例如带上代数）。
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::spinlock::SpinLock;
+
+/// 定时器回调，参数为添加定时器时给出的 `arg` 与到期次数
+pub type TimerFn = fn(usize, u64);
+
+/// 定时器编号，用于取消与查询
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerId(u64);
+
+#[derive(Clone, Copy)]
+struct Timer {
+    callback: TimerFn,
+    arg: usize,
+    /// 周期，0 表示一次性定时器
+    interval: u64,
+}
+
+/// 按到期时间排序的定时器队列
+pub struct TimerQueue {
+    next_id: u64,
+    /// 按 (到期时间, 编号) 从大到小排列，最早到期的在末尾，到期时间相同时按添加顺序；
+    /// 取出的周期定时器放回空出的位置，数组长度不变
+    timers: Vec<((u64, u64), Timer)>,
+    /// 编号到到期时间的索引
+    deadlines: BTreeMap<u64, u64>,
+}
+
+impl TimerQueue {
+    pub const fn new() -> Self {
+        Self {
+            next_id: 0,
+            timers: Vec::new(),
+            deadlines: BTreeMap::new(),
+        }
+    }
+
+    /// 添加一个在 `deadline` 到期的定时器，`interval` 非 0 时此后每隔 `interval` 再次到期
+    pub fn add(&mut self, deadline: u64, interval: u64, callback: TimerFn, arg: usize) -> TimerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.insert((deadline, id), Timer { callback, arg, interval });
+        self.deadlines.insert(id, deadline);
+        TimerId(id)
+    }
+
+    /// 在 `timers` 中查找键 `key`，不存在时返回应插入的位置
+    fn search(&self, key: (u64, u64)) -> Result<usize, usize> {
+        self.timers.binary_search_by(|(k, _)| key.cmp(k))
+    }
+
+    fn insert(&mut self, key: (u64, u64), timer: Timer) {
+        let pos = self.search(key).unwrap_err();
+        self.timers.insert(pos, (key, timer));
+    }
+
+    /// 取消定时器
+    ///
+    /// # 返回值
+    /// 定时器尚未到期（或是周期定时器）时返回它的 (下次到期时间, 周期)，否则返回 `None`
+    pub fn cancel(&mut self, id: TimerId) -> Option<(u64, u64)> {
+        let deadline = self.deadlines.remove(&id.0)?;
+        let (_, timer) = self.timers.remove(self.search((deadline, id.0)).unwrap());
+        Some((deadline, timer.interval))
+    }
+
+    /// 查询定时器的 (下次到期时间, 周期)，已经到期的一次性定时器返回 `None`
+    pub fn get(&self, id: TimerId) -> Option<(u64, u64)> {
+        let deadline = *self.deadlines.get(&id.0)?;
+        Some((deadline, self.timers[self.search((deadline, id.0)).unwrap()].1.interval))
+    }
+
+    /// 最早的到期时间，队列为空时为 `u64::MAX`
+    pub fn next_deadline(&self) -> u64 {
+        self.timers.last().map_or(u64::MAX, |&((deadline, _), _)| deadline)
+    }
+
+    /// 取出一个在 `now` 或之前到期的定时器，返回它的回调、参数与到期次数
+    pub fn pop_expired(&mut self, now: u64) -> Option<(TimerFn, usize, u64)> {
+        let &((deadline, id), _) = self.timers.last()?;
+        if deadline > now {
+            return None;
+        }
+        let (_, timer) = self.timers.pop().unwrap();
+        let mut count = 1;
+        if timer.interval > 0 {
+            count += (now - deadline) / timer.interval;
+            let next = deadline + count * timer.interval;
+            // 只替换已有的值，和放回刚空出的位置一样不分配内存
+            *self.deadlines.get_mut(&id).unwrap() = next;
+            self.insert((next, id), timer);
+        } else {
+            self.deadlines.remove(&id);
+        }
+        Some((timer.callback, timer.arg, count))
+    }
+}
+
+static TIMERS: SpinLock<TimerQueue> = SpinLock::new(TimerQueue::new(), "timer");
+
+/// 添加定时器，见 [`TimerQueue::add`]
+pub fn add(deadline: u64, interval: u64, callback: TimerFn, arg: usize) -> TimerId {
+    TIMERS.lock().add(deadline, interval, callback, arg)
+}
+
+/// 取消定时器，见 [`TimerQueue::cancel`]
+pub fn cancel(id: TimerId) -> Option<(u64, u64)> {
+    TIMERS.lock().cancel(id)
+}
+
+/// 查询定时器，见 [`TimerQueue::get`]
+pub fn get(id: TimerId) -> Option<(u64, u64)> {
+    TIMERS.lock().get(id)
+}
+
+/// 最早的到期时间，没有定时器时为 `u64::MAX`
+pub fn next_deadline() -> u64 {
+    TIMERS.lock().next_deadline()
+}
+
+/// 依次调用所有在 `now` 或之前到期的定时器的回调，由时钟中断调用
+pub fn run_expired(now: u64) {
+    loop {
+        // 取出后立即释放锁再调用回调
+        let expired = TIMERS.lock().pop_expired(now);
+        match expired {
+            Some((callback, arg, count)) => callback(arg, count),
+            None => break,
+        }
+    }
+}
+
+/// 宿主机单元测试，只检查队列本身
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+
+    fn nop(_arg: usize, _count: u64) {}
+
+    #[test]
+    fn pops_in_deadline_order() {
+        let mut queue = TimerQueue::new();
+        assert_eq!(queue.next_deadline(), u64::MAX);
+        queue.add(30, 0, nop, 3);
+        queue.add(10, 0, nop, 1);
+        queue.add(20, 0, nop, 2);
+        assert_eq!(queue.next_deadline(), 10);
+
+        assert!(queue.pop_expired(5).is_none());
+        let args: alloc::vec::Vec<usize> = core::iter::from_fn(|| queue.pop_expired(25))
+            .map(|(_, arg, count)| { assert_eq!(count, 1); arg })
+            .collect();
+        assert_eq!(args, [1, 2]);
+        assert_eq!(queue.next_deadline(), 30);
+    }
+
+    #[test]
+    fn cancel_and_get() {
+        let mut queue = TimerQueue::new();
+        let a = queue.add(10, 0, nop, 0);
+        let b = queue.add(20, 5, nop, 0);
+        assert_eq!(queue.get(b), Some((20, 5)));
+        assert_eq!(queue.cancel(a), Some((10, 0)));
+        assert_eq!(queue.cancel(a), None);
+        assert_eq!(queue.next_deadline(), 20);
+
+        // 一次性定时器到期后不再能查询
+        let c = queue.add(15, 0, nop, 0);
+        assert!(queue.pop_expired(15).is_some());
+        assert_eq!(queue.get(c), None);
+    }
+
+    #[test]
+    fn periodic_timer_counts_overruns() {
+        let mut queue = TimerQueue::new();
+        let id = queue.add(100, 10, nop, 7);
+
+        let (_, arg, count) = queue.pop_expired(100).unwrap();
+        assert_eq!((arg, count), (7, 1));
+        assert_eq!(queue.get(id), Some((110, 10)));
+
+        // 错过了 110、120、130 三个周期
+        let (_, _, count) = queue.pop_expired(135).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(queue.get(id), Some((140, 10)));
+        assert!(queue.pop_expired(135).is_none());
+    }
+
+    #[test]
+    fn periodic_requeue_does_not_allocate() {
+        let mut queue = TimerQueue::new();
+        queue.add(100, 10, nop, 0);
+        queue.add(105, 0, nop, 1);
+        let buf = (queue.timers.as_ptr(), queue.timers.capacity());
+
+        // 时钟中断中重新排入周期定时器不能扩大或移动数组
+        for now in (100..200).step_by(10) {
+            while queue.pop_expired(now).is_some() {}
+        }
+        assert_eq!((queue.timers.as_ptr(), queue.timers.capacity()), buf);
+        assert_eq!(queue.next_deadline(), 200);
+    }
+}
@@ -1,25 +1,24 @@
 //! 中断处理模块，用户或内核模式下发生中断或异常时进行处理
 
-use core::alloc::GlobalAlloc;
-use core::{alloc::Layout, num::Wrapping};
 use core::sync::atomic::Ordering;
 
-use crate::mm::{pg_round_down, PhysAddr, PteFlag, VirtAddr};
-use crate::mm::{trapframe_from_pid, VirtAddr};
-use crate::{consts::{ConstAddr, PAGE_SIZE, TRAMPOLINE, TRAPFRAME, UART0_IRQ, USER_STACK_SIZE, VIRTIO0_IRQ}, mm::KERNEL_HEAP, process::{Process, PROC_MANAGER}};
+use crate::mm::{trapframe_from_pid, PteFlag, VirtAddr};
+use crate::mm::asid;
+use crate::{consts::{ConstAddr, PAGE_SIZE, TRAMPOLINE, TRAPFRAME, USER_STACK_SIZE}, process::{Process, PROC_MANAGER}};
 use crate::register::{stvec, sstatus, sepc, stval, sip,
     scause::{self}};
-use crate::process::{CPU_MANAGER, CpuManager};
+use crate::process::{CpuManager, CPU_MANAGER, RUNQUEUES, pop_off, push_off};
 use crate::spinlock::SpinLock;
+use crate::ipi;
 use crate::plic;
-use crate::driver::virtio_disk::DISK;
-use crate::driver::uart::UART;
+use crate::time::{self, TICK_INTERVAL};
+use crate::timer::{self, TimerFn, TimerId};
 
 /// 初始化当前CPU核心的中断处理
 ///
 /// # 功能说明
 /// 设置监督者模式陷阱向量基址寄存器(stvec)，
-/// 指向内核中断处理程序(kernelvec)。
+/// 指向内核中断处理程序(kernelvec)，开始接收核间中断，并设定第一次时钟中断。
 ///
 /// # 安全性
 /// - 必须在核心启动时调用
@@ -30,6 +29,8 @@ pub unsafe fn trap_init_hart() {
     }
 
     stvec::write(kernelvec as usize);
+    ipi::init_hart();
+    set_next_timer(true);
 }
 
 /// 用户模式陷阱入口（由trampoline.S调用）
@@ -42,9 +43,11 @@ pub unsafe fn trap_init_hart() {
 /// 1. 验证中断来源确为用户模式
 /// 2. 设置陷阱处理程序为内核模式处理入口
 /// 3. 根据中断原因(scause)分发处理：
-///   - 外部中断：处理UART/磁盘中断
-///   - 软件中断：处理时钟中断
+///   - 外部中断：由 PLIC 分发给登记的驱动
+///   - 软件中断：处理核间中断，并在是 machinevec 转发的时钟中断时处理时钟中断
+///   - 时钟中断（Sstc 的定时器中断或 machinevec 转发的软件中断）：处理时钟中断，有其他进程可运行时让出CPU
 ///   - 系统调用：执行系统调用处理
+///   - 缺页：为按需分配的堆分配物理页、从可执行文件读入程序段或换入换出的页，内存耗尽时先换出别的页，仍不足时由 OOM killer 杀死进程
 ///   - 其他异常：终止进程
 /// 4. 处理完成后返回用户空间
 ///
@@ -72,37 +75,22 @@ pub unsafe extern fn user_trap() {
         Trap::Interrupt(scause::Interrupt::SupervisorExternal) => {
             // 监督者模式外部中断
 
-            // 从PLIC中断控制器获取中断号
-            let irq = plic::claim();
-
-            // 处理UART串口中断
-            if irq as usize == UART0_IRQ {
-                UART.intr();
-
-            // 处理虚拟磁盘中断
-            } else if irq as usize == VIRTIO0_IRQ {
-                DISK.lock().intr();
-            } else {
-                //panic!("unexpected interrupt, irq={}", irq);
-            }
-            // 其他中断暂不处理
-
-            // 完成中断处理
-            if irq > 0 {
-                plic::complete(irq);
-            }
+            // 由 PLIC 声明中断并分发给登记的驱动
+            plic::handle_irq();
 
             // 检查进程终止标志
             process.check_abondon(-1);
         }
-        Trap::Interrupt(scause::Interrupt::SupervisorSoft) => {
-            // 监督者模式软件中断
-            // 仅在CPU 0上更新时钟计数
-            if CpuManager::cpu_id() == 0 {
-                clock_intr();
+        Trap::Interrupt(scause::Interrupt::SupervisorSoft)
+        | Trap::Interrupt(scause::Interrupt::SupervisorTimer) => {
+            // 核间中断或时钟中断
+            let clock = matches!(scause.cause(), Trap::Interrupt(scause::Interrupt::SupervisorTimer))
+                || soft_intr();
+            if clock {
+                timer_intr();
             }
 
-            if STARTED.load(Ordering::SeqCst) {
+            if clock && STARTED.load(Ordering::SeqCst) {
                 let pa = unsafe { CPU_MANAGER.my_proc().alarm.get_mut() };
                 let pd = unsafe { CPU_MANAGER.my_proc().data.get_mut() };
                 if pa.interval > 0 {
@@ -115,13 +103,16 @@ pub unsafe extern fn user_trap() {
                     pa.handler_called = true;
                 }
             }
-            // 清除软件中断标志
-            sip::clear_ssip();
 
             // 检查进程终止标志
             process.check_abondon(-1);
-            // 主动让出CPU
-            process.yielding();
+            // 本硬件线程有其他进程等待运行时才让出CPU
+            if RUNQUEUES.has_ready(CpuManager::cpu_id()) {
+                // 等待调度期间内核不会访问本进程的用户内存，它的页可以被换出
+                process.user_preempted.store(true, Ordering::Relaxed);
+                process.yielding();
+                process.user_preempted.store(false, Ordering::Relaxed);
+            }
         }
         Trap::Exception(scause::Exception::UserEnvCall)=> {
             // 用户模式系统调用
@@ -133,19 +124,35 @@ pub unsafe extern fn user_trap() {
             // 再次检查终止标志（系统调用可能设置）
             p.check_abondon(-1);
         }
-        ScauseType::PageFault => {
-            //println!("!!");
-            let layout = Layout::from_size_align(4096, 4096).expect("Invalid layout");
-            // 分配 4KB 的内存空间
-            let ptr = GlobalAlloc::alloc(&KERNEL_HEAP, layout) as *mut u8;
-            if ptr.is_null() {
-                p.killed = true.into();
+        Trap::Exception(scause::Exception::InstructionPageFault)
+        | Trap::Exception(scause::Exception::LoadPageFault)
+        | Trap::Exception(scause::Exception::StorePageFault) => {
+            // 堆按需分配：sbrk 只增加进程大小，第一次访问时才分配物理页；
+            // 程序段第一次访问时才从可执行文件读入，换出到交换区的页在这里换入
+            let va = stval::read();
+            let pdata = process.data.get_mut();
+            if !pdata.is_lazy(va) && !pdata.is_swapped(va) {
+                // 其他缺页是非法访问：访问保护页、写只读页、执行数据页等，报告段错误并杀死进程
+                let (access, what) = match scause.cause() {
+                    Trap::Exception(scause::Exception::InstructionPageFault) => (PteFlag::X, "execute"),
+                    Trap::Exception(scause::Exception::StorePageFault) => (PteFlag::W, "write"),
+                    _ => (PteFlag::R, "read"),
+                };
+                // 先取出原因，不要在持有 pdata 的借用时加进程锁、退出进程
+                let reason = pdata.fault_reason(va, access);
+                let pid = process.excl.lock().pid;
+                println!("segfault: pid={} {} at va={:#x} sepc={:#x}: {}", pid, what, va, sepc::read(), reason);
+                process.abondon(-1);
+            }
+            // 内存耗尽时先换出最近没有访问过的页（可能包括本进程的），返回用户态后重新执行出错的指令；
+            // 没有页可以换出时杀死常驻内存最多的进程，选中本进程时在这里退出，
+            // 否则让出 CPU 等被杀死的进程归还内存
+            let pdata = process.data.get_mut();
+            if pdata.handle_fault(va).is_err() && PROC_MANAGER.reclaim(process.index()) == 0 {
+                PROC_MANAGER.oom_kill();
+                process.check_abondon(-1);
+                process.yielding();
             }
-            p.data.get_mut().pagetable.as_mut().unwrap().map_pages(
-                VirtAddr::from_raw(pg_round_down(stval::read())),
-                4096,
-                PhysAddr::from_raw(ptr as usize),
-                PteFlag::R | PteFlag::W | PteFlag::U);
         }
         _ => {
             // 未知异常
@@ -172,7 +179,7 @@ use crate::process::task::task::trapframe_from_tid;
 /// 1. 禁用中断（sstatus::intr_off）
 /// 2. 设置返回用户模式所需状态（sstatus::user_ret_prepare）
 /// 3. 设置陷阱向量为用户空间处理程序（trampoline.S）
-/// 4. 准备用户空间页表
+/// 4. 准备用户空间页表，为它取得 ASID
 /// 5. 通过trampoline跳转回用户空间
 ///
 /// # 返回值
@@ -218,6 +225,9 @@ pub unsafe fn user_trap_ret() -> ! {
     let userret_virt: extern "C" fn(usize, usize) -> ! =
         core::mem::transmute(Into::<usize>::into(TRAMPOLINE) + distance);
 
+    // 为页表取得 ASID 并登记本硬件线程，此后其他硬件线程修改这个页表时会击落本硬件线程的 TLB
+    let satp = asid::activate(CPU_MANAGER.my_proc().index(), satp);
+
     // 调用userret(TRAPFRAME, satp)返回用户空间
     userret_virt(trapframe_from_tid(tid).into(), satp);
 }
@@ -232,8 +242,9 @@ pub unsafe fn user_trap_ret() -> ! {
 /// 1. 保存关键寄存器状态（sepc, sstatus）
 /// 2. 验证中断来源为内核模式
 /// 3. 根据中断原因分发处理：
-///   - 外部中断：处理UART/磁盘中断
-///   - 软件中断：处理时钟中断并尝试调度
+///   - 外部中断：由 PLIC 分发给登记的驱动
+///   - 软件中断：处理核间中断，并在是 machinevec 转发的时钟中断时处理时钟中断
+///   - 时钟中断：处理时钟中断，有其他进程可运行时尝试调度
 ///   - 系统调用：内核模式不应触发（panic）
 ///   - 其他异常：panic
 /// 4. 恢复保存的寄存器状态
@@ -265,31 +276,18 @@ pub unsafe fn kerneltrap() {
             // 监督者模式外部中断
 
             // 处理PLIC中断（同用户模式）
-            let irq = plic::claim();
-            if irq as usize == UART0_IRQ {
-                UART.intr();
-            } else if irq as usize == VIRTIO0_IRQ {
-                DISK.lock().intr();
-            } else {
-                // panic!("unexpected interrupt, irq={}", irq);
-            }
-            if irq > 0 {
-                plic::complete(irq);
-            }
+            plic::handle_irq();
         }
-        Trap::Interrupt(Interrupt::SupervisorSoft) => {
-            // 监督者模式软件中断
-
-            // 仅在CPU 0上更新时钟计数
-            if CpuManager::cpu_id() == 0 {
-                clock_intr();
+        Trap::Interrupt(Interrupt::SupervisorSoft) | Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // 核间中断或时钟中断
+            if matches!(scause.cause(), Trap::Interrupt(Interrupt::SupervisorTimer)) || soft_intr() {
+                timer_intr();
             }
 
-            // 清除软件中断标志
-            sip::clear_ssip();
-
-            // 尝试让出CPU（调度其他进程）
-            CPU_MANAGER.my_cpu_mut().try_yield_proc();
+            // 本硬件线程有其他进程等待运行时尝试让出CPU（调度其他进程）
+            if RUNQUEUES.has_ready(CpuManager::cpu_id()) {
+                CPU_MANAGER.my_cpu_mut().try_yield_proc();
+            }
         }
         Trap::Exception(Exception::SupervisorEnvCall) => {  // 用户模式系统调用（内核不应触发）
 
@@ -313,19 +311,104 @@ pub unsafe fn kerneltrap() {
     sstatus::write(local_sstatus);
 }
 
-/// 全局时钟计数器（自旋锁保护）
-static TICKS: SpinLock<Wrapping<usize>> = SpinLock::new(Wrapping(0), "time");
-
-/// 处理时钟中断（更新全局计数器）
+/// 处理时钟中断
 ///
 /// # 功能说明
-/// 增加全局时钟计数并唤醒等待时钟的进程。
-/// 由时钟中断处理程序调用。
+/// 任一硬件线程的时钟中断都会调用所有到期定时器的回调，
+/// 回调负责唤醒等待的进程（睡眠者、带超时读取终端的进程、timerfd 的读者等）。
 fn clock_intr() {
-    let mut guard = TICKS.lock();
-    *guard += Wrapping(1);
-    unsafe { PROC_MANAGER.wakeup(&TICKS as *const _ as usize); }
+    timer::run_expired(time::mtime());
+}
+
+/// 处理监督模式软件中断：清除 SSIP，处理核间中断
+///
+/// # 返回值
+/// 这次软件中断是否（也）是 machinevec 转发的时钟中断
+fn soft_intr() -> bool {
+    // 先清除再处理，处理期间到来的核间中断会再次置位
+    sip::clear_ssip();
+    ipi::handle_ipi();
+    time::timer_forwarded()
+}
+
+/// 处理本硬件线程的时钟中断，到时检查负载是否均衡，并设定下一次中断
+fn timer_intr() {
+    clock_intr();
+    RUNQUEUES.balance(unsafe { CpuManager::cpu_id() }, time::mtime());
+    set_next_timer(true);
+}
+
+/// 为本硬件线程设定下一次时钟中断
+///
+/// # 参数
+/// - `busy`: 是否要运行进程。运行进程时在下一个 tick 边界中断以便抢占；
+///   空闲时只在最早的定时器到期时中断，有进程放入本硬件线程的队列时由核间中断叫醒，
+///   其余时间硬件线程停在 WFI 中
+pub fn set_next_timer(busy: bool) {
+    let mut deadline = timer::next_deadline();
+    if busy {
+        let tick = (time::mtime() / TICK_INTERVAL + 1) * TICK_INTERVAL;
+        deadline = deadline.min(tick);
+    }
+    push_off();
+    unsafe { time::set_timer(deadline); }
+    pop_off();
+}
+
+/// 启动一个在 mtime 到达 `deadline` 时到期的内核定时器，并按需提前本硬件线程的下一次时钟中断
+///
+/// # 参数
+/// - `deadline`: 第一次到期的 mtime 值
+/// - `interval`: 周期（mtime 周期数），0 表示一次性定时器
+/// - `callback`, `arg`: 到期时在中断上下文调用 `callback(arg, 到期次数)`
+///
+/// # 返回值
+/// 用于 [`timer::cancel`] 的定时器编号
+pub fn start_timer(deadline: u64, interval: u64, callback: TimerFn, arg: usize) -> TimerId {
+    let id = timer::add(deadline, interval, callback, arg);
+    set_next_timer(true);
+    id
+}
+
+/// 保护定时睡眠的检查与唤醒，避免进程检查完时间、尚未睡下时错过定时器的唤醒
+static SLEEP_LOCK: SpinLock<()> = SpinLock::new((), "sleep");
+
+/// 定时睡眠的定时器回调，`chan` 为睡眠进程的等待通道
+fn sleep_timeout(chan: usize, _count: u64) {
+    let guard = SLEEP_LOCK.lock();
+    unsafe { PROC_MANAGER.wakeup(chan); }
+    drop(guard);
+}
+
+/// 使进程休眠到 mtime 到达 `deadline`
+///
+/// # 功能说明
+/// 为本次睡眠启动一个一次性定时器，只有这个进程会被它唤醒，精度不受 tick 限制。
+///
+/// # 返回值
+/// - `Ok(())`: 已到达 `deadline`
+/// - `Err(())`: 休眠期间进程被终止
+pub fn sleep_until(process: &Process, deadline: u64) -> Result<(), ()> {
+    // 以栈上变量的地址作为等待通道，每次睡眠各不相同
+    let chan_mark = 0u8;
+    let chan = &chan_mark as *const u8 as usize;
+    let id = start_timer(deadline, 0, sleep_timeout, chan);
+
+    let mut ret = Ok(());
+    let mut guard = SLEEP_LOCK.lock();
+    while time::mtime() < deadline {
+        // 检查进程终止标志
+        if process.killed.load(Ordering::Relaxed) {
+            ret = Err(());
+            break;
+        }
+        process.sleep(chan, guard);
+        // 被唤醒后重新获取锁
+        guard = SLEEP_LOCK.lock();
+    }
     drop(guard);
+    timer::cancel(id);
+    ret
 }
 
 /// 使进程休眠指定时钟周期
@@ -341,22 +424,7 @@ fn clock_intr() {
 /// - `Ok(())`: 成功休眠指定周期
 /// - `Err(())`: 休眠期间进程被终止
 pub fn clock_sleep(process: &Process, count: usize) -> Result<(), ()> {
-    let mut guard = TICKS.lock();
-    let old_ticks = *guard; // 记录起始时钟
-
-    // 等待指定周期
-    while (*guard - old_ticks) < Wrapping(count) {
-        // 检查进程终止标志
-        if process.killed.load(Ordering::Relaxed) {
-            return Err(())
-        }
-
-        // 在TICKS地址上休眠
-        process.sleep(&TICKS as *const _ as usize, guard);
-        // 被唤醒后重新获取锁
-        guard = TICKS.lock();
-    }
-    Ok(())
+    sleep_until(process, time::mtime() + count as u64 * TICK_INTERVAL)
 }
 
 /// 读取当前时钟计数值
@@ -364,6 +432,5 @@ pub fn clock_sleep(process: &Process, count: usize) -> Result<(), ()> {
 /// # 返回值
 /// 系统启动以来的时钟周期数
 pub fn clock_read() -> usize {
-    TICKS.lock().0
+    time::ticks()
 }
-
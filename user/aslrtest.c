@@ -0,0 +1,102 @@
+#include "include/types.h"
+#include "include/riscv.h"
+#include "user/user.h"
+
+// with address space layout randomization on, every exec places the
+// stack and the heap at new addresses; with it off they never move.
+
+#define RUNS 4
+
+struct layout {
+  uint64 stack;
+  uint64 heap;
+};
+
+// exec this program as "aslrtest child" with aslr on or off and read
+// back the addresses it saw
+struct layout
+run(int on)
+{
+  struct layout l;
+  int fds[2], pid, status;
+  char *args[] = { "aslrtest", "child", 0 };
+
+  if(pipe(fds) < 0){
+    printf("aslrtest: pipe failed\n");
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("aslrtest: fork failed\n");
+    exit(1);
+  }
+  if(pid == 0){
+    close(fds[0]);
+    close(1);
+    dup(fds[1]);
+    close(fds[1]);
+    setaslr(on);
+    exec("aslrtest", args);
+    exit(1);
+  }
+  close(fds[1]);
+  if(read(fds[0], &l, sizeof(l)) != sizeof(l)){
+    printf("aslrtest: child did not report its layout\n");
+    exit(1);
+  }
+  close(fds[0]);
+  wait(&status);
+  if(status != 0){
+    printf("aslrtest: child failed\n");
+    exit(1);
+  }
+  return l;
+}
+
+int
+main(int argc, char *argv[])
+{
+  struct layout l[RUNS], first;
+  int moved = 0;
+  int local;
+
+  if(argc == 2 && strcmp(argv[1], "child") == 0){
+    first.stack = (uint64)&local;
+    first.heap = (uint64)sbrk(0);
+    // the new stack and heap must work wherever they are
+    *(volatile int *)&local = 1;
+    char *p = sbrk(PGSIZE);
+    if(p == (char *)-1)
+      exit(1);
+    p[0] = p[PGSIZE - 1] = 1;
+    write(1, &first, sizeof(first));
+    exit(0);
+  }
+
+  first = run(0);
+  for(int i = 0; i < RUNS; i++){
+    l[i] = run(0);
+    if(l[i].stack != first.stack || l[i].heap != first.heap){
+      printf("aslrtest: layout moved with aslr off\n");
+      exit(1);
+    }
+  }
+  printf("aslrtest: off OK\n");
+
+  for(int i = 0; i < RUNS; i++){
+    l[i] = run(1);
+    if(l[i].stack != first.stack || l[i].heap != first.heap)
+      moved++;
+    if(l[i].heap < l[i].stack){
+      printf("aslrtest: heap below stack\n");
+      exit(1);
+    }
+  }
+  // a run may land on the unrandomized layout by chance, but not all of them
+  if(moved == 0){
+    printf("aslrtest: layout never moved with aslr on\n");
+    exit(1);
+  }
+  printf("aslrtest: on OK\n");
+  exit(0);
+}
@@ -0,0 +1,67 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/time.h"
+#include "user/user.h"
+
+// ctxbench [rounds]
+//
+// measures context switches per second: a parent and a child pinned
+// to hart 0 pass a byte back and forth through two pipes, so every
+// round trip switches between the two address spaces twice.
+// compare a kernel built normally against one built with
+// make FEATURES=no_asid, which flushes the whole TLB on every return
+// to user space.
+
+static long
+now_ns(void)
+{
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.sec * 1000000000L + ts.nsec;
+}
+
+int
+main(int argc, char *argv[])
+{
+  int ping[2], pong[2];
+  int rounds, i;
+  long start, elapsed;
+  char c = 0;
+
+  rounds = argc > 1 ? atoi(argv[1]) : 10000;
+  if(rounds <= 0){
+    fprintf(2, "usage: ctxbench [rounds]\n");
+    exit(1);
+  }
+  if(pipe(ping) < 0 || pipe(pong) < 0){
+    fprintf(2, "ctxbench: pipe failed\n");
+    exit(1);
+  }
+  // the child inherits the affinity
+  if(sched_setaffinity(0, 1) < 0){
+    fprintf(2, "ctxbench: cannot pin to hart 0\n");
+    exit(1);
+  }
+
+  if(fork() == 0){
+    for(i = 0; i < rounds; i++){
+      if(read(ping[0], &c, 1) != 1 || write(pong[1], &c, 1) != 1)
+        exit(1);
+    }
+    exit(0);
+  }
+
+  start = now_ns();
+  for(i = 0; i < rounds; i++){
+    if(write(ping[1], &c, 1) != 1 || read(pong[0], &c, 1) != 1){
+      fprintf(2, "ctxbench: round %d failed\n", i);
+      exit(1);
+    }
+  }
+  elapsed = now_ns() - start;
+  wait(0);
+
+  printf("ctxbench: %d switches in %d ms, %d switches/s\n", 2 * rounds,
+         (int)(elapsed / 1000000), (int)(2 * rounds * 1000000000L / elapsed));
+  exit(0);
+}
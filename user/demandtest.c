@@ -0,0 +1,56 @@
+#include "include/types.h"
+#include "include/riscv.h"
+#include "include/sysinfo.h"
+#include "user/user.h"
+
+// exec no longer reads the whole program up front: the pages of a large
+// initialized array are read in from the executable on first access.
+
+#define NPAGES 16
+
+char big[NPAGES * PGSIZE] = {
+  [0] = 1,
+  [NPAGES * PGSIZE / 2] = 2,
+  [NPAGES * PGSIZE - 1] = 3,
+};
+
+uint64
+freemem(void)
+{
+  struct sysinfo info;
+
+  if(sysinfo(&info) < 0){
+    printf("demandtest: sysinfo failed\n");
+    exit(1);
+  }
+  return info.freemem;
+}
+
+int
+main(int argc, char *argv[])
+{
+  uint64 before, after;
+  int sum = 0;
+
+  before = freemem();
+  for(int i = 0; i < NPAGES; i++)
+    sum += big[i * PGSIZE];
+  after = freemem();
+
+  if(big[0] != 1 || big[NPAGES * PGSIZE / 2] != 2 || big[NPAGES * PGSIZE - 1] != 3){
+    printf("demandtest: wrong contents\n");
+    exit(1);
+  }
+  // the pages next to code and data touched before the loop may already be in
+  if(before < after || (before - after) / PGSIZE < NPAGES - 2){
+    printf("demandtest: %d pages read in, expected about %d\n",
+      (int)((before - after) / PGSIZE), NPAGES);
+    exit(1);
+  }
+  if(sum != 3){
+    printf("demandtest: wrong sum %d\n", sum);
+    exit(1);
+  }
+  printf("demandtest: OK\n");
+  exit(0);
+}
@@ -0,0 +1,122 @@
+#include "include/types.h"
+#include "include/errno.h"
+#include "include/param.h"
+#include "user/user.h"
+
+// execve passes an environment to the new program, and arguments are
+// limited only by their total size (ARG_MAX), not by their number or
+// length. the new stack holds argc, argv, envp and the auxiliary
+// vector in the standard layout.
+
+#define NARGS   80
+#define LONGARG 1000
+
+static char longarg[LONGARG + 1];
+static char names[NARGS][4];
+static char *args[NARGS + 4];
+static char huge[3][ARG_MAX / 2];
+
+void
+fail(char *why)
+{
+  printf("envtest: %s\n", why);
+  exit(1);
+}
+
+// "envtest many", one long argument and NARGS short ones
+void
+fillargs(void)
+{
+  int i;
+
+  memset(longarg, 'a', LONGARG);
+  args[0] = "envtest";
+  args[1] = "many";
+  args[2] = longarg;
+  for(i = 0; i < NARGS; i++){
+    names[i][0] = 'a';
+    names[i][1] = '0' + i / 10;
+    names[i][2] = '0' + i % 10;
+    args[i + 3] = names[i];
+  }
+  args[NARGS + 3] = 0;
+}
+
+// the new program checks what exec gave it
+void
+child(int argc, char *argv[], char *envp[])
+{
+  int i;
+
+  if(((uint64 *)argv)[-1] != argc)
+    fail("argc is not below argv");
+  if(argv[argc] != 0 || envp != argv + argc + 1)
+    fail("envp does not follow argv");
+  if(strcmp(argv[1], "noenv") == 0){
+    if(argc != 2 || envp[0] != 0)
+      fail("exec passed an environment");
+    exit(0);
+  }
+
+  fillargs();
+  if(argc != NARGS + 3)
+    fail("wrong argc");
+  for(i = 0; i < argc; i++)
+    if(strcmp(argv[i], args[i]) != 0)
+      fail("wrong argument");
+  if(envp[0] == 0 || strcmp(envp[0], "FOO=bar") != 0
+     || envp[1] == 0 || strcmp(envp[1], "EMPTY=") != 0 || envp[2] != 0)
+    fail("wrong environment");
+  exit(0);
+}
+
+// run argv in a child with exec (envp == 0) or execve and expect it to exit 0
+void
+run(char *argv[], char *envp[], char *what)
+{
+  int pid, status;
+
+  pid = fork();
+  if(pid < 0)
+    fail("fork failed");
+  if(pid == 0){
+    if(envp == 0)
+      exec(argv[0], argv);
+    else
+      execve(argv[0], argv, envp);
+    printf("envtest: %s: exec failed\n", what);
+    exit(1);
+  }
+  wait(&status);
+  if(status != 0)
+    fail(what);
+  printf("envtest: %s OK\n", what);
+}
+
+int
+main(int argc, char *argv[], char *envp[])
+{
+  char *noenv[] = { "envtest", "noenv", 0 };
+  char *env[] = { "FOO=bar", "EMPTY=", 0 };
+  char *toobig[] = { "envtest", "toobig", huge[0], huge[1], 0 };
+  char *bigenv[] = { huge[0], huge[1], huge[2], 0 };
+  int i;
+
+  if(argc > 1)
+    child(argc, argv, envp);
+
+  run(noenv, 0, "exec without environment");
+  fillargs();
+  run(args, env, "execve with environment and many arguments");
+
+  for(i = 0; i < 3; i++)
+    memset(huge[i], 'b', sizeof(huge[i]) - 1);
+  if(execve("envtest", toobig, env) != -1 || geterrno() != E2BIG)
+    fail("arguments over ARG_MAX not rejected with E2BIG");
+  if(execve("envtest", noenv, bigenv) != -1 || geterrno() != E2BIG)
+    fail("environment over ARG_MAX not rejected with E2BIG");
+  printf("envtest: ARG_MAX E2BIG OK\n");
+
+  printf("envtest: OK\n");
+  exit(0);
+}
@@ -293,6 +293,21 @@ go(int which_child)
   }
 }
 
+// seed from /dev/random when the kernel has an entropy source,
+// otherwise fall back to a fixed seed.
+unsigned long
+seed(unsigned long dflt)
+{
+  unsigned long x = 0;
+  int fd = open("/dev/random", O_RDONLY);
+  if(fd >= 0){
+    if(read(fd, &x, sizeof(x)) != sizeof(x))
+      x = 0;
+    close(fd);
+  }
+  return x ? x : dflt;
+}
+
 void
 iter()
 {
@@ -305,7 +320,7 @@ iter()
     exit(1);
   }
   if(pid1 == 0){
-    rand_next = 31;
+    rand_next = seed(31);
     go(0);
     exit(0);
   }
@@ -316,7 +331,7 @@ iter()
     exit(1);
   }
   if(pid2 == 0){
-    rand_next = 7177;
+    rand_next = seed(7177);
     go(1);
     exit(0);
   }
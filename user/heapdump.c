@@ -0,0 +1,32 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "user/user.h"
+
+// mark the kernel heap, or dump the objects allocated since the mark
+// and still live; needs a kernel built with FEATURES=heap_debug.
+// e.g. "heapdump mark; usertests; heapdump dump" lists leaks.
+
+int
+main(int argc, char *argv[])
+{
+  int n;
+
+  if(argc != 2 || (strcmp(argv[1], "mark") != 0 && strcmp(argv[1], "dump") != 0)){
+    fprintf(2, "usage: heapdump mark|dump\n");
+    exit(1);
+  }
+  if(strcmp(argv[1], "mark") == 0){
+    if((n = heapdump(0)) < 0){
+      fprintf(2, "heapdump: kernel built without heap_debug\n");
+      exit(1);
+    }
+    printf("heap mark at %d\n", n);
+  } else {
+    if((n = heapdump(1)) < 0){
+      fprintf(2, "heapdump: kernel built without heap_debug\n");
+      exit(1);
+    }
+    printf("%d live objects since mark\n", n);
+  }
+  exit(0);
+}
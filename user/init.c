@@ -11,6 +11,38 @@
 
 char *argv[] = { "sh", 0 };
 
+// Keep a shell running on the second serial port, if there is one.
+void
+serial_sh(void)
+{
+  int fd, pid;
+
+  if((fd = open("/dev/ttyS1", O_RDWR)) < 0)
+    return;
+  if(fork() != 0){
+    close(fd);
+    return;
+  }
+  close(0);
+  close(1);
+  close(2);
+  dup(fd);
+  dup(fd);
+  dup(fd);
+  close(fd);
+  for(;;){
+    pid = fork();
+    if(pid < 0)
+      exit(1);
+    if(pid == 0){
+      exec("sh", argv);
+      exit(1);
+    }
+    while(wait((int *) 0) != pid)
+      ;
+  }
+}
+
 int
 main(void)
 {
@@ -23,6 +55,17 @@ main(void)
   dup(0);  // stdout
   dup(0);  // stderr
 
+  // device nodes under /dev
+  mkdir("/dev");
+  mknod("/dev/random", RANDOM, 0);
+  mknod("/dev/null", MEM, MEM_NULL);
+  mknod("/dev/zero", MEM, MEM_ZERO);
+  mknod("/dev/full", MEM, MEM_FULL);
+  mknod("/dev/ttyS0", TTYS, 0);
+  mknod("/dev/ttyS1", TTYS, 1);
+
+  serial_sh();
+
   for(;;){
     printf("init: starting sh\n");
     pid = fork();
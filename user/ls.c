@@ -3,6 +3,8 @@
 #include "user/user.h"
 #include "include/fs.h"
 
+int lflag;  // -l: long listing with link count, size and modification time
+
 char*
 fmtname(char *path)
 {
@@ -22,6 +24,43 @@ fmtname(char *path)
   return buf;
 }
 
+// Print t, seconds since the epoch, as a UTC date.
+void
+printdate(uint64 t)
+{
+  uint64 days = t / 86400, secs = t % 86400;
+  uint64 era, doe, yoe, doy, mp;
+  uint64 y, m, d;
+
+  // civil_from_days, shifted so that years start in March
+  days += 719468;
+  era = days / 146097;
+  doe = days - era * 146097;
+  yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+  doy = doe - (365*yoe + yoe/4 - yoe/100);
+  mp = (5*doy + 2) / 153;
+  d = doy - (153*mp + 2)/5 + 1;
+  m = mp < 10 ? mp + 3 : mp - 9;
+  y = yoe + era * 400 + (m <= 2);
+
+  printf("%d-%s%d-%s%d %s%d:%s%d", (int)y,
+         m < 10 ? "0" : "", (int)m, d < 10 ? "0" : "", (int)d,
+         secs/3600 < 10 ? "0" : "", (int)(secs/3600),
+         secs/60%60 < 10 ? "0" : "", (int)(secs/60%60));
+}
+
+void
+printstat(char *path, struct stat *st)
+{
+  if(!lflag){
+    printf("%s %d %d %l\n", fmtname(path), st->type, st->ino, st->size);
+    return;
+  }
+  printf("%c %d %l ", "?d-c"[st->type < 4 ? st->type : 0], st->nlink, st->size);
+  printdate(st->mtime);
+  printf(" %s\n", fmtname(path));
+}
+
 void
 ls(char *path)
 {
@@ -43,7 +82,7 @@ ls(char *path)
 
   switch(st.type){
   case T_FILE:
-    printf("%s %d %d %l\n", fmtname(path), st.type, st.ino, st.size);
+    printstat(path, &st);
     break;
 
   case T_DIR:
@@ -63,7 +102,7 @@ ls(char *path)
         printf("ls: cannot stat %s\n", buf);
         continue;
       }
-      printf("%s %d %d %d\n", fmtname(buf), st.type, st.ino, st.size);
+      printstat(buf, &st);
     }
     break;
   }
@@ -73,13 +112,17 @@ ls(char *path)
 int
 main(int argc, char *argv[])
 {
-  int i;
+  int i = 1;
 
-  if(argc < 2){
+  if(argc > 1 && strcmp(argv[1], "-l") == 0){
+    lflag = 1;
+    i++;
+  }
+  if(i >= argc){
     ls(".");
     exit(0);
   }
-  for(i=1; i<argc; i++)
+  for(; i<argc; i++)
     ls(argv[i]);
   exit(0);
 }
@@ -0,0 +1,80 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/errno.h"
+#include "include/riscv.h"
+#include "user/user.h"
+
+// exhaust physical memory in a child and check that sbrk reports ENOMEM
+// or the OOM killer terminates it while the kernel and the parent keep running.
+
+#define CHUNK (1024 * 1024)
+
+void
+too_big(void)
+{
+  // grow without touching until physical memory or the user half of the
+  // address space is used up
+  while(sbrk(0x7fffffff) != (char*)-1)
+    ;
+  if(geterrno() != ENOMEM){
+    printf("oomtest: sbrk failed with errno %d, expected ENOMEM\n", geterrno());
+    exit(1);
+  }
+  printf("oomtest: sbrk beyond the address space: ENOMEM OK\n");
+}
+
+void
+hog(void)
+{
+  int pid, status;
+  char *p;
+
+  pid = fork();
+  if(pid < 0){
+    printf("oomtest: fork failed, errno %d\n", geterrno());
+    exit(1);
+  }
+  if(pid == 0){
+    // touch every page until sbrk runs out of free frames or the OOM killer stops us
+    for(;;){
+      p = sbrk(CHUNK);
+      if(p == (char*)-1){
+        if(geterrno() != ENOMEM){
+          printf("oomtest: sbrk failed with errno %d, expected ENOMEM\n", geterrno());
+          exit(1);
+        }
+        exit(2);
+      }
+      for(char *q = p; q < p + CHUNK; q += PGSIZE)
+        *q = 1;
+    }
+  }
+  wait(&status);
+  if(status == 2){
+    printf("oomtest: hog stopped by sbrk ENOMEM: OK\n");
+  } else if(status == -1){
+    printf("oomtest: hog killed by the OOM killer: OK\n");
+  } else {
+    printf("oomtest: hog exited with %d, expected ENOMEM or to be killed\n", status);
+    exit(1);
+  }
+
+  // the memory came back
+  pid = fork();
+  if(pid < 0){
+    printf("oomtest: fork after OOM failed\n");
+    exit(1);
+  }
+  if(pid == 0)
+    exit(0);
+  wait(0);
+}
+
+int
+main(int argc, char *argv[])
+{
+  hog();
+  too_big();
+  printf("oomtest: OK\n");
+  exit(0);
+}
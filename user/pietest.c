@@ -0,0 +1,101 @@
+#include "include/types.h"
+#include "include/riscv.h"
+#include "user/user.h"
+
+// pietest is linked as a position-independent executable: exec loads it
+// at a load bias above address 0, applies its R_RISCV_RELATIVE
+// relocations and passes an auxiliary vector after envp.
+
+#define AT_NULL   0
+#define AT_PHDR   3
+#define AT_PHENT  4
+#define AT_PHNUM  5
+#define AT_PAGESZ 6
+#define AT_ENTRY  9
+#define AT_RANDOM 25
+
+#define PT_DYNAMIC 2
+
+// ELF program header
+struct proghdr {
+  uint32 type;
+  uint32 flags;
+  uint64 off;
+  uint64 vaddr;
+  uint64 paddr;
+  uint64 filesz;
+  uint64 memsz;
+  uint64 align;
+};
+
+int
+add(int a, int b)
+{
+  return a + b;
+}
+
+int
+sub(int a, int b)
+{
+  return a - b;
+}
+
+// initialized pointers need relocating
+int (*ops[])(int, int) = { add, sub };
+char *words[] = { "alpha", "beta" };
+char **wordp = words;
+
+void
+fail(char *msg)
+{
+  printf("pietest: %s\n", msg);
+  exit(1);
+}
+
+uint64
+getauxval(uint64 *auxv, uint64 key)
+{
+  for(; auxv[0] != AT_NULL; auxv += 2)
+    if(auxv[0] == key)
+      return auxv[1];
+  return 0;
+}
+
+int
+main(int argc, char *argv[], char *envp[])
+{
+  uint64 *auxv;
+  struct proghdr *ph;
+  int dynamic = 0;
+
+  if((uint64)main < 0x100000)
+    fail("not loaded at a load bias");
+  if(ops[0](2, 3) != 5 || ops[1](5, 3) != 2)
+    fail("function pointers not relocated");
+  if(wordp != words || strcmp(words[0], "alpha") != 0 || strcmp(words[1], "beta") != 0)
+    fail("data pointers not relocated");
+
+  // the auxiliary vector follows the null pointer that ends envp
+  while(*envp)
+    envp++;
+  auxv = (uint64 *)(envp + 1);
+  if(getauxval(auxv, AT_ENTRY) != (uint64)main)
+    fail("wrong AT_ENTRY");
+  if(getauxval(auxv, AT_PAGESZ) != PGSIZE)
+    fail("wrong AT_PAGESZ");
+  if(getauxval(auxv, AT_RANDOM) == 0)
+    fail("no AT_RANDOM");
+  if(getauxval(auxv, AT_PHENT) != sizeof(struct proghdr))
+    fail("wrong AT_PHENT");
+  ph = (struct proghdr *)getauxval(auxv, AT_PHDR);
+  if(ph == 0)
+    fail("no AT_PHDR");
+  for(int i = 0; i < (int)getauxval(auxv, AT_PHNUM); i++)
+    if(ph[i].type == PT_DYNAMIC)
+      dynamic = 1;
+  if(!dynamic)
+    fail("AT_PHDR has no dynamic segment");
+
+  printf("pietest: OK\n");
+  exit(0);
+}
@@ -0,0 +1,20 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/sched.h"
+#include "user/user.h"
+
+// print the scheduler statistics of every running hart
+
+int
+main(int argc, char *argv[])
+{
+  struct schedstat st;
+  int hart;
+
+  printf("hart  switches  idle_ms  steals  migrations  queued\n");
+  for(hart = 0; schedstat(hart, &st) == 0; hart++){
+    printf("%d     %d  %d  %d  %d  %d\n", hart, (int)st.switches,
+           (int)(st.idle_ns / 1000000), (int)st.steals, (int)st.migrations, (int)st.queued);
+  }
+  exit(0);
+}
@@ -0,0 +1,39 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "user/user.h"
+
+// setarch on|off command [arg...]   run command with address space
+//                                    layout randomization on or off
+// setarch                           show whether it is on
+//
+// the setting is inherited by children and takes effect at exec.
+
+void
+usage(void)
+{
+  fprintf(2, "usage: setarch [on|off command [arg...]]\n");
+  exit(1);
+}
+
+int
+main(int argc, char *argv[])
+{
+  int on;
+
+  if(argc == 1){
+    printf("aslr %s\n", setaslr(-1) ? "on" : "off");
+    exit(0);
+  }
+  if(argc < 3)
+    usage();
+  if(strcmp(argv[1], "on") == 0)
+    on = 1;
+  else if(strcmp(argv[1], "off") == 0)
+    on = 0;
+  else
+    usage();
+  setaslr(on);
+  exec(argv[2], argv + 2);
+  fprintf(2, "setarch: exec %s failed\n", argv[2]);
+  exit(1);
+}
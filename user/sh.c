@@ -3,6 +3,7 @@
 #include "include/types.h"
 #include "user/user.h"
 #include "include/fcntl.h"
+#include "include/termios.h"
 
 // Parsed command representation
 #define EXEC  1
@@ -145,7 +146,7 @@ int
 main(void)
 {
   static char buf[100];
-  int fd;
+  int fd, pid, none = 0;
 
   // Ensure that three file descriptors are open.
   while((fd = open("console", O_RDWR)) >= 0){
@@ -164,9 +165,12 @@ main(void)
         fprintf(2, "cannot cd %s\n", buf+3);
       continue;
     }
-    if(fork1() == 0)
+    if((pid = fork1()) == 0)
       runcmd(parsecmd(buf));
+    // Let ctrl-c on the console interrupt the command, not the shell.
+    ioctl(0, TIOCSPGRP, &pid);
     wait(0);
+    ioctl(0, TIOCSPGRP, &none);
   }
   exit(0);
 }
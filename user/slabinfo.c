@@ -0,0 +1,21 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/slab.h"
+#include "user/user.h"
+
+// print the statistics of every size class of the kernel slab allocator
+
+int
+main(int argc, char *argv[])
+{
+  struct slabstat st;
+  int class;
+
+  printf("size  slabs  inuse/objects  cached  allocs  frees  refills  flushes\n");
+  for(class = 0; slabstat(class, &st) == 0; class++){
+    printf("%d  %d  %d/%d  %d  %d  %d  %d  %d\n", (int)st.size, (int)st.slabs,
+           (int)(st.objects - st.free - st.cached), (int)st.objects, (int)st.cached,
+           (int)st.allocs, (int)st.frees, (int)st.refills, (int)st.flushes);
+  }
+  exit(0);
+}
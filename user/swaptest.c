@@ -0,0 +1,113 @@
+#include "include/param.h"
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/fs.h"
+#include "include/riscv.h"
+#include "include/sysinfo.h"
+#include "user/user.h"
+
+// use more memory than is free so that part of it is paged out to the
+// swap area, then check that every page comes back intact, both through
+// page faults and through system calls that copy user memory.
+
+// how far past free memory to grow: a quarter of the swap area
+#define EXTRA (SWAPSIZE / 4 * BSIZE)
+
+char *mem;
+uint64 npages;
+
+void
+fill(void)
+{
+  for(uint64 i = 0; i < npages; i++){
+    uint64 *p = (uint64*)(mem + i * PGSIZE);
+    p[0] = i;
+    p[PGSIZE / sizeof(uint64) - 1] = ~i;
+  }
+}
+
+void
+check(char *what)
+{
+  for(uint64 i = 0; i < npages; i++){
+    uint64 *p = (uint64*)(mem + i * PGSIZE);
+    if(p[0] != i || p[PGSIZE / sizeof(uint64) - 1] != ~i){
+      printf("swaptest: %s: page %d corrupted\n", what, (int)i);
+      exit(1);
+    }
+  }
+  printf("swaptest: %s: %d pages OK\n", what, (int)npages);
+}
+
+// the first pages were filled long ago and are likely paged out;
+// pass them through a pipe so the kernel has to bring them back in
+void
+pipe_copy(void)
+{
+  int fds[2];
+  char *src = mem;
+  char *dst = mem + PGSIZE;
+
+  if(pipe(fds) < 0){
+    printf("swaptest: pipe failed\n");
+    exit(1);
+  }
+  if(write(fds[1], src, 64) != 64 || read(fds[0], dst + 128, 64) != 64){
+    printf("swaptest: pipe copy failed\n");
+    exit(1);
+  }
+  if(memcmp(src, dst + 128, 64) != 0){
+    printf("swaptest: pipe copy corrupted\n");
+    exit(1);
+  }
+  // restore the page for check()
+  memset(dst + 128, 0, 64);
+  close(fds[0]);
+  close(fds[1]);
+  printf("swaptest: pipe copy OK\n");
+}
+
+void
+run(void)
+{
+  struct sysinfo info;
+
+  if(sysinfo(&info) < 0){
+    printf("swaptest: sysinfo failed\n");
+    exit(1);
+  }
+  npages = (info.freemem + EXTRA) / PGSIZE;
+  mem = sbrk(npages * PGSIZE);
+  if(mem == (char*)-1){
+    printf("swaptest: sbrk failed\n");
+    exit(1);
+  }
+
+  fill();
+  check("fault");
+  pipe_copy();
+  check("after pipe");
+  exit(0);
+}
+
+int
+main(int argc, char *argv[])
+{
+  int pid, status;
+
+  // run in a child so that all memory and swap slots come back afterwards
+  pid = fork();
+  if(pid < 0){
+    printf("swaptest: fork failed\n");
+    exit(1);
+  }
+  if(pid == 0)
+    run();
+  wait(&status);
+  if(status != 0){
+    printf("swaptest: FAILED\n");
+    exit(1);
+  }
+  printf("swaptest: OK\n");
+  exit(0);
+}
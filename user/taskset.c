@@ -0,0 +1,67 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "user/user.h"
+
+// taskset mask command [arg...]   run command on the harts in mask
+// taskset -p pid [mask]           show or set the harts of a process
+//
+// bit i of mask allows hart i; mask is decimal or 0x-prefixed hex.
+
+int
+parsemask(char *s)
+{
+  int mask = 0;
+
+  if(s[0] != '0' || (s[1] != 'x' && s[1] != 'X'))
+    return atoi(s);
+  for(s += 2; *s; s++){
+    if(*s >= '0' && *s <= '9')
+      mask = mask * 16 + *s - '0';
+    else if(*s >= 'a' && *s <= 'f')
+      mask = mask * 16 + *s - 'a' + 10;
+    else if(*s >= 'A' && *s <= 'F')
+      mask = mask * 16 + *s - 'A' + 10;
+    else
+      return 0;
+  }
+  return mask;
+}
+
+void
+usage(void)
+{
+  fprintf(2, "usage: taskset mask command [arg...]\n");
+  fprintf(2, "       taskset -p pid [mask]\n");
+  exit(1);
+}
+
+int
+main(int argc, char *argv[])
+{
+  int pid, mask;
+
+  if(argc >= 3 && strcmp(argv[1], "-p") == 0){
+    pid = atoi(argv[2]);
+    if(argc == 4 && sched_setaffinity(pid, parsemask(argv[3])) < 0){
+      fprintf(2, "taskset: cannot set affinity of %d\n", pid);
+      exit(1);
+    }
+    if((mask = sched_getaffinity(pid)) < 0){
+      fprintf(2, "taskset: no process %d\n", pid);
+      exit(1);
+    }
+    printf("pid %d affinity 0x%x\n", pid, mask);
+    exit(0);
+  }
+
+  if(argc < 3)
+    usage();
+  // the affinity is inherited across exec
+  if(sched_setaffinity(0, parsemask(argv[1])) < 0){
+    fprintf(2, "taskset: bad mask %s\n", argv[1]);
+    exit(1);
+  }
+  exec(argv[2], argv + 2);
+  fprintf(2, "taskset: exec %s failed\n", argv[2]);
+  exit(1);
+}
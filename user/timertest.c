@@ -0,0 +1,115 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/time.h"
+#include "user/user.h"
+
+// exercises nanosleep, timerfd and alarm
+
+static long
+now_ns(void)
+{
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.sec * 1000000000L + ts.nsec;
+}
+
+// a 5ms sleep must not be rounded up to a whole tick
+void
+nanosleep_test(void)
+{
+  struct timespec req = { 0, 5000000 };
+  long start, elapsed;
+
+  printf("nanosleep test: ");
+  start = now_ns();
+  if(nanosleep(&req, 0) < 0){
+    printf("FAIL: nanosleep failed\n");
+    exit(1);
+  }
+  elapsed = now_ns() - start;
+  if(elapsed < 5000000 || elapsed > 50000000){
+    printf("FAIL: slept %d us\n", (int)(elapsed / 1000));
+    exit(1);
+  }
+  printf("OK\n");
+}
+
+// a periodic timerfd counts every expiry between reads
+void
+timerfd_test(void)
+{
+  struct itimerspec its, cur;
+  uint64 n, total;
+  int fd;
+
+  printf("timerfd test: ");
+  fd = timerfd_create(CLOCK_MONOTONIC, 0);
+  if(fd < 0){
+    printf("FAIL: timerfd_create failed\n");
+    exit(1);
+  }
+  memset(&its, 0, sizeof(its));
+  its.value.nsec = 20000000;
+  its.interval.nsec = 20000000;
+  if(timerfd_settime(fd, 0, &its, 0) < 0){
+    printf("FAIL: timerfd_settime failed\n");
+    exit(1);
+  }
+  if(timerfd_gettime(fd, &cur) < 0 || cur.interval.nsec != 20000000){
+    printf("FAIL: timerfd_gettime\n");
+    exit(1);
+  }
+  for(total = 0; total < 5; total += n){
+    if(read(fd, &n, sizeof(n)) != sizeof(n) || n == 0){
+      printf("FAIL: read\n");
+      exit(1);
+    }
+  }
+
+  // disarmed timers report zero
+  memset(&its, 0, sizeof(its));
+  timerfd_settime(fd, 0, &its, 0);
+  timerfd_gettime(fd, &cur);
+  if(cur.value.sec != 0 || cur.value.nsec != 0){
+    printf("FAIL: timer still armed\n");
+    exit(1);
+  }
+  close(fd);
+  printf("OK\n");
+}
+
+// the alarm kills a child that never exits on its own
+void
+alarm_test(void)
+{
+  int pid, xstatus;
+
+  printf("alarm test: ");
+  pid = fork();
+  if(pid < 0){
+    printf("FAIL: fork failed\n");
+    exit(1);
+  }
+  if(pid == 0){
+    if(alarm(10) != 0 || alarm(1) != 10)
+      exit(2);
+    for(;;)
+      ;
+  }
+  wait(&xstatus);
+  if(xstatus != -1){
+    printf("FAIL: child exited with %d\n", xstatus);
+    exit(1);
+  }
+  printf("OK\n");
+}
+
+int
+main(int argc, char *argv[])
+{
+  nanosleep_test();
+  timerfd_test();
+  alarm_test();
+  printf("timertest: all tests passed\n");
+  exit(0);
+}
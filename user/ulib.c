@@ -1,6 +1,7 @@
 #include "include/types.h"
 #include "include/stat.h"
 #include "include/fcntl.h"
+#include "include/time.h"
 #include "user/user.h"
 #include "include/riscv.h"
 #include "include/memlayout.h"
@@ -142,4 +143,27 @@ ugetpid(void)
 {
   struct usyscall *u = (struct usyscall *)USYSCALL;
   return u->pid;
-}
\ No newline at end of file
+}
+
+// the reason the last failed system call returned -1,
+// one of the E* numbers in errno.h, or 0 if none was given.
+int
+geterrno(void)
+{
+  struct usyscall *u = (struct usyscall *)USYSCALL;
+  return u->errno;
+}
+
+// kill the process after seconds, 0 cancels a pending alarm.
+// returns the seconds left on the previous alarm, rounded up.
+int
+alarm(int seconds)
+{
+  struct itimerval new, old;
+
+  memset(&new, 0, sizeof(new));
+  new.value.sec = seconds;
+  if(setitimer(ITIMER_REAL, &new, &old) < 0)
+    return -1;
+  return old.value.sec + (old.value.usec > 0);
+}
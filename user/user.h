@@ -1,6 +1,11 @@
 struct stat;
 struct rtcdate;
 struct sysinfo;
+struct timespec;
+struct itimerval;
+struct itimerspec;
+struct schedstat;
+struct slabstat;
 
 // system calls
 int fork(void);
@@ -12,6 +17,7 @@ int read(int, void*, int);
 int close(int);
 int kill(int);
 int exec(char*, char**);
+int execve(char*, char**, char**);
 int open(const char*, int);
 int mknod(const char*, short, short);
 int unlink(const char*);
@@ -30,6 +36,19 @@ int trace(int);
 int sysinfo(struct sysinfo *);
 int sigalarm(int ticks, void (*handler)());
 int sigreturn(void);
+int ioctl(int, int, void*);
+int clock_gettime(int, struct timespec*);
+int nanosleep(const struct timespec*, struct timespec*);
+int setitimer(int, const struct itimerval*, struct itimerval*);
+int timerfd_create(int, int);
+int timerfd_settime(int, int, const struct itimerspec*, struct itimerspec*);
+int timerfd_gettime(int, struct itimerspec*);
+int sched_setaffinity(int, int);
+int sched_getaffinity(int);
+int schedstat(int, struct schedstat*);
+int slabstat(int, struct slabstat*);
+int heapdump(int);
+int setaslr(int);
 
 // ulib.c
 int stat(const char*, struct stat*);
@@ -49,4 +68,6 @@ int memcmp(const void *, const void *, uint);
 void *memcpy(void *, const void *, uint);
 
 int pgaccess(void *base, int len, void *mask);
-int ugetpid(void);
\ No newline at end of file
+int ugetpid(void);
+int geterrno(void);
+int alarm(int);
\ No newline at end of file
@@ -39,4 +39,18 @@ entry("uptime");
 entry("setpri");
 entry("getpri");entry("sigalarm");
 entry("sigreturn");entry("pgaccess");entry("trace");
-entry("sysinfo");
\ No newline at end of file
+entry("sysinfo");
+entry("ioctl");
+entry("clock_gettime");
+entry("nanosleep");
+entry("setitimer");
+entry("timerfd_create");
+entry("timerfd_settime");
+entry("timerfd_gettime");
+entry("sched_setaffinity");
+entry("sched_getaffinity");
+entry("schedstat");
+entry("slabstat");
+entry("heapdump");
+entry("setaslr");
+entry("execve");
@@ -0,0 +1,94 @@
+#include "include/types.h"
+#include "include/riscv.h"
+#include "user/user.h"
+
+// user memory is mapped with the permissions of the ELF segments and is
+// never both writable and executable: writing to code, running code on the
+// heap or stack and running off the end of the stack kill the process.
+
+char *name;
+
+// run f in a child and check that the kernel killed it
+void
+expect_killed(char *what, void (*f)(void))
+{
+  int pid, status;
+
+  pid = fork();
+  if(pid < 0){
+    printf("wxtest: fork failed\n");
+    exit(1);
+  }
+  if(pid == 0){
+    f();
+    printf("wxtest: %s was allowed\n", what);
+    exit(0);
+  }
+  wait(&status);
+  if(status != -1){
+    printf("wxtest: %s: child exited with %d, expected to be killed\n", what, status);
+    exit(1);
+  }
+  printf("wxtest: %s OK\n", what);
+}
+
+void
+write_text(void)
+{
+  *(volatile uint32 *)expect_killed = 0;
+}
+
+void
+write_rodata(void)
+{
+  *(volatile char *)name = 'x';
+}
+
+// a single "ret" instruction
+static uint32 ret_insn = 0x00008067;
+
+void
+exec_heap(void)
+{
+  uint32 *code = (uint32 *)sbrk(PGSIZE);
+  *code = ret_insn;
+  ((void (*)(void))code)();
+}
+
+void
+exec_stack(void)
+{
+  uint32 code[1];
+  code[0] = ret_insn;
+  ((void (*)(void))code)();
+}
+
+void
+exec_data(void)
+{
+  ((void (*)(void))&ret_insn)();
+}
+
+void
+below_stack(void)
+{
+  char c;
+  volatile char *p = &c;
+  // the guard page lies at most one stack size below any local variable
+  for(int i = 0; i < 5 * PGSIZE; i += PGSIZE)
+    p[-i] = 0;
+}
+
+int
+main(int argc, char *argv[])
+{
+  name = "wxtest";
+  expect_killed("write to text", write_text);
+  expect_killed("write to rodata", write_rodata);
+  expect_killed("execute heap", exec_heap);
+  expect_killed("execute stack", exec_stack);
+  expect_killed("execute data", exec_data);
+  expect_killed("write below stack", below_stack);
+  printf("wxtest: OK\n");
+  exit(0);
+}
@@ -1,14 +1,19 @@
 #![no_std]
 #![no_main]
 
-use user_rust_lib::{io::getchar, task::sleep, time::get_mtime};
+use user_rust_lib::{env, io::getchar, task::sleep, time::get_mtime};
 
 #[macro_use]
 extern crate user_rust_lib;
 
 #[no_mangle]
-fn main(argc:usize, argv:&[&str]) -> i32 {
-    println!("hello world by rust, {}",argv[1]);
+fn main(_argc:usize, _argv:&[&str]) -> i32 {
+    for arg in env::args().skip(1) {
+        println!("hello world by rust, {}",arg);
+    }
+    if let Some(user) = env::var("USER") {
+        println!("USER={}",user);
+    }
     for i in 0..4{
         sleep(10);
         println!("sleep {}s",i);
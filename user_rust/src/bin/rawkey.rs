@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+use user_rust_lib::io::getchar;
+use user_rust_lib::termios::{tcgetattr, tcsetattr, SetAction, Termios};
+
+#[macro_use]
+extern crate user_rust_lib;
+
+// print the code of every key pressed until 'q', with the console in raw mode
+#[no_mangle]
+fn main() -> i32 {
+    let mut saved = Termios::default();
+    if tcgetattr(0, &mut saved) < 0 {
+        println!("rawkey: stdin is not a terminal");
+        return 1;
+    }
+    let mut raw = saved;
+    raw.make_raw();
+    tcsetattr(0, SetAction::Flush, &raw);
+
+    println!("press keys, q to quit");
+    loop {
+        let c = getchar();
+        if c == b'q' {
+            break;
+        }
+        println!("{:#04x}", c);
+    }
+
+    tcsetattr(0, SetAction::Now, &saved);
+    0
+}
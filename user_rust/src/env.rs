@@ -0,0 +1,80 @@
+//! 命令行参数和环境变量
+//!
+//! exec 在新程序的栈上放好以空指针结尾的 argv 和 envp 数组，`_start` 记下它们的地址，
+//! 之后这里把其中的字符串作为 `&'static str` 返回，程序不需要自己解析裸指针。
+
+use core::ptr;
+use core::slice;
+use core::str;
+
+static mut ARGV: *const *const u8 = ptr::null();
+static mut ENVP: *const *const u8 = ptr::null();
+
+/// 由 `_start` 在调用 `main` 之前记下 exec 传入的 argv 和 envp
+pub(crate) unsafe fn init(argv: *const *const u8, envp: *const *const u8) {
+    ARGV = argv;
+    ENVP = envp;
+}
+
+/// 以空指针结尾的字符串指针数组上的迭代器
+#[derive(Clone)]
+struct CStrs {
+    next: *const *const u8,
+}
+
+impl Iterator for CStrs {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        if self.next.is_null() {
+            return None
+        }
+        let s = unsafe { *self.next };
+        if s.is_null() {
+            return None
+        }
+        self.next = unsafe { self.next.add(1) };
+        let len = (0usize..).find(|&i| unsafe { *s.add(i) } == 0).unwrap();
+        Some(str::from_utf8(unsafe { slice::from_raw_parts(s, len) }).unwrap())
+    }
+}
+
+/// 命令行参数的迭代器，见 [`args`]
+#[derive(Clone)]
+pub struct Args(CStrs);
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        self.0.next()
+    }
+}
+
+/// 环境变量的迭代器，见 [`vars`]
+#[derive(Clone)]
+pub struct Vars(CStrs);
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<(&'static str, &'static str)> {
+        // 没有 `=` 的项看作值为空的变量
+        self.0.next().map(|var| var.split_once('=').unwrap_or((var, "")))
+    }
+}
+
+/// 返回命令行参数，第一个是程序名
+pub fn args() -> Args {
+    Args(CStrs { next: unsafe { ARGV } })
+}
+
+/// 返回所有环境变量的 (名字, 值)
+pub fn vars() -> Vars {
+    Vars(CStrs { next: unsafe { ENVP } })
+}
+
+/// 返回名为 `key` 的环境变量的值，没有这个变量时返回 `None`
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|&(name, _)| name == key).map(|(_, value)| value)
+}
@@ -44,7 +44,11 @@ pub struct Stat{
     pub ino: u32,
     pub ftype: FileT,
     pub nlink: u16,
-    pub size: u64
+    pub size: u64,
+    /// seconds since the epoch
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
 }
 
 #[repr(C)]
@@ -60,7 +64,10 @@ pub struct StatC{
     ino: u32,
     ftype: u16,
     nlink: u16,
-    size: u64
+    size: u64,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
 }
 
 pub fn dup(fd: isize) -> isize {
@@ -83,7 +90,7 @@ pub fn close(fd: isize) -> isize {
     sys_close(fd)
 }
 pub fn fstat(fd: isize, fstat :&mut Stat)->isize{
-    let mut fstat_c = StatC{dev:0,ino:0,ftype:0,nlink:0,size:0};
+    let mut fstat_c = StatC{dev:0,ino:0,ftype:0,nlink:0,size:0,atime:0,mtime:0,ctime:0};
     let res = sys_fstat(fd, &mut fstat_c as * mut StatC as usize);
     if res == -1 {
         return res;
@@ -98,6 +105,9 @@ pub fn fstat(fd: isize, fstat :&mut Stat)->isize{
     };
     fstat.nlink = fstat_c.nlink;
     fstat.size = fstat_c.size;
+    fstat.atime = fstat_c.atime;
+    fstat.mtime = fstat_c.mtime;
+    fstat.ctime = fstat_c.ctime;
     res
 }
 
@@ -158,7 +168,11 @@ pub struct Stat{
     pub ino: u32,
     pub ftype: FileT,
     pub nlink: u16,
-    pub size: u64
+    pub size: u64,
+    /// seconds since the epoch
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
 }
 
 #[repr(C)]
@@ -168,7 +182,10 @@ pub struct StatC{
     ino: u32,
     ftype: u16,
     nlink: u16,
-    size: u64
+    size: u64,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
 }
 
 pub fn dup(fd: isize) -> isize {
@@ -191,7 +208,7 @@ pub fn close(fd: isize) -> isize {
     sys_close(fd)
 }
 pub fn fstat(fd: isize, fstat :&mut Stat)->isize{
-    let mut fstat_c = StatC{dev:0,ino:0,ftype:0,nlink:0,size:0};
+    let mut fstat_c = StatC{dev:0,ino:0,ftype:0,nlink:0,size:0,atime:0,mtime:0,ctime:0};
     let res = sys_fstat(fd, &mut fstat_c as * mut StatC as usize);
     if res == -1 {
         return res;
@@ -206,6 +223,9 @@ pub fn fstat(fd: isize, fstat :&mut Stat)->isize{
     };
     fstat.nlink = fstat_c.nlink;
     fstat.size = fstat_c.size;
+    fstat.atime = fstat_c.atime;
+    fstat.mtime = fstat_c.mtime;
+    fstat.ctime = fstat_c.ctime;
     res
 }
 
@@ -224,3 +244,7 @@ pub fn mkdir(dir_name: &str) -> isize {
 pub fn mknod(path: &str, major: u16, minor: u16) -> isize {
     sys_mknod(path, major, minor)
 }
+
+pub fn ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    syscall_riscv::sys_ioctl(fd, cmd, arg)
+}
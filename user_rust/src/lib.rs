@@ -11,11 +11,14 @@ pub mod io;
 pub mod ralloc;
 pub mod file;
 pub mod time;
+pub mod termios;
 pub mod thread;
+pub mod env;
 
 extern crate alloc;
 extern crate syscall_riscv;
 
+pub use env::args;
 use syscall_riscv::*;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
@@ -35,28 +38,13 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+        env::init(argv as *const *const u8, envp as *const *const u8);
     }
-    let mut v: Vec<&'static str> = Vec::new();
-    for i in 0..argc{
-        let str_start = unsafe {
-            ((argv + i * core::mem::size_of::<usize>()) as * const usize).read_volatile()
-        };
-        let len = (0usize..)
-            .find(|i| unsafe{((str_start + *i) as *const u8).read_volatile() == 0})
-            .unwrap();
-        v.push(
-            core::str::from_utf8(
-                unsafe {
-                    core::slice::from_raw_parts(str_start as *const u8, len)
-                }
-            )
-            .unwrap()
-        );
-    }
+    let v: Vec<&'static str> = env::args().collect();
     exit(main(argc,v.as_slice()));
 }
 
@@ -82,12 +70,15 @@ pub mod io;
 pub mod ralloc;
 pub mod file;
 pub mod time;
+pub mod termios;
 pub mod thread;
+pub mod env;
 pub mod ulib;
 
 extern crate alloc;
 extern crate syscall_riscv;
 
+pub use env::args;
 use syscall_riscv::*;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
@@ -107,28 +98,13 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+        env::init(argv as *const *const u8, envp as *const *const u8);
     }
-    let mut v: Vec<&'static str> = Vec::new();
-    for i in 0..argc{
-        let str_start = unsafe {
-            ((argv + i * core::mem::size_of::<usize>()) as * const usize).read_volatile()
-        };
-        let len = (0usize..)
-            .find(|i| unsafe{((str_start + *i) as *const u8).read_volatile() == 0})
-            .unwrap();
-        v.push(
-            core::str::from_utf8(
-                unsafe {
-                    core::slice::from_raw_parts(str_start as *const u8, len)
-                }
-            )
-            .unwrap()
-        );
-    }
+    let v: Vec<&'static str> = env::args().collect();
     exit(main(argc,v.as_slice()));
 }
 
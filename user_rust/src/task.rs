@@ -1,4 +1,16 @@
-use syscall_riscv::{sys_chdir, sys_exec, sys_fork, sys_getpid, sys_kill, sys_sleep, sys_wait, sys_waitpid};
+use syscall_riscv::{sys_chdir, sys_exec, sys_execve, sys_fork, sys_getpid, sys_kill, sys_sleep, sys_wait, sys_waitpid};
+use syscall_riscv::{sys_sched_setaffinity, sys_sched_getaffinity, sys_schedstat};
+
+/// same layout as struct schedstat in include/sched.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedStat {
+    pub switches: u64,
+    pub idle_ns: u64,
+    pub steals: u64,
+    pub migrations: u64,
+    pub queued: u64,
+}
 
 pub fn fork() -> isize {
     sys_fork()
@@ -10,6 +22,9 @@ pub fn kill(pid: isize) -> isize{
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path,args)
 }
+pub fn execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    sys_execve(path, args, envs)
+}
 
 pub fn getpid() -> isize {
     sys_getpid()
@@ -29,4 +44,22 @@ pub fn chdir(working_dir:&str) -> isize {
 
 pub fn waitpid(pid: isize, exit_code: &mut i32) -> isize {
     sys_waitpid(pid, exit_code as *mut i32)
-}
\ No newline at end of file
+}
+
+/// pid 0 means the calling process; bit i of mask allows hart i
+pub fn sched_setaffinity(pid: isize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, mask)
+}
+
+pub fn sched_getaffinity(pid: isize) -> isize {
+    sys_sched_getaffinity(pid)
+}
+
+/// None once hart is past the last running hart
+pub fn schedstat(hart: usize) -> Option<SchedStat> {
+    let mut stat = SchedStat::default();
+    if sys_schedstat(hart, &mut stat as *mut SchedStat as usize) < 0 {
+        return None
+    }
+    Some(stat)
+}
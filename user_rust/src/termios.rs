@@ -0,0 +1,114 @@
+use crate::file::ioctl;
+
+// same layout as struct termios in include/termios.h
+pub const NCCS: usize = 8;
+
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+
+pub const CBAUD: u32 = 0o10017;
+pub const B1200: u32 = 0o11;
+pub const B2400: u32 = 0o13;
+pub const B4800: u32 = 0o14;
+pub const B9600: u32 = 0o15;
+pub const B19200: u32 = 0o16;
+pub const B38400: u32 = 0o17;
+pub const B57600: u32 = 0o10001;
+pub const B115200: u32 = 0o10002;
+pub const CSIZE: u32 = 0o60;
+pub const CS5: u32 = 0o0;
+pub const CS6: u32 = 0o20;
+pub const CS7: u32 = 0o40;
+pub const CS8: u32 = 0o60;
+pub const CSTOPB: u32 = 0o100;
+pub const CREAD: u32 = 0o200;
+pub const PARENB: u32 = 0o400;
+pub const PARODD: u32 = 0o1000;
+
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+
+const TCGETS: u32 = 0x5401;
+const TCSETS: u32 = 0x5402;
+const TCSETSW: u32 = 0x5403;
+const TCSETSF: u32 = 0x5404;
+const TIOCGPGRP: u32 = 0x540F;
+const TIOCSPGRP: u32 = 0x5410;
+
+/// When a new setting takes effect, see tcsetattr
+#[derive(Clone, Copy, Debug)]
+pub enum SetAction {
+    /// immediately
+    Now,
+    /// after pending output is written
+    Drain,
+    /// after pending output is written, discarding unread input
+    Flush,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// Switch to raw mode: no line editing, echo, signal characters or
+    /// input translation; every read returns as soon as one byte arrives.
+    pub fn make_raw(&mut self) {
+        self.iflag &= !(INLCR | IGNCR | ICRNL);
+        self.oflag &= !OPOST;
+        self.lflag &= !(ISIG | ICANON | ECHO | ECHOE | ECHOK);
+        self.cc[VMIN] = 1;
+        self.cc[VTIME] = 0;
+    }
+}
+
+/// Set the baud rate, one of the B* constants; applied by tcsetattr
+pub fn cfsetspeed(termios: &mut Termios, speed: u32) {
+    termios.cflag = (termios.cflag & !CBAUD) | (speed & CBAUD);
+}
+
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    ioctl(fd, TCGETS, termios as *mut Termios as usize)
+}
+
+pub fn tcsetattr(fd: usize, action: SetAction, termios: &Termios) -> isize {
+    let cmd = match action {
+        SetAction::Now => TCSETS,
+        SetAction::Drain => TCSETSW,
+        SetAction::Flush => TCSETSF,
+    };
+    ioctl(fd, cmd, termios as *const Termios as usize)
+}
+
+/// pid of the foreground process, 0 if none
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pid = 0i32;
+    let ret = ioctl(fd, TIOCGPGRP, &mut pid as *mut i32 as usize);
+    if ret < 0 { ret } else { pid as isize }
+}
+
+/// make pid the process interrupted by ctrl-c, 0 for none
+pub fn tcsetpgrp(fd: usize, pid: i32) -> isize {
+    ioctl(fd, TIOCSPGRP, &pid as *const i32 as usize)
+}
@@ -1,4 +1,43 @@
-use syscall_riscv::{sys_uptime,sys_getmtime};
+use syscall_riscv::{sys_uptime,sys_getmtime,sys_clock_gettime,sys_nanosleep,sys_setitimer};
+use syscall_riscv::{sys_timerfd_create,sys_timerfd_settime,sys_timerfd_gettime};
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+pub const ITIMER_REAL: usize = 0;
+pub const TFD_TIMER_ABSTIME: usize = 1;
+
+/// same layout as struct timespec in include/time.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timespec {
+    pub sec: i64,
+    pub nsec: i64,
+}
+
+/// same layout as struct timeval in include/time.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeval {
+    pub sec: i64,
+    pub usec: i64,
+}
+
+/// same layout as struct itimerval in include/time.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Itimerval {
+    pub interval: Timeval,
+    pub value: Timeval,
+}
+
+/// same layout as struct itimerspec in include/time.h
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Itimerspec {
+    pub interval: Timespec,
+    pub value: Timespec,
+}
 
 pub fn get_uptime() -> usize {
     sys_uptime() as usize
@@ -6,4 +45,55 @@ pub fn get_uptime() -> usize {
 
 pub fn get_mtime() -> usize {
     sys_getmtime() as usize
-}
\ No newline at end of file
+}
+
+pub fn clock_gettime(clock: usize, tp: &mut Timespec) -> isize {
+    sys_clock_gettime(clock, tp as *mut Timespec as usize)
+}
+
+/// seconds since the epoch, -1 on error
+pub fn time() -> i64 {
+    let mut tp = Timespec::default();
+    if clock_gettime(CLOCK_REALTIME, &mut tp) < 0 {
+        return -1;
+    }
+    tp.sec
+}
+
+/// sleep for req; if interrupted, the time left is stored in rem
+pub fn nanosleep(req: &Timespec, rem: Option<&mut Timespec>) -> isize {
+    let rem = rem.map_or(0, |rem| rem as *mut Timespec as usize);
+    sys_nanosleep(req as *const Timespec as usize, rem)
+}
+
+pub fn setitimer(which: usize, new: &Itimerval, old: Option<&mut Itimerval>) -> isize {
+    let old = old.map_or(0, |old| old as *mut Itimerval as usize);
+    sys_setitimer(which, new as *const Itimerval as usize, old)
+}
+
+/// kill the process after seconds, 0 cancels; returns the seconds left on
+/// the previous alarm, rounded up
+pub fn alarm(seconds: u32) -> isize {
+    let mut new = Itimerval::default();
+    new.value.sec = seconds as i64;
+    let mut old = Itimerval::default();
+    if setitimer(ITIMER_REAL, &new, Some(&mut old)) < 0 {
+        return -1;
+    }
+    (old.value.sec + (old.value.usec > 0) as i64) as isize
+}
+
+/// a file that becomes readable when its timer expires; reading it yields
+/// the number of expirations as a u64
+pub fn timerfd_create(clock: usize, flags: usize) -> isize {
+    sys_timerfd_create(clock, flags)
+}
+
+pub fn timerfd_settime(fd: usize, flags: usize, new: &Itimerspec, old: Option<&mut Itimerspec>) -> isize {
+    let old = old.map_or(0, |old| old as *mut Itimerspec as usize);
+    sys_timerfd_settime(fd, flags, new as *const Itimerspec as usize, old)
+}
+
+pub fn timerfd_gettime(fd: usize, cur: &mut Itimerspec) -> isize {
+    sys_timerfd_gettime(fd, cur as *mut Itimerspec as usize)
+}
@@ -40,6 +40,17 @@ const SYSCALL_MKDIR: usize = 20;
 const SYSCALL_CLOSE: usize = 21;
 const SYSCALL_GETMTIME: usize = 22;
 const SYSCALL_WAITPID: usize = 23;
+const SYSCALL_IOCTL: usize = 24;
+const SYSCALL_CLOCK_GETTIME: usize = 25;
+const SYSCALL_NANOSLEEP: usize = 26;
+const SYSCALL_SETITIMER: usize = 27;
+const SYSCALL_TIMERFD_CREATE: usize = 28;
+const SYSCALL_TIMERFD_SETTIME: usize = 29;
+const SYSCALL_TIMERFD_GETTIME: usize = 30;
+const SYSCALL_SCHED_SETAFFINITY: usize = 31;
+const SYSCALL_SCHED_GETAFFINITY: usize = 32;
+const SYSCALL_SCHEDSTAT: usize = 33;
+const SYSCALL_EXECVE: usize = 37;
 const SYSCALL_TEST: usize = 99;
 
 ///进程 A 调用 fork 系统调用之后，内核会创建一个新进程 B，这个进程 B 和调用 fork 的进程A在它们分别返回用户态那一瞬间几乎处于相同的状态：这意味着它们包含的用户态的代码段、堆栈段及其他数据段的内容完全相同，但是它们是被放在两个独立的地址空间中的。因此新进程的地址空间需要从原有进程的地址空间完整拷贝一份。两个进程通用寄存器也几乎完全相同。
@@ -80,6 +91,15 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
         [path.as_ptr() as usize, args.as_ptr() as usize, 0, 0, 0, 0],
     )
 }
+/// 功能：与 sys_exec 相同，另外把环境变量传给新程序。
+/// 参数：args 和 envs 都是以空指针结尾的字符串指针数组；
+/// 返回值：出错时返回 -1，参数和环境变量太多（超过 ARG_MAX）时错误码为 E2BIG，否则不应该返回。
+pub fn sys_execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXECVE,
+        [path.as_ptr() as usize, args.as_ptr() as usize, envs.as_ptr() as usize, 0, 0, 0],
+    )
+}
 pub fn sys_fstat(fd: isize, fstat: usize) -> isize {
     syscall(
         SYSCALL_FSTAT,
@@ -125,6 +145,46 @@ pub fn sys_mknod(path: &str, major: u16, minor: u16) -> isize {
     syscall(SYSCALL_MKNOD, [path.as_ptr() as usize, major as usize, minor as usize, 0, 0, 0])
 }
 
+pub fn sys_ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd as usize, arg, 0, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock: usize, tp: usize) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock, tp, 0, 0, 0, 0])
+}
+
+pub fn sys_nanosleep(req: usize, rem: usize) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [req, rem, 0, 0, 0, 0])
+}
+
+pub fn sys_setitimer(which: usize, new: usize, old: usize) -> isize {
+    syscall(SYSCALL_SETITIMER, [which, new, old, 0, 0, 0])
+}
+
+pub fn sys_timerfd_create(clock: usize, flags: usize) -> isize {
+    syscall(SYSCALL_TIMERFD_CREATE, [clock, flags, 0, 0, 0, 0])
+}
+
+pub fn sys_timerfd_settime(fd: usize, flags: usize, new: usize, old: usize) -> isize {
+    syscall(SYSCALL_TIMERFD_SETTIME, [fd, flags, new, old, 0, 0])
+}
+
+pub fn sys_timerfd_gettime(fd: usize, cur: usize) -> isize {
+    syscall(SYSCALL_TIMERFD_GETTIME, [fd, cur, 0, 0, 0, 0])
+}
+
+pub fn sys_sched_setaffinity(pid: isize, mask: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETAFFINITY, [pid as usize, mask, 0, 0, 0, 0])
+}
+
+pub fn sys_sched_getaffinity(pid: isize) -> isize {
+    syscall(SYSCALL_SCHED_GETAFFINITY, [pid as usize, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_schedstat(hart: usize, stat: usize) -> isize {
+    syscall(SYSCALL_SCHEDSTAT, [hart, stat, 0, 0, 0, 0])
+}
+
 pub fn sys_unlink(path: &str) -> isize {
     syscall(SYSCALL_UNLINK, [path.as_ptr() as usize, 0, 0, 0, 0, 0])
 }